@@ -17,6 +17,11 @@
 */
 
 use std::collections::HashMap;
+use std::convert::Infallible;
+use std::fmt::Display;
+use std::str::FromStr;
+
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
 
 pub struct AccountConfiguration {
 	pub id: Option<u64>,
@@ -25,10 +30,77 @@ pub struct AccountConfiguration {
 	pub data: Option<String>,
 }
 
+/// Kind of account, as recorded in an [AccountConfiguration]
+///
+/// The built-in `drcr.*` kinds are represented by their own variants. Any other kind (e.g. `austax.d1`, defined by a plugin) is represented by [AccountKind::Other]. Serialised as the string form of the kind.
+#[derive(Clone, Debug, Eq, Hash, PartialEq)]
+pub enum AccountKind {
+	Asset,
+	Liability,
+	Equity,
+	Income,
+	Expense,
+	Cash,
+	/// Account is shown in reports even if its balance is zero
+	AlwaysVisible,
+	Other(String),
+}
+
+impl AccountKind {
+	pub fn as_str(&self) -> &str {
+		match self {
+			AccountKind::Asset => "drcr.asset",
+			AccountKind::Liability => "drcr.liability",
+			AccountKind::Equity => "drcr.equity",
+			AccountKind::Income => "drcr.income",
+			AccountKind::Expense => "drcr.expense",
+			AccountKind::Cash => "drcr.cash",
+			AccountKind::AlwaysVisible => crate::ALWAYS_VISIBLE_KIND,
+			AccountKind::Other(kind) => kind,
+		}
+	}
+}
+
+impl FromStr for AccountKind {
+	type Err = Infallible;
+
+	fn from_str(s: &str) -> Result<Self, Self::Err> {
+		Ok(match s {
+			"drcr.asset" => AccountKind::Asset,
+			"drcr.liability" => AccountKind::Liability,
+			"drcr.equity" => AccountKind::Equity,
+			"drcr.income" => AccountKind::Income,
+			"drcr.expense" => AccountKind::Expense,
+			"drcr.cash" => AccountKind::Cash,
+			crate::ALWAYS_VISIBLE_KIND => AccountKind::AlwaysVisible,
+			_ => AccountKind::Other(s.to_string()),
+		})
+	}
+}
+
+impl Display for AccountKind {
+	fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+		f.write_str(self.as_str())
+	}
+}
+
+impl Serialize for AccountKind {
+	fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+		serializer.serialize_str(self.as_str())
+	}
+}
+
+impl<'de> Deserialize<'de> for AccountKind {
+	fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+		let kind = String::deserialize(deserializer)?;
+		Ok(kind.parse().unwrap()) // Parsing is infallible
+	}
+}
+
 /// Convert [`Vec<AccountConfiguration>`] into a [HashMap] mapping account names to account kinds
 pub fn kinds_for_account(
 	account_configurations: Vec<AccountConfiguration>,
-) -> HashMap<String, Vec<String>> {
+) -> HashMap<String, Vec<AccountKind>> {
 	let mut result = HashMap::new();
 
 	for account_configuration in account_configurations {
@@ -36,7 +108,154 @@ pub fn kinds_for_account(
 		result
 			.entry(account_configuration.account)
 			.or_insert_with(|| Vec::new())
-			.push(account_configuration.kind);
+			.push(account_configuration.kind.parse().unwrap()); // Parsing is infallible
+	}
+
+	result
+}
+
+/// Convert [`Vec<AccountConfiguration>`] into a [HashMap] mapping account names to the data of each account kind
+///
+/// Data is parsed as JSON, and data which is not valid JSON is given as a string. Account kinds without data are omitted.
+pub fn data_for_account(
+	account_configurations: &[AccountConfiguration],
+) -> HashMap<String, HashMap<String, serde_json::Value>> {
+	let mut result: HashMap<String, HashMap<String, serde_json::Value>> = HashMap::new();
+
+	for account_configuration in account_configurations {
+		if let Some(data) = &account_configuration.data {
+			let data: serde_json::Value = serde_json::from_str(data)
+				.unwrap_or_else(|_| serde_json::Value::String(data.clone()));
+
+			result
+				.entry(account_configuration.account.clone())
+				.or_default()
+				.insert(account_configuration.kind.clone(), data);
+		}
+	}
+
+	result
+}
+
+/// Return true if the account is configured as a contra account within the given kind, i.e. the data of its account configuration for that kind is a JSON object with `"contra": true`
+///
+/// A contra account (e.g. accumulated depreciation within assets) is shown in reports with the opposite sign to the other accounts of its kind. See [Row::contra](crate::reporting::dynamic_report::Row::contra).
+pub fn is_contra_account(
+	data_for_account: &HashMap<String, HashMap<String, serde_json::Value>>,
+	account: &str,
+	kind: &AccountKind,
+) -> bool {
+	data_for_account
+		.get(account)
+		.and_then(|data| data.get(kind.as_str()))
+		.and_then(|data| data.get("contra"))
+		.and_then(|contra| contra.as_bool())
+		.unwrap_or(false)
+}
+
+/// Account kinds which determine the section of the balance sheet or income statement in which an account appears
+///
+/// These kinds are mutually exclusive.
+pub const STATEMENT_ACCOUNT_KINDS: &[AccountKind] = &[
+	AccountKind::Asset,
+	AccountKind::Liability,
+	AccountKind::Equity,
+	AccountKind::Income,
+	AccountKind::Expense,
+];
+
+/// Problem with the configuration of an account, see [account_config_warnings]
+#[derive(Clone, Debug, Deserialize, Eq, PartialEq, Serialize)]
+pub enum AccountConfigWarning {
+	/// The account appears in transactions but has none of the [STATEMENT_ACCOUNT_KINDS], so is omitted from the balance sheet and income statement
+	MissingKind { account: String },
+	/// The account has more than one of the mutually exclusive [STATEMENT_ACCOUNT_KINDS]
+	ConflictingKinds {
+		account: String,
+		kinds: Vec<AccountKind>,
+	},
+}
+
+/// Find accounts whose configured kinds are missing or conflicting
+///
+/// `accounts` is the list of accounts which appear in transactions, e.g. from [DbConnection::get_posting_accounts](crate::db::DbConnection::get_posting_accounts).
+pub fn account_config_warnings(
+	accounts: &[String],
+	kinds_for_account: &HashMap<String, Vec<AccountKind>>,
+) -> Vec<AccountConfigWarning> {
+	let mut warnings = Vec::new();
+
+	for account in accounts {
+		if !kinds_for_account
+			.get(account)
+			.is_some_and(|kinds| kinds.iter().any(|k| STATEMENT_ACCOUNT_KINDS.contains(k)))
+		{
+			warnings.push(AccountConfigWarning::MissingKind {
+				account: account.clone(),
+			});
+		}
+	}
+
+	// Conflicting kinds are reported even for accounts without transactions
+	let mut accounts_with_kinds = kinds_for_account.keys().collect::<Vec<_>>();
+	accounts_with_kinds.sort();
+
+	for account in accounts_with_kinds {
+		let mut statement_kinds = Vec::new();
+		for kind in kinds_for_account[account].iter() {
+			if STATEMENT_ACCOUNT_KINDS.contains(kind) && !statement_kinds.contains(kind) {
+				statement_kinds.push(kind.clone());
+			}
+		}
+
+		if statement_kinds.len() > 1 {
+			warnings.push(AccountConfigWarning::ConflictingKinds {
+				account: account.clone(),
+				kinds: statement_kinds,
+			});
+		}
+	}
+
+	warnings
+}
+
+/// Account kinds relied upon by each built-in report
+pub const REPORT_ACCOUNT_KINDS: &[(&str, &[AccountKind])] = &[
+	(
+		"BalanceSheet",
+		&[
+			AccountKind::Asset,
+			AccountKind::Liability,
+			AccountKind::Equity,
+		],
+	),
+	(
+		"IncomeStatement",
+		&[AccountKind::Income, AccountKind::Expense],
+	),
+];
+
+/// Find built-in reports for which no account is configured with any of the account kinds the report relies on
+///
+/// Such reports would render empty, which usually means the chart of accounts has not been configured. System accounts are disregarded.
+///
+/// Returns the report names and the account kinds each report relies on.
+pub fn reports_missing_account_kinds(
+	account_configurations: &Vec<AccountConfiguration>,
+) -> Vec<(String, Vec<String>)> {
+	let mut result = Vec::new();
+
+	for (report_name, kinds) in REPORT_ACCOUNT_KINDS {
+		if !account_configurations
+			.iter()
+			.filter(|c| c.id.is_some())
+			.any(|c| kinds.iter().any(|k| k.as_str() == c.kind))
+		{
+			result.push((
+				report_name.to_string(),
+				kinds.iter().map(|k| k.to_string()).collect(),
+			));
+		}
 	}
 
 	result
@@ -16,47 +16,275 @@
 	along with this program.  If not, see <https://www.gnu.org/licenses/>.
 */
 
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
+use std::fmt::Display;
+use std::fs;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+use std::time::SystemTime;
 
+use chrono::format::{Item, StrftimeItems};
 use chrono::{NaiveDate, NaiveDateTime};
-use sqlx::sqlite::SqliteRow;
-use sqlx::{Connection, Row, SqliteConnection};
+use sqlx::pool::PoolConnection;
+use sqlx::sqlite::{SqlitePoolOptions, SqliteRow};
+use sqlx::{Connection, Row, Sqlite, SqliteConnection, SqlitePool};
+use tokio::sync::Mutex;
 
-use crate::account_config::AccountConfiguration;
+use crate::account_config::{AccountConfiguration, AccountKind};
 use crate::model::assertions::BalanceAssertion;
-use crate::model::statements::StatementLine;
-use crate::model::transaction::{Posting, Transaction, TransactionWithPostings};
-use crate::{util::format_date, QuantityInt};
+use crate::model::audit_log::AuditLogEntry;
+use crate::model::commodity_price::{CommodityPrice, Price};
+use crate::model::opening_balance::OpeningBalance;
+use crate::model::statements::{AccountReconciliationStatus, StatementLine};
+use crate::model::transaction::{
+	update_balances_from_transactions, Posting, Transaction, TransactionWithPostings,
+};
+use crate::util::{bare_commodity, format_date, format_datetime};
+use crate::QuantityInt;
 
+/// Version of the database schema in `schema.sql` (should match `DB_VERSION` in the frontend)
+const DB_VERSION: u32 = 13;
+
+/// Counter used to name in-memory databases uniquely
+static NEXT_IN_MEMORY_ID: AtomicUsize = AtomicUsize::new(0);
+
+/// Error when accessing the database
+#[derive(Clone, Debug)]
+pub enum DbError {
+	/// An SQL query failed, while performing the described operation
+	Sql {
+		context: &'static str,
+		source: Arc<sqlx::Error>,
+	},
+	/// A required key is missing from the metadata table
+	MissingMetadata(&'static str),
+	/// A metadata key (or other configuration, e.g. `financial_year_ends.dt`) has a value which could not be parsed
+	InvalidMetadata { key: &'static str, value: String },
+}
+
+impl DbError {
+	/// Return a function which wraps an [sqlx::Error] as a [DbError] with the given context, for use with [Result::map_err]
+	fn sql(context: &'static str) -> impl FnOnce(sqlx::Error) -> DbError {
+		move |source| DbError::Sql {
+			context,
+			source: Arc::new(source),
+		}
+	}
+}
+
+impl Display for DbError {
+	fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+		match self {
+			DbError::Sql { context, source } => {
+				f.write_fmt(format_args!("SQL error when {}: {}", context, source))
+			}
+			DbError::MissingMetadata(key) => f.write_fmt(format_args!(
+				"database is missing required metadata: {}",
+				key
+			)),
+			DbError::InvalidMetadata { key, value } => f.write_fmt(format_args!(
+				"database has invalid metadata: {} = {:?}",
+				key, value
+			)),
+		}
+	}
+}
+
+impl std::error::Error for DbError {
+	fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+		match self {
+			DbError::Sql { source, .. } => Some(source.as_ref()),
+			DbError::MissingMetadata(_) | DbError::InvalidMetadata { .. } => None,
+		}
+	}
+}
+
+/// Balance of each account, and balance of each account in each commodity, as in [BalancesAt](crate::reporting::types::BalancesAt)
+pub type BalancesWithCommodityBalances = (
+	HashMap<String, QuantityInt>,
+	HashMap<String, HashMap<String, QuantityInt>>,
+);
+
+/// Connection to the database
+///
+/// Cloning a [DbConnection] is cheap, and the clone shares the same pool of connections.
+#[derive(Clone)]
 pub struct DbConnection {
 	url: String,
+	/// Pool of connections to the database, so that connections are reused across queries
+	pool: SqlitePool,
 	metadata: DbMetadata,
+	/// For in-memory databases, a connection which is held open to keep the database alive
+	_keep_alive: Option<Arc<Mutex<SqliteConnection>>>,
 }
 
 impl DbConnection {
-	pub async fn new(url: &str) -> Self {
-		let mut connection = SqliteConnection::connect(url).await.expect("SQL error");
-		let metadata = DbMetadata::from_database(&mut connection).await;
+	pub async fn new(url: &str) -> Result<Self, DbError> {
+		let pool = Self::connect_pool(url).await?;
+		let mut connection = pool
+			.acquire()
+			.await
+			.map_err(DbError::sql("connecting to database"))?;
+		let metadata = DbMetadata::from_database(&mut connection).await?;
 
-		Self {
+		Ok(Self {
 			url: url.to_string(),
+			pool,
 			metadata,
+			_keep_alive: None,
+		})
+	}
+
+	/// Copy the database into a new in-memory database
+	///
+	/// Changes made through the returned [DbConnection] (e.g. by [DbConnection::insert_transaction]) do not affect this database.
+	/// The in-memory database is discarded when the returned [DbConnection] and all its clones are dropped.
+	pub async fn snapshot_in_memory(&self) -> Result<DbConnection, DbError> {
+		let filename = format!(
+			"file:drcr_snapshot_{}?mode=memory&cache=shared",
+			NEXT_IN_MEMORY_ID.fetch_add(1, Ordering::Relaxed)
+		);
+		let url = format!("sqlite:{}", filename);
+
+		// The in-memory database exists only while a connection to it is open
+		let mut keep_alive = SqliteConnection::connect(&url)
+			.await
+			.map_err(DbError::sql("connecting to in-memory database"))?;
+
+		let mut connection = self.connect().await?;
+		sqlx::query(&format!("VACUUM INTO '{}'", filename))
+			.execute(&mut *connection)
+			.await
+			.map_err(DbError::sql("copying database into memory"))?;
+
+		let metadata = DbMetadata::from_database(&mut keep_alive).await?;
+
+		Ok(Self {
+			pool: Self::connect_pool(&url).await?,
+			url,
+			metadata,
+			_keep_alive: Some(Arc::new(Mutex::new(keep_alive))),
+		})
+	}
+
+	/// Write a copy of the database to a new file at `path`, which must not already exist
+	///
+	/// The copy is made with `VACUUM INTO`, so it is consistent and includes all committed changes, even those not yet checkpointed from the write-ahead log. Uncommitted changes are not included.
+	/// For an in-memory database, `path` must be a URI filename with `mode=rwc`, or else the copy is also made in memory.
+	pub async fn backup_into(&self, path: &str) -> Result<(), DbError> {
+		let mut connection = self.connect().await?;
+		sqlx::query("VACUUM INTO $1")
+			.bind(path)
+			.execute(&mut *connection)
+			.await
+			.map_err(DbError::sql("backing up database"))?;
+		Ok(())
+	}
+
+	/// Create a new, empty in-memory database with the current schema, e.g. for testing reporting steps without a database file
+	///
+	/// Each call returns an independent database, initialised with the given metadata in the same way as a new database created in the frontend. Further metadata may be inserted into the `metadata` table, followed by a call to [DbConnection::reload_metadata].
+	/// The in-memory database is discarded when the returned [DbConnection] and all its clones are dropped.
+	pub async fn new_in_memory(
+		eofy_date: NaiveDate,
+		reporting_commodity: &str,
+		dps: u32,
+	) -> Result<DbConnection, DbError> {
+		let url = format!(
+			"sqlite:file:drcr_memory_{}?mode=memory&cache=shared",
+			NEXT_IN_MEMORY_ID.fetch_add(1, Ordering::Relaxed)
+		);
+
+		// The in-memory database exists only while a connection to it is open
+		let mut keep_alive = SqliteConnection::connect(&url)
+			.await
+			.map_err(DbError::sql("connecting to in-memory database"))?;
+
+		sqlx::raw_sql(include_str!("../../schema.sql"))
+			.execute(&mut keep_alive)
+			.await
+			.map_err(DbError::sql("initialising database schema"))?;
+
+		for (key, value) in [
+			("version", DB_VERSION.to_string()),
+			("eofy_date", eofy_date.format("%Y-%m-%d").to_string()),
+			("reporting_commodity", reporting_commodity.to_string()),
+			("amount_dps", dps.to_string()),
+			("plugins", String::new()),
+		] {
+			sqlx::query("INSERT INTO metadata (key, value) VALUES ($1, $2)")
+				.bind(key)
+				.bind(value)
+				.execute(&mut keep_alive)
+				.await
+				.map_err(DbError::sql("initialising metadata"))?;
 		}
+
+		let metadata = DbMetadata::from_database(&mut keep_alive).await?;
+
+		Ok(Self {
+			pool: Self::connect_pool(&url).await?,
+			url,
+			metadata,
+			_keep_alive: Some(Arc::new(Mutex::new(keep_alive))),
+		})
 	}
 
 	pub fn metadata(&self) -> &DbMetadata {
 		&self.metadata
 	}
 
-	pub async fn connect(&self) -> SqliteConnection {
-		SqliteConnection::connect(&self.url)
+	/// Re-read the [DbMetadata] from the database, e.g. after the metadata table has been modified through another connection
+	pub async fn reload_metadata(&mut self) -> Result<(), DbError> {
+		let mut connection = self.connect().await?;
+		self.metadata = DbMetadata::from_database(&mut connection).await?;
+		Ok(())
+	}
+
+	pub fn url(&self) -> &str {
+		&self.url
+	}
+
+	/// Get the time at which the database file was last modified, or `None` for an in-memory database or if this cannot be determined
+	///
+	/// Where the database is in WAL mode, writes to the write-ahead log are also taken into account.
+	pub fn last_write_time(&self) -> Option<SystemTime> {
+		if self.url.contains(":memory:") || self.url.contains("mode=memory") {
+			return None;
+		}
+
+		let path = self.url.strip_prefix("sqlite:")?;
+		let path = path.strip_prefix("//").unwrap_or(path);
+		let path = path.strip_prefix("file:").unwrap_or(path);
+		let path = path.split('?').next().unwrap();
+
+		let db_time = fs::metadata(path).and_then(|m| m.modified()).ok()?;
+		let wal_time = fs::metadata(format!("{}-wal", path))
+			.and_then(|m| m.modified())
+			.ok();
+
+		Some(wal_time.map_or(db_time, |t| t.max(db_time)))
+	}
+
+	/// Get a connection to the database from the pool
+	pub async fn connect(&self) -> Result<PoolConnection<Sqlite>, DbError> {
+		self.pool
+			.acquire()
 			.await
-			.expect("SQL error")
+			.map_err(DbError::sql("connecting to database"))
+	}
+
+	/// Create a [SqlitePool] for the database
+	async fn connect_pool(url: &str) -> Result<SqlitePool, DbError> {
+		SqlitePoolOptions::new()
+			.connect(url)
+			.await
+			.map_err(DbError::sql("connecting to database"))
 	}
 
 	/// Get account configurations from the database
-	pub async fn get_account_configurations(&self) -> Vec<AccountConfiguration> {
-		let mut connection = self.connect().await;
+	pub async fn get_account_configurations(&self) -> Result<Vec<AccountConfiguration>, DbError> {
+		let mut connection = self.connect().await?;
 
 		let mut account_configurations =
 			sqlx::query("SELECT id, account, kind, data FROM account_configurations")
@@ -66,36 +294,120 @@ impl DbConnection {
 					kind: r.get("kind"),
 					data: r.get("data"),
 				})
-				.fetch_all(&mut connection)
+				.fetch_all(&mut *connection)
 				.await
-				.expect("SQL error");
+				.map_err(DbError::sql("getting account configurations"))?;
 
 		// System accounts
 		account_configurations.push(AccountConfiguration {
 			id: None,
 			account: crate::CURRENT_YEAR_EARNINGS.to_string(),
-			kind: "drcr.equity".to_string(),
+			kind: AccountKind::Equity.to_string(),
 			data: None,
 		});
 		account_configurations.push(AccountConfiguration {
 			id: None,
 			account: crate::RETAINED_EARNINGS.to_string(),
-			kind: "drcr.equity".to_string(),
+			kind: AccountKind::Equity.to_string(),
 			data: None,
 		});
+		if self.metadata.rounding_reconciliation
+			&& !account_configurations
+				.iter()
+				.any(|c| c.account == crate::ROUNDING)
+		{
+			// Unless the user has configured an account of the same name
+			account_configurations.push(AccountConfiguration {
+				id: None,
+				account: crate::ROUNDING.to_string(),
+				kind: AccountKind::Equity.to_string(),
+				data: None,
+			});
+		}
+		if !account_configurations
+			.iter()
+			.any(|c| c.account == crate::OPENING_BALANCES && c.kind == AccountKind::Equity.as_str())
+		{
+			account_configurations.push(AccountConfiguration {
+				id: None,
+				account: crate::OPENING_BALANCES.to_string(),
+				kind: AccountKind::Equity.to_string(),
+				data: None,
+			});
+		}
 
-		account_configurations
+		Ok(account_configurations)
+	}
+
+	/// Get accounts which appear in postings but have no account configuration, together with their balances
+	///
+	/// Such accounts are omitted from every report section, so this is used to prompt the user to finish configuring them.
+	pub async fn accounts_without_configuration(
+		&self,
+	) -> Result<Vec<(String, QuantityInt)>, DbError> {
+		// Include system accounts, which are always considered configured
+		let configured_accounts = self
+			.get_account_configurations()
+			.await?
+			.into_iter()
+			.map(|c| c.account)
+			.collect::<HashSet<_>>();
+
+		let mut connection = self.connect().await?;
+
+		let rows = sqlx::query(
+			"SELECT account, IFNULL(SUM(quantity_ascost), 0) AS quantity
+			FROM transactions_with_quantity_ascost
+			GROUP BY account
+			ORDER BY account",
+		)
+		.map(|r: SqliteRow| (r.get("account"), r.get("quantity")))
+		.fetch_all(&mut *connection)
+		.await
+		.map_err(DbError::sql("getting account balances"))?;
+
+		Ok(rows
+			.into_iter()
+			.filter(|(account, _): &(String, QuantityInt)| !configured_accounts.contains(account))
+			.collect())
+	}
+
+	/// Get the names of all accounts which appear in postings, in sorted order
+	pub async fn get_posting_accounts(&self) -> Result<Vec<String>, DbError> {
+		let mut connection = self.connect().await?;
+
+		sqlx::query("SELECT DISTINCT account FROM postings ORDER BY account")
+			.map(|r: SqliteRow| r.get("account"))
+			.fetch_all(&mut *connection)
+			.await
+			.map_err(DbError::sql("getting accounts"))
 	}
 
 	/// Get balance assertions from the database
-	pub async fn get_balance_assertions(&self) -> Vec<BalanceAssertion> {
-		let mut connection = self.connect().await;
+	pub async fn get_balance_assertions(&self) -> Result<Vec<BalanceAssertion>, DbError> {
+		self.get_balance_assertions_filtered(None, None, None).await
+	}
+
+	/// Get balance assertions from the database, most recent first, optionally only those for the given account and/or dated within the given range (inclusive)
+	pub async fn get_balance_assertions_filtered(
+		&self,
+		account: Option<&str>,
+		date_start: Option<NaiveDate>,
+		date_end: Option<NaiveDate>,
+	) -> Result<Vec<BalanceAssertion>, DbError> {
+		let mut connection = self.connect().await?;
 
 		let balance_assertions = sqlx::query(
 			"SELECT id, dt, description, account, quantity, commodity
 			FROM balance_assertions
+			WHERE ($1 IS NULL OR account = $1)
+				AND ($2 IS NULL OR DATE(dt) >= DATE($2))
+				AND ($3 IS NULL OR DATE(dt) <= DATE($3))
 			ORDER BY dt DESC, id DESC",
 		)
+		.bind(account)
+		.bind(date_start.map(format_date))
+		.bind(date_end.map(format_date))
 		.map(|r: SqliteRow| BalanceAssertion {
 			id: r.get("id"),
 			dt: NaiveDateTime::parse_from_str(r.get("dt"), "%Y-%m-%d %H:%M:%S.%6f")
@@ -105,23 +417,174 @@ impl DbConnection {
 			quantity: r.get("quantity"),
 			commodity: r.get("commodity"),
 		})
-		.fetch_all(&mut connection)
+		.fetch_all(&mut *connection)
 		.await
-		.expect("SQL error");
+		.map_err(DbError::sql("getting balance assertions"))?;
 
-		balance_assertions
+		Ok(balance_assertions)
 	}
 
-	/// Get account balances from the database
-	pub async fn get_balances(&self, date: NaiveDate) -> HashMap<String, QuantityInt> {
-		let mut connection = self.connect().await;
+	/// Get entries from the audit log, most recent first
+	pub async fn get_audit_log(&self) -> Result<Vec<AuditLogEntry>, DbError> {
+		let mut connection = self.connect().await?;
 
 		let rows = sqlx::query(
+			"SELECT id, dt, operation, table_name, row_ids, user
+			FROM audit_log
+			ORDER BY id DESC",
+		)
+		.fetch_all(&mut *connection)
+		.await
+		.map_err(DbError::sql("getting audit log"))?;
+
+		let mut audit_log = Vec::new();
+		for row in rows {
+			let dt: String = row.get("dt");
+			let dt = NaiveDateTime::parse_from_str(&dt, "%Y-%m-%d %H:%M:%S.%6f").map_err(|_| {
+				DbError::InvalidMetadata {
+					key: "audit_log.dt",
+					value: dt.clone(),
+				}
+			})?;
+
+			let row_ids: Option<String> = row.get("row_ids");
+			let row_ids = match row_ids {
+				Some(row_ids) if !row_ids.is_empty() => row_ids
+					.split(',')
+					.map(|id| id.parse())
+					.collect::<Result<Vec<_>, _>>()
+					.map_err(|_| DbError::InvalidMetadata {
+						key: "audit_log.row_ids",
+						value: row_ids.clone(),
+					})?,
+				_ => Vec::new(),
+			};
+
+			audit_log.push(AuditLogEntry {
+				id: row.get("id"),
+				dt,
+				operation: row.get("operation"),
+				table_name: row.get("table_name"),
+				row_ids,
+				user: row.get("user"),
+			});
+		}
+
+		Ok(audit_log)
+	}
+
+	/// Get account balances from the database
+	pub async fn get_balances(
+		&self,
+		date: NaiveDate,
+	) -> Result<HashMap<String, QuantityInt>, DbError> {
+		self.get_balances_where("DATE(dt) <= DATE($1)", format_date(date))
+			.await
+	}
+
+	/// Get account balances, and balances of each account in each commodity, from the database as at each of the given dates, in a single query
+	///
+	/// Equivalent to calling [DbConnection::get_balances] and [DbConnection::get_commodity_balances] for each date. Every requested date is present in the result.
+	pub async fn get_balances_at_dates(
+		&self,
+		dates: &[NaiveDate],
+	) -> Result<HashMap<NaiveDate, BalancesWithCommodityBalances>, DbError> {
+		let mut result = dates
+			.iter()
+			.map(|date| (*date, (HashMap::new(), HashMap::new())))
+			.collect::<HashMap<_, _>>();
+
+		if dates.is_empty() {
+			return Ok(result);
+		}
+
+		let mut connection = self.connect().await?;
+
+		// Dates are identified by their index in the VALUES clause
+		// Rows with NULL commodity give the balance of the account, and other rows the balance in the commodity
+		let sql = format!(
+			"WITH dates(idx, date) AS (VALUES {}),
+			-- Get last transaction for each account at each date
+			max_dt_by_account AS (
+				SELECT dates.idx, account, max(dt) AS max_dt
+				FROM dates
+				JOIN joined_transactions ON DATE(dt) <= DATE(dates.date)
+				GROUP BY dates.idx, account
+			),
+			max_tid_by_account AS (
+				SELECT max_dt_by_account.idx, max_dt_by_account.account, max(transaction_id) AS max_tid
+				FROM max_dt_by_account
+				JOIN joined_transactions ON max_dt_by_account.account = joined_transactions.account AND max_dt_by_account.max_dt = joined_transactions.dt
+				GROUP BY max_dt_by_account.idx, max_dt_by_account.account
+			)
+			-- Get running balance at last transaction for each account at each date
+			SELECT max_tid_by_account.idx, max_tid_by_account.account, NULL AS commodity, running_balance AS quantity
+			FROM max_tid_by_account
+			JOIN transactions_with_running_balances ON max_tid = transactions_with_running_balances.transaction_id AND max_tid_by_account.account = transactions_with_running_balances.account
+			UNION ALL
+			-- Get balance in each commodity for each account at each date
+			SELECT dates.idx, account, commodity, IFNULL(SUM(quantity), 0) AS quantity
+			FROM dates
+			JOIN joined_transactions ON DATE(dt) <= DATE(dates.date)
+			GROUP BY dates.idx, account, commodity",
+			vec!["(?, ?)"; dates.len()].join(", ")
+		);
+		let mut query = sqlx::query(&sql);
+		for (idx, date) in dates.iter().enumerate() {
+			query = query.bind(idx as i64).bind(format_date(*date));
+		}
+
+		let rows = query
+			.fetch_all(&mut *connection)
+			.await
+			.map_err(DbError::sql("getting account balances"))?;
+
+		for row in rows {
+			let date = dates[row.get::<i64, _>("idx") as usize];
+			let (balances, commodity_balances) = result.get_mut(&date).unwrap();
+			let quantity: QuantityInt = row.get("quantity");
+
+			match row.get::<Option<String>, _>("commodity") {
+				None => {
+					balances.insert(row.get("account"), quantity);
+				}
+				Some(commodity) => {
+					// Combine lots with different cost bases under the bare commodity, as in get_commodity_balances
+					*commodity_balances
+						.entry(row.get("account"))
+						.or_default()
+						.entry(bare_commodity(&commodity).to_string())
+						.or_default() += quantity;
+				}
+			}
+		}
+
+		Ok(result)
+	}
+
+	/// Get account balances from the database, as at the given date and time
+	pub async fn get_balances_at_time(
+		&self,
+		dt: NaiveDateTime,
+	) -> Result<HashMap<String, QuantityInt>, DbError> {
+		self.get_balances_where("dt <= $1", format_datetime(dt))
+			.await
+	}
+
+	/// Get account balances from the database, considering only transactions satisfying `condition` with `$1` bound to `bound`
+	async fn get_balances_where(
+		&self,
+		condition: &str,
+		bound: String,
+	) -> Result<HashMap<String, QuantityInt>, DbError> {
+		let mut connection = self.connect().await?;
+
+		let rows = sqlx::query(&format!(
 			"-- Get last transaction for each account
 			WITH max_dt_by_account AS (
 				SELECT account, max(dt) AS max_dt
 				FROM joined_transactions
-				WHERE DATE(dt) <= DATE($1)
+				WHERE {}
 				GROUP BY account
 			),
 			max_tid_by_account AS (
@@ -133,26 +596,298 @@ impl DbConnection {
 			-- Get running balance at last transaction for each account
 			SELECT max_tid_by_account.account, running_balance AS quantity
 			FROM max_tid_by_account
-			JOIN transactions_with_running_balances ON max_tid = transactions_with_running_balances.transaction_id AND max_tid_by_account.account = transactions_with_running_balances.account"
-		).bind(format_date(date)).fetch_all(&mut connection).await.expect("SQL error");
+			JOIN transactions_with_running_balances ON max_tid = transactions_with_running_balances.transaction_id AND max_tid_by_account.account = transactions_with_running_balances.account",
+			condition
+		)).bind(bound).fetch_all(&mut *connection).await.map_err(DbError::sql("getting account balances"))?;
 
 		let mut balances = HashMap::new();
 		for row in rows {
 			balances.insert(row.get("account"), row.get("quantity"));
 		}
 
-		balances
+		Ok(balances)
+	}
+
+	/// Insert the transaction and its postings into the database, returning the new transaction ID
+	///
+	/// Intended for previewing the effect of transactions on a [DbConnection::snapshot_in_memory], or for populating a [DbConnection::new_in_memory].
+	pub async fn insert_transaction(
+		&self,
+		transaction: &TransactionWithPostings,
+	) -> Result<i64, DbError> {
+		let mut connection = self.connect().await?;
+		let mut db_transaction = connection
+			.begin()
+			.await
+			.map_err(DbError::sql("beginning database transaction"))?;
+
+		let transaction_id =
+			sqlx::query("INSERT INTO transactions (dt, description) VALUES ($1, $2)")
+				.bind(format_datetime(transaction.transaction.dt))
+				.bind(&transaction.transaction.description)
+				.execute(&mut *db_transaction)
+				.await
+				.map_err(DbError::sql("inserting transaction"))?
+				.last_insert_rowid();
+
+		for posting in transaction.postings.iter() {
+			sqlx::query("INSERT INTO postings (transaction_id, description, account, quantity, commodity) VALUES ($1, $2, $3, $4, $5)")
+				.bind(transaction_id)
+				.bind(&posting.description)
+				.bind(&posting.account)
+				.bind(posting.quantity)
+				.bind(&posting.commodity)
+				.execute(&mut *db_transaction)
+				.await
+				.map_err(DbError::sql("inserting posting"))?;
+		}
+
+		db_transaction
+			.commit()
+			.await
+			.map_err(DbError::sql("committing database transaction"))?;
+
+		Ok(transaction_id)
+	}
+
+	/// Get the balance of the account in the given commodity (not converted to the reporting commodity), as at the given date
+	pub async fn get_commodity_balance(
+		&self,
+		account: &str,
+		commodity: &str,
+		date: NaiveDate,
+	) -> Result<QuantityInt, DbError> {
+		let mut connection = self.connect().await?;
+
+		sqlx::query(
+			"SELECT IFNULL(SUM(quantity), 0) AS quantity
+			FROM joined_transactions
+			WHERE account = $1 AND commodity = $2 AND DATE(dt) <= DATE($3)",
+		)
+		.bind(account)
+		.bind(commodity)
+		.bind(format_date(date))
+		.map(|r: SqliteRow| r.get("quantity"))
+		.fetch_one(&mut *connection)
+		.await
+		.map_err(DbError::sql("getting commodity balance"))
+	}
+
+	/// Get the balance of each account in each commodity (not converted to the reporting commodity), as at the given date
+	///
+	/// Returns a map from account to a map from commodity to balance. Lots with different cost bases are combined under the bare commodity name.
+	pub async fn get_commodity_balances(
+		&self,
+		date: NaiveDate,
+	) -> Result<HashMap<String, HashMap<String, QuantityInt>>, DbError> {
+		self.get_commodity_balances_where("DATE(dt) <= DATE($1)", format_date(date))
+			.await
+	}
+
+	/// Get the balance of each account in each commodity (not converted to the reporting commodity), as at the given date and time
+	pub async fn get_commodity_balances_at_time(
+		&self,
+		dt: NaiveDateTime,
+	) -> Result<HashMap<String, HashMap<String, QuantityInt>>, DbError> {
+		self.get_commodity_balances_where("dt <= $1", format_datetime(dt))
+			.await
+	}
+
+	/// Get the balance of each account in each commodity, considering only transactions satisfying `condition` with `$1` bound to `bound`
+	async fn get_commodity_balances_where(
+		&self,
+		condition: &str,
+		bound: String,
+	) -> Result<HashMap<String, HashMap<String, QuantityInt>>, DbError> {
+		let mut connection = self.connect().await?;
+
+		let rows = sqlx::query(&format!(
+			"SELECT account, commodity, IFNULL(SUM(quantity), 0) AS quantity
+			FROM joined_transactions
+			WHERE {}
+			GROUP BY account, commodity",
+			condition
+		))
+		.bind(bound)
+		.fetch_all(&mut *connection)
+		.await
+		.map_err(DbError::sql("getting commodity balances"))?;
+
+		let mut balances: HashMap<String, HashMap<String, QuantityInt>> = HashMap::new();
+		for row in rows {
+			// Combine lots with different cost bases (e.g. "USD {1.50}") under the bare commodity
+			let commodity: String = row.get("commodity");
+			let commodity = bare_commodity(&commodity).to_string();
+			let quantity: QuantityInt = row.get("quantity");
+
+			*balances
+				.entry(row.get("account"))
+				.or_default()
+				.entry(commodity)
+				.or_default() += quantity;
+		}
+
+		Ok(balances)
+	}
+
+	/// Get the balances arising from only those transactions satisfying `condition` on the `transactions_with_quantity_ascost` view, with `?` placeholders bound to `binds`
+	///
+	/// Postings are summed in the database rather than loaded individually. Returns the balance of each account and the balance of each account in each commodity, as described in [BalancesAt](crate::reporting::types::BalancesAt).
+	/// As in [DbConnection::get_balances], postings in other commodities with no cost basis do not count towards the balance in the reporting commodity.
+	pub async fn get_balances_of_transactions_where(
+		&self,
+		condition: &str,
+		binds: Vec<String>,
+		reporting_commodity: &str,
+	) -> Result<
+		(
+			HashMap<String, QuantityInt>,
+			HashMap<String, HashMap<String, QuantityInt>>,
+		),
+		DbError,
+	> {
+		let mut connection = self.connect().await?;
+
+		let sql = format!(
+			"SELECT account, commodity, SUM(quantity) AS quantity, IFNULL(SUM(quantity_ascost), 0) AS quantity_ascost
+			FROM transactions_with_quantity_ascost
+			WHERE {}
+			GROUP BY account, commodity",
+			condition
+		);
+		let mut query = sqlx::query(&sql);
+		for bind in binds {
+			query = query.bind(bind);
+		}
+		let rows = query
+			.fetch_all(&mut *connection)
+			.await
+			.map_err(DbError::sql("getting balances of transactions"))?;
+
+		// Treat the total for each account and commodity as a single posting
+		let summary = TransactionWithPostings {
+			transaction: Transaction {
+				id: None,
+				dt: NaiveDateTime::MIN,
+				description: String::new(),
+				origin: None,
+			},
+			postings: rows
+				.into_iter()
+				.map(|row| Posting {
+					id: None,
+					transaction_id: None,
+					description: None,
+					account: row.get("account"),
+					quantity: row.get("quantity"),
+					commodity: row.get("commodity"),
+					quantity_ascost: row.get("quantity_ascost"),
+					running_balance: None,
+				})
+				.collect(),
+		};
+
+		let mut balances = HashMap::new();
+		let mut commodity_balances = HashMap::new();
+		update_balances_from_transactions(
+			&mut balances,
+			&mut commodity_balances,
+			std::iter::once(&summary),
+			reporting_commodity,
+		);
+
+		Ok((balances, commodity_balances))
+	}
+
+	/// Get the ID of each transaction whose postings do not sum to zero at cost in the reporting commodity, together with the amount by which they do not, in order of ID
+	///
+	/// Postings in other commodities with no cost basis are disregarded, so these are not checked (see [TransactionWithPostings::validate]).
+	pub async fn get_unbalanced_transactions(&self) -> Result<Vec<(u64, QuantityInt)>, DbError> {
+		let mut connection = self.connect().await?;
+
+		sqlx::query(
+			"SELECT transaction_id, SUM(quantity_ascost) AS imbalance
+			FROM transactions_with_quantity_ascost
+			GROUP BY transaction_id
+			HAVING SUM(quantity_ascost) != 0
+			ORDER BY transaction_id",
+		)
+		.map(|r: SqliteRow| (r.get("transaction_id"), r.get("imbalance")))
+		.fetch_all(&mut *connection)
+		.await
+		.map_err(DbError::sql("getting unbalanced transactions"))
+	}
+
+	/// Get commodity prices from the database, ordered by commodity and date
+	pub async fn get_commodity_prices(&self) -> Result<Vec<CommodityPrice>, DbError> {
+		let mut connection = self.connect().await?;
+
+		let rows = sqlx::query(
+			"SELECT id, dt, commodity, price_numerator, price_denominator
+			FROM commodity_prices
+			ORDER BY commodity, dt, id",
+		)
+		.fetch_all(&mut *connection)
+		.await
+		.map_err(DbError::sql("getting commodity prices"))?;
+
+		rows.into_iter()
+			.map(|r| {
+				let price = Price {
+					numerator: r.get("price_numerator"),
+					denominator: r.get("price_denominator"),
+				};
+				if price.denominator <= 0 {
+					return Err(DbError::InvalidMetadata {
+						key: "commodity_prices.price_denominator",
+						value: price.denominator.to_string(),
+					});
+				}
+
+				Ok(CommodityPrice {
+					id: r.get("id"),
+					dt: NaiveDateTime::parse_from_str(r.get("dt"), "%Y-%m-%d %H:%M:%S.%6f")
+						.expect("Invalid commodity_prices.dt"),
+					commodity: r.get("commodity"),
+					price,
+				})
+			})
+			.collect()
+	}
+
+	/// Get opening balances from the database
+	pub async fn get_opening_balances(&self) -> Result<Vec<OpeningBalance>, DbError> {
+		let mut connection = self.connect().await?;
+
+		let opening_balances = sqlx::query(
+			"SELECT id, account, dt, quantity, commodity
+			FROM opening_balances
+			ORDER BY dt, id",
+		)
+		.map(|r: SqliteRow| OpeningBalance {
+			id: r.get("id"),
+			account: r.get("account"),
+			dt: NaiveDateTime::parse_from_str(r.get("dt"), "%Y-%m-%d %H:%M:%S.%6f")
+				.expect("Invalid opening_balances.dt"),
+			quantity: r.get("quantity"),
+			commodity: r.get("commodity"),
+		})
+		.fetch_all(&mut *connection)
+		.await
+		.map_err(DbError::sql("getting opening balances"))?;
+
+		Ok(opening_balances)
 	}
 
 	/// Get transactions from the database
-	pub async fn get_transactions(&self) -> Vec<TransactionWithPostings> {
-		let mut connection = self.connect().await;
+	pub async fn get_transactions(&self) -> Result<Vec<TransactionWithPostings>, DbError> {
+		let mut connection = self.connect().await?;
 
 		let rows = sqlx::query(
 			"SELECT transaction_id, dt, transaction_description, id, description, account, quantity, commodity, quantity_ascost
 			FROM transactions_with_quantity_ascost
 			ORDER BY dt, transaction_id, id"
-		).fetch_all(&mut connection).await.expect("SQL error");
+		).fetch_all(&mut *connection).await.map_err(DbError::sql("getting transactions"))?;
 
 		// Un-flatten transaction list
 		let mut transactions: Vec<TransactionWithPostings> = Vec::new();
@@ -168,6 +903,7 @@ impl DbConnection {
 						dt: NaiveDateTime::parse_from_str(row.get("dt"), "%Y-%m-%d %H:%M:%S.%6f")
 							.expect("Invalid transactions.dt"),
 						description: row.get("transaction_description"),
+						origin: None,
 					},
 					postings: Vec::new(),
 				});
@@ -181,15 +917,16 @@ impl DbConnection {
 				quantity: row.get("quantity"),
 				commodity: row.get("commodity"),
 				quantity_ascost: row.get("quantity_ascost"),
+				running_balance: None,
 			});
 		}
 
-		transactions
+		Ok(transactions)
 	}
 
 	/// Get unreconciled statement lines from the database
-	pub async fn get_unreconciled_statement_lines(&self) -> Vec<StatementLine> {
-		let mut connection = self.connect().await;
+	pub async fn get_unreconciled_statement_lines(&self) -> Result<Vec<StatementLine>, DbError> {
+		let mut connection = self.connect().await?;
 
 		let rows = sqlx::query(
 			// On testing, JOIN is much faster than WHERE NOT EXISTS
@@ -204,64 +941,149 @@ impl DbConnection {
 			quantity: r.get("quantity"),
 			balance: r.get("balance"),
 			commodity: r.get("commodity"),
-		}).fetch_all(&mut connection).await.expect("SQL error");
+			fitid: r.get("fitid"),
+		}).fetch_all(&mut *connection).await.map_err(DbError::sql("getting unreconciled statement lines"))?;
+
+		Ok(rows)
+	}
 
-		rows
+	/// Get the number and total of reconciled and unreconciled statement lines for each source account
+	pub async fn get_reconciliation_status(
+		&self,
+	) -> Result<Vec<AccountReconciliationStatus>, DbError> {
+		let mut connection = self.connect().await?;
+
+		let rows = sqlx::query(
+			// A statement line may be reconciled against more than one transaction, so consider each line only once
+			"SELECT statement_lines.source_account,
+				IFNULL(SUM(reconciled.statement_line_id IS NOT NULL), 0) AS reconciled_count,
+				IFNULL(SUM(CASE WHEN reconciled.statement_line_id IS NOT NULL THEN statement_lines.quantity END), 0) AS reconciled_quantity,
+				IFNULL(SUM(reconciled.statement_line_id IS NULL), 0) AS unreconciled_count,
+				IFNULL(SUM(CASE WHEN reconciled.statement_line_id IS NULL THEN statement_lines.quantity END), 0) AS unreconciled_quantity
+			FROM statement_lines
+			LEFT JOIN (SELECT DISTINCT statement_line_id FROM statement_line_reconciliations) AS reconciled ON statement_lines.id = reconciled.statement_line_id
+			GROUP BY statement_lines.source_account
+			ORDER BY statement_lines.source_account",
+		)
+		.map(|r: SqliteRow| AccountReconciliationStatus {
+			source_account: r.get("source_account"),
+			reconciled_count: r.get("reconciled_count"),
+			reconciled_quantity: r.get("reconciled_quantity"),
+			unreconciled_count: r.get("unreconciled_count"),
+			unreconciled_quantity: r.get("unreconciled_quantity"),
+		})
+		.fetch_all(&mut *connection)
+		.await
+		.map_err(DbError::sql("getting reconciliation status"))?;
+
+		Ok(rows)
+	}
+
+	/// Get the FITIDs of all statement lines for the given source account, for detecting duplicate imports (see [skip_duplicate_fitids](crate::import::skip_duplicate_fitids))
+	///
+	/// Statement lines marked to be ignored for deduplication are excluded.
+	pub async fn get_statement_line_fitids(
+		&self,
+		source_account: &str,
+	) -> Result<HashSet<String>, DbError> {
+		let mut connection = self.connect().await?;
+
+		let rows = sqlx::query(
+			"SELECT fitid FROM statement_lines
+			WHERE source_account = $1 AND fitid IS NOT NULL AND NOT dedup_ignore",
+		)
+		.bind(source_account)
+		.map(|r: SqliteRow| r.get("fitid"))
+		.fetch_all(&mut *connection)
+		.await
+		.map_err(DbError::sql("getting statement line FITIDs"))?;
+
+		Ok(rows.into_iter().collect())
 	}
 }
 
 /// Container for cached database-related metadata
+#[derive(Clone)]
 pub struct DbMetadata {
 	pub version: u32,
 	pub eofy_date: NaiveDate,
+	/// End dates of past financial years where the end of the financial year has changed, in ascending order (see [get_eofy_with_history](crate::util::get_eofy_with_history))
+	pub financial_year_ends: Vec<NaiveDate>,
 	pub reporting_commodity: String,
 	pub dps: u32,
 	pub plugins: Vec<String>,
+	/// Whether generated transactions post known rounding differences to the [ROUNDING](crate::ROUNDING) account, shown as a rounding line in the income statement
+	pub rounding_reconciliation: bool,
+	/// Whether to check that net surplus in the income statement agrees with the transfer to current year earnings, displaying the difference if not
+	pub check_current_year_earnings: bool,
+	/// Whether the income statement and income tax are computed on a cash basis, recognising only transactions with a posting to a `drcr.cash` account (see [CashBasisTransactions](crate::reporting::steps::CashBasisTransactions))
+	pub cash_basis: bool,
+	/// Whether to show the native commodity amount beside balance sheet accounts held in a single foreign commodity
+	pub show_native_amounts: bool,
+	/// Whether to group accounts in reports into nested sections according to their `:`-separated components
+	pub group_accounts: bool,
+	/// strftime-style format used to label date columns in reports (e.g. `FY%Y`)
+	pub column_date_format: String,
+	/// Label used for the column header of single-currency reports
+	pub currency_label: String,
+	/// All keys and values in the metadata table, including those not otherwise parsed (e.g. for use by plugins)
+	pub entries: HashMap<String, String>,
 }
 
 impl DbMetadata {
 	/// Initialise [DbMetadata] with values from the metadata database table
-	async fn from_database(connection: &mut SqliteConnection) -> Self {
-		let version = sqlx::query("SELECT value FROM metadata WHERE key = 'version'")
-			.map(|r: SqliteRow| {
-				r.get::<String, _>(0)
-					.parse()
-					.expect("Invalid metadata.version")
-			})
-			.fetch_one(&mut *connection)
-			.await
-			.expect("SQL error");
+	async fn from_database(connection: &mut SqliteConnection) -> Result<Self, DbError> {
+		let version = get_required_metadata(connection, "version").await?;
+		let version = version.parse().map_err(|_| DbError::InvalidMetadata {
+			key: "version",
+			value: version,
+		})?;
 
-		let eofy_date = sqlx::query("SELECT value FROM metadata WHERE key ='eofy_date'")
-			.map(|r: SqliteRow| {
-				NaiveDate::parse_from_str(r.get(0), "%Y-%m-%d").expect("Invalid metadata.eofy_date")
-			})
-			.fetch_one(&mut *connection)
-			.await
-			.expect("SQL error");
+		let eofy_date = get_required_metadata(connection, "eofy_date").await?;
+		let eofy_date = NaiveDate::parse_from_str(&eofy_date, "%Y-%m-%d").map_err(|_| {
+			DbError::InvalidMetadata {
+				key: "eofy_date",
+				value: eofy_date,
+			}
+		})?;
 
-		let reporting_commodity =
-			sqlx::query("SELECT value FROM metadata WHERE key = 'reporting_commodity'")
-				.map(|r: SqliteRow| r.get(0))
-				.fetch_one(&mut *connection)
+		// Databases before version 10 predate changes of financial year end
+		let financial_year_ends = if version >= 10 {
+			sqlx::query("SELECT dt FROM financial_year_ends ORDER BY dt")
+				.map(|r: SqliteRow| r.get::<String, _>("dt"))
+				.fetch_all(&mut *connection)
 				.await
-				.expect("SQL error");
+				.map_err(DbError::sql("getting financial year ends"))?
+				.into_iter()
+				.map(|dt| {
+					NaiveDateTime::parse_from_str(&dt, "%Y-%m-%d %H:%M:%S.%6f")
+						.map(|dt| dt.date())
+						.map_err(|_| DbError::InvalidMetadata {
+							key: "financial_year_ends.dt",
+							value: dt,
+						})
+				})
+				.collect::<Result<Vec<_>, _>>()?
+		} else {
+			vec![]
+		};
 
-		let dps = sqlx::query("SELECT value FROM metadata WHERE key = 'amount_dps'")
-			.map(|r: SqliteRow| {
-				r.get::<String, _>(0)
-					.parse()
-					.expect("Invalid metadata.amount_dps")
-			})
-			.fetch_one(&mut *connection)
-			.await
-			.expect("SQL error");
+		let reporting_commodity = get_required_metadata(connection, "reporting_commodity").await?;
 
+		// amount_dps determines how stored quantities are interpreted, so cannot safely be defaulted
+		let dps = get_required_metadata(connection, "amount_dps").await?;
+		let dps = dps.parse().map_err(|_| DbError::InvalidMetadata {
+			key: "amount_dps",
+			value: dps,
+		})?;
+
+		// Older databases may predate plugin support, in which case no plugins are enabled
 		let plugins_joined = sqlx::query("SELECT value FROM metadata WHERE key = 'plugins'")
 			.map(|r: SqliteRow| r.get::<String, _>(0))
-			.fetch_one(&mut *connection)
+			.fetch_optional(&mut *connection)
 			.await
-			.expect("SQL error");
+			.map_err(DbError::sql("getting metadata"))?
+			.unwrap_or_default();
 
 		let plugins = if plugins_joined.len() > 0 {
 			plugins_joined
@@ -272,12 +1094,119 @@ impl DbMetadata {
 			vec![]
 		};
 
-		DbMetadata {
+		let entries = sqlx::query("SELECT key, value FROM metadata")
+			.map(|r: SqliteRow| (r.get("key"), r.get("value")))
+			.fetch_all(&mut *connection)
+			.await
+			.map_err(DbError::sql("getting metadata"))?
+			.into_iter()
+			.collect();
+
+		let rounding_reconciliation = optional_bool_metadata(&entries, "rounding_reconciliation")?;
+		let check_current_year_earnings =
+			optional_bool_metadata(&entries, "check_current_year_earnings")?;
+		let cash_basis = optional_bool_metadata(&entries, "cash_basis")?;
+		let show_native_amounts = optional_bool_metadata(&entries, "show_native_amounts")?;
+		let group_accounts = optional_bool_metadata(&entries, "group_accounts")?;
+
+		let column_date_format =
+			sqlx::query("SELECT value FROM metadata WHERE key = 'column_date_format'")
+				.map(|r: SqliteRow| r.get::<String, _>(0))
+				.fetch_optional(&mut *connection)
+				.await
+				.map_err(DbError::sql("getting metadata"))?
+				.unwrap_or_else(|| "%Y-%m-%d".to_string());
+
+		// Check the format is valid, as formatting would otherwise panic when generating reports
+		if StrftimeItems::new(&column_date_format).any(|i| i == Item::Error) {
+			return Err(DbError::InvalidMetadata {
+				key: "column_date_format",
+				value: column_date_format,
+			});
+		}
+
+		let currency_label = sqlx::query("SELECT value FROM metadata WHERE key = 'currency_label'")
+			.map(|r: SqliteRow| r.get::<String, _>(0))
+			.fetch_optional(&mut *connection)
+			.await
+			.map_err(DbError::sql("getting metadata"))?
+			.unwrap_or_else(|| reporting_commodity.clone());
+
+		Ok(DbMetadata {
 			version,
 			eofy_date,
+			financial_year_ends,
 			reporting_commodity,
 			dps,
 			plugins,
-		}
+			rounding_reconciliation,
+			check_current_year_earnings,
+			cash_basis,
+			show_native_amounts,
+			group_accounts,
+			column_date_format,
+			currency_label,
+			entries,
+		})
+	}
+
+	/// Format the date as a report column label, according to `column_date_format`
+	pub fn format_column_date(&self, date: NaiveDate) -> String {
+		date.format(&self.column_date_format).to_string()
+	}
+
+	/// Get the account to which unreconciled statement lines of the given source account are posted, for lines which debit (`is_debit`) or credit the source account
+	///
+	/// This is given by the metadata key `unclassified_statement_line_debits:<source account>` (or `unclassified_statement_line_credits:<source account>`) if present, otherwise `unclassified_statement_line_debits` (or `unclassified_statement_line_credits`), otherwise [UNCLASSIFIED_STATEMENT_LINE_DEBITS](crate::UNCLASSIFIED_STATEMENT_LINE_DEBITS) (or [UNCLASSIFIED_STATEMENT_LINE_CREDITS](crate::UNCLASSIFIED_STATEMENT_LINE_CREDITS)).
+	pub fn unclassified_statement_line_account(
+		&self,
+		source_account: &str,
+		is_debit: bool,
+	) -> &str {
+		let (key, default) = if is_debit {
+			(
+				"unclassified_statement_line_debits",
+				crate::UNCLASSIFIED_STATEMENT_LINE_DEBITS,
+			)
+		} else {
+			(
+				"unclassified_statement_line_credits",
+				crate::UNCLASSIFIED_STATEMENT_LINE_CREDITS,
+			)
+		};
+
+		self.entries
+			.get(&format!("{}:{}", key, source_account))
+			.or_else(|| self.entries.get(key))
+			.map(|account| account.as_str())
+			.unwrap_or(default)
+	}
+}
+
+/// Get the value of a metadata key which must be present in the database
+async fn get_required_metadata(
+	connection: &mut SqliteConnection,
+	key: &'static str,
+) -> Result<String, DbError> {
+	sqlx::query("SELECT value FROM metadata WHERE key = ?")
+		.bind(key)
+		.map(|r: SqliteRow| r.get::<String, _>(0))
+		.fetch_optional(connection)
+		.await
+		.map_err(DbError::sql("getting metadata"))?
+		.ok_or(DbError::MissingMetadata(key))
+}
+
+/// Get the value of a boolean metadata key from the given metadata entries, defaulting to `false` if not present
+fn optional_bool_metadata(
+	entries: &HashMap<String, String>,
+	key: &'static str,
+) -> Result<bool, DbError> {
+	match entries.get(key) {
+		Some(value) => value.parse().map_err(|_| DbError::InvalidMetadata {
+			key,
+			value: value.clone(),
+		}),
+		None => Ok(false),
 	}
 }
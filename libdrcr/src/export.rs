@@ -0,0 +1,78 @@
+/*
+	DrCr: Web-based double-entry bookkeeping framework
+	Copyright (C) 2022-2025  Lee Yingtong Li (RunasSudo)
+
+	This program is free software: you can redistribute it and/or modify
+	it under the terms of the GNU Affero General Public License as published by
+	the Free Software Foundation, either version 3 of the License, or
+	(at your option) any later version.
+
+	This program is distributed in the hope that it will be useful,
+	but WITHOUT ANY WARRANTY; without even the implied warranty of
+	MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+	GNU Affero General Public License for more details.
+
+	You should have received a copy of the GNU Affero General Public License
+	along with this program.  If not, see <https://www.gnu.org/licenses/>.
+*/
+
+//! Export of transactions to other formats
+
+use crate::model::transaction::TransactionWithPostings;
+use crate::util::serialise_quantity;
+use crate::QuantityInt;
+
+/// Export the transactions affecting `account` as QIF, from the perspective of that account
+///
+/// `transactions` would usually be taken from a [Transactions](crate::reporting::types::Transactions) reporting product. Transactions with no posting to `account` are omitted.
+/// Each transaction is emitted as a split transaction, with one split per posting to another account. Amounts are positive for debits to `account` (e.g. deposits to a bank account), and are formatted with the given number of decimal places.
+pub fn to_qif(transactions: &[TransactionWithPostings], account: &str, dps: u32) -> String {
+	let mut qif = String::from("!Type:Bank\n");
+
+	for transaction in transactions.iter() {
+		if !transaction.postings.iter().any(|p| p.account == account) {
+			continue;
+		}
+
+		let total = transaction
+			.postings
+			.iter()
+			.filter(|p| p.account == account)
+			.map(|p| p.quantity)
+			.sum::<QuantityInt>();
+
+		qif.push_str(&format!(
+			"D{}\n",
+			transaction.transaction.dt.format("%m/%d/%Y")
+		));
+		qif.push_str(&format!("T{}\n", serialise_quantity(total, dps)));
+		qif.push_str(&format!(
+			"P{}\n",
+			qif_text(&transaction.transaction.description)
+		));
+
+		for posting in transaction.postings.iter() {
+			if posting.account == account {
+				continue;
+			}
+
+			// Splits are expressed in the opposite sense to the other postings, so that they sum to the total
+			let quantity = -posting.quantity_ascost.unwrap_or(posting.quantity);
+
+			qif.push_str(&format!("S{}\n", qif_text(&posting.account)));
+			if let Some(description) = &posting.description {
+				qif.push_str(&format!("E{}\n", qif_text(description)));
+			}
+			qif.push_str(&format!("${}\n", serialise_quantity(quantity, dps)));
+		}
+
+		qif.push_str("^\n");
+	}
+
+	qif
+}
+
+/// Make the text suitable for a single QIF field, which may not span multiple lines
+fn qif_text(text: &str) -> String {
+	text.replace(['\r', '\n'], " ")
+}
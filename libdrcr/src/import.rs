@@ -0,0 +1,530 @@
+/*
+	DrCr: Web-based double-entry bookkeeping framework
+	Copyright (C) 2022-2025  Lee Yingtong Li (RunasSudo)
+
+	This program is free software: you can redistribute it and/or modify
+	it under the terms of the GNU Affero General Public License as published by
+	the Free Software Foundation, either version 3 of the License, or
+	(at your option) any later version.
+
+	This program is distributed in the hope that it will be useful,
+	but WITHOUT ANY WARRANTY; without even the implied warranty of
+	MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+	GNU Affero General Public License for more details.
+
+	You should have received a copy of the GNU Affero General Public License
+	along with this program.  If not, see <https://www.gnu.org/licenses/>.
+*/
+
+//! Import of bank statements into [StatementLine]s
+
+use std::collections::HashSet;
+use std::fmt::Display;
+use std::io::Read;
+
+use chrono::{NaiveDate, NaiveDateTime};
+
+use crate::model::statements::StatementLine;
+use crate::QuantityInt;
+
+/// Error when importing a statement
+#[derive(Debug)]
+pub enum ImportError {
+	/// The statement could not be read
+	Io(std::io::Error),
+	/// The statement is not in the expected format
+	Malformed(String),
+	/// One or more rows of the statement could not be parsed
+	InvalidRows(Vec<RowError>),
+}
+
+/// Error in a single row of a statement
+#[derive(Debug)]
+pub struct RowError {
+	/// Line number (from 1) in the file at which the row begins
+	pub line: usize,
+	pub message: String,
+}
+
+impl Display for ImportError {
+	fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+		match self {
+			ImportError::Io(source) => {
+				f.write_fmt(format_args!("error reading statement: {}", source))
+			}
+			ImportError::Malformed(message) => {
+				f.write_fmt(format_args!("malformed statement: {}", message))
+			}
+			ImportError::InvalidRows(errors) => {
+				f.write_str("invalid rows in statement:")?;
+				for error in errors.iter() {
+					f.write_fmt(format_args!("\nline {}: {}", error.line, error.message))?;
+				}
+				Ok(())
+			}
+		}
+	}
+}
+
+impl std::error::Error for ImportError {
+	fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+		match self {
+			ImportError::Io(source) => Some(source),
+			ImportError::Malformed(_) | ImportError::InvalidRows(_) => None,
+		}
+	}
+}
+
+impl From<std::io::Error> for ImportError {
+	fn from(value: std::io::Error) -> Self {
+		ImportError::Io(value)
+	}
+}
+
+/// Parse an OFX (1.x SGML or 2.x XML) statement into [StatementLine]s for the given source account
+///
+/// Amounts are scaled to [QuantityInt] using `dps`, which should be the number of decimal places of the source account. Lines are returned in date order.
+/// Where the statement reports a ledger balance (`LEDGERBAL`), the balance of each line is derived from it; otherwise [StatementLine::balance] is `None`.
+pub fn parse_ofx<R: Read>(
+	mut reader: R,
+	source_account: &str,
+	commodity: &str,
+	dps: u32,
+) -> Result<Vec<StatementLine>, ImportError> {
+	let mut content = Vec::new();
+	reader.read_to_end(&mut content)?;
+	let content = String::from_utf8_lossy(&content);
+
+	// Skip OFX header (everything before the first <OFX>)
+	let start = content
+		.to_ascii_uppercase()
+		.find("<OFX>")
+		.ok_or_else(|| ImportError::Malformed("OFX payload not found".to_string()))?;
+	let root = parse_elements(&content[start..]);
+
+	let mut statement_lines = Vec::new();
+	let mut ledger_balance = None;
+
+	for statement in root.descendants("BANKTRANLIST") {
+		for transaction in statement.children_named("STMTTRN") {
+			let dt = parse_ofx_date(transaction.required_text("DTPOSTED")?)?;
+			let quantity = parse_ofx_amount(transaction.required_text("TRNAMT")?, dps)?;
+
+			let name = transaction.text("NAME").unwrap_or("");
+			let memo = transaction.text("MEMO").unwrap_or("");
+			let description = format!("{} {}", name, memo).trim().to_string();
+
+			statement_lines.push(StatementLine {
+				id: None,
+				source_account: source_account.to_string(),
+				dt,
+				description,
+				quantity,
+				balance: None,
+				commodity: commodity.to_string(),
+				fitid: transaction.text("FITID").map(|f| f.to_string()),
+			});
+		}
+	}
+
+	if let Some(ledger_balance_element) = root.descendants("LEDGERBAL").first() {
+		ledger_balance = Some(parse_ofx_amount(
+			ledger_balance_element.required_text("BALAMT")?,
+			dps,
+		)?);
+	}
+
+	statement_lines.sort_by_key(|l| l.dt);
+
+	// Derive running balances backwards from the closing ledger balance
+	if let Some(mut balance) = ledger_balance {
+		for line in statement_lines.iter_mut().rev() {
+			line.balance = Some(balance);
+			balance -= line.quantity;
+		}
+	}
+
+	Ok(statement_lines)
+}
+
+/// Column mapping for [parse_csv]
+///
+/// Columns are identified by index, counting from 0.
+pub struct CsvImportConfig {
+	/// Number of header rows to skip
+	pub header_rows: usize,
+	/// Column containing the date of each line
+	pub date_column: usize,
+	/// [chrono] format string for the date column, e.g. `%d/%m/%Y`
+	///
+	/// The format may be a date-only or a date and time format.
+	pub date_format: String,
+	pub amount_columns: CsvAmountColumns,
+	/// Columns which are concatenated, separated by spaces, to form the description
+	pub description_columns: Vec<usize>,
+	/// Column containing the running balance, if any
+	pub balance_column: Option<usize>,
+	/// Field delimiter, usually `,`
+	pub delimiter: char,
+}
+
+/// Convention for the amount of each line in a CSV statement
+pub enum CsvAmountColumns {
+	/// A single column, positive for deposits and negative for withdrawals
+	Signed(usize),
+	/// Separate columns for debits (withdrawals) and credits (deposits), as they appear on a bank statement
+	///
+	/// For each row, one column is usually blank. The sign of the amounts is disregarded.
+	DebitCredit { debit: usize, credit: usize },
+}
+
+/// Parse a CSV statement into [StatementLine]s for the given source account, according to the given [CsvImportConfig]
+///
+/// Amounts are scaled to [QuantityInt] using `dps`, which should be the number of decimal places of the source account. Blank rows are ignored.
+/// If any row is invalid, all such rows are reported in [ImportError::InvalidRows].
+pub fn parse_csv<R: Read>(
+	mut reader: R,
+	config: &CsvImportConfig,
+	source_account: &str,
+	commodity: &str,
+	dps: u32,
+) -> Result<Vec<StatementLine>, ImportError> {
+	let mut content = String::new();
+	reader.read_to_string(&mut content)?;
+
+	let mut statement_lines = Vec::new();
+	let mut errors = Vec::new();
+
+	for (line, record) in parse_csv_records(&content, config.delimiter)
+		.into_iter()
+		.skip(config.header_rows)
+	{
+		if record.iter().all(|f| f.trim().is_empty()) {
+			continue;
+		}
+
+		match parse_csv_record(&record, config, dps) {
+			Ok((dt, description, quantity, balance)) => statement_lines.push(StatementLine {
+				id: None,
+				source_account: source_account.to_string(),
+				dt,
+				description,
+				quantity,
+				balance,
+				commodity: commodity.to_string(),
+				fitid: None,
+			}),
+			Err(message) => errors.push(RowError { line, message }),
+		}
+	}
+
+	if !errors.is_empty() {
+		return Err(ImportError::InvalidRows(errors));
+	}
+
+	Ok(statement_lines)
+}
+
+/// Parse a single CSV record, returning the date, description, quantity and balance, or an error message
+fn parse_csv_record(
+	record: &[String],
+	config: &CsvImportConfig,
+	dps: u32,
+) -> Result<(NaiveDateTime, String, QuantityInt, Option<QuantityInt>), String> {
+	let field = |column: usize| {
+		record
+			.get(column)
+			.map(|f| f.trim())
+			.ok_or_else(|| format!("missing column {}", column + 1))
+	};
+	let amount = |column: usize| -> Result<Option<QuantityInt>, String> {
+		let value = field(column)?;
+		if value.is_empty() {
+			return Ok(None);
+		}
+		// Disregard currency symbol and thousands separators
+		parse_amount(&value.replace(['$', ','], ""), dps)
+			.map(Some)
+			.ok_or_else(|| format!("invalid amount {:?} in column {}", value, column + 1))
+	};
+
+	let date = field(config.date_column)?;
+	let dt = NaiveDateTime::parse_from_str(date, &config.date_format)
+		.or_else(|_| {
+			NaiveDate::parse_from_str(date, &config.date_format)
+				.map(|d| d.and_hms_opt(0, 0, 0).unwrap())
+		})
+		.map_err(|e| format!("invalid date {:?} ({})", date, e))?;
+
+	let mut description_parts = Vec::new();
+	for column in config.description_columns.iter() {
+		let part = field(*column)?;
+		if !part.is_empty() {
+			description_parts.push(part);
+		}
+	}
+
+	let quantity = match config.amount_columns {
+		CsvAmountColumns::Signed(column) => {
+			amount(column)?.ok_or_else(|| format!("missing amount in column {}", column + 1))?
+		}
+		CsvAmountColumns::DebitCredit { debit, credit } => {
+			match (amount(debit)?, amount(credit)?) {
+				(None, None) => return Err("missing debit or credit amount".to_string()),
+				(debit, credit) => credit.unwrap_or(0).abs() - debit.unwrap_or(0).abs(),
+			}
+		}
+	};
+
+	let balance = match config.balance_column {
+		Some(column) => amount(column)?,
+		None => None,
+	};
+
+	Ok((dt, description_parts.join(" "), quantity, balance))
+}
+
+/// Split CSV content into records, each with the line number (from 1) at which it begins
+///
+/// Fields may be quoted with `"`, in which case they may contain the delimiter, newlines and escaped (doubled) quotes.
+fn parse_csv_records(content: &str, delimiter: char) -> Vec<(usize, Vec<String>)> {
+	let mut records = Vec::new();
+	let mut record = Vec::new();
+	let mut field = String::new();
+	let mut in_quotes = false;
+	let mut line = 1;
+	let mut record_line = 1;
+
+	let mut chars = content.chars().peekable();
+	while let Some(c) = chars.next() {
+		if c == '\n' {
+			line += 1;
+		}
+
+		if in_quotes {
+			if c == '"' {
+				if chars.peek() == Some(&'"') {
+					field.push(chars.next().unwrap());
+				} else {
+					in_quotes = false;
+				}
+			} else {
+				field.push(c);
+			}
+		} else if c == '"' {
+			in_quotes = true;
+		} else if c == delimiter {
+			record.push(std::mem::take(&mut field));
+		} else if c == '\n' {
+			record.push(std::mem::take(&mut field));
+			records.push((record_line, std::mem::take(&mut record)));
+			record_line = line;
+		} else if c != '\r' {
+			field.push(c);
+		}
+	}
+
+	if !field.is_empty() || !record.is_empty() {
+		record.push(field);
+		records.push((record_line, record));
+	}
+
+	records
+}
+
+/// Remove [StatementLine]s whose FITID is in `existing_fitids`, or which repeat the FITID of an earlier line
+///
+/// `existing_fitids` would usually be obtained from [DbConnection::get_statement_line_fitids](crate::db::DbConnection::get_statement_line_fitids). Lines without a FITID are always retained.
+pub fn skip_duplicate_fitids(
+	statement_lines: Vec<StatementLine>,
+	existing_fitids: &HashSet<String>,
+) -> Vec<StatementLine> {
+	let mut seen_fitids = HashSet::new();
+	statement_lines
+		.into_iter()
+		.filter(|line| match &line.fitid {
+			Some(fitid) => !existing_fitids.contains(fitid) && seen_fitids.insert(fitid.clone()),
+			None => true,
+		})
+		.collect()
+}
+
+/// Element of an OFX document
+struct OfxElement {
+	name: String,
+	text: Option<String>,
+	children: Vec<OfxElement>,
+}
+
+impl OfxElement {
+	fn new(name: String) -> Self {
+		Self {
+			name,
+			text: None,
+			children: Vec::new(),
+		}
+	}
+
+	fn children_named<'a>(&'a self, name: &'a str) -> impl Iterator<Item = &'a OfxElement> {
+		self.children.iter().filter(move |c| c.name == name)
+	}
+
+	/// Return all elements with the given name nested (at any depth) within this element
+	fn descendants(&self, name: &str) -> Vec<&OfxElement> {
+		let mut result = Vec::new();
+		for child in self.children.iter() {
+			if child.name == name {
+				result.push(child);
+			}
+			result.extend(child.descendants(name));
+		}
+		result
+	}
+
+	/// Return the text of the first child with the given name, if any
+	fn text(&self, name: &str) -> Option<&str> {
+		self.children
+			.iter()
+			.find(|c| c.name == name)
+			.and_then(|c| c.text.as_deref())
+	}
+
+	fn required_text(&self, name: &str) -> Result<&str, ImportError> {
+		self.text(name).ok_or_else(|| {
+			ImportError::Malformed(format!("{} is missing required {}", self.name, name))
+		})
+	}
+}
+
+/// Parse OFX elements into a tree, accepting both SGML (where leaf elements are not closed) and XML
+fn parse_elements(payload: &str) -> OfxElement {
+	let mut stack = vec![OfxElement::new(String::new())];
+	let mut rest = payload;
+
+	while let Some(lt) = rest.find('<') {
+		let Some(gt) = rest[lt..].find('>').map(|i| lt + i) else {
+			break;
+		};
+		let tag = rest[lt + 1..gt].trim();
+		rest = &rest[gt + 1..];
+
+		if tag.starts_with('!') || tag.starts_with('?') {
+			// Comment or declaration
+			continue;
+		}
+
+		if let Some(close_name) = tag.strip_prefix('/') {
+			// Close up to the matching element, if it is open
+			let close_name = close_name.trim().to_ascii_uppercase();
+			if let Some(pos) = stack.iter().rposition(|e| e.name == close_name) {
+				if pos > 0 {
+					while stack.len() > pos {
+						let element = stack.pop().unwrap();
+						stack.last_mut().unwrap().children.push(element);
+					}
+				}
+			}
+			continue;
+		}
+
+		let name = tag
+			.split_whitespace()
+			.next()
+			.unwrap_or("")
+			.trim_end_matches('/')
+			.to_ascii_uppercase();
+		let mut element = OfxElement::new(name);
+
+		let next_lt = rest.find('<').unwrap_or(rest.len());
+		let text = rest[..next_lt].trim();
+		if !text.is_empty() {
+			// Leaf element with inline text
+			element.text = Some(decode_entities(text));
+			rest = &rest[next_lt..];
+
+			// Skip the closing tag, if present (XML)
+			let closing = format!("</{}>", element.name);
+			if rest.len() >= closing.len() && rest[..closing.len()].eq_ignore_ascii_case(&closing) {
+				rest = &rest[closing.len()..];
+			}
+
+			stack.last_mut().unwrap().children.push(element);
+		} else if tag.ends_with('/') {
+			// Empty XML element
+			stack.last_mut().unwrap().children.push(element);
+		} else {
+			// Container element
+			stack.push(element);
+		}
+	}
+
+	// Close any remaining open elements
+	while stack.len() > 1 {
+		let element = stack.pop().unwrap();
+		stack.last_mut().unwrap().children.push(element);
+	}
+
+	stack.pop().unwrap()
+}
+
+fn decode_entities(text: &str) -> String {
+	text.replace("&lt;", "<")
+		.replace("&gt;", ">")
+		.replace("&quot;", "\"")
+		.replace("&apos;", "'")
+		.replace("&nbsp;", " ")
+		.replace("&amp;", "&")
+}
+
+/// Parse an OFX date (`YYYYMMDD`, optionally followed by a time and time zone), disregarding the time
+fn parse_ofx_date(value: &str) -> Result<NaiveDateTime, ImportError> {
+	value
+		.get(0..8)
+		.and_then(|d| NaiveDate::parse_from_str(d, "%Y%m%d").ok())
+		.map(|d| d.and_hms_opt(0, 0, 0).unwrap())
+		.ok_or_else(|| ImportError::Malformed(format!("invalid date {:?}", value)))
+}
+
+/// Parse an OFX amount and scale it to [QuantityInt] with the given number of decimal places
+fn parse_ofx_amount(value: &str, dps: u32) -> Result<QuantityInt, ImportError> {
+	// Some institutions use a decimal comma
+	parse_amount(&value.replace(',', "."), dps)
+		.ok_or_else(|| ImportError::Malformed(format!("invalid amount {:?}", value)))
+}
+
+/// Parse a decimal amount and scale it to [QuantityInt] with the given number of decimal places, rounding half away from zero
+///
+/// Returns `None` if the amount is not a valid decimal number or is out of range.
+fn parse_amount(value: &str, dps: u32) -> Option<QuantityInt> {
+	let value = value.trim();
+	let (negative, digits) = match value.strip_prefix('-') {
+		Some(d) => (true, d),
+		None => (false, value.strip_prefix('+').unwrap_or(value)),
+	};
+	let (integer_part, fractional_part) = digits.split_once('.').unwrap_or((digits, ""));
+
+	if (integer_part.is_empty() && fractional_part.is_empty())
+		|| !integer_part.chars().all(|c| c.is_ascii_digit())
+		|| !fractional_part.chars().all(|c| c.is_ascii_digit())
+	{
+		return None;
+	}
+
+	let mut quantity: QuantityInt = 0;
+	let mut fractional_digits = fractional_part.chars();
+	for c in integer_part
+		.chars()
+		.chain((0..dps).map(|_| fractional_digits.next().unwrap_or('0')))
+	{
+		quantity = quantity
+			.checked_mul(10)?
+			.checked_add(c.to_digit(10).unwrap() as QuantityInt)?;
+	}
+
+	// Round on the first truncated digit
+	if fractional_digits.next().is_some_and(|c| c >= '5') {
+		quantity = quantity.checked_add(1)?;
+	}
+
+	Some(if negative { -quantity } else { quantity })
+}
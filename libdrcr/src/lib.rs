@@ -1,6 +1,8 @@
 pub mod account_config;
 //pub mod austax;
 pub mod db;
+pub mod export;
+pub mod import;
 pub mod model;
 pub mod plugin;
 pub mod reporting;
@@ -13,6 +15,13 @@ pub type QuantityInt = i64;
 // Magic strings
 // TODO: Make this configurable
 pub const CURRENT_YEAR_EARNINGS: &'static str = "Current Year Earnings";
+pub const FX_GAIN_LOSS: &'static str = "FX Gain/Loss";
+pub const OPENING_BALANCES: &'static str = "Opening Balances";
 pub const RETAINED_EARNINGS: &'static str = "Retained Earnings";
+/// Account to which generated transactions post known rounding differences (e.g. austax rounding PAYG withheld amounts down to whole dollars), where `rounding_reconciliation` is enabled
+pub const ROUNDING: &str = "Rounding";
 pub const UNCLASSIFIED_STATEMENT_LINE_CREDITS: &'static str = "Unclassified Statement Line Credits";
 pub const UNCLASSIFIED_STATEMENT_LINE_DEBITS: &'static str = "Unclassified Statement Line Debits";
+
+/// Account kind marking an account to be shown in reports even when its balance is zero
+pub const ALWAYS_VISIBLE_KIND: &'static str = "drcr.always_visible";
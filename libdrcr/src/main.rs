@@ -20,8 +20,8 @@ use std::sync::Arc;
 
 use chrono::NaiveDate;
 use libdrcr::db::DbConnection;
+use libdrcr::reporting::api;
 use libdrcr::reporting::calculator::{steps_as_graphviz, steps_for_targets};
-use libdrcr::reporting::dynamic_report::DynamicReport;
 use libdrcr::reporting::generate_report;
 use libdrcr::reporting::types::{
 	DateArgs, DateStartDateEndArgs, MultipleDateArgs, MultipleDateStartDateEndArgs,
@@ -33,7 +33,7 @@ async fn main() {
 	const YEAR: i32 = 2025;
 
 	// Connect to database
-	let db_connection = DbConnection::new("sqlite:drcr_testing.db").await;
+	let db_connection = DbConnection::new("sqlite:drcr_testing.db").await.unwrap();
 
 	// Initialise ReportingContext
 	let mut context = ReportingContext::new(
@@ -43,7 +43,7 @@ async fn main() {
 		NaiveDate::from_ymd_opt(2025, 6, 30).unwrap(),
 		"$".to_string(),
 	);
-	libdrcr::plugin::register_lookup_fns(&mut context);
+	libdrcr::plugin::register_lookup_fns(&mut context).unwrap();
 	libdrcr::reporting::steps::register_lookup_fns(&mut context);
 	libdrcr::reporting::builders::register_dynamic_builders(&mut context);
 
@@ -70,7 +70,9 @@ async fn main() {
 			args: ReportingStepArgs::MultipleDateArgs(MultipleDateArgs {
 				dates: vec![DateArgs {
 					date: NaiveDate::from_ymd_opt(YEAR, 6, 30).unwrap(),
+					time: None,
 				}],
+				with_prior_year: false,
 			}),
 		},
 		ReportingProductId {
@@ -81,6 +83,7 @@ async fn main() {
 					date_start: NaiveDate::from_ymd_opt(YEAR - 1, 7, 1).unwrap(),
 					date_end: NaiveDate::from_ymd_opt(YEAR, 6, 30).unwrap(),
 				}],
+				cumulative: false,
 			}),
 		},
 	];
@@ -121,7 +124,7 @@ async fn main() {
 
 	println!("Tax summary:");
 	println!("{:?}", result);
-	
+
 	let result = products
 		.get_or_err(&ReportingProductId {
 			name: "AllTransactionsExceptEarningsToEquity".to_string(),
@@ -138,79 +141,28 @@ async fn main() {
 
 	// Get balance sheet
 
-	let targets = vec![
-		ReportingProductId {
-			name: "CalculateIncomeTax".to_string(),
-			kind: ReportingProductKind::Transactions,
-			args: ReportingStepArgs::VoidArgs,
-		},
-		ReportingProductId {
-			name: "BalanceSheet".to_string(),
-			kind: ReportingProductKind::DynamicReport,
-			args: ReportingStepArgs::MultipleDateArgs(MultipleDateArgs {
-				dates: vec![DateArgs {
-					date: NaiveDate::from_ymd_opt(YEAR, 6, 30).unwrap(),
-				}],
-			}),
-		},
-	];
-
-	let products = generate_report(targets, Arc::clone(&context))
-		.await
-		.unwrap();
-	let result = products
-		.get_or_err(&ReportingProductId {
-			name: "BalanceSheet".to_string(),
-			kind: ReportingProductKind::DynamicReport,
-			args: ReportingStepArgs::MultipleDateArgs(MultipleDateArgs {
-				dates: vec![DateArgs {
-					date: NaiveDate::from_ymd_opt(YEAR, 6, 30).unwrap(),
-				}],
-			}),
-		})
-		.unwrap();
+	let result = api::balance_sheet(
+		Arc::clone(&context),
+		&[NaiveDate::from_ymd_opt(YEAR, 6, 30).unwrap()],
+	)
+	.await
+	.unwrap();
 
 	println!("Balance sheet:");
-	println!(
-		"{}",
-		result.downcast_ref::<DynamicReport>().unwrap().to_json()
-	);
+	println!("{}", result.to_json());
 
 	// Get trial balance
 
-	let targets = vec![
-		ReportingProductId {
-			name: "CalculateIncomeTax".to_string(),
-			kind: ReportingProductKind::Transactions,
-			args: ReportingStepArgs::VoidArgs,
-		},
-		ReportingProductId {
-			name: "TrialBalance".to_string(),
-			kind: ReportingProductKind::DynamicReport,
-			args: ReportingStepArgs::DateArgs(DateArgs {
-				date: NaiveDate::from_ymd_opt(YEAR, 6, 30).unwrap(),
-			}),
-		},
-	];
-
-	let products = generate_report(targets, Arc::clone(&context))
-		.await
-		.unwrap();
-	let result = products
-		.get_or_err(&ReportingProductId {
-			name: "TrialBalance".to_string(),
-			kind: ReportingProductKind::DynamicReport,
-			args: ReportingStepArgs::DateArgs(DateArgs {
-				date: NaiveDate::from_ymd_opt(YEAR, 6, 30).unwrap(),
-			}),
-		})
-		.unwrap();
+	let result = api::trial_balance(
+		Arc::clone(&context),
+		NaiveDate::from_ymd_opt(YEAR, 6, 30).unwrap(),
+		false,
+	)
+	.await
+	.unwrap();
 
 	println!("Trial balance:");
-	println!(
-		"{}",
-		result.downcast_ref::<DynamicReport>().unwrap().to_json()
-	);
+	println!("{}", result.to_json());
 
 	// Get all transactions
 
@@ -225,6 +177,7 @@ async fn main() {
 			kind: ReportingProductKind::Transactions,
 			args: Box::new(DateArgs {
 				date: NaiveDate::from_ymd_opt(YEAR, 6, 30).unwrap(),
+				time: None,
 			}),
 		},
 	];
@@ -238,6 +191,7 @@ async fn main() {
 			kind: ReportingProductKind::Transactions,
 			args: Box::new(DateArgs {
 				date: NaiveDate::from_ymd_opt(YEAR, 6, 30).unwrap(),
+				time: None,
 			}),
 		})
 		.unwrap();
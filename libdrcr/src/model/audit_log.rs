@@ -0,0 +1,36 @@
+/*
+	DrCr: Web-based double-entry bookkeeping framework
+	Copyright (C) 2022-2025  Lee Yingtong Li (RunasSudo)
+
+	This program is free software: you can redistribute it and/or modify
+	it under the terms of the GNU Affero General Public License as published by
+	the Free Software Foundation, either version 3 of the License, or
+	(at your option) any later version.
+
+	This program is distributed in the hope that it will be useful,
+	but WITHOUT ANY WARRANTY; without even the implied warranty of
+	MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+	GNU Affero General Public License for more details.
+
+	You should have received a copy of the GNU Affero General Public License
+	along with this program.  If not, see <https://www.gnu.org/licenses/>.
+*/
+
+use chrono::NaiveDateTime;
+use serde::{Deserialize, Serialize};
+
+/// Records an operation performed through DrCr, or a change to a row of a ledger table as logged by the database triggers on that table
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct AuditLogEntry {
+	pub id: u64,
+	/// Time of the change, in UTC
+	#[serde(with = "crate::serde::naivedatetime_to_js")]
+	pub dt: NaiveDateTime,
+	/// Name of the operation (e.g. `insert_transaction`), or `INSERT`, `UPDATE` or `DELETE` for a change to a row
+	pub operation: String,
+	pub table_name: String,
+	/// Ids of the affected rows in `table_name`
+	pub row_ids: Vec<u64>,
+	/// User who performed the operation, if known (changes to rows are not attributed to a user)
+	pub user: Option<String>,
+}
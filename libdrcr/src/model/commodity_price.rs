@@ -0,0 +1,86 @@
+/*
+	DrCr: Web-based double-entry bookkeeping framework
+	Copyright (C) 2022-2025  Lee Yingtong Li (RunasSudo)
+
+	This program is free software: you can redistribute it and/or modify
+	it under the terms of the GNU Affero General Public License as published by
+	the Free Software Foundation, either version 3 of the License, or
+	(at your option) any later version.
+
+	This program is distributed in the hope that it will be useful,
+	but WITHOUT ANY WARRANTY; without even the implied warranty of
+	MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+	GNU Affero General Public License for more details.
+
+	You should have received a copy of the GNU Affero General Public License
+	along with this program.  If not, see <https://www.gnu.org/licenses/>.
+*/
+
+use chrono::NaiveDateTime;
+use serde::{Deserialize, Serialize};
+
+use crate::QuantityInt;
+
+/// Records the price of a commodity in terms of the reporting commodity
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct CommodityPrice {
+	pub id: Option<u64>,
+	#[serde(with = "crate::serde::naivedatetime_to_js")]
+	pub dt: NaiveDateTime,
+	pub commodity: String,
+	/// Price of one unit of the commodity, in the reporting commodity
+	pub price: Price,
+}
+
+/// Price of one unit of a commodity in the reporting commodity, represented exactly as the ratio `numerator / denominator`
+///
+/// For example, a price of 1.4725 is stored as 14725 / 10000.
+#[derive(Clone, Copy, Debug, Deserialize, Eq, PartialEq, Serialize)]
+pub struct Price {
+	pub numerator: i64,
+	/// Always positive
+	pub denominator: i64,
+}
+
+impl Price {
+	/// Convert the given quantity of the commodity into the reporting commodity, rounding half away from zero
+	pub fn convert(&self, quantity: QuantityInt) -> QuantityInt {
+		let scaled = quantity as i128 * self.numerator as i128;
+		let denominator = self.denominator as i128;
+		let quotient = scaled / denominator;
+		let remainder = scaled % denominator;
+
+		// Round half away from zero
+		let rounded = if remainder.abs() * 2 >= denominator {
+			quotient + scaled.signum()
+		} else {
+			quotient
+		};
+
+		rounded as QuantityInt
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn price_convert_rounds_half_away_from_zero() {
+		let price = Price {
+			numerator: 3,
+			denominator: 2,
+		};
+		assert_eq!(price.convert(1000), 1500);
+		assert_eq!(price.convert(1), 2);
+		assert_eq!(price.convert(-1), -2);
+
+		let price = Price {
+			numerator: 1,
+			denominator: 3,
+		};
+		assert_eq!(price.convert(1), 0);
+		assert_eq!(price.convert(-5), -2);
+		assert_eq!(price.convert(i64::MAX), 3074457345618258602);
+	}
+}
@@ -17,5 +17,8 @@
 */
 
 pub mod assertions;
+pub mod audit_log;
+pub mod commodity_price;
+pub mod opening_balance;
 pub mod statements;
 pub mod transaction;
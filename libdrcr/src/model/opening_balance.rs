@@ -0,0 +1,33 @@
+/*
+	DrCr: Web-based double-entry bookkeeping framework
+	Copyright (C) 2022-2025  Lee Yingtong Li (RunasSudo)
+
+	This program is free software: you can redistribute it and/or modify
+	it under the terms of the GNU Affero General Public License as published by
+	the Free Software Foundation, either version 3 of the License, or
+	(at your option) any later version.
+
+	This program is distributed in the hope that it will be useful,
+	but WITHOUT ANY WARRANTY; without even the implied warranty of
+	MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+	GNU Affero General Public License for more details.
+
+	You should have received a copy of the GNU Affero General Public License
+	along with this program.  If not, see <https://www.gnu.org/licenses/>.
+*/
+
+use chrono::NaiveDateTime;
+use serde::{Deserialize, Serialize};
+
+use crate::QuantityInt;
+
+/// Records the balance of an account at the date the file was started
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct OpeningBalance {
+	pub id: Option<u64>,
+	pub account: String,
+	#[serde(with = "crate::serde::naivedatetime_to_js")]
+	pub dt: NaiveDateTime,
+	pub quantity: QuantityInt,
+	pub commodity: String,
+}
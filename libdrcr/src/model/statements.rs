@@ -26,6 +26,19 @@ pub struct StatementLine {
 	pub dt: NaiveDateTime,
 	pub description: String,
 	pub quantity: QuantityInt,
-	pub balance: QuantityInt,
+	pub balance: Option<QuantityInt>,
 	pub commodity: String,
+	/// Financial institution's unique identifier for the line (e.g. OFX `FITID`), used to detect duplicate imports
+	pub fitid: Option<String>,
+}
+
+/// Counts and totals of reconciled and unreconciled statement lines for a source account
+///
+/// Totals are expressed in the commodity of the statement lines.
+pub struct AccountReconciliationStatus {
+	pub source_account: String,
+	pub reconciled_count: i64,
+	pub reconciled_quantity: QuantityInt,
+	pub unreconciled_count: i64,
+	pub unreconciled_quantity: QuantityInt,
 }
@@ -17,10 +17,12 @@
 */
 
 use std::collections::HashMap;
+use std::fmt::Display;
 
-use chrono::NaiveDateTime;
+use chrono::{NaiveDate, NaiveDateTime};
 use serde::{Deserialize, Serialize};
 
+use crate::util::bare_commodity;
 use crate::QuantityInt;
 
 #[derive(Clone, Debug, Deserialize, Serialize)]
@@ -29,6 +31,9 @@ pub struct Transaction {
 	#[serde(with = "crate::serde::naivedatetime_to_js")]
 	pub dt: NaiveDateTime,
 	pub description: String,
+	/// Name of the [ReportingStep](crate::reporting::types::ReportingStep) which generated this transaction, or `None` if the transaction is from the database
+	#[serde(default)]
+	pub origin: Option<String>,
 }
 
 #[derive(Clone, Debug, Deserialize, Serialize)]
@@ -38,6 +43,131 @@ pub struct TransactionWithPostings {
 	pub postings: Vec<Posting>,
 }
 
+impl TransactionWithPostings {
+	/// Return the amount by which the postings fail to sum to zero, for each commodity in which they do not
+	///
+	/// Where the postings are in more than one commodity and all have a cost (see [Posting::quantity_ascost]), they are summed at cost in the reporting commodity, so that transactions exchanging one commodity for another balance. Otherwise, postings are summed separately in each commodity, disregarding any cost basis.
+	/// The result is empty if the transaction is balanced.
+	pub fn validate(&self, reporting_commodity: &str) -> HashMap<String, QuantityInt> {
+		let mut totals: HashMap<String, QuantityInt> = HashMap::new();
+		for posting in self.postings.iter() {
+			*totals
+				.entry(bare_commodity(&posting.commodity).to_string())
+				.or_default() += posting.quantity;
+		}
+
+		if totals.len() > 1 && self.postings.iter().all(|p| p.quantity_ascost.is_some()) {
+			let total_ascost = self
+				.postings
+				.iter()
+				.map(|p| p.quantity_ascost.unwrap())
+				.sum::<QuantityInt>();
+			totals = HashMap::from([(reporting_commodity.to_string(), total_ascost)]);
+		}
+
+		totals.retain(|_, quantity| *quantity != 0);
+		totals
+	}
+
+	/// Return true if the postings sum to zero, as described in [TransactionWithPostings::validate]
+	pub fn is_balanced(&self, reporting_commodity: &str) -> bool {
+		self.validate(reporting_commodity).is_empty()
+	}
+
+	/// Replace the posting at `index` with one posting for each of `parts`, given as an account and quantity
+	///
+	/// The quantities of the parts must sum to the quantity of the original posting, so that the transaction remains balanced if it was before. The new postings keep the description and commodity of the original posting, and any [Posting::quantity_ascost] is apportioned between them in proportion to quantity. The first new posting keeps the id of the original posting, so that it can be updated in place.
+	pub fn split_posting(
+		&mut self,
+		index: usize,
+		parts: &[(&str, QuantityInt)],
+	) -> Result<(), SplitPostingError> {
+		let posting = self
+			.postings
+			.get(index)
+			.ok_or(SplitPostingError::NoSuchPosting(index))?;
+
+		let total = parts
+			.iter()
+			.map(|(_, quantity)| quantity)
+			.sum::<QuantityInt>();
+		if parts.is_empty() || total != posting.quantity {
+			return Err(SplitPostingError::PartsDoNotSum {
+				expected: posting.quantity,
+				actual: total,
+			});
+		}
+
+		let mut new_postings = Vec::new();
+		let mut remaining_ascost = posting.quantity_ascost;
+		for (i, (account, quantity)) in parts.iter().enumerate() {
+			let quantity_ascost = if i == parts.len() - 1 {
+				// Assign any remainder to the last part, so that the cost is preserved exactly
+				remaining_ascost
+			} else {
+				posting.quantity_ascost.map(|ascost| {
+					(ascost as i128 * *quantity as i128)
+						.checked_div(posting.quantity as i128)
+						.unwrap_or(0) as QuantityInt
+				})
+			};
+			remaining_ascost = remaining_ascost.zip(quantity_ascost).map(|(r, q)| r - q);
+
+			new_postings.push(Posting {
+				id: if i == 0 { posting.id } else { None },
+				transaction_id: posting.transaction_id,
+				description: posting.description.clone(),
+				account: account.to_string(),
+				quantity: *quantity,
+				commodity: posting.commodity.clone(),
+				quantity_ascost,
+				running_balance: None,
+			});
+		}
+
+		self.postings.splice(index..=index, new_postings);
+		Ok(())
+	}
+
+	/// Move the postings of `other` into this transaction
+	///
+	/// The postings keep their ids, so that they can be reassigned to this transaction in place. The result is balanced if both transactions are, as described in [TransactionWithPostings::validate].
+	pub fn merge(&mut self, other: TransactionWithPostings) {
+		for mut posting in other.postings {
+			posting.transaction_id = self.transaction.id;
+			self.postings.push(posting);
+		}
+	}
+}
+
+/// Error when splitting a posting, see [TransactionWithPostings::split_posting]
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub enum SplitPostingError {
+	/// There is no posting at the given index
+	NoSuchPosting(usize),
+	/// The quantities of the parts do not sum to the quantity of the original posting
+	PartsDoNotSum {
+		expected: QuantityInt,
+		actual: QuantityInt,
+	},
+}
+
+impl Display for SplitPostingError {
+	fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+		match self {
+			SplitPostingError::NoSuchPosting(index) => {
+				f.write_fmt(format_args!("no posting at index {}", index))
+			}
+			SplitPostingError::PartsDoNotSum { expected, actual } => f.write_fmt(format_args!(
+				"parts sum to {} but the posting is {}",
+				actual, expected
+			)),
+		}
+	}
+}
+
+impl std::error::Error for SplitPostingError {}
+
 #[derive(Clone, Debug, Deserialize, Serialize)]
 pub struct Posting {
 	pub id: Option<u64>,
@@ -47,21 +177,165 @@ pub struct Posting {
 	pub quantity: QuantityInt,
 	pub commodity: String,
 	pub quantity_ascost: Option<QuantityInt>,
-	//pub running_balance: Option<QuantityInt>,
+	/// Balance of the account (at cost) after this posting, where computed by [transactions_for_account]
+	#[serde(default, skip_serializing_if = "Option::is_none")]
+	pub running_balance: Option<QuantityInt>,
 }
 
+/// Add the postings of the given transactions to the balances
+///
+/// `balances` and `commodity_balances` are as described in [BalancesAt](crate::reporting::types::BalancesAt).
 pub(crate) fn update_balances_from_transactions<
 	'a,
 	I: Iterator<Item = &'a TransactionWithPostings>,
 >(
 	balances: &mut HashMap<String, QuantityInt>,
+	commodity_balances: &mut HashMap<String, HashMap<String, QuantityInt>>,
 	transactions: I,
+	reporting_commodity: &str,
 ) {
 	for transaction in transactions {
 		for posting in transaction.postings.iter() {
-			// FIXME: Do currency conversion
-			let running_balance = balances.get(&posting.account).unwrap_or(&0) + posting.quantity;
+			let commodity = bare_commodity(&posting.commodity);
+			if commodity != reporting_commodity {
+				track_commodities(
+					&posting.account,
+					balances,
+					commodity_balances,
+					reporting_commodity,
+				);
+			}
+			if let Some(account_balances) = commodity_balances.get_mut(&posting.account) {
+				*account_balances.entry(commodity.to_string()).or_default() += posting.quantity;
+			}
+
+			// As in DbConnection::get_balances, postings in other commodities with no cost basis count towards commodity_balances only
+			let quantity = match posting.quantity_ascost {
+				Some(quantity_ascost) => quantity_ascost,
+				None if commodity == reporting_commodity => posting.quantity,
+				None => continue,
+			};
+			let running_balance = balances.get(&posting.account).unwrap_or(&0) + quantity;
 			balances.insert(posting.account.clone(), running_balance);
 		}
 	}
 }
+
+/// Add `sign` times `other_balances` and `other_commodity_balances` to `balances` and `commodity_balances`
+///
+/// All balances are as described in [BalancesAt](crate::reporting::types::BalancesAt).
+pub(crate) fn combine_balances(
+	balances: &mut HashMap<String, QuantityInt>,
+	commodity_balances: &mut HashMap<String, HashMap<String, QuantityInt>>,
+	other_balances: &HashMap<String, QuantityInt>,
+	other_commodity_balances: &HashMap<String, HashMap<String, QuantityInt>>,
+	sign: QuantityInt,
+	reporting_commodity: &str,
+) {
+	for account in other_commodity_balances.keys() {
+		track_commodities(account, balances, commodity_balances, reporting_commodity);
+	}
+
+	for (account, balance) in other_balances.iter() {
+		if let Some(account_balances) = commodity_balances.get_mut(account) {
+			match other_commodity_balances.get(account) {
+				Some(other_account_balances) => {
+					for (commodity, quantity) in other_account_balances.iter() {
+						*account_balances.entry(commodity.clone()).or_default() += sign * quantity;
+					}
+				}
+				None => {
+					// Balance is entirely in the reporting commodity
+					*account_balances
+						.entry(reporting_commodity.to_string())
+						.or_default() += sign * balance;
+				}
+			}
+		}
+
+		let running_balance = balances.get(account).unwrap_or(&0) + sign * balance;
+		balances.insert(account.clone(), running_balance);
+	}
+}
+
+/// Transactions affecting a single account within a date range, together with the balance of the account at the start of the range
+///
+/// See [transactions_for_account].
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct AccountTransactions {
+	/// Balance of the account in the reporting commodity (converting other commodities at cost) from transactions before the start of the range
+	pub opening_balance: QuantityInt,
+	pub transactions: Vec<TransactionWithPostings>,
+}
+
+/// Return the transactions affecting the given account between `date_start` and `date_end` (inclusive), and the opening balance of the account from transactions before `date_start`
+///
+/// Transactions are returned in chronological order, and each posting to the account has its [Posting::running_balance] set.
+pub fn transactions_for_account(
+	transactions: Vec<TransactionWithPostings>,
+	account: &str,
+	date_start: NaiveDate,
+	date_end: NaiveDate,
+) -> AccountTransactions {
+	let mut result = AccountTransactions {
+		opening_balance: 0,
+		transactions: Vec::new(),
+	};
+
+	for transaction in transactions {
+		if !transaction.postings.iter().any(|p| p.account == account) {
+			continue;
+		}
+
+		let date = transaction.transaction.dt.date();
+		if date < date_start {
+			result.opening_balance += transaction
+				.postings
+				.iter()
+				.filter(|p| p.account == account)
+				.map(|p| p.quantity_ascost.unwrap_or(p.quantity))
+				.sum::<QuantityInt>();
+		} else if date <= date_end {
+			result.transactions.push(transaction);
+		}
+	}
+
+	// Transactions generated by reporting steps have no id, and are ordered after database transactions on the same date
+	// sort_by_key is stable, so otherwise the order of generation is preserved
+	result.transactions.sort_by_key(|t| {
+		(
+			t.transaction.dt,
+			t.transaction.id.is_none(),
+			t.transaction.id,
+		)
+	});
+
+	let mut balance = result.opening_balance;
+	for transaction in result.transactions.iter_mut() {
+		for posting in transaction.postings.iter_mut() {
+			if posting.account == account {
+				balance += posting.quantity_ascost.unwrap_or(posting.quantity);
+				posting.running_balance = Some(balance);
+			}
+		}
+	}
+
+	result
+}
+
+/// Begin recording the balance of the account in each commodity, if not already recorded
+fn track_commodities(
+	account: &str,
+	balances: &HashMap<String, QuantityInt>,
+	commodity_balances: &mut HashMap<String, HashMap<String, QuantityInt>>,
+	reporting_commodity: &str,
+) {
+	if !commodity_balances.contains_key(account) {
+		// Until now, the account has been held only in the reporting commodity
+		let balance = *balances.get(account).unwrap_or(&0);
+		commodity_balances.insert(
+			account.to_string(),
+			HashMap::from([(reporting_commodity.to_string(), balance)]),
+		);
+	}
+}
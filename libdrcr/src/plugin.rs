@@ -16,7 +16,9 @@
 	along with this program.  If not, see <https://www.gnu.org/licenses/>.
 */
 
+use std::collections::HashMap;
 use std::fmt::Display;
+use std::sync::Mutex;
 
 use async_trait::async_trait;
 use chrono::NaiveDate;
@@ -24,18 +26,17 @@ use mlua::{FromLua, Function, Lua, LuaSerdeExt, Table, Value};
 use serde::{Deserialize, Serialize};
 use tokio::sync::RwLock;
 
-use crate::account_config::kinds_for_account;
+use crate::account_config::{data_for_account, kinds_for_account, AccountKind};
 use crate::reporting::calculator::ReportingGraphDependencies;
 use crate::reporting::dynamic_report::DynamicReport;
 use crate::reporting::executor::ReportingExecutionError;
 use crate::reporting::types::{
 	BalancesAt, BalancesBetween, ReportingContext, ReportingProduct, ReportingProductId,
-	ReportingProductKind, ReportingProducts, ReportingStep, ReportingStepArgs, ReportingStepId,
-	Transactions,
+	ReportingProductKind, ReportingProducts, ReportingStep, ReportingStepArgs,
+	ReportingStepArgsKind, ReportingStepDynamicBuilder, ReportingStepId, Transactions,
 };
-use crate::util::sofy_from_eofy;
 
-fn load_plugin(plugin_dir: &str, plugin_name: &str) -> (Lua, Plugin) {
+fn load_plugin(plugin_dir: &str, plugin_name: &str) -> mlua::Result<(Lua, Plugin)> {
 	let lua = Lua::new();
 
 	// Init Lua environment
@@ -46,15 +47,28 @@ fn load_plugin(plugin_dir: &str, plugin_name: &str) -> (Lua, Plugin) {
 
 	// Require and call the plugin
 	let require = lua.load("require").eval::<Function>().unwrap();
-	let plugin = require.call::<Plugin>(plugin_name).expect("Lua error");
+	let plugin = require.call::<Plugin>(plugin_name)?;
 
-	(lua, plugin)
+	Ok((lua, plugin))
+}
+
+/// Return a function converting an [mlua::Error] raised by the named plugin into [ReportingExecutionError::PluginError]
+///
+/// Errors raised within Lua carry the Lua traceback in their message.
+fn plugin_error(plugin_name: &str) -> impl Fn(mlua::Error) -> ReportingExecutionError + '_ {
+	move |err| ReportingExecutionError::PluginError {
+		plugin: plugin_name.to_string(),
+		message: err.to_string(),
+	}
 }
 
 /// Call [ReportingContext::register_lookup_fn] for all steps provided by this module
-pub fn register_lookup_fns(context: &mut ReportingContext) {
+///
+/// Returns an error if any plugin cannot be loaded.
+pub fn register_lookup_fns(context: &mut ReportingContext) -> Result<(), ReportingExecutionError> {
 	for plugin_path in context.plugin_names.clone().iter() {
-		let (_, plugin) = load_plugin(&context.plugin_dir, plugin_path);
+		let (_, plugin) =
+			load_plugin(&context.plugin_dir, plugin_path).map_err(plugin_error(plugin_path))?;
 
 		for reporting_step in plugin.reporting_steps.iter() {
 			context.register_lookup_fn(
@@ -69,6 +83,64 @@ pub fn register_lookup_fns(context: &mut ReportingContext) {
 			.plugin_specs
 			.insert(plugin_path.clone(), plugin.into());
 	}
+
+	// A single dynamic builder dispatches to the dynamic builders of all plugins
+	if context
+		.plugin_specs
+		.values()
+		.any(|plugin_spec| !plugin_spec.dynamic_builders.is_empty())
+	{
+		context.register_dynamic_builder(ReportingStepDynamicBuilder {
+			name: "PluginDynamicBuilder",
+			can_build: PluginReportingStep::can_build,
+			build: PluginReportingStep::build,
+		});
+	}
+
+	Ok(())
+}
+
+/// Find the plugins installed in the given directory, i.e. subdirectories containing an `init.luau`
+///
+/// Each plugin is loaded to check that it is valid, and the error message (including the Lua traceback where available) is returned alongside any plugin which cannot be loaded. Plugins are sorted by name.
+pub fn discover_plugins(plugin_dir: &str) -> std::io::Result<Vec<(String, Result<(), String>)>> {
+	let mut plugin_names = Vec::new();
+	for entry in std::fs::read_dir(plugin_dir)? {
+		let entry = entry?;
+		if entry.path().join("init.luau").is_file() {
+			if let Some(name) = entry.file_name().to_str() {
+				plugin_names.push(name.to_string());
+			}
+		}
+	}
+	plugin_names.sort();
+
+	Ok(plugin_names
+		.into_iter()
+		.map(|name| {
+			let result = load_plugin(plugin_dir, &name)
+				.map(|_| ())
+				.map_err(|err| err.to_string());
+			(name, result)
+		})
+		.collect())
+}
+
+/// Get the names of all steps provided by plugins which generate [Transactions] and accept [ReportingStepArgs::VoidArgs]
+pub fn transaction_step_names(context: &ReportingContext) -> Vec<String> {
+	let mut names = context
+		.plugin_specs
+		.values()
+		.flat_map(|plugin_spec| plugin_spec.reporting_steps.iter())
+		.filter(|s| {
+			s.product_kinds
+				.contains(&ReportingProductKind::Transactions)
+				&& s.arg_kinds.contains(&ReportingStepArgsKind::VoidArgs)
+		})
+		.map(|s| s.name.clone())
+		.collect::<Vec<_>>();
+	names.sort();
+	names
 }
 
 /// Represents a libdrcr plugin specification and implementation
@@ -76,6 +148,7 @@ pub fn register_lookup_fns(context: &mut ReportingContext) {
 pub struct Plugin {
 	name: String,
 	reporting_steps: Vec<LuaReportingStep>,
+	dynamic_builders: Vec<LuaDynamicBuilder>,
 }
 
 impl FromLua for Plugin {
@@ -84,6 +157,9 @@ impl FromLua for Plugin {
 		Ok(Self {
 			name: value.get("name")?,
 			reporting_steps: value.get("reporting_steps")?,
+			dynamic_builders: value
+				.get::<Option<Vec<LuaDynamicBuilder>>>("dynamic_builders")?
+				.unwrap_or_default(),
 		})
 	}
 }
@@ -93,6 +169,7 @@ impl FromLua for Plugin {
 pub struct PluginSpec {
 	name: String,
 	reporting_steps: Vec<ReportingStepSpec>,
+	dynamic_builders: Vec<DynamicBuilderSpec>,
 }
 
 impl From<Plugin> for PluginSpec {
@@ -100,12 +177,13 @@ impl From<Plugin> for PluginSpec {
 		Self {
 			name: value.name,
 			reporting_steps: value.reporting_steps.into_iter().map(|s| s.spec).collect(),
+			dynamic_builders: value.dynamic_builders.into_iter().map(|b| b.spec).collect(),
 		}
 	}
 }
 
 /// [ReportingStep] provided by the plugin specification and implementation
-#[derive(Debug)]
+#[derive(Clone, Debug)]
 pub struct LuaReportingStep {
 	spec: ReportingStepSpec,
 	requires: Function,
@@ -120,6 +198,11 @@ impl FromLua for LuaReportingStep {
 			spec: ReportingStepSpec {
 				name: value.get("name")?,
 				product_kinds: lua.from_value(value.get("product_kinds")?)?,
+				// Steps which do not declare arg_kinds take only VoidArgs
+				arg_kinds: match value.get::<Option<Value>>("arg_kinds")? {
+					Some(arg_kinds) => lua.from_value(arg_kinds)?,
+					None => vec![ReportingStepArgsKind::VoidArgs],
+				},
 			},
 			requires: value.get("requires")?,
 			after_init_graph: value.get("after_init_graph")?,
@@ -133,6 +216,40 @@ impl FromLua for LuaReportingStep {
 pub struct ReportingStepSpec {
 	name: String,
 	product_kinds: Vec<ReportingProductKind>,
+	/// Kinds of [ReportingStepArgs] which the step accepts
+	arg_kinds: Vec<ReportingStepArgsKind>,
+}
+
+/// [ReportingStepDynamicBuilder] provided by the plugin specification and implementation
+///
+/// `can_build` is called with the name, kind and args of the requested product, the [ReportingStepId]s of all steps so far, and the context. `build` is called with the name, kind and args of the requested product, and the context, and returns a [LuaReportingStep].
+#[derive(Debug)]
+pub struct LuaDynamicBuilder {
+	spec: DynamicBuilderSpec,
+	can_build: Function,
+	build: Function,
+}
+
+impl FromLua for LuaDynamicBuilder {
+	fn from_lua(value: Value, lua: &Lua) -> mlua::Result<Self> {
+		let value = value.as_table().unwrap();
+		Ok(Self {
+			spec: DynamicBuilderSpec {
+				name: value.get("name")?,
+				product_kinds: lua.from_value(value.get("product_kinds")?)?,
+			},
+			can_build: value.get("can_build")?,
+			build: value.get("build")?,
+		})
+	}
+}
+
+/// [ReportingStepDynamicBuilder] provided by the plugin specification
+#[derive(Clone, Debug, Deserialize, Eq, PartialEq, Serialize)]
+pub struct DynamicBuilderSpec {
+	name: String,
+	/// Kinds of [ReportingProduct] which the builder may be able to build, so that the plugin need not be called for other kinds
+	product_kinds: Vec<ReportingProductKind>,
 }
 
 /// Represents a [ReportingProduct] which can be represented in Lua
@@ -180,15 +297,19 @@ struct LuaReportingContext {
 	pub eofy_date: NaiveDate,
 	pub reporting_commodity: String,
 	pub dps: u32,
+	pub currency_label: String,
+	pub metadata: HashMap<String, String>,
 }
 
 impl LuaReportingContext {
 	fn from(context: &ReportingContext) -> Self {
 		Self {
-			sofy_date: sofy_from_eofy(context.eofy_date),
+			sofy_date: context.sofy_from_eofy(context.eofy_date),
 			eofy_date: context.eofy_date,
 			reporting_commodity: context.reporting_commodity.clone(),
 			dps: context.db_connection.metadata().dps,
+			currency_label: context.db_connection.metadata().currency_label.clone(),
+			metadata: context.db_connection.metadata().entries.clone(),
 		}
 	}
 }
@@ -198,12 +319,20 @@ impl LuaReportingContext {
 pub struct PluginReportingStep {
 	pub plugin_path: String,
 	pub spec: ReportingStepSpec,
-	pub args: ReportingStepArgs, // Currently only VoidArgs is supported
+	pub args: ReportingStepArgs,
+	/// If the step was generated by a plugin dynamic builder, the name of the builder and the product kind it was requested for
+	pub builder: Option<(String, ReportingProductKind)>,
+	/// Message of any error raised by the plugin while building the reporting graph
+	///
+	/// [ReportingStep::requires] and [ReportingStep::after_init_graph] cannot return an error, so the error is reported when the step is executed.
+	deferred_error: Mutex<Option<String>>,
 }
 
 impl PluginReportingStep {
-	fn takes_args(_name: &str, args: &ReportingStepArgs, _context: &ReportingContext) -> bool {
-		*args == ReportingStepArgs::VoidArgs
+	fn takes_args(name: &str, args: &ReportingStepArgs, context: &ReportingContext) -> bool {
+		// If no plugin provides the step, decline it so that an error is reported by the calculator
+		Self::find_spec(name, context)
+			.is_some_and(|(_, spec)| spec.arg_kinds.contains(&args.kind()))
 	}
 
 	fn from_args(
@@ -211,20 +340,198 @@ impl PluginReportingStep {
 		args: ReportingStepArgs,
 		context: &ReportingContext,
 	) -> Box<dyn ReportingStep> {
-		// Look up plugin
-		for (plugin_path, plugin_spec) in context.plugin_specs.iter() {
-			if let Some(reporting_step_spec) =
-				plugin_spec.reporting_steps.iter().find(|s| s.name == name)
-			{
-				return Box::new(Self {
-					plugin_path: plugin_path.to_string(),
-					spec: reporting_step_spec.clone(),
-					args,
-				});
+		let (plugin_path, reporting_step_spec) = Self::find_spec(name, context).unwrap(); // Existence is checked in takes_args
+
+		Box::new(Self {
+			plugin_path: plugin_path.to_string(),
+			spec: reporting_step_spec.clone(),
+			args,
+			builder: None,
+			deferred_error: Mutex::new(None),
+		})
+	}
+
+	fn can_build(
+		name: &str,
+		kind: ReportingProductKind,
+		args: &ReportingStepArgs,
+		steps: &Vec<Box<dyn ReportingStep>>,
+		_dependencies: &ReportingGraphDependencies,
+		context: &ReportingContext,
+	) -> bool {
+		// If the plugin raises an error, the product is claimed so that the error is reported when the step is executed
+		Self::find_builder(name, kind, args, steps, context).is_some()
+	}
+
+	fn build(
+		name: String,
+		kind: ReportingProductKind,
+		args: ReportingStepArgs,
+		steps: &Vec<Box<dyn ReportingStep>>,
+		_dependencies: &ReportingGraphDependencies,
+		context: &ReportingContext,
+	) -> Box<dyn ReportingStep> {
+		let (plugin_path, builder) =
+			Self::find_builder(&name, kind, &args, steps, context).unwrap(); // Existence is checked in can_build
+
+		let mut step = Self {
+			plugin_path,
+			spec: ReportingStepSpec {
+				name: name.clone(),
+				product_kinds: vec![kind],
+				arg_kinds: vec![args.kind()],
+			},
+			args,
+			builder: None,
+			deferred_error: Mutex::new(None),
+		};
+
+		match builder {
+			Ok(builder_name) => {
+				step.builder = Some((builder_name, kind));
+
+				// Get the product kinds from the plugin
+				match step.load_lua_step(context) {
+					Ok((_, lua_step)) => step.spec.product_kinds = lua_step.spec.product_kinds,
+					Err(message) => step.defer_error(message),
+				}
 			}
+			Err(message) => step.defer_error(message),
 		}
 
-		panic!("No plugin provides step {}", name);
+		Box::new(step)
+	}
+
+	/// Look up the plugin dynamic builder which can build the requested product, returning the plugin path and the name of the builder
+	///
+	/// If the plugin raises an error, returns the plugin path and the error message.
+	fn find_builder(
+		name: &str,
+		kind: ReportingProductKind,
+		args: &ReportingStepArgs,
+		steps: &Vec<Box<dyn ReportingStep>>,
+		context: &ReportingContext,
+	) -> Option<(String, Result<String, String>)> {
+		// Consider plugins in a consistent order
+		let mut plugin_paths = context
+			.plugin_specs
+			.iter()
+			.filter(|(_, plugin_spec)| {
+				plugin_spec
+					.dynamic_builders
+					.iter()
+					.any(|b| b.product_kinds.contains(&kind))
+			})
+			.map(|(plugin_path, _)| plugin_path)
+			.collect::<Vec<_>>();
+		plugin_paths.sort();
+
+		for plugin_path in plugin_paths {
+			let (lua, plugin) = match load_plugin(&context.plugin_dir, plugin_path) {
+				Ok(loaded) => loaded,
+				Err(err) => return Some((plugin_path.clone(), Err(err.to_string()))),
+			};
+			let lua_context = lua.to_value(&LuaReportingContext::from(context)).unwrap();
+			let step_ids = lua
+				.to_value(&steps.iter().map(|s| s.id()).collect::<Vec<_>>())
+				.unwrap();
+
+			for builder in plugin.dynamic_builders.iter() {
+				if !builder.spec.product_kinds.contains(&kind) {
+					continue;
+				}
+
+				// Call to plugin
+				let can_build = builder.can_build.call::<bool>((
+					name,
+					lua.to_value(&kind).unwrap(),
+					lua.to_value(args).unwrap(),
+					step_ids.clone(),
+					lua_context.clone(),
+				));
+
+				match can_build {
+					Ok(true) => return Some((plugin_path.clone(), Ok(builder.spec.name.clone()))),
+					Ok(false) => (),
+					Err(err) => return Some((plugin_path.clone(), Err(err.to_string()))),
+				}
+			}
+		}
+
+		None
+	}
+
+	/// Load the plugin and get the implementation of this step, returning the error message on failure
+	fn load_lua_step(&self, context: &ReportingContext) -> Result<(Lua, LuaReportingStep), String> {
+		// Do not call the plugin again if it has already raised an error
+		if let Some(message) = self.deferred_error.lock().unwrap().as_ref() {
+			return Err(message.clone());
+		}
+
+		let (lua, plugin) =
+			load_plugin(&context.plugin_dir, &self.plugin_path).map_err(|err| err.to_string())?;
+		let lua_step = self
+			.lua_step(&lua, &plugin, context)
+			.map_err(|err| err.to_string())?;
+		Ok((lua, lua_step))
+	}
+
+	/// Record an error raised by the plugin while building the reporting graph, to be reported when the step is executed
+	///
+	/// Only the first error is recorded.
+	fn defer_error(&self, message: String) {
+		self.deferred_error.lock().unwrap().get_or_insert(message);
+	}
+
+	/// Get the implementation of this step from the loaded plugin
+	///
+	/// If the step was generated by a dynamic builder, the builder is called again to obtain the implementation.
+	fn lua_step(
+		&self,
+		lua: &Lua,
+		plugin: &Plugin,
+		context: &ReportingContext,
+	) -> mlua::Result<LuaReportingStep> {
+		match &self.builder {
+			None => Ok(plugin
+				.reporting_steps
+				.iter()
+				.find(|s| s.spec == self.spec)
+				.unwrap()
+				.clone()),
+			Some((builder_name, kind)) => {
+				let builder = plugin
+					.dynamic_builders
+					.iter()
+					.find(|b| &b.spec.name == builder_name)
+					.unwrap();
+
+				// Call to plugin
+				builder.build.call::<LuaReportingStep>((
+					self.spec.name.as_str(),
+					lua.to_value(kind)?,
+					lua.to_value(&self.args)?,
+					lua.to_value(&LuaReportingContext::from(context))?,
+				))
+			}
+		}
+	}
+
+	/// Look up the plugin which provides the named step, returning its path and the [ReportingStepSpec]
+	fn find_spec<'a>(
+		name: &str,
+		context: &'a ReportingContext,
+	) -> Option<(&'a String, &'a ReportingStepSpec)> {
+		context
+			.plugin_specs
+			.iter()
+			.find_map(|(plugin_path, plugin_spec)| {
+				plugin_spec
+					.reporting_steps
+					.iter()
+					.find(|s| s.name == name)
+					.map(|s| (plugin_path, s))
+			})
 	}
 }
 
@@ -245,30 +552,32 @@ impl ReportingStep for PluginReportingStep {
 	}
 
 	fn requires(&self, context: &ReportingContext) -> Vec<ReportingProductId> {
-		// Call to plugin
-		let (lua, plugin) = load_plugin(&context.plugin_dir, &self.plugin_path);
-		let plugin_step = plugin
-			.reporting_steps
-			.iter()
-			.find(|s| s.spec == self.spec)
-			.unwrap();
-
-		let result_table = plugin_step
-			.requires
-			.call::<Table>((
-				lua.to_value(&self.args).unwrap(),
-				lua.to_value(&LuaReportingContext::from(context)).unwrap(),
-			))
-			.expect("Lua error");
-
-		// Convert result to Rust
-		let result = result_table
-			.sequence_values()
-			.map(|s| s.expect("Lua error"))
-			.map(|v| lua.from_value(v).expect("Deserialise error"))
-			.collect::<Vec<ReportingProductId>>();
-
-		result
+		let result = self.load_lua_step(context).and_then(|(lua, plugin_step)| {
+			// Call to plugin
+			let result_table = plugin_step
+				.requires
+				.call::<Table>((
+					lua.to_value(&self.args).unwrap(),
+					lua.to_value(&LuaReportingContext::from(context)).unwrap(),
+				))
+				.map_err(|err| err.to_string())?;
+
+			// Convert result to Rust
+			result_table
+				.sequence_values()
+				.map(|v| lua.from_value(v?))
+				.collect::<mlua::Result<Vec<ReportingProductId>>>()
+				.map_err(|err| err.to_string())
+		});
+
+		match result {
+			Ok(result) => result,
+			Err(message) => {
+				// Report the error when the step is executed
+				self.defer_error(message);
+				vec![]
+			}
+		}
 	}
 
 	fn after_init_graph(
@@ -278,15 +587,16 @@ impl ReportingStep for PluginReportingStep {
 		context: &ReportingContext,
 	) {
 		// Load plugin
-		let (lua, plugin) = load_plugin(&context.plugin_dir, &self.plugin_path);
-		let plugin_step = plugin
-			.reporting_steps
-			.iter()
-			.find(|s| s.spec == self.spec)
-			.unwrap();
+		let (lua, plugin_step) = match self.load_lua_step(context) {
+			Ok(loaded) => loaded,
+			Err(message) => {
+				self.defer_error(message);
+				return;
+			}
+		};
 
 		// Create a new scope since `add_dependency` depends on `dependencies`
-		lua.scope(|scope| {
+		let result = lua.scope(|scope| {
 			// Init Lua environment
 			let add_dependency = scope.create_function_mut(|_, (step, product)| {
 				let step_id = lua.from_value::<ReportingStepId>(step)?;
@@ -305,8 +615,12 @@ impl ReportingStep for PluginReportingStep {
 			))?;
 
 			Ok(())
-		})
-		.expect("Lua error");
+		});
+
+		if let Err(err) = result {
+			// Report the error when the step is executed
+			self.defer_error(err.to_string());
+		}
 	}
 
 	async fn execute(
@@ -316,59 +630,207 @@ impl ReportingStep for PluginReportingStep {
 		_dependencies: &ReportingGraphDependencies,
 		products: &RwLock<ReportingProducts>,
 	) -> Result<ReportingProducts, ReportingExecutionError> {
-		// Pre-compute some context for Lua
-		let kinds_for_account =
-			kinds_for_account(context.db_connection.get_account_configurations().await);
+		execute_plugin_steps(&[self], context, products)
+			.await
+			.pop()
+			.unwrap()
+	}
 
-		let products = products.read().await;
+	fn batch_key(&self) -> Option<String> {
+		// Steps provided by the same plugin are batched, so they can share one Lua scope
+		Some(format!("plugin:{}", self.plugin_path))
+	}
 
-		// Load plugin
-		let (lua, plugin) = load_plugin(&context.plugin_dir, &self.plugin_path);
-		let plugin_step = plugin
-			.reporting_steps
+	async fn execute_batch(
+		&self,
+		batch: &[&dyn ReportingStep],
+		context: &ReportingContext,
+		_steps: &Vec<Box<dyn ReportingStep>>,
+		_dependencies: &ReportingGraphDependencies,
+		products: &RwLock<ReportingProducts>,
+	) -> Vec<Result<ReportingProducts, ReportingExecutionError>> {
+		let batch = batch
 			.iter()
-			.find(|s| s.spec == self.spec)
-			.unwrap();
-
-		// Create a new scope since `get_product` depends on `products`
-		let result_table = lua
-			.scope(|scope| {
-				// Init Lua environment
-				let get_product = scope.create_function(|_, product| {
-					let product_id = lua.from_value::<ReportingProductId>(product)?;
-					let product = products.get_or_err(&product_id).unwrap();
-					let product_enum: LuaReportingProduct = product.clone().into();
-					Ok(lua.to_value(&product_enum))
-				})?;
+			.map(|step| step.downcast_ref::<PluginReportingStep>().unwrap()) // Steps with batch key plugin:... are always PluginReportingStep
+			.collect::<Vec<_>>();
+		execute_plugin_steps(&batch, context, products).await
+	}
+}
 
-				// Call to plugin
-				let result_table = plugin_step.execute.call::<Table>((
-					lua.to_value(&self.args).unwrap(),
-					lua.to_value(&LuaReportingContext::from(context)).unwrap(),
-					lua.to_value(&kinds_for_account).unwrap(),
-					get_product,
-				))?;
+/// Execute the given [PluginReportingStep]s, which must all be provided by the same plugin
+///
+/// The plugin is loaded once, and all steps are executed within a single Lua scope. Returns the result of each step, in the same order as `steps`. If the steps are provided by different plugins, every step fails with [ReportingExecutionError::PluginError].
+pub async fn execute_plugin_steps(
+	steps: &[&PluginReportingStep],
+	context: &ReportingContext,
+	products: &RwLock<ReportingProducts>,
+) -> Vec<Result<ReportingProducts, ReportingExecutionError>> {
+	let Some(plugin_path) = steps.first().map(|s| &s.plugin_path) else {
+		return Vec::new();
+	};
+	if steps.iter().any(|s| &s.plugin_path != plugin_path) {
+		return steps
+			.iter()
+			.map(|s| {
+				Err(ReportingExecutionError::PluginError {
+					plugin: s.plugin_path.clone(),
+					message: "attempt to batch execute steps from different plugins".to_string(),
+				})
+			})
+			.collect();
+	}
 
-				Ok(result_table)
+	// Pre-compute some context for Lua
+	let (data_for_account, kinds_for_account) =
+		match context.db_connection.get_account_configurations().await {
+			Ok(account_configurations) => (
+				data_for_account(&account_configurations),
+				kinds_for_account(account_configurations),
+			),
+			Err(err) => return steps.iter().map(|_| Err(err.clone().into())).collect(),
+		};
+
+	let products = products.read().await;
+
+	// Steps which raised an error while building the reporting graph are not executed
+	let mut results = steps
+		.iter()
+		.map(|step| {
+			step.deferred_error.lock().unwrap().clone().map(|message| {
+				Err(ReportingExecutionError::PluginError {
+					plugin: plugin_path.clone(),
+					message,
+				})
 			})
-			.expect("Lua error");
-
-		// Convert to Rust
-		let mut products = ReportingProducts::new();
-		for pair in result_table.pairs::<Value, Value>() {
-			let pair = pair.expect("Lua error");
-			let product_id = lua
-				.from_value::<ReportingProductId>(pair.0)
-				.expect("Deserialise error");
-			let product = lua
-				.from_value::<LuaReportingProduct>(pair.1)
-				.expect("Deserialise error");
-
-			products.insert(product_id, product.into());
+		})
+		.collect::<Vec<_>>();
+
+	let steps_to_execute = steps
+		.iter()
+		.zip(results.iter())
+		.filter(|(_, result)| result.is_none())
+		.map(|(step, _)| *step)
+		.collect::<Vec<_>>();
+
+	if !steps_to_execute.is_empty() {
+		let mut executed_results = execute_lua_steps(
+			&steps_to_execute,
+			context,
+			&products,
+			&kinds_for_account,
+			&data_for_account,
+		)
+		.into_iter();
+
+		for result in results.iter_mut().filter(|r| r.is_none()) {
+			*result = executed_results.next();
 		}
-
-		Ok(products)
 	}
+
+	results.into_iter().map(|r| r.unwrap()).collect()
+}
+
+/// Execute the given [PluginReportingStep]s within a single Lua scope, as for [execute_plugin_steps]
+fn execute_lua_steps(
+	steps: &[&PluginReportingStep],
+	context: &ReportingContext,
+	products: &ReportingProducts,
+	kinds_for_account: &HashMap<String, Vec<AccountKind>>,
+	data_for_account: &HashMap<String, HashMap<String, serde_json::Value>>,
+) -> Vec<Result<ReportingProducts, ReportingExecutionError>> {
+	let plugin_path = &steps[0].plugin_path;
+	let to_error = plugin_error(plugin_path);
+
+	// Load plugin
+	let (lua, plugin) = match load_plugin(&context.plugin_dir, plugin_path) {
+		Ok(loaded) => loaded,
+		Err(err) => return steps.iter().map(|_| Err(to_error(err.clone()))).collect(),
+	};
+
+	// Create a new scope since `get_product` depends on `products`
+	let result_tables = lua
+		.scope(|scope| {
+			// Init Lua environment
+			let get_product = scope.create_function(|_, product| {
+				let product_id = lua.from_value::<ReportingProductId>(product)?;
+				let product = products
+					.get_or_err(&product_id)
+					.map_err(|err| mlua::Error::runtime(format!("{:?}", err)))?;
+				let product_enum: LuaReportingProduct = product.clone().into();
+				Ok(lua.to_value(&product_enum))
+			})?;
+
+			let lua_context = lua.to_value(&LuaReportingContext::from(context)).unwrap();
+			let lua_kinds_for_account = lua.to_value(kinds_for_account).unwrap();
+			let lua_data_for_account = lua.to_value(data_for_account).unwrap();
+
+			// Call to plugin for each step
+			let mut result_tables = Vec::new();
+			for step in steps.iter() {
+				let plugin_step = step.lua_step(&lua, &plugin, context)?;
+
+				result_tables.push(plugin_step.execute.call::<Table>((
+					lua.to_value(&step.args).unwrap(),
+					lua_context.clone(),
+					lua_kinds_for_account.clone(),
+					get_product.clone(),
+					lua_data_for_account.clone(),
+				))?);
+			}
+
+			Ok(result_tables)
+		})
+		.map_err(|err| err.to_string());
+
+	// Report any error raised by the plugin against each step
+	let result_tables = match result_tables {
+		Ok(result_tables) => result_tables,
+		Err(message) => {
+			return steps
+				.iter()
+				.map(|_| {
+					Err(ReportingExecutionError::PluginError {
+						plugin: plugin_path.clone(),
+						message: message.clone(),
+					})
+				})
+				.collect();
+		}
+	};
+
+	// Convert to Rust
+	result_tables
+		.into_iter()
+		.map(|result_table| {
+			let mut products = ReportingProducts::new();
+			for pair in result_table.pairs::<Value, Value>() {
+				let pair = pair.map_err(&to_error)?;
+				let product_id = lua
+					.from_value::<ReportingProductId>(pair.0)
+					.map_err(&to_error)?;
+				let mut product = lua
+					.from_value::<LuaReportingProduct>(pair.1)
+					.map_err(&to_error)?;
+
+				// Record the plugin step as the origin of any transactions it generated
+				if let LuaReportingProduct::Transactions(transactions) = &mut product {
+					for transaction in transactions.transactions.iter_mut() {
+						transaction
+							.transaction
+							.origin
+							.get_or_insert_with(|| product_id.name.clone());
+					}
+
+					if context.reject_unbalanced_transactions {
+						transactions.check_balanced(&context.reporting_commodity)?;
+					}
+				}
+
+				products.insert(product_id, product.into());
+			}
+			Ok(products)
+		})
+		.collect()
 }
 
 /// Format the [Table] as a string
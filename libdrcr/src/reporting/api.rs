@@ -0,0 +1,169 @@
+/*
+	DrCr: Web-based double-entry bookkeeping framework
+	Copyright (C) 2022-2025  Lee Yingtong Li (RunasSudo)
+
+	This program is free software: you can redistribute it and/or modify
+	it under the terms of the GNU Affero General Public License as published by
+	the Free Software Foundation, either version 3 of the License, or
+	(at your option) any later version.
+
+	This program is distributed in the hope that it will be useful,
+	but WITHOUT ANY WARRANTY; without even the implied warranty of
+	MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+	GNU Affero General Public License for more details.
+
+	You should have received a copy of the GNU Affero General Public License
+	along with this program.  If not, see <https://www.gnu.org/licenses/>.
+*/
+
+//! Convenience functions for generating common reports without constructing [ReportingProductId]s by hand
+
+use std::sync::Arc;
+
+use chrono::NaiveDate;
+
+use crate::model::transaction::TransactionWithPostings;
+
+use super::dynamic_report::{DrilldownReport, DynamicReport};
+use super::generate_report;
+use super::types::{
+	DateArgs, DateStartDateEndArgs, MultipleDateArgs, MultipleDateStartDateEndArgs,
+	ReportingContext, ReportingProductId, ReportingProductKind, ReportingStepArgs,
+};
+use super::ReportingError;
+
+/// Generate the balance sheet, with one column for each of the given dates
+pub async fn balance_sheet(
+	context: Arc<ReportingContext>,
+	dates: &[NaiveDate],
+) -> Result<DynamicReport, ReportingError> {
+	dynamic_report(
+		context,
+		"BalanceSheet",
+		ReportingStepArgs::MultipleDateArgs(MultipleDateArgs {
+			dates: dates
+				.iter()
+				.map(|date| DateArgs {
+					date: *date,
+					time: None,
+				})
+				.collect(),
+			with_prior_year: false,
+		}),
+	)
+	.await
+}
+
+/// Generate the income statement, with one column for each of the given `(date_start, date_end)` periods
+///
+/// If `cumulative` is true, each column shows the cumulative total up to that period (see [MultipleDateStartDateEndArgs::cumulative]).
+pub async fn income_statement(
+	context: Arc<ReportingContext>,
+	periods: &[(NaiveDate, NaiveDate)],
+	cumulative: bool,
+) -> Result<DynamicReport, ReportingError> {
+	dynamic_report(
+		context,
+		"IncomeStatement",
+		ReportingStepArgs::MultipleDateStartDateEndArgs(MultipleDateStartDateEndArgs {
+			dates: periods
+				.iter()
+				.map(|(date_start, date_end)| DateStartDateEndArgs {
+					date_start: *date_start,
+					date_end: *date_end,
+				})
+				.collect(),
+			cumulative,
+		}),
+	)
+	.await
+}
+
+/// Generate the trial balance at the given date
+///
+/// If `by_commodity` is true, the balance of each account in each commodity is also shown (see [ReportingStepArgs::DateByCommodityArgs]).
+pub async fn trial_balance(
+	context: Arc<ReportingContext>,
+	date: NaiveDate,
+	by_commodity: bool,
+) -> Result<DynamicReport, ReportingError> {
+	let args = DateArgs { date, time: None };
+	dynamic_report(
+		context,
+		"TrialBalance",
+		if by_commodity {
+			ReportingStepArgs::DateByCommodityArgs(args)
+		} else {
+			ReportingStepArgs::DateArgs(args)
+		},
+	)
+	.await
+}
+
+/// Generate the report of reconciled and unreconciled statement lines for each source account
+pub async fn reconciliation_status(
+	context: Arc<ReportingContext>,
+) -> Result<DynamicReport, ReportingError> {
+	dynamic_report(context, "ReconciliationStatus", ReportingStepArgs::VoidArgs).await
+}
+
+/// Get the transactions underlying a row of the given report, e.g. to drill down into the report without going through the frontend
+///
+/// `report` identifies the report as a [DynamicReport] product, and `row` the row within it (see [DrilldownReport::transactions_for_row]). Transactions are ordered by date.
+pub async fn transactions_for_row(
+	context: Arc<ReportingContext>,
+	report: &ReportingProductId,
+	row: &str,
+) -> Result<Vec<TransactionWithPostings>, ReportingError> {
+	let target = ReportingProductId {
+		kind: ReportingProductKind::DrilldownReport,
+		..report.clone()
+	};
+
+	let products =
+		generate_report(with_plugin_transactions(&context, target.clone()), context).await?;
+	let result = products.get_or_err(&target)?;
+	let drilldown_report = result.downcast_ref::<DrilldownReport>().unwrap(); // Products of kind DrilldownReport are always DrilldownReport
+
+	drilldown_report
+		.transactions_for_row(row)
+		.ok_or_else(|| ReportingError::RowNotFound(row.to_string()))
+}
+
+/// Generate the named [DynamicReport] with the given args
+///
+/// The [Transactions][super::types::Transactions] of all plugin steps are also requested, so that they are reflected in the report.
+pub async fn dynamic_report(
+	context: Arc<ReportingContext>,
+	name: &str,
+	args: ReportingStepArgs,
+) -> Result<DynamicReport, ReportingError> {
+	let target = ReportingProductId {
+		name: name.to_string(),
+		kind: ReportingProductKind::DynamicReport,
+		args,
+	};
+
+	let products =
+		generate_report(with_plugin_transactions(&context, target.clone()), context).await?;
+	let result = products.get_owned_or_err(&target)?;
+
+	Ok(*result.downcast::<DynamicReport>().unwrap()) // Products of kind DynamicReport are always DynamicReport
+}
+
+/// Return the given target together with the [Transactions][super::types::Transactions] of all plugin steps, so that they are reflected in the target
+fn with_plugin_transactions(
+	context: &ReportingContext,
+	target: ReportingProductId,
+) -> Vec<ReportingProductId> {
+	// Plugin transaction steps are requested with VoidArgs (see transaction_step_names)
+	let mut targets = vec![target];
+	for name in crate::plugin::transaction_step_names(context) {
+		targets.push(ReportingProductId {
+			name,
+			kind: ReportingProductKind::Transactions,
+			args: ReportingStepArgs::VoidArgs,
+		});
+	}
+	targets
+}
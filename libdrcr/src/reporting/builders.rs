@@ -24,11 +24,15 @@ use std::collections::HashMap;
 use std::fmt::Display;
 
 use async_trait::async_trait;
+use chrono::NaiveDate;
 use tokio::sync::RwLock;
 
-use crate::model::transaction::update_balances_from_transactions;
+use crate::model::transaction::{
+	combine_balances, update_balances_from_transactions, TransactionWithPostings,
+};
 
 use super::calculator::{has_step_or_can_build, HasStepOrCanBuild, ReportingGraphDependencies};
+use super::dynamic_report::{DrilldownReport, DynamicReport};
 use super::executor::ReportingExecutionError;
 use super::types::{
 	BalancesAt, BalancesBetween, DateArgs, DateStartDateEndArgs, ReportingContext,
@@ -41,6 +45,7 @@ pub fn register_dynamic_builders(context: &mut ReportingContext) {
 	GenerateBalances::register_dynamic_builder(context);
 	UpdateBalancesBetween::register_dynamic_builder(context);
 	UpdateBalancesAt::register_dynamic_builder(context);
+	DynamicReportToDrilldownReport::register_dynamic_builder(context);
 
 	// This is the least efficient way of generating BalancesBetween so put at the end
 	BalancesAtToBalancesBetween::register_dynamic_builder(context);
@@ -81,6 +86,7 @@ impl BalancesAtToBalancesBetween {
 						kind: ReportingProductKind::BalancesAt,
 						args: ReportingStepArgs::DateArgs(DateArgs {
 							date: args.date_start.clone(),
+							time: None,
 						}),
 					},
 					steps,
@@ -143,6 +149,7 @@ impl ReportingStep for BalancesAtToBalancesBetween {
 				kind: ReportingProductKind::BalancesAt,
 				args: ReportingStepArgs::DateArgs(DateArgs {
 					date: self.args.date_start.pred_opt().unwrap(), // Opening balance is the closing balance of the preceding day
+					time: None,
 				}),
 			},
 			ReportingProductId {
@@ -150,6 +157,7 @@ impl ReportingStep for BalancesAtToBalancesBetween {
 				kind: ReportingProductKind::BalancesAt,
 				args: ReportingStepArgs::DateArgs(DateArgs {
 					date: self.args.date_end,
+					time: None,
 				}),
 			},
 		]
@@ -157,7 +165,7 @@ impl ReportingStep for BalancesAtToBalancesBetween {
 
 	async fn execute(
 		&self,
-		_context: &ReportingContext,
+		context: &ReportingContext,
 		_steps: &Vec<Box<dyn ReportingStep>>,
 		_dependencies: &ReportingGraphDependencies,
 		products: &RwLock<ReportingProducts>,
@@ -165,39 +173,43 @@ impl ReportingStep for BalancesAtToBalancesBetween {
 		let products = products.read().await;
 
 		// Get balances at dates
-		let balances_start = &products
+		let balances_start = products
 			.get_or_err(&ReportingProductId {
 				name: self.step_name.clone(),
 				kind: ReportingProductKind::BalancesAt,
 				args: ReportingStepArgs::DateArgs(DateArgs {
 					date: self.args.date_start.pred_opt().unwrap(), // Opening balance is the closing balance of the preceding day
+					time: None,
 				}),
 			})?
 			.downcast_ref::<BalancesAt>()
-			.unwrap()
-			.balances;
+			.unwrap();
 
-		let balances_end = &products
+		let balances_end = products
 			.get_or_err(&ReportingProductId {
 				name: self.step_name.clone(),
 				kind: ReportingProductKind::BalancesAt,
 				args: ReportingStepArgs::DateArgs(DateArgs {
 					date: self.args.date_end,
+					time: None,
 				}),
 			})?
 			.downcast_ref::<BalancesAt>()
-			.unwrap()
-			.balances;
+			.unwrap();
 
 		// Compute balances_end - balances_start
 		let mut balances = BalancesBetween {
-			balances: balances_end.clone(),
+			balances: balances_end.balances.clone(),
+			commodity_balances: balances_end.commodity_balances.clone(),
 		};
-
-		for (account, balance) in balances_start.iter() {
-			let running_balance = balances.balances.get(account).unwrap_or(&0) - balance;
-			balances.balances.insert(account.clone(), running_balance);
-		}
+		combine_balances(
+			&mut balances.balances,
+			&mut balances.commodity_balances,
+			&balances_start.balances,
+			&balances_start.commodity_balances,
+			-1,
+			&context.reporting_commodity,
+		);
 
 		// Store result
 		let mut result = ReportingProducts::new();
@@ -213,6 +225,241 @@ impl ReportingStep for BalancesAtToBalancesBetween {
 	}
 }
 
+/// This dynamic builder automatically generates a [DrilldownReport] from a step which generates a [DynamicReport], by attaching the transactions underlying each account row
+///
+/// For reports over a period (e.g. [IncomeStatement][super::steps::IncomeStatement]), transactions within the period are attached, excluding earnings to equity transactions, from the same source as the income statement (i.e. [CashBasisTransactions][super::steps::CashBasisTransactions] if the `cash_basis` metadata flag is set). Otherwise (e.g. [BalanceSheet][super::steps::BalanceSheet]), all transactions up to the latest date are attached. Dates only are considered, and any times in the args are ignored.
+#[derive(Debug)]
+pub struct DynamicReportToDrilldownReport {
+	step_name: String,
+	args: ReportingStepArgs,
+}
+
+impl DynamicReportToDrilldownReport {
+	// Implements DynamicReport, Transactions -> DrilldownReport
+
+	fn register_dynamic_builder(context: &mut ReportingContext) {
+		context.register_dynamic_builder(ReportingStepDynamicBuilder {
+			name: "DynamicReportToDrilldownReport",
+			can_build: Self::can_build,
+			build: Self::build,
+		});
+	}
+
+	fn can_build(
+		name: &str,
+		kind: ReportingProductKind,
+		args: &ReportingStepArgs,
+		steps: &Vec<Box<dyn ReportingStep>>,
+		dependencies: &ReportingGraphDependencies,
+		context: &ReportingContext,
+	) -> bool {
+		// Check for DynamicReport -> DrilldownReport
+		if kind == ReportingProductKind::DrilldownReport && drilldown_period(args).is_some() {
+			match has_step_or_can_build(
+				&ReportingProductId {
+					name: name.to_string(),
+					kind: ReportingProductKind::DynamicReport,
+					args: args.clone(),
+				},
+				steps,
+				dependencies,
+				context,
+			) {
+				HasStepOrCanBuild::HasStep(_)
+				| HasStepOrCanBuild::CanLookup(_)
+				| HasStepOrCanBuild::CanBuild(_) => {
+					return true;
+				}
+				HasStepOrCanBuild::None => {}
+			}
+		}
+		return false;
+	}
+
+	fn build(
+		name: String,
+		_kind: ReportingProductKind,
+		args: ReportingStepArgs,
+		_steps: &Vec<Box<dyn ReportingStep>>,
+		_dependencies: &ReportingGraphDependencies,
+		_context: &ReportingContext,
+	) -> Box<dyn ReportingStep> {
+		Box::new(DynamicReportToDrilldownReport {
+			step_name: name,
+			args,
+		})
+	}
+
+	/// Return the [ReportingProductId] of the [Transactions] for the given step, up to the latest date of the report
+	fn transactions_product(&self, name: &str) -> ReportingProductId {
+		let (_, date_end) = drilldown_period(&self.args).unwrap();
+		ReportingProductId {
+			name: name.to_string(),
+			kind: ReportingProductKind::Transactions,
+			args: ReportingStepArgs::DateArgs(DateArgs {
+				date: date_end,
+				time: None,
+			}),
+		}
+	}
+
+	/// Return the [ReportingProductId] of the ordinary [Transactions] to attach
+	///
+	/// As for [IncomeStatement][super::steps::IncomeStatement], reports over a period use [CashBasisTransactions][super::steps::CashBasisTransactions] if the `cash_basis` metadata flag is set, otherwise [AllTransactionsExceptEarningsToEquity][super::steps::AllTransactionsExceptEarningsToEquity].
+	fn ordinary_transactions_product(&self, context: &ReportingContext) -> ReportingProductId {
+		match drilldown_period(&self.args).unwrap() {
+			(Some(date_start), date_end) if context.db_connection.metadata().cash_basis => {
+				ReportingProductId {
+					name: "CashBasisTransactions".to_string(),
+					kind: ReportingProductKind::Transactions,
+					args: ReportingStepArgs::DateStartDateEndArgs(DateStartDateEndArgs {
+						date_start,
+						date_end,
+					}),
+				}
+			}
+			_ => self.transactions_product("AllTransactionsExceptEarningsToEquity"),
+		}
+	}
+
+	/// Return true if earnings to equity transactions should be attached, i.e. if the report is as at a point in time rather than over a period
+	fn includes_earnings_to_equity(&self) -> bool {
+		drilldown_period(&self.args).unwrap().0.is_none()
+	}
+}
+
+impl Display for DynamicReportToDrilldownReport {
+	fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+		f.write_fmt(format_args!(
+			"{} {{DynamicReportToDrilldownReport}}",
+			self.id()
+		))
+	}
+}
+
+#[async_trait]
+impl ReportingStep for DynamicReportToDrilldownReport {
+	fn id(&self) -> ReportingStepId {
+		ReportingStepId {
+			name: self.step_name.clone(),
+			product_kinds: vec![ReportingProductKind::DrilldownReport],
+			args: self.args.clone(),
+		}
+	}
+
+	fn requires(&self, context: &ReportingContext) -> Vec<ReportingProductId> {
+		// DynamicReportToDrilldownReport depends on the DynamicReport and the Transactions to attach
+		let mut result = vec![
+			ReportingProductId {
+				name: self.step_name.clone(),
+				kind: ReportingProductKind::DynamicReport,
+				args: self.args.clone(),
+			},
+			self.ordinary_transactions_product(context),
+		];
+		if self.includes_earnings_to_equity() {
+			result.push(self.transactions_product("CurrentYearEarningsToEquity"));
+			result.push(self.transactions_product("RetainedEarningsToEquity"));
+		}
+		result
+	}
+
+	async fn execute(
+		&self,
+		context: &ReportingContext,
+		_steps: &Vec<Box<dyn ReportingStep>>,
+		_dependencies: &ReportingGraphDependencies,
+		products: &RwLock<ReportingProducts>,
+	) -> Result<ReportingProducts, ReportingExecutionError> {
+		let products = products.read().await;
+
+		// Get the report
+		let report = products
+			.get_or_err(&ReportingProductId {
+				name: self.step_name.clone(),
+				kind: ReportingProductKind::DynamicReport,
+				args: self.args.clone(),
+			})?
+			.downcast_ref::<DynamicReport>()
+			.unwrap();
+
+		let mut drilldown_report = DrilldownReport::new(report.clone());
+		let (date_start, date_end) = drilldown_period(&self.args).unwrap();
+		let in_period = |transaction: &&TransactionWithPostings| {
+			let date = transaction.transaction.dt.date();
+			date <= date_end && date_start.is_none_or(|d| date >= d)
+		};
+
+		// Attach ordinary transactions within the report period, and not after the cutoff if any
+		let transactions = &products
+			.get_or_err(&self.ordinary_transactions_product(context))?
+			.downcast_ref::<Transactions>()
+			.unwrap()
+			.transactions;
+		drilldown_report.attach_transactions(
+			transactions.iter().filter(in_period).filter(|t| {
+				context
+					.transactions_cutoff
+					.is_none_or(|d| t.transaction.dt.date() <= d)
+			}),
+			|_| true,
+		);
+
+		if self.includes_earnings_to_equity() {
+			// Attach earnings to equity transactions to the equity accounts only, as these transactions are not reflected in income and expense balances
+			for name in ["CurrentYearEarningsToEquity", "RetainedEarningsToEquity"] {
+				let transactions = &products
+					.get_or_err(&self.transactions_product(name))?
+					.downcast_ref::<Transactions>()
+					.unwrap()
+					.transactions;
+				drilldown_report.attach_transactions(
+					transactions.iter().filter(in_period),
+					|account| {
+						account == crate::CURRENT_YEAR_EARNINGS
+							|| account == crate::RETAINED_EARNINGS
+					},
+				);
+			}
+		}
+
+		// Store result
+		let mut result = ReportingProducts::new();
+		result.insert(
+			ReportingProductId {
+				name: self.id().name,
+				kind: ReportingProductKind::DrilldownReport,
+				args: self.args.clone(),
+			},
+			Box::new(drilldown_report),
+		);
+		Ok(result)
+	}
+}
+
+/// Return the earliest start date (for reports over a period) and the latest end date of a report with the given args, or `None` if the args do not specify any date
+fn drilldown_period(args: &ReportingStepArgs) -> Option<(Option<NaiveDate>, NaiveDate)> {
+	match args {
+		ReportingStepArgs::VoidArgs => None,
+		ReportingStepArgs::DateArgs(args) | ReportingStepArgs::DateByCommodityArgs(args) => {
+			Some((None, args.date))
+		}
+		ReportingStepArgs::DateStartDateEndArgs(args) => {
+			Some((Some(args.date_start), args.date_end))
+		}
+		ReportingStepArgs::DateStartDateEndAccountsArgs(args) => {
+			Some((Some(args.date_start), args.date_end))
+		}
+		ReportingStepArgs::MultipleDateArgs(args) => {
+			args.dates.iter().map(|d| d.date).max().map(|d| (None, d))
+		}
+		ReportingStepArgs::MultipleDateStartDateEndArgs(args) => Some((
+			Some(args.dates.iter().map(|d| d.date_start).min()?),
+			args.dates.iter().map(|d| d.date_end).max()?,
+		)),
+	}
+}
+
 /// This dynamic builder automatically generates a [BalancesAt] from a step which has no dependencies and generates [Transactions] (e.g. [PostUnreconciledStatementLines][super::steps::PostUnreconciledStatementLines])
 #[derive(Debug)]
 pub struct GenerateBalances {
@@ -376,7 +623,7 @@ impl ReportingStep for GenerateBalances {
 
 	async fn execute(
 		&self,
-		_context: &ReportingContext,
+		context: &ReportingContext,
 		_steps: &Vec<Box<dyn ReportingStep>>,
 		dependencies: &ReportingGraphDependencies,
 		products: &RwLock<ReportingProducts>,
@@ -394,12 +641,16 @@ impl ReportingStep for GenerateBalances {
 		// Sum balances
 		let mut balances = BalancesAt {
 			balances: HashMap::new(),
+			commodity_balances: HashMap::new(),
 		};
 		update_balances_from_transactions(
 			&mut balances.balances,
-			transactions
-				.iter()
-				.filter(|t| t.transaction.dt.date() <= self.args.date),
+			&mut balances.commodity_balances,
+			transactions.iter().filter(|t| {
+				self.args.includes_dt(t.transaction.dt)
+					&& !context.excludes_transaction(&self.step_name, t)
+			}),
+			&context.reporting_commodity,
 		);
 
 		// Store result
@@ -471,7 +722,10 @@ impl UpdateBalancesAt {
 							&ReportingProductId {
 								name: dependencies_for_step[0].product.name.clone(),
 								kind: ReportingProductKind::BalancesAt,
-								args: ReportingStepArgs::DateArgs(DateArgs { date: args.date }),
+								args: ReportingStepArgs::DateArgs(DateArgs {
+									date: args.date,
+									time: args.time,
+								}),
 							},
 							steps,
 							dependencies,
@@ -568,6 +822,7 @@ impl ReportingStep for UpdateBalancesAt {
 					kind: ReportingProductKind::BalancesAt,
 					args: ReportingStepArgs::DateArgs(DateArgs {
 						date: self.args.date,
+						time: self.args.time,
 					}),
 				},
 			);
@@ -576,7 +831,7 @@ impl ReportingStep for UpdateBalancesAt {
 
 	async fn execute(
 		&self,
-		_context: &ReportingContext,
+		context: &ReportingContext,
 		steps: &Vec<Box<dyn ReportingStep>>,
 		dependencies: &ReportingGraphDependencies,
 		products: &RwLock<ReportingProducts>,
@@ -625,6 +880,7 @@ impl ReportingStep for UpdateBalancesAt {
 					kind: ReportingProductKind::BalancesAt,
 					args: ReportingStepArgs::DateArgs(DateArgs {
 						date: self.args.date,
+						time: self.args.time,
 					}),
 				})?
 				.downcast_ref()
@@ -632,14 +888,15 @@ impl ReportingStep for UpdateBalancesAt {
 		}
 
 		// Sum balances
-		let mut balances = BalancesAt {
-			balances: opening_balances_at.balances.clone(),
-		};
+		let mut balances = opening_balances_at.clone();
 		update_balances_from_transactions(
 			&mut balances.balances,
-			transactions
-				.iter()
-				.filter(|t| t.transaction.dt.date() <= self.args.date),
+			&mut balances.commodity_balances,
+			transactions.iter().filter(|t| {
+				self.args.includes_dt(t.transaction.dt)
+					&& !context.excludes_transaction(&self.step_name, t)
+			}),
+			&context.reporting_commodity,
 		);
 
 		// Store result
@@ -788,7 +1045,7 @@ impl ReportingStep for UpdateBalancesBetween {
 
 	async fn execute(
 		&self,
-		_context: &ReportingContext,
+		context: &ReportingContext,
 		steps: &Vec<Box<dyn ReportingStep>>,
 		dependencies: &ReportingGraphDependencies,
 		products: &RwLock<ReportingProducts>,
@@ -822,26 +1079,26 @@ impl ReportingStep for UpdateBalancesBetween {
 		let balances_between_product = &dependencies_for_step[0].product; // Existence and uniqueness is checked in can_build
 
 		// Get opening balances
-		let opening_balances = &products
+		let opening_balances = products
 			.get_or_err(&ReportingProductId {
 				name: balances_between_product.name.clone(),
 				kind: ReportingProductKind::BalancesBetween,
 				args: ReportingStepArgs::DateStartDateEndArgs(self.args.clone()),
 			})?
 			.downcast_ref::<BalancesBetween>()
-			.unwrap()
-			.balances;
+			.unwrap();
 
 		// Sum balances
-		let mut balances = BalancesBetween {
-			balances: opening_balances.clone(),
-		};
+		let mut balances = opening_balances.clone();
 		update_balances_from_transactions(
 			&mut balances.balances,
+			&mut balances.commodity_balances,
 			transactions.iter().filter(|t| {
 				t.transaction.dt.date() >= self.args.date_start
 					&& t.transaction.dt.date() <= self.args.date_end
+					&& !context.excludes_transaction(&self.step_name, t)
 			}),
+			&context.reporting_commodity,
 		);
 
 		// Store result
@@ -18,9 +18,16 @@
 
 //! This module implements the dependency resolution for [ReportingStep]s
 
+use std::collections::{BTreeMap, HashSet};
+
+use chrono::NaiveDate;
+use serde::{Deserialize, Serialize};
+
+use crate::plugin::PluginReportingStep;
+
 use super::types::{
-	ReportingContext, ReportingProductId, ReportingStep, ReportingStepDynamicBuilder,
-	ReportingStepFromArgsFn, ReportingStepId,
+	ReportingContext, ReportingProductId, ReportingProductKind, ReportingStep,
+	ReportingStepDynamicBuilder, ReportingStepFromArgsFn, ReportingStepId,
 };
 
 /// List of dependencies between [ReportingStep]s and [ReportingProduct][super::types::ReportingProduct]s
@@ -50,10 +57,40 @@ impl ReportingGraphDependencies {
 	pub fn dependencies_for_step(&self, step: &ReportingStepId) -> Vec<&Dependency> {
 		return self.vec.iter().filter(|d| d.step == *step).collect();
 	}
+
+	/// Return true if the [ReportingProduct][super::types::ReportingProduct] `product` depends, directly or transitively, on the product `other`
+	pub fn product_depends_on(
+		&self,
+		product: &ReportingProductId,
+		other: &ReportingProductId,
+	) -> bool {
+		let mut visited = HashSet::new();
+		let mut to_visit = vec![product];
+
+		while let Some(product) = to_visit.pop() {
+			if !visited.insert(product) {
+				continue;
+			}
+
+			// Get dependencies of the step which generates the product
+			for dependency in self.vec.iter().filter(|d| {
+				d.step.name == product.name
+					&& d.step.args == product.args
+					&& d.step.product_kinds.contains(&product.kind)
+			}) {
+				if dependency.product == *other {
+					return true;
+				}
+				to_visit.push(&dependency.product);
+			}
+		}
+
+		false
+	}
 }
 
 /// Represents that a [ReportingStep] depends on a [ReportingProduct][super::types::ReportingProduct]
-#[derive(Debug)]
+#[derive(Clone, Debug, Deserialize, Serialize)]
 pub struct Dependency {
 	pub step: ReportingStepId,
 	pub product: ReportingProductId,
@@ -118,6 +155,15 @@ pub fn has_step_or_can_build<'a, 'b>(
 	return HasStepOrCanBuild::None;
 }
 
+/// Records how a [ReportingStep] was generated during dependency resolution, so it can be generated again from a [ReportingPlan]
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub enum StepSource {
+	/// Generated by a lookup function (see [ReportingContext::register_lookup_fn])
+	LookupFn,
+	/// Generated by the named dynamic builder (see [ReportingContext::register_dynamic_builder])
+	DynamicBuilder(String),
+}
+
 /// Generates a new step which generates the requested [ReportingProduct][super::types::ReportingProduct], using a lookup function or dynamic builder
 ///
 /// Panics if a known step already generates the requested [ReportingProduct][super::types::ReportingProduct].
@@ -126,14 +172,16 @@ fn build_step_for_product(
 	steps: &Vec<Box<dyn ReportingStep>>,
 	dependencies: &ReportingGraphDependencies,
 	context: &ReportingContext,
-) -> Option<Box<dyn ReportingStep>> {
+) -> Option<(Box<dyn ReportingStep>, StepSource)> {
 	let new_step;
+	let source;
 	match has_step_or_can_build(product, steps, dependencies, context) {
 		HasStepOrCanBuild::HasStep(_) => {
 			panic!("Attempted to call build_step_for_product for already existing step")
 		}
 		HasStepOrCanBuild::CanLookup(from_args_fn) => {
 			new_step = from_args_fn(&product.name, product.args.clone(), context);
+			source = StepSource::LookupFn;
 
 			// Check new step meets the dependency
 			if new_step.id().name != product.name {
@@ -169,6 +217,7 @@ fn build_step_for_product(
 				&dependencies,
 				&context,
 			);
+			source = StepSource::DynamicBuilder(builder.name.to_string());
 
 			// Check new step meets the dependency
 			if new_step.id().name != product.name {
@@ -203,7 +252,7 @@ fn build_step_for_product(
 		}
 	}
 
-	Some(new_step)
+	Some((new_step, source))
 }
 
 /// Check whether the [ReportingStep] would be ready to execute, if the given previous steps have already completed
@@ -240,7 +289,24 @@ pub fn steps_for_targets(
 	targets: Vec<ReportingProductId>,
 	context: &ReportingContext,
 ) -> Result<(Vec<Box<dyn ReportingStep>>, ReportingGraphDependencies), ReportingCalculationError> {
+	let (steps, dependencies, _plan) = plan_for_targets(targets, context)?;
+	Ok((steps, dependencies))
+}
+
+/// Recursively resolve the dependencies of the target [ReportingProductId]s and return a sorted [Vec] of [ReportingStep]s, together with a [ReportingPlan] which can be used to skip dependency resolution in future
+pub fn plan_for_targets(
+	targets: Vec<ReportingProductId>,
+	context: &ReportingContext,
+) -> Result<
+	(
+		Vec<Box<dyn ReportingStep>>,
+		ReportingGraphDependencies,
+		ReportingPlan,
+	),
+	ReportingCalculationError,
+> {
 	let mut steps: Vec<Box<dyn ReportingStep>> = Vec::new();
+	let mut sources = Vec::new();
 	let mut dependencies = ReportingGraphDependencies { vec: Vec::new() };
 
 	// Process initial targets
@@ -251,9 +317,11 @@ pub fn steps_for_targets(
 				&& s.id().product_kinds.contains(&target.kind)
 		}) {
 			// No current step generates the product - try to lookup or build
-			if let Some(new_step) = build_step_for_product(&target, &steps, &dependencies, context)
+			if let Some((new_step, source)) =
+				build_step_for_product(&target, &steps, &dependencies, context)
 			{
 				steps.push(new_step);
+				sources.push(source);
 				let new_step = steps.last().unwrap();
 				for dependency in new_step.requires(&context) {
 					dependencies.add_dependency(new_step.id(), dependency);
@@ -289,10 +357,10 @@ pub fn steps_for_targets(
 					&& s.id().product_kinds.contains(&dependency.product.kind)
 			}) {
 				// No current step generates the product - try to lookup or build
-				if let Some(new_step) =
+				if let Some(new_step_and_source) =
 					build_step_for_product(&dependency.product, &steps, &dependencies, context)
 				{
-					new_steps.push(new_step);
+					new_steps.push(new_step_and_source);
 				}
 			}
 		}
@@ -303,9 +371,10 @@ pub fn steps_for_targets(
 
 		// Initialise new steps
 		let mut new_step_indexes = Vec::new();
-		for new_step in new_steps {
+		for (new_step, source) in new_steps {
 			new_step_indexes.push(steps.len());
 			steps.push(new_step);
+			sources.push(source);
 			let new_step = steps.last().unwrap();
 			for dependency in new_step.requires(&context) {
 				dependencies.add_dependency(new_step.id(), dependency);
@@ -369,19 +438,142 @@ pub fn steps_for_targets(
 	}
 
 	// TODO: This can be done in place
-	let mut sorted_steps = steps.into_iter().zip(sort_mapping).collect::<Vec<_>>();
-	sorted_steps.sort_unstable_by_key(|(_s, order)| *order);
-	let sorted_steps = sorted_steps
+	let mut sorted_steps = steps
 		.into_iter()
-		.map(|(s, _idx)| s)
+		.zip(sources)
+		.zip(sort_mapping)
 		.collect::<Vec<_>>();
+	sorted_steps.sort_unstable_by_key(|(_s, order)| *order);
+	let (sorted_steps, sorted_sources): (Vec<_>, Vec<_>) =
+		sorted_steps.into_iter().map(|(s, _idx)| s).unzip();
+
+	let plan = ReportingPlan {
+		targets,
+		plugin_names: context.plugin_names.clone(),
+		eofy_date: context.eofy_date,
+		financial_year_ends: context.financial_year_ends.clone(),
+		metadata: plan_metadata(context),
+		steps: sorted_steps
+			.iter()
+			.zip(sorted_sources)
+			.map(|(step, source)| PlannedStep {
+				id: step.id(),
+				source,
+			})
+			.collect(),
+		dependencies: dependencies.vec.clone(),
+	};
+
+	Ok((sorted_steps, dependencies, plan))
+}
 
-	Ok((sorted_steps, dependencies))
+/// Return the database metadata recorded in a [ReportingPlan]
+fn plan_metadata(context: &ReportingContext) -> BTreeMap<String, String> {
+	context
+		.db_connection
+		.metadata()
+		.entries
+		.iter()
+		.map(|(k, v)| (k.clone(), v.clone()))
+		.collect()
+}
+
+/// Serialisable record of the sorted [ReportingStep]s and [Dependency]s resolved for a set of targets
+///
+/// Generated by [plan_for_targets]. The plan can be persisted and later passed to [ReportingPlan::steps], to skip dependency resolution when generating the same targets again.
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct ReportingPlan {
+	pub targets: Vec<ReportingProductId>,
+	pub plugin_names: Vec<String>,
+	#[serde(with = "crate::serde::naivedate_to_js")]
+	pub eofy_date: NaiveDate,
+	#[serde(with = "crate::serde::vec_naivedate_to_js")]
+	pub financial_year_ends: Vec<NaiveDate>,
+	/// Database metadata at the time the plan was resolved, as some steps (e.g. under `cash_basis` or `check_current_year_earnings`) resolve different dependencies depending on metadata flags
+	pub metadata: BTreeMap<String, String>,
+	pub steps: Vec<PlannedStep>,
+	pub dependencies: Vec<Dependency>,
+}
+
+/// Entry in [ReportingPlan::steps]
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct PlannedStep {
+	pub id: ReportingStepId,
+	pub source: StepSource,
+}
+
+impl ReportingPlan {
+	/// Return true if the plan was resolved for the given targets, and for the plugins, financial years and database metadata currently in the [ReportingContext]
+	pub fn is_valid_for(
+		&self,
+		targets: &Vec<ReportingProductId>,
+		context: &ReportingContext,
+	) -> bool {
+		self.targets == *targets
+			&& self.plugin_names == context.plugin_names
+			&& self.eofy_date == context.eofy_date
+			&& self.financial_year_ends == context.financial_year_ends
+			&& self.metadata == plan_metadata(context)
+	}
+
+	/// Generate the sorted [ReportingStep]s and [ReportingGraphDependencies] recorded in the plan, without resolving dependencies
+	///
+	/// Returns an error if any step can no longer be generated (e.g. because a lookup function or dynamic builder has been removed), in which case the plan should be discarded.
+	pub fn steps(
+		&self,
+		context: &ReportingContext,
+	) -> Result<(Vec<Box<dyn ReportingStep>>, ReportingGraphDependencies), ReportingCalculationError>
+	{
+		let dependencies = ReportingGraphDependencies {
+			vec: self.dependencies.clone(),
+		};
+
+		let mut steps: Vec<Box<dyn ReportingStep>> = Vec::new();
+		for planned_step in self.steps.iter() {
+			let id = &planned_step.id;
+			let new_step = match &planned_step.source {
+				StepSource::LookupFn => context
+					.step_lookup_fn
+					.iter()
+					.find(|((name, kinds), _)| *name == id.name && *kinds == id.product_kinds)
+					.map(|(_, (_takes_args_fn, from_args_fn))| {
+						from_args_fn(&id.name, id.args.clone(), context)
+					}),
+				StepSource::DynamicBuilder(builder_name) => context
+					.step_dynamic_builders
+					.iter()
+					.find(|b| b.name == builder_name)
+					.map(|builder| {
+						(builder.build)(
+							id.name.clone(),
+							id.product_kinds[0],
+							id.args.clone(),
+							&steps,
+							&dependencies,
+							context,
+						)
+					}),
+			};
+
+			match new_step {
+				Some(new_step) if new_step.id() == *id => steps.push(new_step),
+				_ => {
+					return Err(ReportingCalculationError::UnknownStep {
+						message: format!("Unable to generate planned step {}", id),
+					});
+				}
+			}
+		}
+
+		Ok((steps, dependencies))
+	}
 }
 
 /// Generate graphviz code representing the dependency tree
 ///
 /// Useful for debugging or visualisation. Can be compiled using e.g. `dot -Tpdf -O output.gv`.
+///
+/// Steps are coloured by type: plugin steps in purple, steps generated by dynamic builders in blue (and dashed), steps without dependencies (which source data from the database) in green, and steps generating [DynamicReport][super::dynamic_report::DynamicReport]s in orange. Edges into steps are labelled with the [ReportingProductKind][super::types::ReportingProductKind] of the product required.
 pub fn steps_as_graphviz(
 	steps: &Vec<Box<dyn ReportingStep>>,
 	dependencies: &ReportingGraphDependencies,
@@ -391,27 +583,45 @@ pub fn steps_as_graphviz(
 	// Output all steps
 	for step in steps.iter() {
 		let step_display_name = step.to_string();
-		if step_display_name.contains("{") {
+		let node_attrs = if step.is::<PluginReportingStep>() {
+			"shape=box, style=filled, fillcolor=plum".to_string()
+		} else if step_display_name.contains("{") {
 			// Bodge: Detect dynamic step builders
-			result.push_str(&format!(
-				"\"{}\" [shape=box, style=dashed, label=\"{}\"];\n",
-				step.id(),
-				step_display_name
-			));
+			format!(
+				"shape=box, style=\"dashed,filled\", fillcolor=lightblue, label=\"{}\"",
+				escape_graphviz(&step_display_name)
+			)
+		} else if dependencies.dependencies_for_step(&step.id()).is_empty() {
+			"shape=box, style=filled, fillcolor=palegreen".to_string()
+		} else if step
+			.id()
+			.product_kinds
+			.contains(&ReportingProductKind::DynamicReport)
+		{
+			"shape=box, style=filled, fillcolor=orange".to_string()
 		} else {
-			result.push_str(&format!("\"{}\" [shape=box];\n", step.id()));
-		}
+			"shape=box".to_string()
+		};
+
+		result.push_str(&format!(
+			"\"{}\" [{}];\n",
+			escape_graphviz(&step.id().to_string()),
+			node_attrs
+		));
 
 		// Output the products of the step
 		for product_kind in step.id().product_kinds.iter() {
 			result.push_str(&format!(
 				"\"{}\" -> \"{}\";\n",
-				step.id(),
-				ReportingProductId {
-					name: step.id().name,
-					kind: *product_kind,
-					args: step.id().args
-				}
+				escape_graphviz(&step.id().to_string()),
+				escape_graphviz(
+					&ReportingProductId {
+						name: step.id().name,
+						kind: *product_kind,
+						args: step.id().args
+					}
+					.to_string()
+				)
 			));
 		}
 	}
@@ -419,11 +629,18 @@ pub fn steps_as_graphviz(
 	// Output all dependencies
 	for dependency in dependencies.vec().iter() {
 		result.push_str(&format!(
-			"\"{}\" -> \"{}\";\n",
-			dependency.product, dependency.step
+			"\"{}\" -> \"{}\" [label=\"{:?}\"];\n",
+			escape_graphviz(&dependency.product.to_string()),
+			escape_graphviz(&dependency.step.to_string()),
+			dependency.product.kind
 		));
 	}
 
 	result.push_str("}");
 	result
 }
+
+/// Escape the string for use within a quoted graphviz ID
+fn escape_graphviz(s: &str) -> String {
+	s.replace('\\', "\\\\").replace('"', "\\\"")
+}
@@ -16,10 +16,12 @@
 	along with this program.  If not, see <https://www.gnu.org/licenses/>.
 */
 
-use std::collections::HashMap;
+use std::collections::{BTreeMap, HashMap, HashSet};
 
 use serde::{Deserialize, Serialize};
 
+use crate::account_config::{is_contra_account, AccountKind};
+use crate::model::transaction::TransactionWithPostings;
 use crate::QuantityInt;
 
 use super::types::ReportingProduct;
@@ -30,6 +32,15 @@ pub struct DynamicReport {
 	pub title: String,
 	pub columns: Vec<String>,
 	pub entries: Vec<DynamicReportEntry>,
+	/// Narrative notes referenced by [Row::note] and [Section::note]
+	#[serde(default)]
+	pub notes: Vec<Note>,
+	/// Columns which express another column as a percentage, see [DynamicReport::add_percentage_column]
+	#[serde(default, skip_serializing_if = "Vec::is_empty")]
+	pub percentage_columns: Vec<PercentageColumn>,
+	/// Title of a column shown after [DynamicReport::columns] with each row's [Row::reference_quantity] (e.g. figures from a lodged tax return), if any
+	#[serde(default, skip_serializing_if = "Option::is_none")]
+	pub reference_column: Option<String>,
 }
 
 impl DynamicReport {
@@ -38,14 +49,181 @@ impl DynamicReport {
 			title,
 			columns,
 			entries,
+			notes: Vec::new(),
+			percentage_columns: Vec::new(),
+			reference_column: None,
 		}
 	}
 
+	/// Attach the given narrative notes, keyed by [Row] or [Section] id, to the report
+	///
+	/// Notes are numbered in the order the corresponding entries appear in the report, and each entry's `note` is set to its note number.
+	/// Notes for ids which do not appear in the report are ignored.
+	pub fn attach_notes(&mut self, notes: &HashMap<String, String>) {
+		attach_notes_to_entries(&mut self.entries, notes, &mut self.notes);
+	}
+
+	/// Remove all entries from the report where `auto_hide` is enabled and all quantities are zero
+	///
+	/// See [Row::can_auto_hide] and [Section::can_auto_hide_self].
+	pub fn auto_hide(&mut self) {
+		auto_hide_entries(&mut self.entries);
+	}
+
+	/// Replace each [CalculatedRow] with the [Row] returned by its `calculate_fn`
+	///
+	/// Rows are calculated in report order, so each `calculate_fn` sees the results of preceding [CalculatedRow]s. This should be called after all other rows have been populated.
+	pub fn calculate(&mut self) {
+		while let Some(DynamicReportEntry::CalculatedRow(row)) =
+			first_calculated_row(&mut self.entries)
+		{
+			let calculate_fn = row.calculate_fn;
+			let row = calculate_fn(self);
+			*first_calculated_row(&mut self.entries).unwrap() = row.into();
+		}
+	}
+
+	/// Append a column expressing `source_column` as a percentage (in basis points) of the value in that column of the [Row] with id `base_id`
+	///
+	/// Percentages are relative to the magnitude of the base, so a negative base does not invert the sign of each row. If the base is zero or missing, the column is marked [PercentageColumn::base_is_zero] and should be rendered blank.
+	/// This should be called after [DynamicReport::calculate].
+	pub fn add_percentage_column(&mut self, title: String, source_column: usize, base_id: &str) {
+		let base = self
+			.quantity_for_id(base_id)
+			.map(|quantity| quantity[source_column].abs())
+			.unwrap_or(0);
+
+		add_percentage_to_entries(&mut self.entries, source_column, base);
+
+		self.percentage_columns.push(PercentageColumn {
+			column: self.columns.len(),
+			base_id: base_id.to_string(),
+			base_is_zero: base == 0,
+		});
+		self.columns.push(title);
+	}
+
 	/// Serialise the report (as JSON) using serde
 	pub fn to_json(&self) -> String {
 		serde_json::to_string(self).unwrap()
 	}
 
+	/// Serialise the report as CSV, formatting quantities with the given number of decimal places
+	///
+	/// Equivalent to `DynamicReport.toCSV` in the frontend.
+	pub fn to_csv(&self, dps: u32) -> String {
+		let mut csv = String::new();
+
+		// Title
+		csv.push_str(&escape_csv(&self.title));
+		csv.push('\n');
+
+		// Columns
+		for column in self.columns.iter().chain(self.reference_column.iter()) {
+			csv.push(',');
+			csv.push_str(&escape_csv(column));
+		}
+		csv.push('\n');
+
+		// Entries
+		entries_to_csv(
+			&self.entries,
+			dps,
+			&self.percentage_columns,
+			self.reference_column.is_some(),
+			&mut csv,
+		);
+
+		csv
+	}
+
+	/// Render the report as a self-contained HTML `<table>`, formatting quantities with the given number of decimal places
+	///
+	/// Rows are given the CSS classes `heading`, `bordered` and `invisible` according to their flags. Quantities are right-aligned. Invisible rows and sections are included, so that callers can choose whether to show them.
+	pub fn to_html(&self, dps: u32) -> String {
+		let mut html = String::new();
+		html.push_str("<table class=\"dynamic-report\">\n");
+		html.push_str(&format!(
+			"<caption>{}</caption>\n",
+			escape_html(&self.title)
+		));
+
+		// Columns
+		html.push_str("<thead><tr><th></th>");
+		for column in self.columns.iter().chain(self.reference_column.iter()) {
+			html.push_str(&format!(
+				"<th class=\"quantity\" style=\"text-align: right\">{}</th>",
+				escape_html(column)
+			));
+		}
+		html.push_str("</tr></thead>\n");
+
+		// Entries
+		html.push_str("<tbody>\n");
+		entries_to_html(
+			&self.entries,
+			dps,
+			&self.percentage_columns,
+			self.reference_column.is_some(),
+			self.num_columns(),
+			true,
+			&mut html,
+		);
+		html.push_str("</tbody>\n");
+
+		// Notes
+		if !self.notes.is_empty() {
+			html.push_str("<tfoot>\n");
+			for note in self.notes.iter() {
+				html.push_str(&format!(
+					"<tr class=\"note\"><td colspan=\"{}\">{}. {}</td></tr>\n",
+					self.num_columns() + 1,
+					escape_html(&note.reference),
+					escape_html(&note.text)
+				));
+			}
+			html.push_str("</tfoot>\n");
+		}
+
+		html.push_str("</table>\n");
+		html
+	}
+
+	/// Return the number of columns of quantities shown, including any [DynamicReport::reference_column]
+	fn num_columns(&self) -> usize {
+		self.columns.len() + usize::from(self.reference_column.is_some())
+	}
+
+	/// Render the report as a standalone HTML document, suitable for printing or saving as PDF from a browser
+	///
+	/// The report is rendered as for [DynamicReport::to_html], with styles for its CSS classes. Invisible rows and sections are hidden.
+	pub fn to_html_document(&self, dps: u32) -> String {
+		format!(
+			"<!DOCTYPE html>
+<html>
+<head>
+<meta charset=\"utf-8\">
+<title>{}</title>
+<style>
+body {{ font-family: sans-serif; }}
+table.dynamic-report {{ border-collapse: collapse; }}
+table.dynamic-report caption {{ font-size: 1.25em; font-weight: bold; margin-bottom: 0.5em; }}
+table.dynamic-report td, table.dynamic-report th {{ padding: 0.1em 0.5em; }}
+tr.heading {{ font-weight: bold; }}
+tr.bordered td {{ border-top: 1px solid black; }}
+.mismatch {{ color: red; }}
+.invisible {{ display: none; }}
+</style>
+</head>
+<body>
+{}</body>
+</html>
+",
+			escape_html(&self.title),
+			self.to_html(dps)
+		)
+	}
+
 	/// Look up [DynamicReportEntry] by id
 	pub fn by_id(&self, id: &str) -> Option<&DynamicReportEntry> {
 		// Manually iterate over self.entries rather than self.entries()
@@ -69,14 +247,67 @@ impl DynamicReport {
 						}
 					}
 				}
-				DynamicReportEntry::Spacer => (),
+				DynamicReportEntry::CalculatedRow(_) | DynamicReportEntry::Spacer => (),
 			}
 		}
 
 		None
 	}
 
-	// Return the quantities for the [LiteralRow] with the given id
+	/// Return the ids which are shared by more than one [Row] or [Section] in the report, each listed once
+	///
+	/// Since [DynamicReport::by_id] returns the first matching entry, any later entry with a duplicate id cannot be looked up.
+	pub fn validate_ids(&self) -> Vec<String> {
+		let mut seen = HashSet::new();
+		let mut duplicates = Vec::new();
+		find_duplicate_ids(&self.entries, &mut seen, &mut duplicates);
+		duplicates
+	}
+
+	/// Return a new report retaining only the columns at `indices` (in the given order)
+	///
+	/// Since subtotal rows are computed per column, these remain correct for the retained columns.
+	///
+	/// Returns `None` if any index is out of range for the columns of the report, or for the quantities of any [Row].
+	pub fn select_columns(&self, indices: &[usize]) -> Option<DynamicReport> {
+		if indices.iter().any(|i| *i >= self.columns.len()) {
+			return None;
+		}
+
+		Some(DynamicReport {
+			title: self.title.clone(),
+			columns: indices.iter().map(|i| self.columns[*i].clone()).collect(),
+			entries: select_columns_in_entries(&self.entries, indices)?,
+			notes: self.notes.clone(),
+			percentage_columns: self
+				.percentage_columns
+				.iter()
+				.filter_map(|c| {
+					indices
+						.iter()
+						.position(|i| *i == c.column)
+						.map(|column| PercentageColumn {
+							column,
+							..c.clone()
+						})
+				})
+				.collect(),
+			reference_column: self.reference_column.clone(),
+		})
+	}
+
+	/// Iterate over all [Row]s in the report, descending into [Section]s
+	///
+	/// Yields `(depth, row)` pairs, where `depth` is the number of enclosing sections.
+	pub fn iter_rows(&self) -> RowIter<'_> {
+		RowIter {
+			stack: vec![self.entries.iter()],
+		}
+	}
+
+	/// Return the quantities for the [Row] with the given id
+	///
+	/// Panics if the id refers to a [Section]. See also [DynamicReport::subtotal_for_id].
 	pub fn quantity_for_id(&self, id: &str) -> Option<&Vec<QuantityInt>> {
 		if let Some(entry) = self.by_id(id) {
 			if let DynamicReportEntry::Row(row) = entry {
@@ -88,14 +319,562 @@ impl DynamicReport {
 			None
 		}
 	}
+
+	/// Return the quantities for the [Row] with the given id, or the subtotals (as calculated by [Section::subtotal]) for the [Section] with the given id
+	pub fn subtotal_for_id(&self, id: &str) -> Option<Vec<QuantityInt>> {
+		match self.by_id(id)? {
+			DynamicReportEntry::Section(section) => Some(section.subtotal(self)),
+			DynamicReportEntry::Row(row) => Some(row.quantity.clone()),
+			DynamicReportEntry::CalculatedRow(_) | DynamicReportEntry::Spacer => {
+				unreachable!("by_id returns only Sections and Rows")
+			}
+		}
+	}
 }
 
 impl ReportingProduct for DynamicReport {}
 
+/// A [DynamicReport] together with the transactions underlying each account row, so the report can be drilled down without further queries
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct DrilldownReport {
+	pub report: DynamicReport,
+	/// Transactions with a posting to each account shown in the report, keyed by account name
+	pub transactions: BTreeMap<String, Vec<TransactionWithPostings>>,
+}
+
+impl DrilldownReport {
+	/// Initialise a [DrilldownReport] with no transactions attached
+	pub fn new(report: DynamicReport) -> Self {
+		Self {
+			report,
+			transactions: BTreeMap::new(),
+		}
+	}
+
+	/// Attach the given transactions under each account shown in the report which they post to, if `include_account` returns true for that account
+	pub fn attach_transactions<'a, I, F>(&mut self, transactions: I, include_account: F)
+	where
+		I: Iterator<Item = &'a TransactionWithPostings>,
+		F: Fn(&str) -> bool,
+	{
+		let mut accounts = HashSet::new();
+		account_rows(&self.report.entries, &mut accounts);
+
+		for transaction in transactions {
+			// Do not repeat transactions with multiple postings to the same account
+			let mut transaction_accounts = HashSet::new();
+			for posting in transaction.postings.iter() {
+				if accounts.contains(posting.account.as_str())
+					&& include_account(&posting.account)
+					&& transaction_accounts.insert(posting.account.as_str())
+				{
+					self.transactions
+						.entry(posting.account.clone())
+						.or_default()
+						.push(transaction.clone());
+				}
+			}
+		}
+
+		for account_transactions in self.transactions.values_mut() {
+			account_transactions.sort_by_key(|t| t.transaction.dt);
+		}
+	}
+
+	/// Get the transactions underlying the given row of the report
+	///
+	/// `row` is the [Row::id] of a row or [Section::id] of a section, or the name of an account shown in its own row. An account row is traced to that account, a section to all accounts shown within it, and any other row (e.g. a total) to all accounts shown within the same section.
+	///
+	/// Returns `None` if no such row or section exists in the report.
+	pub fn transactions_for_row(&self, row: &str) -> Option<Vec<TransactionWithPostings>> {
+		let accounts = accounts_for_row(&self.report.entries, row)?;
+
+		let mut accounts = accounts.into_iter().collect::<Vec<_>>();
+		accounts.sort_by(|a, b| crate::util::natural_cmp(a, b));
+
+		let mut result = Vec::new();
+		for (i, account) in accounts.iter().enumerate() {
+			for transaction in self.transactions.get(*account).into_iter().flatten() {
+				// Do not repeat transactions already attached to an earlier account
+				if transaction
+					.postings
+					.iter()
+					.any(|p| accounts[..i].contains(&p.account.as_str()))
+				{
+					continue;
+				}
+				result.push(transaction.clone());
+			}
+		}
+
+		result.sort_by_key(|t| t.transaction.dt);
+		Some(result)
+	}
+
+	/// Serialise the report (as JSON) using serde
+	pub fn to_json(&self) -> String {
+		serde_json::to_string(self).unwrap()
+	}
+}
+
+impl ReportingProduct for DrilldownReport {}
+
+/// Collect the text of account rows underlying the row or section with the given id, or the row for the given account, within the given entries
+///
+/// See [DrilldownReport::transactions_for_row].
+fn accounts_for_row<'a>(entries: &'a [DynamicReportEntry], row: &str) -> Option<HashSet<&'a str>> {
+	for entry in entries.iter() {
+		match entry {
+			DynamicReportEntry::Section(section) => {
+				if section.id.as_deref() == Some(row) {
+					let mut accounts = HashSet::new();
+					account_rows(&section.entries, &mut accounts);
+					return Some(accounts);
+				}
+				if let Some(accounts) = accounts_for_row(&section.entries, row) {
+					return Some(accounts);
+				}
+			}
+			DynamicReportEntry::Row(r) => {
+				if r.id.as_deref() == Some(row) {
+					let mut accounts = HashSet::new();
+					account_rows(entries, &mut accounts);
+					return Some(accounts);
+				}
+				if r.id.is_none() && r.text == row {
+					return Some(HashSet::from([r.text.as_str()]));
+				}
+			}
+			DynamicReportEntry::CalculatedRow(_) | DynamicReportEntry::Spacer => (),
+		}
+	}
+	None
+}
+
+/// Collect the text of account rows (rows without an id) within the given entries
+fn account_rows<'a>(entries: &'a [DynamicReportEntry], accounts: &mut HashSet<&'a str>) {
+	for entry in entries.iter() {
+		match entry {
+			DynamicReportEntry::Section(section) => account_rows(&section.entries, accounts),
+			DynamicReportEntry::Row(row) => {
+				if row.id.is_none() {
+					accounts.insert(row.text.as_str());
+				}
+			}
+			DynamicReportEntry::CalculatedRow(_) | DynamicReportEntry::Spacer => (),
+		}
+	}
+}
+
+/// Add ids of entries within the given entries which have already been seen to `duplicates`
+///
+/// See [DynamicReport::validate_ids].
+fn find_duplicate_ids<'a>(
+	entries: &'a [DynamicReportEntry],
+	seen: &mut HashSet<&'a str>,
+	duplicates: &mut Vec<String>,
+) {
+	for entry in entries.iter() {
+		let id = match entry {
+			DynamicReportEntry::Section(section) => {
+				find_duplicate_ids(&section.entries, seen, duplicates);
+				&section.id
+			}
+			DynamicReportEntry::Row(row) => &row.id,
+			DynamicReportEntry::CalculatedRow(_) | DynamicReportEntry::Spacer => continue,
+		};
+
+		if let Some(id) = id {
+			if !seen.insert(id.as_str()) && !duplicates.contains(id) {
+				duplicates.push(id.clone());
+			}
+		}
+	}
+}
+
+/// Narrative note attached to a [DynamicReport]
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct Note {
+	/// Reference by which the note is cited, e.g. "4" for "see note 4"
+	pub reference: String,
+	pub text: String,
+}
+
+fn attach_notes_to_entries(
+	entries: &mut [DynamicReportEntry],
+	notes: &HashMap<String, String>,
+	report_notes: &mut Vec<Note>,
+) {
+	for entry in entries.iter_mut() {
+		let (id, note) = match entry {
+			DynamicReportEntry::Section(section) => (&section.id, &mut section.note),
+			DynamicReportEntry::Row(row) => (&row.id, &mut row.note),
+			DynamicReportEntry::CalculatedRow(_) | DynamicReportEntry::Spacer => continue,
+		};
+
+		if let Some(text) = id.as_ref().and_then(|id| notes.get(id)) {
+			let reference = (report_notes.len() + 1).to_string();
+			*note = Some(reference.clone());
+			report_notes.push(Note {
+				reference,
+				text: text.clone(),
+			});
+		}
+
+		if let DynamicReportEntry::Section(section) = entry {
+			attach_notes_to_entries(&mut section.entries, notes, report_notes);
+		}
+	}
+}
+
+/// Append to each [Row] within the given entries its value in `source_column` as a percentage of `base`
+fn add_percentage_to_entries(
+	entries: &mut [DynamicReportEntry],
+	source_column: usize,
+	base: QuantityInt,
+) {
+	for entry in entries.iter_mut() {
+		match entry {
+			DynamicReportEntry::Section(section) => {
+				add_percentage_to_entries(&mut section.entries, source_column, base);
+			}
+			DynamicReportEntry::Row(row) => {
+				let percentage = match row.unit {
+					RowUnit::Currency => {
+						crate::util::basis_points(row.quantity[source_column], base).unwrap_or(0)
+					}
+					// Percentages of percentages or counts are not meaningful
+					RowUnit::BasisPoints | RowUnit::Count => 0,
+				};
+				row.quantity.push(percentage);
+				if !row.native_amounts.is_empty() {
+					row.native_amounts.push(None);
+				}
+			}
+			DynamicReportEntry::CalculatedRow(_) | DynamicReportEntry::Spacer => (),
+		}
+	}
+}
+
+/// Return the first [DynamicReportEntry::CalculatedRow] within the given entries, descending into [Section]s
+fn first_calculated_row(entries: &mut [DynamicReportEntry]) -> Option<&mut DynamicReportEntry> {
+	for entry in entries.iter_mut() {
+		if matches!(entry, DynamicReportEntry::CalculatedRow(_)) {
+			return Some(entry);
+		}
+		if let DynamicReportEntry::Section(section) = entry {
+			if let Some(calculated_row) = first_calculated_row(&mut section.entries) {
+				return Some(calculated_row);
+			}
+		}
+	}
+	None
+}
+
+/// Remove the entries which can be auto-hidden, descending into [Section]s
+///
+/// A [DynamicReportEntry::Spacer] immediately following a hidden [Section] is also removed.
+fn auto_hide_entries(entries: &mut Vec<DynamicReportEntry>) {
+	let mut hid_section = false;
+	entries.retain_mut(|entry| {
+		let keep = match entry {
+			DynamicReportEntry::Section(section) => {
+				section.auto_hide_children();
+				!section.can_auto_hide_self()
+			}
+			DynamicReportEntry::Row(row) => !row.can_auto_hide(),
+			DynamicReportEntry::CalculatedRow(_) => true,
+			DynamicReportEntry::Spacer => !hid_section,
+		};
+		hid_section = !keep && matches!(entry, DynamicReportEntry::Section(_));
+		keep
+	});
+}
+
+/// Escape the given text as contents of a single CSV field
+fn escape_csv(cell: &str) -> String {
+	if cell.contains('"') {
+		format!("\"{}\"", cell.replace('"', "\"\""))
+	} else if cell.contains([',', '\n', '\r']) {
+		format!("\"{}\"", cell)
+	} else {
+		cell.to_string()
+	}
+}
+
+fn entries_to_csv(
+	entries: &[DynamicReportEntry],
+	dps: u32,
+	percentage_columns: &[PercentageColumn],
+	reference_column: bool,
+	csv: &mut String,
+) {
+	for entry in entries.iter() {
+		match entry {
+			DynamicReportEntry::Section(section) => entries_to_csv(
+				&section.entries,
+				dps,
+				percentage_columns,
+				reference_column,
+				csv,
+			),
+			DynamicReportEntry::Row(row) => {
+				csv.push_str(&escape_csv(&row.text));
+				for (col_idx, quantity) in row.quantity.iter().enumerate() {
+					csv.push(',');
+					match percentage_columns.iter().find(|c| c.column == col_idx) {
+						// Percentage of a zero base is undefined
+						Some(c) if c.base_is_zero => (),
+						Some(_) => csv.push_str(&crate::util::format_basis_points(*quantity)),
+						None => csv.push_str(&match row.unit {
+							RowUnit::Currency => crate::util::serialise_quantity(*quantity, dps),
+							RowUnit::BasisPoints => crate::util::format_basis_points(*quantity),
+							RowUnit::Count => quantity.to_string(),
+						}),
+					}
+				}
+				if reference_column {
+					csv.push(',');
+					if let Some(quantity) = row.reference_quantity {
+						csv.push_str(&crate::util::serialise_quantity(quantity, dps));
+					}
+				}
+				csv.push('\n');
+			}
+			DynamicReportEntry::CalculatedRow(_) => (),
+			DynamicReportEntry::Spacer => csv.push('\n'),
+		}
+	}
+}
+
+/// Escape the given text for inclusion in HTML content or a quoted attribute
+fn escape_html(text: &str) -> String {
+	text.replace('&', "&amp;")
+		.replace('<', "&lt;")
+		.replace('>', "&gt;")
+		.replace('"', "&quot;")
+}
+
+fn entries_to_html(
+	entries: &[DynamicReportEntry],
+	dps: u32,
+	percentage_columns: &[PercentageColumn],
+	reference_column: bool,
+	num_columns: usize,
+	visible: bool,
+	html: &mut String,
+) {
+	for entry in entries.iter() {
+		match entry {
+			DynamicReportEntry::Section(section) => {
+				let visible = visible && section.visible;
+				if let Some(text) = &section.text {
+					html.push_str(&format!(
+						"<tr class=\"section{}\"><th colspan=\"{}\" style=\"text-align: left\">{}{}</th></tr>\n",
+						if visible { "" } else { " invisible" },
+						num_columns + 1,
+						escape_html(text),
+						note_html(&section.note)
+					));
+				}
+				entries_to_html(
+					&section.entries,
+					dps,
+					percentage_columns,
+					reference_column,
+					num_columns,
+					visible,
+					html,
+				);
+			}
+			DynamicReportEntry::Row(row) => {
+				let mut classes = Vec::new();
+				if row.heading {
+					classes.push("heading");
+				}
+				if row.bordered {
+					classes.push("bordered");
+				}
+				if !(visible && row.visible) {
+					classes.push("invisible");
+				}
+				let cell_tag = if row.heading { "th" } else { "td" };
+
+				if classes.is_empty() {
+					html.push_str("<tr>");
+				} else {
+					html.push_str(&format!("<tr class=\"{}\">", classes.join(" ")));
+				}
+
+				// Row label
+				let text = match &row.link {
+					Some(link) => format!(
+						"<a href=\"{}\">{}</a>",
+						escape_html(link),
+						escape_html(&row.text)
+					),
+					None => escape_html(&row.text),
+				};
+				html.push_str(&format!(
+					"<{} class=\"text\" style=\"text-align: left\">{}{}</{}>",
+					cell_tag,
+					text,
+					note_html(&row.note),
+					cell_tag
+				));
+
+				// Quantities
+				for (col_idx, quantity) in row.quantity.iter().enumerate() {
+					let percentage_column = percentage_columns.iter().find(|c| c.column == col_idx);
+					let cell = if *quantity == 0 && !row.heading {
+						String::new()
+					} else {
+						match percentage_column {
+							// Percentage of a zero base is undefined
+							Some(c) if c.base_is_zero => String::new(),
+							Some(_) => crate::util::format_basis_points(*quantity),
+							None => match row.unit {
+								RowUnit::Currency => format_quantity_bracketed(*quantity, dps),
+								RowUnit::BasisPoints => crate::util::format_basis_points(*quantity),
+								RowUnit::Count => quantity.to_string(),
+							},
+						}
+					};
+					html.push_str(&format!(
+						"<{} class=\"quantity\" style=\"text-align: right\">{}</{}>",
+						cell_tag, cell, cell_tag
+					));
+				}
+
+				// Reference figure, flagged if the row does not match it
+				if reference_column {
+					let cell = match row.reference_quantity {
+						Some(quantity) => format_quantity_bracketed(quantity, dps),
+						None => String::new(),
+					};
+					html.push_str(&format!(
+						"<{} class=\"{}\" style=\"text-align: right\">{}</{}>",
+						cell_tag,
+						if row.mismatch {
+							"quantity mismatch"
+						} else {
+							"quantity"
+						},
+						cell,
+						cell_tag
+					));
+				}
+
+				html.push_str("</tr>\n");
+			}
+			DynamicReportEntry::CalculatedRow(_) => (),
+			DynamicReportEntry::Spacer => html.push_str(&format!(
+				"<tr class=\"spacer{}\"><td colspan=\"{}\">&nbsp;</td></tr>\n",
+				if visible { "" } else { " invisible" },
+				num_columns + 1
+			)),
+		}
+	}
+}
+
+/// Render a reference to a [Note] as a superscript, if any
+fn note_html(note: &Option<String>) -> String {
+	match note {
+		Some(reference) => format!("<sup>{}</sup>", escape_html(reference)),
+		None => String::new(),
+	}
+}
+
+/// Format the quantity with thousands separators and the given number of decimal places, with negative numbers in brackets, e.g. "(1,234.50)"
+fn format_quantity_bracketed(quantity: QuantityInt, dps: u32) -> String {
+	let formatted = crate::util::format_quantity(quantity.abs(), dps, "");
+	if quantity < 0 {
+		format!("({})", formatted)
+	} else {
+		formatted
+	}
+}
+
+/// Iterator over the [Row]s of a [DynamicReport], returned by [DynamicReport::iter_rows]
+pub struct RowIter<'a> {
+	stack: Vec<std::slice::Iter<'a, DynamicReportEntry>>,
+}
+
+impl<'a> Iterator for RowIter<'a> {
+	type Item = (usize, &'a Row);
+
+	fn next(&mut self) -> Option<Self::Item> {
+		while let Some(entries) = self.stack.last_mut() {
+			match entries.next() {
+				Some(DynamicReportEntry::Section(section)) => {
+					self.stack.push(section.entries.iter());
+				}
+				Some(DynamicReportEntry::Row(row)) => {
+					return Some((self.stack.len() - 1, row));
+				}
+				Some(DynamicReportEntry::CalculatedRow(_)) | Some(DynamicReportEntry::Spacer) => (),
+				None => {
+					self.stack.pop();
+				}
+			}
+		}
+
+		None
+	}
+}
+
+/// Return a copy of the given entries retaining only the columns at `indices`
+fn select_columns_in_entries(
+	entries: &[DynamicReportEntry],
+	indices: &[usize],
+) -> Option<Vec<DynamicReportEntry>> {
+	entries
+		.iter()
+		.map(|entry| match entry {
+			DynamicReportEntry::Section(section) => Some(
+				Section {
+					text: section.text.clone(),
+					id: section.id.clone(),
+					visible: section.visible,
+					auto_hide: section.auto_hide,
+					entries: select_columns_in_entries(&section.entries, indices)?,
+					note: section.note.clone(),
+				}
+				.into(),
+			),
+			DynamicReportEntry::Row(row) => Some(
+				Row {
+					quantity: indices
+						.iter()
+						.map(|i| row.quantity.get(*i).copied())
+						.collect::<Option<_>>()?,
+					// Native amounts, where present, are given for each column
+					native_amounts: if row.native_amounts.is_empty() {
+						Vec::new()
+					} else {
+						indices
+							.iter()
+							.map(|i| row.native_amounts.get(*i).cloned())
+							.collect::<Option<_>>()?
+					},
+					..row.clone()
+				}
+				.into(),
+			),
+			DynamicReportEntry::CalculatedRow(_) | DynamicReportEntry::Spacer => {
+				Some(entry.clone())
+			}
+		})
+		.collect()
+}
+
 #[derive(Clone, Debug, Deserialize, Serialize)]
 pub enum DynamicReportEntry {
 	Section(Section),
 	Row(Row),
+	/// Must be replaced with a [Row] by [DynamicReport::calculate] before the report is serialised
+	#[serde(skip)]
+	CalculatedRow(CalculatedRow),
 	Spacer,
 }
 
@@ -111,12 +890,24 @@ impl From<Row> for DynamicReportEntry {
 	}
 }
 
+impl From<CalculatedRow> for DynamicReportEntry {
+	fn from(value: CalculatedRow) -> Self {
+		DynamicReportEntry::CalculatedRow(value)
+	}
+}
+
 #[derive(Clone, Debug, Deserialize, Serialize)]
 pub struct Section {
 	pub text: Option<String>,
 	pub id: Option<String>,
 	pub visible: bool,
+	/// If true, the section is removed by [DynamicReport::auto_hide] when it has no nonzero entries
+	#[serde(default)]
+	pub auto_hide: bool,
 	pub entries: Vec<DynamicReportEntry>,
+	/// Reference to a [Note] in [DynamicReport::notes]
+	#[serde(default)]
+	pub note: Option<String>,
 }
 
 impl Section {
@@ -143,14 +934,50 @@ impl Section {
 						}
 					}
 				}
-				DynamicReportEntry::Spacer => (),
+				DynamicReportEntry::CalculatedRow(_) | DynamicReportEntry::Spacer => (),
 			}
 		}
 
 		None
 	}
 
+	/// Replace each [CalculatedRow] in this section with the [Row] returned by its `calculate_fn` for the given report
+	///
+	/// Unlike [DynamicReport::calculate], each `calculate_fn` is called with the report as given, and does not see the results of other [CalculatedRow]s in this section.
+	pub fn calculate(&mut self, report: &DynamicReport) {
+		for entry in self.entries.iter_mut() {
+			match entry {
+				DynamicReportEntry::Section(section) => section.calculate(report),
+				DynamicReportEntry::CalculatedRow(row) => {
+					*entry = (row.calculate_fn)(report).into();
+				}
+				DynamicReportEntry::Row(_) | DynamicReportEntry::Spacer => (),
+			}
+		}
+	}
+
+	/// Remove all child entries which can be auto-hidden
+	pub fn auto_hide_children(&mut self) {
+		auto_hide_entries(&mut self.entries);
+	}
+
+	/// Return true if `auto_hide` is enabled and the section has no nonzero entries
+	///
+	/// Heading rows do not prevent the section from being hidden if their quantities are zero, but are otherwise never hidden.
+	pub fn can_auto_hide_self(&self) -> bool {
+		self.auto_hide
+			&& self.entries.iter().all(|entry| match entry {
+				DynamicReportEntry::Section(section) => section.can_auto_hide_self(),
+				DynamicReportEntry::Row(row) => {
+					row.can_auto_hide() || (row.heading && row.quantity.iter().all(|q| *q == 0))
+				}
+				DynamicReportEntry::CalculatedRow(_) | DynamicReportEntry::Spacer => true,
+			})
+	}
+
 	/// Calculate the subtotals for this [Section]
+	///
+	/// Heading rows (e.g. subtotals of nested sections generated by [entries_for_kind]) are not included.
 	pub fn subtotal(&self, report: &DynamicReport) -> Vec<QuantityInt> {
 		let mut subtotals = vec![0; report.columns.len()];
 		for entry in self.entries.iter() {
@@ -161,11 +988,20 @@ impl Section {
 					}
 				}
 				DynamicReportEntry::Row(row) => {
+					// Percentages cannot be added to amounts
+					if row.unit != RowUnit::Currency {
+						continue;
+					}
+					// Subtotals would otherwise be counted twice
+					if row.heading {
+						continue;
+					}
+					let sign = if row.contra { -1 } else { 1 };
 					for (col_idx, subtotal) in row.quantity.iter().enumerate() {
-						subtotals[col_idx] += subtotal;
+						subtotals[col_idx] += sign * subtotal;
 					}
 				}
-				DynamicReportEntry::Spacer => (),
+				DynamicReportEntry::CalculatedRow(_) | DynamicReportEntry::Spacer => (),
 			}
 		}
 		subtotals
@@ -178,40 +1014,117 @@ pub struct Row {
 	pub quantity: Vec<QuantityInt>,
 	pub id: Option<String>,
 	pub visible: bool,
+	/// If true, the row is removed by [DynamicReport::auto_hide] when all quantities are zero, unless it is a heading row
+	#[serde(default)]
+	pub auto_hide: bool,
 	pub link: Option<String>,
 	pub heading: bool,
 	pub bordered: bool,
+	/// If true, the row is for a contra account whose quantities are shown with the opposite sign to its contribution to the section (e.g. accumulated depreciation shown as a positive amount deducted from assets), so they are subtracted in [Section::subtotal]
+	#[serde(default)]
+	pub contra: bool,
+	/// Reference to a [Note] in [DynamicReport::notes]
+	#[serde(default)]
+	pub note: Option<String>,
+	#[serde(default)]
+	pub unit: RowUnit,
+	/// For a row representing an account held in a single commodity other than the reporting commodity, the amount in that commodity for each column
+	///
+	/// Empty if not applicable. See [annotate_native_amounts].
+	#[serde(default)]
+	pub native_amounts: Vec<Option<NativeAmount>>,
+	/// Figure shown for the row in [DynamicReport::reference_column], or `None` if there is no corresponding reference figure
+	#[serde(default)]
+	pub reference_quantity: Option<QuantityInt>,
+	/// If true, the row's quantity deviates materially from [Row::reference_quantity]
+	#[serde(default)]
+	pub mismatch: bool,
+}
+
+impl Row {
+	/// Return true if `auto_hide` is enabled, the row is not a heading row, and all quantities are zero
+	pub fn can_auto_hide(&self) -> bool {
+		self.auto_hide && !self.heading && self.quantity.iter().all(|q| *q == 0)
+	}
+}
+
+/// Column of a [DynamicReport] expressing another column as a percentage of a base row
+///
+/// See [DynamicReport::add_percentage_column].
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct PercentageColumn {
+	/// Index of the column in [DynamicReport::columns], whose quantities are in basis points
+	pub column: usize,
+	/// Id of the [Row] taken as 100%
+	pub base_id: String,
+	/// True if the base is zero or missing, in which case the percentages are undefined
+	pub base_is_zero: bool,
+}
+
+/// Row whose value is computed from other entries of the report (e.g. by looking up rows with [DynamicReport::by_id])
+///
+/// See [DynamicReport::calculate].
+#[derive(Clone, Debug)]
+pub struct CalculatedRow {
+	pub calculate_fn: fn(report: &DynamicReport) -> Row,
+}
+
+/// Amount in a commodity other than the reporting commodity
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct NativeAmount {
+	pub quantity: QuantityInt,
+	pub commodity: String,
 }
 
+/// Unit in which the quantities of a [Row] are expressed
+#[derive(Clone, Copy, Debug, Default, Deserialize, Eq, PartialEq, Serialize)]
+pub enum RowUnit {
+	/// Amount in the reporting commodity, as a multiple of 10^-dps
+	#[default]
+	Currency,
+	/// Percentage in basis points (hundredths of a percent), e.g. 1234 represents 12.34%
+	///
+	/// Such rows are excluded from [Section::subtotal], and can be formatted with [crate::util::format_basis_points].
+	BasisPoints,
+	/// Number of items (e.g. statement lines), shown as a plain integer
+	///
+	/// Such rows are excluded from [Section::subtotal].
+	Count,
+}
+
+/// Generate a [Row] for each account of the given kind, with the balances in each column
+///
+/// If `group_accounts` is true, accounts are grouped into nested [Section]s according to their `:`-separated components (e.g. `Expenses:Office:Stationery` within `Expenses:Office` within `Expenses`), with a subtotal row at the end of each group.
+///
+/// Quantities of contra accounts (see [is_contra_account]) are inverted relative to `invert`, and the row is marked as [Row::contra], so that subtotals remain correct.
 pub fn entries_for_kind(
-	kind: &str,
+	kind: &AccountKind,
 	invert: bool,
+	group_accounts: bool,
 	balances: &Vec<&HashMap<String, QuantityInt>>,
-	kinds_for_account: &HashMap<String, Vec<String>>,
+	kinds_for_account: &HashMap<String, Vec<AccountKind>>,
+	data_for_account: &HashMap<String, HashMap<String, serde_json::Value>>,
 ) -> Vec<DynamicReportEntry> {
 	// Get accounts of specified kind
 	let mut accounts = kinds_for_account
 		.iter()
-		.filter_map(|(a, k)| {
-			if k.iter().any(|k| k == kind) {
-				Some(a)
-			} else {
-				None
-			}
-		})
+		.filter_map(|(a, k)| if k.contains(kind) { Some(a) } else { None })
 		.collect::<Vec<_>>();
 
-	accounts.sort();
+	accounts.sort_by(|a, b| crate::util::natural_cmp(a, b));
 
-	let mut entries = Vec::new();
+	let mut rows = Vec::new();
 	for account in accounts {
+		let contra = is_contra_account(data_for_account, account, kind);
 		let quantities = balances
 			.iter()
-			.map(|b| b.get(account).unwrap_or(&0) * if invert { -1 } else { 1 })
+			.map(|b| b.get(account).unwrap_or(&0) * if invert != contra { -1 } else { 1 })
 			.collect::<Vec<_>>();
 
-		// Do not show if all quantities are zero
-		if quantities.iter().all(|q| *q == 0) {
+		// Do not show if all quantities are zero, unless the account is marked always visible
+		if quantities.iter().all(|q| *q == 0)
+			&& !kinds_for_account[account].contains(&AccountKind::AlwaysVisible)
+		{
 			continue;
 		}
 
@@ -230,12 +1143,225 @@ pub fn entries_for_kind(
 			quantity: quantities,
 			id: None,
 			visible: true,
+			auto_hide: false,
 			link,
 			heading: false,
 			bordered: false,
+			contra,
+			unit: RowUnit::Currency,
+			native_amounts: Vec::new(),
+			reference_quantity: None,
+			mismatch: false,
+			note: None,
 		};
-		entries.push(entry.into());
+		rows.push(entry);
+	}
+
+	if group_accounts {
+		group_rows_by_account(rows, 0)
+	} else {
+		rows.into_iter().map(|r| r.into()).collect()
+	}
+}
+
+/// Group [Row]s labelled with account names (sorted by [crate::util::natural_cmp]) into nested [Section]s, considering the `:`-separated components of the account name from index `depth` onwards
+fn group_rows_by_account(rows: Vec<Row>, depth: usize) -> Vec<DynamicReportEntry> {
+	let mut entries = Vec::new();
+	let mut rows = rows.into_iter().peekable();
+
+	while let Some(row) = rows.next() {
+		let components = row.text.split(':').collect::<Vec<_>>();
+		if components.len() <= depth + 1 {
+			// No further components, so this is a leaf
+			entries.push(row.into());
+			continue;
+		}
+
+		// Collect all following rows within the same group
+		let prefix = components[..=depth].join(":");
+		let mut group_rows = vec![row];
+		while let Some(next_row) = rows.peek() {
+			if !next_row.text.starts_with(&format!("{}:", prefix)) {
+				break;
+			}
+			group_rows.push(rows.next().unwrap());
+		}
+
+		// Subtract contra accounts, as in Section::subtotal
+		let mut subtotal = vec![0; group_rows[0].quantity.len()];
+		for row in group_rows.iter() {
+			let sign = if row.contra { -1 } else { 1 };
+			for (col_idx, quantity) in row.quantity.iter().enumerate() {
+				subtotal[col_idx] += sign * quantity;
+			}
+		}
+
+		// If the group consists only of contra accounts, show the subtotal with the same sign as its rows
+		let contra = group_rows.iter().all(|row| row.contra);
+		if contra {
+			subtotal
+				.iter_mut()
+				.for_each(|quantity| *quantity = -*quantity);
+		}
+
+		let mut group_entries = group_rows_by_account(group_rows, depth + 1);
+		group_entries.push(
+			Row {
+				text: format!("Total {}", prefix),
+				quantity: subtotal,
+				id: None,
+				visible: true,
+				auto_hide: false,
+				link: None,
+				heading: true,
+				bordered: true,
+				contra,
+				unit: RowUnit::Currency,
+				native_amounts: Vec::new(),
+				reference_quantity: None,
+				mismatch: false,
+				note: None,
+			}
+			.into(),
+		);
+
+		entries.push(
+			Section {
+				text: Some(prefix),
+				id: None,
+				visible: true,
+				auto_hide: false,
+				entries: group_entries,
+				note: None,
+			}
+			.into(),
+		);
 	}
 
 	entries
 }
+
+/// Set [Row::native_amounts] for rows generated by [entries_for_kind] for accounts held in a single commodity other than the reporting commodity
+///
+/// `commodity_balances` gives, for each column, the balance of each account in each commodity (see [BalancesAt::commodity_balances](crate::reporting::types::BalancesAt::commodity_balances)). `invert` should match the argument passed to [entries_for_kind].
+pub fn annotate_native_amounts(
+	entries: &mut [DynamicReportEntry],
+	commodity_balances: &[&HashMap<String, HashMap<String, QuantityInt>>],
+	invert: bool,
+	reporting_commodity: &str,
+) {
+	for entry in entries.iter_mut() {
+		match entry {
+			DynamicReportEntry::Section(section) => {
+				annotate_native_amounts(
+					&mut section.entries,
+					commodity_balances,
+					invert,
+					reporting_commodity,
+				);
+			}
+			DynamicReportEntry::Row(row) => {
+				// Rows generated by entries_for_kind have no id and are labelled with the account name
+				if row.id.is_some() {
+					continue;
+				}
+
+				let native_amounts = commodity_balances
+					.iter()
+					.map(|balances| {
+						// Disregard commodities no longer held
+						balances.get(&row.text).map(|account_balances| {
+							account_balances
+								.iter()
+								.filter(|(_, quantity)| **quantity != 0)
+								.collect::<Vec<_>>()
+						})
+					})
+					.map(|account_balances| match account_balances {
+						Some(account_balances) if account_balances.len() == 1 => {
+							let (commodity, quantity) = account_balances[0];
+							if commodity == reporting_commodity {
+								None
+							} else {
+								Some(NativeAmount {
+									quantity: quantity * if invert != row.contra { -1 } else { 1 },
+									commodity: commodity.clone(),
+								})
+							}
+						}
+						_ => None,
+					})
+					.collect::<Vec<_>>();
+
+				if native_amounts.iter().any(|a| a.is_some()) {
+					row.native_amounts = native_amounts;
+				}
+			}
+			DynamicReportEntry::CalculatedRow(_) | DynamicReportEntry::Spacer => (),
+		}
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn escape_csv_quotes() {
+		assert_eq!(escape_csv("Cash"), "Cash");
+		assert_eq!(escape_csv("Cash, savings"), "\"Cash, savings\"");
+		assert_eq!(escape_csv("Say \"hi\""), "\"Say \"\"hi\"\"\"");
+		assert_eq!(escape_csv("Line 1\nLine 2"), "\"Line 1\nLine 2\"");
+		assert_eq!(escape_csv("Line 1\r\nLine 2"), "\"Line 1\r\nLine 2\"");
+	}
+
+	#[test]
+	fn html_document_escapes_title() {
+		let report = DynamicReport {
+			title: "Profit & loss".to_string(),
+			columns: vec!["2025".to_string()],
+			entries: Vec::new(),
+			notes: Vec::new(),
+			percentage_columns: Vec::new(),
+			reference_column: None,
+		};
+		let html = report.to_html_document(2);
+		assert!(html.starts_with("<!DOCTYPE html>"));
+		assert!(html.contains("<title>Profit &amp; loss</title>"));
+		assert!(html.contains(&report.to_html(2)));
+	}
+
+	#[test]
+	fn csv_reference_column() {
+		let row = |text: &str, reference_quantity| {
+			DynamicReportEntry::Row(Row {
+				text: text.to_string(),
+				quantity: vec![150000],
+				id: None,
+				visible: true,
+				auto_hide: false,
+				link: None,
+				heading: false,
+				bordered: false,
+				contra: false,
+				note: None,
+				unit: RowUnit::Currency,
+				native_amounts: Vec::new(),
+				reference_quantity,
+				mismatch: false,
+			})
+		};
+		let mut report = DynamicReport::new(
+			"Tax summary".to_string(),
+			vec!["2025".to_string()],
+			vec![row("Income", Some(100000)), row("Deductions", None)],
+		);
+		report.reference_column = Some("2024 (lodged)".to_string());
+
+		// Rows without a reference figure have a blank cell, not zero
+		assert_eq!(
+			report.to_csv(2),
+			"Tax summary\n,2025,2024 (lodged)\nIncome,1500.00,1000.00\nDeductions,1500.00,\n"
+		);
+	}
+}
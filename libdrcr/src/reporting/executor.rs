@@ -16,18 +16,107 @@
 	along with this program.  If not, see <https://www.gnu.org/licenses/>.
 */
 
+use std::collections::HashMap;
+use std::fmt::Display;
 use std::sync::Arc;
 
+use chrono::NaiveDate;
 use tokio::{sync::RwLock, task::JoinSet};
+use tokio_util::sync::CancellationToken;
+
+use crate::db::DbError;
+use crate::QuantityInt;
 
 use super::{
 	calculator::{would_be_ready_to_execute, ReportingGraphDependencies},
-	types::{ReportingContext, ReportingProducts, ReportingStep},
+	dynamic_report::DynamicReport,
+	types::{ReportingContext, ReportingProductId, ReportingProducts, ReportingStep},
 };
 
-#[derive(Debug)]
+#[derive(Clone, Debug)]
 pub enum ReportingExecutionError {
-	DependencyNotAvailable { message: String },
+	DependencyNotAvailable {
+		message: String,
+	},
+	/// A step which selects among its dependencies found none able to provide the requested product
+	NoDependencyProvided {
+		message: String,
+	},
+	DbError(DbError),
+	/// Report generation was cancelled via its [CancellationToken]
+	Cancelled,
+	/// Error raised by a plugin, including the Lua traceback where available
+	PluginError {
+		plugin: String,
+		message: String,
+	},
+	/// No price is recorded for the commodity on or before the date
+	MissingCommodityPrice {
+		commodity: String,
+		date: NaiveDate,
+	},
+	/// A transaction's postings do not sum to zero, see [TransactionWithPostings::validate](crate::model::transaction::TransactionWithPostings::validate)
+	UnbalancedTransaction {
+		transaction_id: Option<u64>,
+		imbalance: HashMap<String, QuantityInt>,
+	},
+}
+
+impl Display for ReportingExecutionError {
+	fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+		match self {
+			ReportingExecutionError::DependencyNotAvailable { message }
+			| ReportingExecutionError::NoDependencyProvided { message } => f.write_str(message),
+			ReportingExecutionError::DbError(err) => err.fmt(f),
+			ReportingExecutionError::Cancelled => f.write_str("report generation was cancelled"),
+			ReportingExecutionError::PluginError { plugin, message } => {
+				f.write_fmt(format_args!("error in plugin {}: {}", plugin, message))
+			}
+			ReportingExecutionError::MissingCommodityPrice { commodity, date } => {
+				f.write_fmt(format_args!(
+					"no price is recorded for {} on or before {}",
+					commodity, date
+				))
+			}
+			ReportingExecutionError::UnbalancedTransaction {
+				transaction_id,
+				imbalance,
+			} => {
+				let mut imbalance = imbalance
+					.iter()
+					.map(|(commodity, quantity)| format!("{} {}", quantity, commodity))
+					.collect::<Vec<_>>();
+				imbalance.sort();
+
+				match transaction_id {
+					Some(transaction_id) => f.write_fmt(format_args!(
+						"transaction {} does not balance (imbalance {})",
+						transaction_id,
+						imbalance.join(", ")
+					)),
+					None => f.write_fmt(format_args!(
+						"generated transaction does not balance (imbalance {})",
+						imbalance.join(", ")
+					)),
+				}
+			}
+		}
+	}
+}
+
+impl std::error::Error for ReportingExecutionError {
+	fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+		match self {
+			ReportingExecutionError::DbError(err) => Some(err),
+			_ => None,
+		}
+	}
+}
+
+impl From<DbError> for ReportingExecutionError {
+	fn from(value: DbError) -> Self {
+		ReportingExecutionError::DbError(value)
+	}
 }
 
 async fn execute_step(
@@ -36,21 +125,87 @@ async fn execute_step(
 	dependencies: Arc<ReportingGraphDependencies>,
 	context: Arc<ReportingContext>,
 	products: Arc<RwLock<ReportingProducts>>,
-) -> (usize, Result<ReportingProducts, ReportingExecutionError>) {
+) -> Vec<(usize, Result<ReportingProducts, ReportingExecutionError>)> {
 	let step = &steps[step_idx];
 	let result = step
 		.execute(&*context, &*steps, &*dependencies, &*products)
 		.await;
 
-	(step_idx, result)
+	vec![(step_idx, result)]
 }
 
+/// Execute the given [ReportingStep]s, which share the same [ReportingStep::batch_key], via [ReportingStep::execute_batch]
+async fn execute_step_batch(
+	step_idxs: Vec<usize>,
+	steps: Arc<Vec<Box<dyn ReportingStep>>>,
+	dependencies: Arc<ReportingGraphDependencies>,
+	context: Arc<ReportingContext>,
+	products: Arc<RwLock<ReportingProducts>>,
+) -> Vec<(usize, Result<ReportingProducts, ReportingExecutionError>)> {
+	let batch = step_idxs
+		.iter()
+		.map(|i| steps[*i].as_ref())
+		.collect::<Vec<_>>();
+	let results = batch[0]
+		.execute_batch(&batch, &context, &steps, &dependencies, &products)
+		.await;
+
+	step_idxs.into_iter().zip(results).collect()
+}
+
+/// Execute the given [ReportingStep]s, which must be sorted in dependency order
+///
+/// If `cancellation_token` is cancelled, outstanding tasks are aborted and [ReportingExecutionError::Cancelled] is returned. Note that a plugin step which is executing Lua code cannot be interrupted, and continues to occupy its runtime worker thread, but its result is discarded.
 pub async fn execute_steps(
 	steps: Vec<Box<dyn ReportingStep>>,
 	dependencies: ReportingGraphDependencies,
 	context: Arc<ReportingContext>,
+	cancellation_token: Option<&CancellationToken>,
 ) -> Result<ReportingProducts, ReportingExecutionError> {
-	let products = Arc::new(RwLock::new(ReportingProducts::new()));
+	// If no token is given, use one which is never cancelled
+	let cancellation_token = cancellation_token.cloned().unwrap_or_default();
+
+	let mut products = ReportingProducts::new();
+	let mut steps_done = Vec::new();
+	let mut steps_remaining = (0..steps.len()).collect::<Vec<_>>();
+
+	// Skip any steps whose products are all cached
+	let mut cache_key_generation = None;
+	if let Some(cache) = &context.cache {
+		let cache_key = context.cache_key();
+		let mut cache = cache.lock().unwrap();
+		let generation = cache.generation();
+		let cached_products = cache.products_for_key(&cache_key);
+
+		for (step_idx, step) in steps.iter().enumerate() {
+			let product_ids = step
+				.id()
+				.product_kinds
+				.iter()
+				.map(|kind| ReportingProductId {
+					name: step.id().name,
+					kind: *kind,
+					args: step.id().args,
+				})
+				.collect::<Vec<_>>();
+
+			if product_ids
+				.iter()
+				.all(|p| cached_products.map().contains_key(p))
+			{
+				for product_id in product_ids {
+					let product = cached_products.map()[&product_id].clone();
+					products.insert(product_id, product);
+				}
+				steps_done.push(step_idx);
+				steps_remaining.retain(|i| *i != step_idx);
+			}
+		}
+
+		cache_key_generation = Some((cache_key, generation));
+	}
+
+	let products = Arc::new(RwLock::new(products));
 
 	// Prepare for async
 	let steps = Arc::new(steps);
@@ -58,14 +213,22 @@ pub async fn execute_steps(
 
 	// Execute steps asynchronously
 	let mut handles = JoinSet::new();
-	let mut steps_done = Vec::new();
-	let mut steps_remaining = (0..steps.len()).collect::<Vec<_>>();
 
 	while steps_done.len() != steps.len() {
 		// Execute each step which is ready to run
+		// Steps with a batch key are executed together, e.g. DBBalances steps so that balances at all dates can be retrieved in one query
+		let mut batches: HashMap<String, Vec<usize>> = HashMap::new();
 		for step_idx in steps_remaining.iter().copied().collect::<Vec<_>>() {
 			// Check if ready to run
 			if would_be_ready_to_execute(&steps[step_idx], &steps, &dependencies, &steps_done) {
+				steps_remaining
+					.remove(steps_remaining.iter().position(|i| *i == step_idx).unwrap());
+
+				if let Some(batch_key) = steps[step_idx].batch_key() {
+					batches.entry(batch_key).or_default().push(step_idx);
+					continue;
+				}
+
 				// Spawn new task
 				// Unfortunately the compiler cannot guarantee lifetimes are correct, so we must pass Arc across thread boundaries
 				handles.spawn(execute_step(
@@ -75,46 +238,87 @@ pub async fn execute_steps(
 					Arc::clone(&context),
 					Arc::clone(&products),
 				));
-				steps_remaining
-					.remove(steps_remaining.iter().position(|i| *i == step_idx).unwrap());
 			}
 		}
+		for (_, step_idxs) in batches {
+			handles.spawn(execute_step_batch(
+				step_idxs,
+				Arc::clone(&steps),
+				Arc::clone(&dependencies),
+				Arc::clone(&context),
+				Arc::clone(&products),
+			));
+		}
 
-		// Join next result
-		let (step_idx, result) = handles.join_next().await.unwrap().unwrap();
-		let step = &steps[step_idx];
-		steps_done.push(step_idx);
-
-		let mut new_products = result?;
-
-		// Sanity check the new products
-		for (product_id, _product) in new_products.map().iter() {
-			if product_id.name != step.id().name {
-				panic!(
-					"Unexpected product name {} from step {}",
-					product_id,
-					step.id()
-				);
-			}
-			if !step.id().product_kinds.contains(&product_id.kind) {
-				panic!(
-					"Unexpected product kind {} from step {}",
-					product_id,
-					step.id()
-				);
+		// Join next result, unless cancelled
+		let results = tokio::select! {
+			biased;
+			_ = cancellation_token.cancelled() => {
+				handles.abort_all();
+				return Err(ReportingExecutionError::Cancelled);
 			}
-			if product_id.args != step.id().args {
-				panic!(
-					"Unexpected product args {} from step {}",
-					product_id,
-					step.id()
-				);
+			results = handles.join_next() => results.unwrap().unwrap(),
+		};
+		for (step_idx, result) in results {
+			let step = &steps[step_idx];
+			steps_done.push(step_idx);
+
+			let mut new_products = result?;
+
+			// Sanity check the new products
+			for (product_id, product) in new_products.map().iter() {
+				if product_id.name != step.id().name {
+					panic!(
+						"Unexpected product name {} from step {}",
+						product_id,
+						step.id()
+					);
+				}
+				if !step.id().product_kinds.contains(&product_id.kind) {
+					panic!(
+						"Unexpected product kind {} from step {}",
+						product_id,
+						step.id()
+					);
+				}
+				if product_id.args != step.id().args {
+					panic!(
+						"Unexpected product args {} from step {}",
+						product_id,
+						step.id()
+					);
+				}
+
+				// In debug builds, check that ids are unique within dynamic reports, which is easy to get wrong in plugins
+				if cfg!(debug_assertions) {
+					if let Some(report) = product.downcast_ref::<DynamicReport>() {
+						let duplicates = report.validate_ids();
+						if !duplicates.is_empty() {
+							panic!(
+								"Duplicate ids {:?} in product {} from step {}",
+								duplicates,
+								product_id,
+								step.id()
+							);
+						}
+					}
+				}
 			}
+
+			// Insert the new products
+			products.write().await.append(&mut new_products);
 		}
+	}
+
+	let products = Arc::into_inner(products).unwrap().into_inner();
 
-		// Insert the new products
-		products.write().await.append(&mut new_products);
+	// Update the cache
+	if let (Some(cache), Some((cache_key, generation))) = (&context.cache, &cache_key_generation) {
+		cache
+			.lock()
+			.unwrap()
+			.store(cache_key, *generation, &products);
 	}
 
-	Ok(Arc::into_inner(products).unwrap().into_inner())
+	Ok(products)
 }
@@ -18,10 +18,13 @@
 
 use std::sync::Arc;
 
-use calculator::{steps_for_targets, ReportingCalculationError};
+use tokio_util::sync::CancellationToken;
+
+use calculator::{plan_for_targets, steps_for_targets, ReportingCalculationError, ReportingPlan};
 use executor::{execute_steps, ReportingExecutionError};
 use types::{ReportingContext, ReportingProductId, ReportingProducts};
 
+pub mod api;
 pub mod builders;
 pub mod calculator;
 pub mod dynamic_report;
@@ -33,6 +36,8 @@ pub mod types;
 pub enum ReportingError {
 	ReportingCalculationError(ReportingCalculationError),
 	ReportingExecutionError(ReportingExecutionError),
+	/// No row or section with the given id, or row for the given account, exists in the report (see [api::transactions_for_row])
+	RowNotFound(String),
 }
 
 impl From<ReportingCalculationError> for ReportingError {
@@ -58,7 +63,60 @@ pub async fn generate_report(
 	let (sorted_steps, dependencies) = steps_for_targets(targets, &*context)?;
 
 	// Execute steps
-	let products = execute_steps(sorted_steps, dependencies, context).await?;
+	let products = execute_steps(sorted_steps, dependencies, context, None).await?;
+
+	Ok(products)
+}
+
+/// Calculate the steps required to generate the requested [ReportingProductId]s and then execute them, aborting if `cancellation_token` is cancelled
+///
+/// See [generate_report]. A timeout may be applied by cancelling the token after a delay, e.g. from a task spawned with [tokio::time::sleep].
+pub async fn generate_report_with_cancellation(
+	targets: Vec<ReportingProductId>,
+	context: Arc<ReportingContext>,
+	cancellation_token: &CancellationToken,
+) -> Result<ReportingProducts, ReportingError> {
+	// Solve dependencies
+	let (sorted_steps, dependencies) = steps_for_targets(targets, &context)?;
+
+	// Execute steps
+	let products = execute_steps(
+		sorted_steps,
+		dependencies,
+		context,
+		Some(cancellation_token),
+	)
+	.await?;
+
+	Ok(products)
+}
+
+/// Generate the requested [ReportingProductId]s as for [generate_report], reusing the given [ReportingPlan] where possible
+///
+/// If `plan` is `None`, or is no longer valid (see [ReportingPlan::is_valid_for]), dependencies are resolved afresh and `plan` is replaced with the new [ReportingPlan].
+pub async fn generate_report_with_plan(
+	targets: Vec<ReportingProductId>,
+	context: Arc<ReportingContext>,
+	plan: &mut Option<ReportingPlan>,
+) -> Result<ReportingProducts, ReportingError> {
+	// Reuse the existing plan, if valid
+	let planned_steps = match plan {
+		Some(plan) if plan.is_valid_for(&targets, &context) => plan.steps(&context).ok(),
+		_ => None,
+	};
+
+	let (sorted_steps, dependencies) = match planned_steps {
+		Some(planned_steps) => planned_steps,
+		None => {
+			// Solve dependencies
+			let (sorted_steps, dependencies, new_plan) = plan_for_targets(targets, &context)?;
+			*plan = Some(new_plan);
+			(sorted_steps, dependencies)
+		}
+	};
+
+	// Execute steps
+	let products = execute_steps(sorted_steps, dependencies, context, None).await?;
 
 	Ok(products)
 }
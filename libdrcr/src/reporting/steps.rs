@@ -18,46 +18,270 @@
 
 //! This module contains concrete [ReportingStep] implementations
 
-use std::collections::HashMap;
+use std::collections::{BTreeMap, HashMap, HashSet};
 use std::fmt::Display;
 
 use async_trait::async_trait;
-use chrono::Datelike;
+use chrono::NaiveDate;
 use tokio::sync::RwLock;
 
-use crate::account_config::kinds_for_account;
+use crate::account_config::{data_for_account, kinds_for_account, AccountKind};
+use crate::db::BalancesWithCommodityBalances;
+use crate::model::commodity_price::CommodityPrice;
 use crate::model::transaction::{
-	update_balances_from_transactions, Posting, Transaction, TransactionWithPostings,
+	combine_balances, update_balances_from_transactions, Posting, Transaction,
+	TransactionWithPostings,
 };
 use crate::reporting::types::{BalancesAt, DateStartDateEndArgs, ReportingProductId, Transactions};
-use crate::util::{get_eofy, sofy_from_eofy};
-use crate::{QuantityInt, UNCLASSIFIED_STATEMENT_LINE_CREDITS, UNCLASSIFIED_STATEMENT_LINE_DEBITS};
+use crate::util::{bare_commodity, format_date, format_datetime, natural_cmp};
+use crate::{QuantityInt, OPENING_BALANCES};
 
 use super::calculator::ReportingGraphDependencies;
-use super::dynamic_report::{entries_for_kind, DynamicReport, DynamicReportEntry, Row, Section};
+use super::dynamic_report::{
+	annotate_native_amounts, entries_for_kind, DynamicReport, DynamicReportEntry, Row, RowUnit,
+	Section,
+};
 use super::executor::ReportingExecutionError;
 use super::types::{
-	BalancesBetween, DateArgs, MultipleDateArgs, MultipleDateStartDateEndArgs, ReportingContext,
-	ReportingProductKind, ReportingProducts, ReportingStep, ReportingStepArgs, ReportingStepId,
+	BalancesBetween, DateArgs, DateStartDateEndAccountsArgs, MultipleDateArgs,
+	MultipleDateStartDateEndArgs, ReportingContext, ReportingProductKind, ReportingProducts,
+	ReportingStep, ReportingStepArgs, ReportingStepId,
 };
 
 /// Call [ReportingContext::register_lookup_fn] for all steps provided by this module
 pub fn register_lookup_fns(context: &mut ReportingContext) {
+	AdjustmentEffects::register_lookup_fn(context);
 	AllTransactionsExceptEarningsToEquity::register_lookup_fn(context);
 	AllTransactionsExceptEarningsToEquityBalances::register_lookup_fn(context);
 	AllTransactionsIncludingEarningsToEquity::register_lookup_fn(context);
 	BalanceSheet::register_lookup_fn(context);
+	CashBasisTransactions::register_lookup_fn(context);
 	CombineOrdinaryTransactions::register_lookup_fn(context);
 	CombineOrdinaryTransactionsBalances::register_lookup_fn(context);
+	ConvertToCost::register_lookup_fn(context);
 	CurrentYearEarningsToEquity::register_lookup_fn(context);
 	DBBalances::register_lookup_fn(context);
 	DBTransactions::register_lookup_fn(context);
+	GeneralLedger::register_lookup_fn(context);
+	GeneratedTransactions::register_lookup_fn(context);
 	IncomeStatement::register_lookup_fn(context);
+	OpeningBalances::register_lookup_fn(context);
 	PostUnreconciledStatementLines::register_lookup_fn(context);
+	ReconciliationStatus::register_lookup_fn(context);
 	RetainedEarningsToEquity::register_lookup_fn(context);
+	RevalueForeignCurrency::register_lookup_fn(context);
 	TrialBalance::register_lookup_fn(context);
 }
 
+/// Shows the balances from ordinary transactions, and the net effect of each adjustment step, as at the given date (returns [DynamicReport])
+///
+/// Adjustment steps are [CurrentYearEarningsToEquity], [RetainedEarningsToEquity] and steps provided by plugins which generate transactions (e.g. [CalculateIncomeTax]).
+#[derive(Debug)]
+pub struct AdjustmentEffects {
+	pub args: DateArgs,
+}
+
+impl AdjustmentEffects {
+	fn register_lookup_fn(context: &mut ReportingContext) {
+		context.register_lookup_fn(
+			"AdjustmentEffects".to_string(),
+			vec![ReportingProductKind::DynamicReport],
+			Self::takes_args,
+			Self::from_args,
+		);
+	}
+
+	fn takes_args(_name: &str, args: &ReportingStepArgs, _context: &ReportingContext) -> bool {
+		matches!(args, ReportingStepArgs::DateArgs(_))
+	}
+
+	fn from_args(
+		_name: &str,
+		args: ReportingStepArgs,
+		_context: &ReportingContext,
+	) -> Box<dyn ReportingStep> {
+		Box::new(AdjustmentEffects { args: args.into() })
+	}
+
+	/// Get the [ReportingProductId]s of the [Transactions] for each adjustment step
+	fn adjustment_products(&self, context: &ReportingContext) -> Vec<ReportingProductId> {
+		let mut result = vec![
+			ReportingProductId {
+				name: "CurrentYearEarningsToEquity".to_string(),
+				kind: ReportingProductKind::Transactions,
+				args: ReportingStepArgs::DateArgs(self.args.clone()),
+			},
+			ReportingProductId {
+				name: "RetainedEarningsToEquity".to_string(),
+				kind: ReportingProductKind::Transactions,
+				args: ReportingStepArgs::DateArgs(self.args.clone()),
+			},
+		];
+
+		// Plugin steps currently only take VoidArgs
+		for name in crate::plugin::transaction_step_names(context) {
+			result.push(ReportingProductId {
+				name,
+				kind: ReportingProductKind::Transactions,
+				args: ReportingStepArgs::VoidArgs,
+			});
+		}
+
+		result
+	}
+}
+
+impl Display for AdjustmentEffects {
+	fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+		f.write_fmt(format_args!("{}", self.id()))
+	}
+}
+
+#[async_trait]
+impl ReportingStep for AdjustmentEffects {
+	fn id(&self) -> ReportingStepId {
+		ReportingStepId {
+			name: "AdjustmentEffects".to_string(),
+			product_kinds: vec![ReportingProductKind::DynamicReport],
+			args: ReportingStepArgs::DateArgs(self.args.clone()),
+		}
+	}
+
+	fn requires(&self, context: &ReportingContext) -> Vec<ReportingProductId> {
+		// AdjustmentEffects depends on CombineOrdinaryTransactions and the transactions of each adjustment step
+		let mut result = vec![ReportingProductId {
+			name: "CombineOrdinaryTransactions".to_string(),
+			kind: ReportingProductKind::BalancesAt,
+			args: ReportingStepArgs::DateArgs(self.args.clone()),
+		}];
+		result.extend(self.adjustment_products(context));
+		result
+	}
+
+	async fn execute(
+		&self,
+		context: &ReportingContext,
+		_steps: &Vec<Box<dyn ReportingStep>>,
+		_dependencies: &ReportingGraphDependencies,
+		products: &RwLock<ReportingProducts>,
+	) -> Result<ReportingProducts, ReportingExecutionError> {
+		let products = products.read().await;
+
+		// Get balances from ordinary transactions
+		let ordinary_balances = &products
+			.get_or_err(&ReportingProductId {
+				name: "CombineOrdinaryTransactions".to_string(),
+				kind: ReportingProductKind::BalancesAt,
+				args: ReportingStepArgs::DateArgs(self.args.clone()),
+			})?
+			.downcast_ref::<BalancesAt>()
+			.unwrap()
+			.balances;
+
+		// Get net effect of each adjustment step
+		let adjustment_products = self.adjustment_products(context);
+		let mut adjustment_effects = Vec::new();
+		for product_id in adjustment_products.iter() {
+			let transactions = &products
+				.get_or_err(product_id)?
+				.downcast_ref::<Transactions>()
+				.unwrap()
+				.transactions;
+
+			let mut effects = HashMap::new();
+			update_balances_from_transactions(
+				&mut effects,
+				&mut HashMap::new(),
+				transactions
+					.iter()
+					.filter(|t| self.args.includes_dt(t.transaction.dt)),
+				&context.reporting_commodity,
+			);
+			adjustment_effects.push(effects);
+		}
+
+		// Get sorted list of accounts
+		let mut accounts = ordinary_balances.keys().collect::<Vec<_>>();
+		for effects in adjustment_effects.iter() {
+			accounts.extend(effects.keys());
+		}
+		accounts.sort_by(|a, b| natural_cmp(a, b));
+		accounts.dedup();
+
+		// Init report
+		let mut columns = vec!["Ordinary transactions".to_string()];
+		columns.extend(adjustment_products.iter().map(|p| p.name.clone()));
+		columns.push("Total".to_string());
+
+		let mut report = DynamicReport {
+			title: "Effect of adjustments".to_string(),
+			columns,
+			entries: Vec::new(),
+			notes: Vec::new(),
+			percentage_columns: Vec::new(),
+			reference_column: None,
+		};
+
+		// Add entry for each account
+		let mut section = Section {
+			text: None,
+			id: Some("accounts".to_string()),
+			visible: true,
+			auto_hide: false,
+			entries: Vec::new(),
+			note: None,
+		};
+		for account in accounts {
+			let mut quantity = vec![*ordinary_balances.get(account).unwrap_or(&0)];
+			quantity.extend(
+				adjustment_effects
+					.iter()
+					.map(|effects| *effects.get(account).unwrap_or(&0)),
+			);
+
+			// Do not show if all quantities are zero
+			if quantity.iter().all(|q| *q == 0) {
+				continue;
+			}
+
+			quantity.push(quantity.iter().sum());
+
+			section.entries.push(
+				Row {
+					text: account.clone(),
+					quantity,
+					id: None,
+					visible: true,
+					auto_hide: false,
+					link: Some(format!("/transactions/{}", account)),
+					heading: false,
+					bordered: false,
+					contra: false,
+					unit: RowUnit::Currency,
+					native_amounts: Vec::new(),
+					reference_quantity: None,
+					mismatch: false,
+					note: None,
+				}
+				.into(),
+			);
+		}
+		report.entries.push(section.into());
+
+		// Store result
+		let mut result = ReportingProducts::new();
+		result.insert(
+			ReportingProductId {
+				name: "AdjustmentEffects".to_string(),
+				kind: ReportingProductKind::DynamicReport,
+				args: ReportingStepArgs::DateArgs(self.args.clone()),
+			},
+			Box::new(report),
+		);
+		Ok(result)
+	}
+}
+
 /// Target representing all transactions except charging current year and retained earnings to equity (returns transaction list)
 ///
 /// By default, this is [CombineOrdinaryTransactions] and, if requested, [CalculateIncomeTax].
@@ -204,29 +428,40 @@ impl ReportingStep for AllTransactionsExceptEarningsToEquityBalances {
 		// Get all dependencies
 		let step_dependencies = dependencies.dependencies_for_step(&self.id());
 
-		// Identify the product_kind dependency most recently generated
-		// TODO: Make this deterministic - parallel execution may cause the order to vary
-		for (product_id, product) in products.map().iter().rev() {
-			if step_dependencies.iter().any(|d| d.product == *product_id) {
-				// Store the result
-				let mut result = ReportingProducts::new();
-				result.insert(
-					ReportingProductId {
-						name: self.id().name,
-						kind: self.product_kind,
-						args: self.args.clone(),
-					},
-					product.clone(),
-				);
-				return Ok(result);
-			}
+		// Identify the product_kind dependency which incorporates all the others, i.e. the last in the chain
+		// Do not rely on the order in which products were generated, as this varies with parallel execution
+		// If the dependencies do not form a chain, prefer the one most recently added to the graph
+		let candidates = step_dependencies
+			.iter()
+			.filter(|d| d.product.kind == self.product_kind)
+			.map(|d| &d.product)
+			.collect::<Vec<_>>();
+
+		if let Some(product_id) = candidates.iter().rev().find(|c| {
+			!candidates
+				.iter()
+				.any(|other| other != *c && dependencies.product_depends_on(other, c))
+		}) {
+			// Store the result
+			let mut result = ReportingProducts::new();
+			result.insert(
+				ReportingProductId {
+					name: self.id().name,
+					kind: self.product_kind,
+					args: self.args.clone(),
+				},
+				products.get_or_err(product_id)?.clone(),
+			);
+			return Ok(result);
 		}
 
 		// No dependencies?! - this is likely a mistake
-		panic!(
-			"Requested {:?} but no available dependencies to provide it",
-			self.product_kind
-		);
+		Err(ReportingExecutionError::NoDependencyProvided {
+			message: format!(
+				"Requested {:?} but no available dependencies to provide it",
+				self.product_kind
+			),
+		})
 	}
 }
 
@@ -234,6 +469,8 @@ impl ReportingStep for AllTransactionsExceptEarningsToEquityBalances {
 ///
 /// In other words, this is [AllTransactionsExceptEarningsToEquity], [CurrentYearEarningsToEquity] and [RetainedEarningsToEquity].
 ///
+/// Postings in other commodities with no cost basis are included at their value converted by [ConvertToCost]. Where such a posting is to an income or expense account, its value is charged to current year or retained earnings.
+///
 /// Used as the basis for the balance sheet.
 #[derive(Debug)]
 pub struct AllTransactionsIncludingEarningsToEquity {
@@ -299,12 +536,23 @@ impl ReportingStep for AllTransactionsIncludingEarningsToEquity {
 				kind: ReportingProductKind::Transactions,
 				args: ReportingStepArgs::DateArgs(self.args.clone()),
 			},
+			// AllTransactionsIncludingEarningsToEquity requires AllTransactionsExceptEarningsToEquity and ConvertToCost, to identify converted postings
+			ReportingProductId {
+				name: "AllTransactionsExceptEarningsToEquity".to_string(),
+				kind: ReportingProductKind::Transactions,
+				args: ReportingStepArgs::DateArgs(self.args.clone()),
+			},
+			ReportingProductId {
+				name: "ConvertToCost".to_string(),
+				kind: ReportingProductKind::Transactions,
+				args: ReportingStepArgs::DateArgs(self.args.clone()),
+			},
 		]
 	}
 
 	async fn execute(
 		&self,
-		_context: &ReportingContext,
+		context: &ReportingContext,
 		_steps: &Vec<Box<dyn ReportingStep>>,
 		_dependencies: &ReportingGraphDependencies,
 		products: &RwLock<ReportingProducts>,
@@ -342,18 +590,85 @@ impl ReportingStep for AllTransactionsIncludingEarningsToEquity {
 			.unwrap();
 
 		// Update balances
-		let mut balances = BalancesAt {
-			balances: opening_balances.balances.clone(),
-		};
+		let reporting_commodity = &context.reporting_commodity;
+		let mut balances = opening_balances.clone();
 		update_balances_from_transactions(
 			&mut balances.balances,
+			&mut balances.commodity_balances,
 			transactions_current.transactions.iter(),
+			reporting_commodity,
 		);
 		update_balances_from_transactions(
 			&mut balances.balances,
+			&mut balances.commodity_balances,
 			transactions_retained.transactions.iter(),
+			reporting_commodity,
 		);
 
+		// Get transactions before and after ConvertToCost
+		let transactions_unconverted = products
+			.get_or_err(&ReportingProductId {
+				name: "AllTransactionsExceptEarningsToEquity".to_string(),
+				kind: ReportingProductKind::Transactions,
+				args: ReportingStepArgs::DateArgs(self.args.clone()),
+			})?
+			.downcast_ref::<Transactions>()
+			.unwrap();
+		let transactions_converted = products
+			.get_or_err(&ReportingProductId {
+				name: "ConvertToCost".to_string(),
+				kind: ReportingProductKind::Transactions,
+				args: ReportingStepArgs::DateArgs(self.args.clone()),
+			})?
+			.downcast_ref::<Transactions>()
+			.unwrap();
+
+		// Add the value of postings converted by ConvertToCost, which are omitted from the balances above
+		let kinds_for_account =
+			kinds_for_account(context.db_connection.get_account_configurations().await?);
+		let sofy = context.sofy_from_eofy(context.get_eofy(&self.args.date));
+
+		// ConvertToCost preserves the order of transactions and postings
+		for (transaction_unconverted, transaction) in transactions_unconverted
+			.transactions
+			.iter()
+			.zip(transactions_converted.transactions.iter())
+		{
+			for (posting_unconverted, posting) in transaction_unconverted
+				.postings
+				.iter()
+				.zip(transaction.postings.iter())
+			{
+				let (None, Some(quantity_ascost)) =
+					(posting_unconverted.quantity_ascost, posting.quantity_ascost)
+				else {
+					continue;
+				};
+				if posting.commodity == *reporting_commodity {
+					continue;
+				}
+
+				// Balances in income and expense accounts have already been charged to equity
+				let is_income_or_expense =
+					kinds_for_account
+						.get(&posting.account)
+						.is_some_and(|kinds| {
+							kinds
+								.iter()
+								.any(|k| matches!(k, AccountKind::Income | AccountKind::Expense))
+						});
+				let account = if !is_income_or_expense {
+					posting.account.as_str()
+				} else if transaction.transaction.dt.date() >= sofy {
+					crate::CURRENT_YEAR_EARNINGS
+				} else {
+					crate::RETAINED_EARNINGS
+				};
+
+				*balances.balances.entry(account.to_string()).or_default() += quantity_ascost;
+			}
+		}
+
 		// Store result
 		let mut result = ReportingProducts::new();
 		result.insert(
@@ -395,6 +710,25 @@ impl BalanceSheet {
 	) -> Box<dyn ReportingStep> {
 		Box::new(BalanceSheet { args: args.into() })
 	}
+
+	/// Return the dates to report, including prior year comparatives if [MultipleDateArgs::with_prior_year] is set
+	fn dates(&self, context: &ReportingContext) -> Vec<DateArgs> {
+		let mut dates = self.args.dates.clone();
+		if self.args.with_prior_year {
+			for date_args in self.args.dates.iter() {
+				// Compare with the end of the preceding financial year
+				let prior_date = context.prior_eofy(context.get_eofy(&date_args.date));
+				let prior_args = DateArgs {
+					date: prior_date,
+					time: None,
+				};
+				if !dates.contains(&prior_args) {
+					dates.push(prior_args);
+				}
+			}
+		}
+		dates
+	}
 }
 
 impl Display for BalanceSheet {
@@ -413,15 +747,15 @@ impl ReportingStep for BalanceSheet {
 		}
 	}
 
-	fn requires(&self, _context: &ReportingContext) -> Vec<ReportingProductId> {
+	fn requires(&self, context: &ReportingContext) -> Vec<ReportingProductId> {
 		let mut result = Vec::new();
 
 		// BalanceSheet depends on AllTransactionsIncludingEarningsToEquity in each requested period
-		for date_args in self.args.dates.iter() {
+		for date_args in self.dates(context) {
 			result.push(ReportingProductId {
 				name: "AllTransactionsIncludingEarningsToEquity".to_string(),
 				kind: ReportingProductKind::BalancesAt,
-				args: ReportingStepArgs::DateArgs(date_args.clone()),
+				args: ReportingStepArgs::DateArgs(date_args),
 			});
 		}
 
@@ -438,35 +772,69 @@ impl ReportingStep for BalanceSheet {
 		let products = products.read().await;
 
 		// Get balances for each period
+		let dates = self.dates(context);
 		let mut balances: Vec<&HashMap<String, QuantityInt>> = Vec::new();
-		for date_args in self.args.dates.iter() {
-			let product = products.get_or_err(&ReportingProductId {
-				name: "AllTransactionsIncludingEarningsToEquity".to_string(),
-				kind: ReportingProductKind::BalancesAt,
-				args: ReportingStepArgs::DateArgs(date_args.clone()),
-			})?;
+		let mut commodity_balances = Vec::new();
+		for date_args in dates.iter() {
+			let product = products
+				.get_or_err(&ReportingProductId {
+					name: "AllTransactionsIncludingEarningsToEquity".to_string(),
+					kind: ReportingProductKind::BalancesAt,
+					args: ReportingStepArgs::DateArgs(date_args.clone()),
+				})?
+				.downcast_ref::<BalancesAt>()
+				.unwrap();
 
-			balances.push(&product.downcast_ref::<BalancesAt>().unwrap().balances);
+			balances.push(&product.balances);
+			commodity_balances.push(&product.commodity_balances);
 		}
 
 		// Get names of all balance sheet accounts
-		let kinds_for_account =
-			kinds_for_account(context.db_connection.get_account_configurations().await);
+		let account_configurations = context.db_connection.get_account_configurations().await?;
+		let data_for_account = data_for_account(&account_configurations);
+		let kinds_for_account = kinds_for_account(account_configurations);
 
 		// Init report
 		let mut report = DynamicReport::new(
 			"Balance sheet".to_string(),
-			self.args.dates.iter().map(|d| d.date.to_string()).collect(),
+			dates
+				.iter()
+				.map(|d| context.db_connection.metadata().format_column_date(d.date))
+				.collect(),
 			Vec::new(),
 		);
 
+		// Show balances in each commodity, if native amounts are to be shown
+		let commodity_balances = if context.db_connection.metadata().show_native_amounts {
+			Some(commodity_balances)
+		} else {
+			None
+		};
+
 		// Add assets section
 		let mut assets = Section {
 			text: Some("Assets".to_string()),
 			id: None,
 			visible: true,
-			entries: entries_for_kind("drcr.asset", false, &balances, &kinds_for_account),
+			auto_hide: false,
+			entries: entries_for_kind(
+				&AccountKind::Asset,
+				false,
+				context.db_connection.metadata().group_accounts,
+				&balances,
+				&kinds_for_account,
+				&data_for_account,
+			),
+			note: None,
 		};
+		if let Some(commodity_balances) = &commodity_balances {
+			annotate_native_amounts(
+				&mut assets.entries,
+				commodity_balances,
+				false,
+				&context.reporting_commodity,
+			);
+		}
 		let total_assets = assets.subtotal(&report);
 		assets.entries.push(
 			Row {
@@ -474,9 +842,16 @@ impl ReportingStep for BalanceSheet {
 				quantity: total_assets,
 				id: Some("total_assets".to_string()),
 				visible: true,
+				auto_hide: false,
 				link: None,
 				heading: true,
 				bordered: true,
+				contra: false,
+				unit: RowUnit::Currency,
+				native_amounts: Vec::new(),
+				reference_quantity: None,
+				mismatch: false,
+				note: None,
 			}
 			.into(),
 		);
@@ -488,8 +863,25 @@ impl ReportingStep for BalanceSheet {
 			text: Some("Liabilities".to_string()),
 			id: None,
 			visible: true,
-			entries: entries_for_kind("drcr.liability", true, &balances, &kinds_for_account),
+			auto_hide: false,
+			entries: entries_for_kind(
+				&AccountKind::Liability,
+				true,
+				context.db_connection.metadata().group_accounts,
+				&balances,
+				&kinds_for_account,
+				&data_for_account,
+			),
+			note: None,
 		};
+		if let Some(commodity_balances) = &commodity_balances {
+			annotate_native_amounts(
+				&mut liabilities.entries,
+				commodity_balances,
+				true,
+				&context.reporting_commodity,
+			);
+		}
 		let total_liabilities = liabilities.subtotal(&report);
 		liabilities.entries.push(
 			Row {
@@ -497,9 +889,16 @@ impl ReportingStep for BalanceSheet {
 				quantity: total_liabilities,
 				id: Some("total_liabilities".to_string()),
 				visible: true,
+				auto_hide: false,
 				link: None,
 				heading: true,
 				bordered: true,
+				contra: false,
+				unit: RowUnit::Currency,
+				native_amounts: Vec::new(),
+				reference_quantity: None,
+				mismatch: false,
+				note: None,
 			}
 			.into(),
 		);
@@ -511,8 +910,25 @@ impl ReportingStep for BalanceSheet {
 			text: Some("Equity".to_string()),
 			id: None,
 			visible: true,
-			entries: entries_for_kind("drcr.equity", true, &balances, &kinds_for_account),
+			auto_hide: false,
+			entries: entries_for_kind(
+				&AccountKind::Equity,
+				true,
+				context.db_connection.metadata().group_accounts,
+				&balances,
+				&kinds_for_account,
+				&data_for_account,
+			),
+			note: None,
 		};
+		if let Some(commodity_balances) = &commodity_balances {
+			annotate_native_amounts(
+				&mut equity.entries,
+				commodity_balances,
+				true,
+				&context.reporting_commodity,
+			);
+		}
 		let total_equity = equity.subtotal(&report);
 		equity.entries.push(
 			Row {
@@ -520,9 +936,16 @@ impl ReportingStep for BalanceSheet {
 				quantity: total_equity,
 				id: Some("total_equity".to_string()),
 				visible: true,
+				auto_hide: false,
 				link: None,
 				heading: true,
 				bordered: true,
+				contra: false,
+				unit: RowUnit::Currency,
+				native_amounts: Vec::new(),
+				reference_quantity: None,
+				mismatch: false,
+				note: None,
 			}
 			.into(),
 		);
@@ -542,26 +965,29 @@ impl ReportingStep for BalanceSheet {
 	}
 }
 
-/// Combines all steps producing ordinary transactions (returns transaction list)
+/// Transactions recognised on a cash basis, i.e. [CombineOrdinaryTransactions] which have a posting to an account of kind `drcr.cash`, within the given period (returns transaction list and balances)
 ///
-/// By default, these are [DBTransactions] and [PostUnreconciledStatementLines].
+/// Used in place of [AllTransactionsExceptEarningsToEquity] by the income statement and income tax calculations if the `cash_basis` metadata flag is set. Transactions generated by adjustment steps (e.g. [CalculateIncomeTax]) are accruals, so are not recognised.
 #[derive(Debug)]
-pub struct CombineOrdinaryTransactions {
-	pub args: DateArgs,
+pub struct CashBasisTransactions {
+	pub args: DateStartDateEndArgs,
 }
 
-impl CombineOrdinaryTransactions {
+impl CashBasisTransactions {
 	fn register_lookup_fn(context: &mut ReportingContext) {
 		context.register_lookup_fn(
-			"CombineOrdinaryTransactions".to_string(),
-			vec![ReportingProductKind::Transactions],
+			"CashBasisTransactions".to_string(),
+			vec![
+				ReportingProductKind::Transactions,
+				ReportingProductKind::BalancesBetween,
+			],
 			Self::takes_args,
 			Self::from_args,
 		);
 	}
 
 	fn takes_args(_name: &str, args: &ReportingStepArgs, _context: &ReportingContext) -> bool {
-		matches!(args, ReportingStepArgs::DateArgs(_))
+		matches!(args, ReportingStepArgs::DateStartDateEndArgs(_))
 	}
 
 	fn from_args(
@@ -569,64 +995,213 @@ impl CombineOrdinaryTransactions {
 		args: ReportingStepArgs,
 		_context: &ReportingContext,
 	) -> Box<dyn ReportingStep> {
-		Box::new(CombineOrdinaryTransactions { args: args.into() })
+		Box::new(CashBasisTransactions { args: args.into() })
 	}
 }
 
-impl Display for CombineOrdinaryTransactions {
+impl Display for CashBasisTransactions {
 	fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
 		f.write_fmt(format_args!("{}", self.id()))
 	}
 }
 
 #[async_trait]
-impl ReportingStep for CombineOrdinaryTransactions {
+impl ReportingStep for CashBasisTransactions {
 	fn id(&self) -> ReportingStepId {
 		ReportingStepId {
-			name: "CombineOrdinaryTransactions".to_string(),
-			product_kinds: vec![ReportingProductKind::Transactions],
-			args: ReportingStepArgs::DateArgs(self.args.clone()),
+			name: "CashBasisTransactions".to_string(),
+			product_kinds: vec![
+				ReportingProductKind::Transactions,
+				ReportingProductKind::BalancesBetween,
+			],
+			args: ReportingStepArgs::DateStartDateEndArgs(self.args.clone()),
 		}
 	}
 
 	fn requires(&self, _context: &ReportingContext) -> Vec<ReportingProductId> {
-		vec![
-			// CombineOrdinaryTransactions depends on DBTransactions
-			ReportingProductId {
-				name: "DBTransactions".to_string(),
-				kind: ReportingProductKind::Transactions,
-				args: ReportingStepArgs::VoidArgs,
-			},
-			// CombineOrdinaryTransactions depends on PostUnreconciledStatementLines
-			ReportingProductId {
-				name: "PostUnreconciledStatementLines".to_string(),
-				kind: ReportingProductKind::Transactions,
-				args: ReportingStepArgs::VoidArgs,
-			},
-		]
+		// CashBasisTransactions depends on CombineOrdinaryTransactions at the end of the period
+		// Not AllTransactionsExceptEarningsToEquity, which may depend on CalculateIncomeTax, which may itself depend on CashBasisTransactions
+		vec![ReportingProductId {
+			name: "CombineOrdinaryTransactions".to_string(),
+			kind: ReportingProductKind::Transactions,
+			args: ReportingStepArgs::DateArgs(DateArgs {
+				date: self.args.date_end,
+				time: None,
+			}),
+		}]
 	}
 
 	async fn execute(
 		&self,
-		_context: &ReportingContext,
+		context: &ReportingContext,
 		_steps: &Vec<Box<dyn ReportingStep>>,
-		dependencies: &ReportingGraphDependencies,
+		_dependencies: &ReportingGraphDependencies,
 		products: &RwLock<ReportingProducts>,
 	) -> Result<ReportingProducts, ReportingExecutionError> {
-		combine_transactions_of_all_dependencies(self.id(), dependencies, products).await
-	}
-}
-
-/// Combines all steps producing ordinary transactions (returns balances)
-///
-/// By default, these are [DBBalances] and [PostUnreconciledStatementLines].
-#[derive(Debug)]
-pub struct CombineOrdinaryTransactionsBalances {
-	pub args: DateArgs,
-}
+		let products = products.read().await;
 
-impl CombineOrdinaryTransactionsBalances {
-	fn register_lookup_fn(context: &mut ReportingContext) {
+		// Get all ordinary transactions
+		let all_transactions = &products
+			.get_or_err(&ReportingProductId {
+				name: "CombineOrdinaryTransactions".to_string(),
+				kind: ReportingProductKind::Transactions,
+				args: ReportingStepArgs::DateArgs(DateArgs {
+					date: self.args.date_end,
+					time: None,
+				}),
+			})?
+			.downcast_ref::<Transactions>()
+			.unwrap()
+			.transactions;
+
+		// Get cash accounts
+		let kinds_for_account =
+			kinds_for_account(context.db_connection.get_account_configurations().await?);
+
+		let is_cash_account = |account: &String| {
+			kinds_for_account
+				.get(account)
+				.map(|kinds| kinds.contains(&AccountKind::Cash))
+				.unwrap_or(false)
+		};
+
+		// Recognise only transactions in the period which touch a cash account
+		let transactions = Transactions {
+			transactions: all_transactions
+				.iter()
+				.filter(|t| {
+					t.transaction.dt.date() >= self.args.date_start
+						&& t.transaction.dt.date() <= self.args.date_end
+				})
+				.filter(|t| t.postings.iter().any(|p| is_cash_account(&p.account)))
+				.cloned()
+				.collect(),
+		};
+
+		let mut balances = BalancesBetween {
+			balances: HashMap::new(),
+			commodity_balances: HashMap::new(),
+		};
+		update_balances_from_transactions(
+			&mut balances.balances,
+			&mut balances.commodity_balances,
+			transactions.transactions.iter(),
+			&context.reporting_commodity,
+		);
+
+		// Store result
+		let mut result = ReportingProducts::new();
+		result.insert(
+			ReportingProductId {
+				name: "CashBasisTransactions".to_string(),
+				kind: ReportingProductKind::Transactions,
+				args: ReportingStepArgs::DateStartDateEndArgs(self.args.clone()),
+			},
+			Box::new(transactions),
+		);
+		result.insert(
+			ReportingProductId {
+				name: "CashBasisTransactions".to_string(),
+				kind: ReportingProductKind::BalancesBetween,
+				args: ReportingStepArgs::DateStartDateEndArgs(self.args.clone()),
+			},
+			Box::new(balances),
+		);
+		Ok(result)
+	}
+}
+
+/// Combines all steps producing ordinary transactions (returns transaction list)
+///
+/// By default, these are [DBTransactions], [OpeningBalances] and [PostUnreconciledStatementLines].
+#[derive(Debug)]
+pub struct CombineOrdinaryTransactions {
+	pub args: DateArgs,
+}
+
+impl CombineOrdinaryTransactions {
+	fn register_lookup_fn(context: &mut ReportingContext) {
+		context.register_lookup_fn(
+			"CombineOrdinaryTransactions".to_string(),
+			vec![ReportingProductKind::Transactions],
+			Self::takes_args,
+			Self::from_args,
+		);
+	}
+
+	fn takes_args(_name: &str, args: &ReportingStepArgs, _context: &ReportingContext) -> bool {
+		matches!(args, ReportingStepArgs::DateArgs(_))
+	}
+
+	fn from_args(
+		_name: &str,
+		args: ReportingStepArgs,
+		_context: &ReportingContext,
+	) -> Box<dyn ReportingStep> {
+		Box::new(CombineOrdinaryTransactions { args: args.into() })
+	}
+}
+
+impl Display for CombineOrdinaryTransactions {
+	fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+		f.write_fmt(format_args!("{}", self.id()))
+	}
+}
+
+#[async_trait]
+impl ReportingStep for CombineOrdinaryTransactions {
+	fn id(&self) -> ReportingStepId {
+		ReportingStepId {
+			name: "CombineOrdinaryTransactions".to_string(),
+			product_kinds: vec![ReportingProductKind::Transactions],
+			args: ReportingStepArgs::DateArgs(self.args.clone()),
+		}
+	}
+
+	fn requires(&self, _context: &ReportingContext) -> Vec<ReportingProductId> {
+		vec![
+			// CombineOrdinaryTransactions depends on DBTransactions
+			ReportingProductId {
+				name: "DBTransactions".to_string(),
+				kind: ReportingProductKind::Transactions,
+				args: ReportingStepArgs::VoidArgs,
+			},
+			// CombineOrdinaryTransactions depends on OpeningBalances
+			ReportingProductId {
+				name: "OpeningBalances".to_string(),
+				kind: ReportingProductKind::Transactions,
+				args: ReportingStepArgs::VoidArgs,
+			},
+			// CombineOrdinaryTransactions depends on PostUnreconciledStatementLines
+			ReportingProductId {
+				name: "PostUnreconciledStatementLines".to_string(),
+				kind: ReportingProductKind::Transactions,
+				args: ReportingStepArgs::VoidArgs,
+			},
+		]
+	}
+
+	async fn execute(
+		&self,
+		_context: &ReportingContext,
+		_steps: &Vec<Box<dyn ReportingStep>>,
+		dependencies: &ReportingGraphDependencies,
+		products: &RwLock<ReportingProducts>,
+	) -> Result<ReportingProducts, ReportingExecutionError> {
+		combine_transactions_of_all_dependencies(self.id(), dependencies, products).await
+	}
+}
+
+/// Combines all steps producing ordinary transactions (returns balances)
+///
+/// By default, these are [DBBalances], [OpeningBalances] and [PostUnreconciledStatementLines].
+#[derive(Debug)]
+pub struct CombineOrdinaryTransactionsBalances {
+	pub args: DateArgs,
+}
+
+impl CombineOrdinaryTransactionsBalances {
+	fn register_lookup_fn(context: &mut ReportingContext) {
 		context.register_lookup_fn(
 			"CombineOrdinaryTransactions".to_string(),
 			vec![ReportingProductKind::BalancesAt],
@@ -672,6 +1247,12 @@ impl ReportingStep for CombineOrdinaryTransactionsBalances {
 				kind: ReportingProductKind::BalancesAt,
 				args: ReportingStepArgs::DateArgs(self.args.clone()),
 			},
+			// CombineOrdinaryTransactions depends on OpeningBalances
+			ReportingProductId {
+				name: "OpeningBalances".to_string(),
+				kind: ReportingProductKind::BalancesAt,
+				args: ReportingStepArgs::DateArgs(self.args.clone()),
+			},
 			// CombineOrdinaryTransactions depends on PostUnreconciledStatementLines
 			ReportingProductId {
 				name: "PostUnreconciledStatementLines".to_string(),
@@ -683,7 +1264,7 @@ impl ReportingStep for CombineOrdinaryTransactionsBalances {
 
 	async fn execute(
 		&self,
-		_context: &ReportingContext,
+		context: &ReportingContext,
 		_steps: &Vec<Box<dyn ReportingStep>>,
 		dependencies: &ReportingGraphDependencies,
 		products: &RwLock<ReportingProducts>,
@@ -694,18 +1275,22 @@ impl ReportingStep for CombineOrdinaryTransactionsBalances {
 
 		let mut balances = BalancesAt {
 			balances: HashMap::new(),
+			commodity_balances: HashMap::new(),
 		};
 
 		for dependency in dependencies.dependencies_for_step(&self.id()) {
-			let dependency_balances = &products
+			let dependency_balances = products
 				.get_or_err(&dependency.product)?
 				.downcast_ref::<BalancesAt>()
-				.unwrap()
-				.balances;
-			for (account, balance) in dependency_balances.iter() {
-				let running_balance = balances.balances.get(account).unwrap_or(&0) + balance;
-				balances.balances.insert(account.clone(), running_balance);
-			}
+				.unwrap();
+			combine_balances(
+				&mut balances.balances,
+				&mut balances.commodity_balances,
+				&dependency_balances.balances,
+				&dependency_balances.commodity_balances,
+				1,
+				&context.reporting_commodity,
+			);
 		}
 
 		// Store result
@@ -722,6 +1307,129 @@ impl ReportingStep for CombineOrdinaryTransactionsBalances {
 	}
 }
 
+/// Converts postings in [AllTransactionsExceptEarningsToEquity] into the reporting commodity, for postings without a cost basis (returns transaction list)
+///
+/// Sets [Posting::quantity_ascost] using the most recent price in the `commodity_prices` table on or before the date of the transaction. Transactions after the date (or the [ReportingContext::transactions_cutoff]), or excluded by [ReportingContext::transaction_exclusions], do not contribute to balances as at the date, so are not converted.
+///
+/// Used by [AllTransactionsIncludingEarningsToEquity] to include such postings in the balance sheet.
+#[derive(Debug)]
+pub struct ConvertToCost {
+	pub args: DateArgs,
+}
+
+impl ConvertToCost {
+	fn register_lookup_fn(context: &mut ReportingContext) {
+		context.register_lookup_fn(
+			"ConvertToCost".to_string(),
+			vec![ReportingProductKind::Transactions],
+			Self::takes_args,
+			Self::from_args,
+		);
+	}
+
+	fn takes_args(_name: &str, args: &ReportingStepArgs, _context: &ReportingContext) -> bool {
+		matches!(args, ReportingStepArgs::DateArgs(_))
+	}
+
+	fn from_args(
+		_name: &str,
+		args: ReportingStepArgs,
+		_context: &ReportingContext,
+	) -> Box<dyn ReportingStep> {
+		Box::new(ConvertToCost { args: args.into() })
+	}
+}
+
+impl Display for ConvertToCost {
+	fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+		f.write_fmt(format_args!("{}", self.id()))
+	}
+}
+
+#[async_trait]
+impl ReportingStep for ConvertToCost {
+	fn id(&self) -> ReportingStepId {
+		ReportingStepId {
+			name: "ConvertToCost".to_string(),
+			product_kinds: vec![ReportingProductKind::Transactions],
+			args: ReportingStepArgs::DateArgs(self.args.clone()),
+		}
+	}
+
+	fn requires(&self, _context: &ReportingContext) -> Vec<ReportingProductId> {
+		// ConvertToCost depends on AllTransactionsExceptEarningsToEquity
+		vec![ReportingProductId {
+			name: "AllTransactionsExceptEarningsToEquity".to_string(),
+			kind: ReportingProductKind::Transactions,
+			args: ReportingStepArgs::DateArgs(self.args.clone()),
+		}]
+	}
+
+	async fn execute(
+		&self,
+		context: &ReportingContext,
+		_steps: &Vec<Box<dyn ReportingStep>>,
+		_dependencies: &ReportingGraphDependencies,
+		products: &RwLock<ReportingProducts>,
+	) -> Result<ReportingProducts, ReportingExecutionError> {
+		let products = products.read().await;
+
+		// Get transactions
+		let mut transactions = products
+			.get_or_err(&ReportingProductId {
+				name: "AllTransactionsExceptEarningsToEquity".to_string(),
+				kind: ReportingProductKind::Transactions,
+				args: ReportingStepArgs::DateArgs(self.args.clone()),
+			})?
+			.downcast_ref::<Transactions>()
+			.unwrap()
+			.clone();
+
+		let prices = get_prices_by_commodity(context).await?;
+		let args = context.limit_to_cutoff(&self.args);
+
+		for transaction in transactions.transactions.iter_mut() {
+			if !args.includes_dt(transaction.transaction.dt)
+				|| context.excludes_transaction(
+					transaction
+						.transaction
+						.origin
+						.as_deref()
+						.unwrap_or("DBTransactions"),
+					transaction,
+				) {
+				continue;
+			}
+
+			let date = transaction.transaction.dt.date();
+			for posting in transaction.postings.iter_mut() {
+				if posting.quantity_ascost.is_some() {
+					continue;
+				}
+				if posting.commodity == context.reporting_commodity {
+					posting.quantity_ascost = Some(posting.quantity);
+					continue;
+				}
+
+				let price = price_at(&prices, bare_commodity(&posting.commodity), date)?;
+				posting.quantity_ascost = Some(price.price.convert(posting.quantity));
+			}
+		}
+
+		// Store result
+		let mut result = ReportingProducts::new();
+		result.insert(
+			ReportingProductId {
+				name: self.id().name,
+				kind: ReportingProductKind::Transactions,
+				args: ReportingStepArgs::DateArgs(self.args.clone()),
+			},
+			Box::new(transactions),
+		);
+		Ok(result)
+	}
+}
+
 /// Transfer year-to-date balances in income and expense accounts (as at the requested date) to the current year earnings equity account
 #[derive(Debug)]
 pub struct CurrentYearEarningsToEquity {
@@ -773,7 +1481,7 @@ impl ReportingStep for CurrentYearEarningsToEquity {
 			name: "AllTransactionsExceptEarningsToEquity".to_string(),
 			kind: ReportingProductKind::BalancesBetween,
 			args: ReportingStepArgs::DateStartDateEndArgs(DateStartDateEndArgs {
-				date_start: sofy_from_eofy(get_eofy(&self.args.date, &context.eofy_date)),
+				date_start: context.sofy_from_eofy(context.get_eofy(&self.args.date)),
 				date_end: self.args.date,
 			}),
 		}]
@@ -794,7 +1502,7 @@ impl ReportingStep for CurrentYearEarningsToEquity {
 				name: "AllTransactionsExceptEarningsToEquity".to_string(),
 				kind: ReportingProductKind::BalancesBetween,
 				args: ReportingStepArgs::DateStartDateEndArgs(DateStartDateEndArgs {
-					date_start: sofy_from_eofy(get_eofy(&self.args.date, &context.eofy_date)),
+					date_start: context.sofy_from_eofy(context.get_eofy(&self.args.date)),
 					date_end: self.args.date,
 				}),
 			})?
@@ -803,7 +1511,7 @@ impl ReportingStep for CurrentYearEarningsToEquity {
 
 		// Get income and expense accounts
 		let kinds_for_account =
-			kinds_for_account(context.db_connection.get_account_configurations().await);
+			kinds_for_account(context.db_connection.get_account_configurations().await?);
 
 		// Transfer income and expense balances to current year earnings
 		let mut transactions = Transactions {
@@ -814,13 +1522,14 @@ impl ReportingStep for CurrentYearEarningsToEquity {
 			if let Some(kinds) = kinds_for_account.get(account) {
 				if kinds
 					.iter()
-					.any(|k| k == "drcr.income" || k == "drcr.expense")
+					.any(|k| matches!(k, AccountKind::Income | AccountKind::Expense))
 				{
 					transactions.transactions.push(TransactionWithPostings {
 						transaction: Transaction {
 							id: None,
 							dt: self.args.date.and_hms_opt(0, 0, 0).unwrap(),
 							description: "Current year earnings".to_string(),
+							origin: Some("CurrentYearEarningsToEquity".to_string()),
 						},
 						postings: vec![
 							Posting {
@@ -831,6 +1540,7 @@ impl ReportingStep for CurrentYearEarningsToEquity {
 								quantity: -balance,
 								commodity: context.reporting_commodity.clone(),
 								quantity_ascost: Some(-balance),
+								running_balance: None,
 							},
 							Posting {
 								id: None,
@@ -840,6 +1550,7 @@ impl ReportingStep for CurrentYearEarningsToEquity {
 								quantity: *balance,
 								commodity: context.reporting_commodity.clone(),
 								quantity_ascost: Some(*balance),
+								running_balance: None,
 							},
 						],
 					})
@@ -888,38 +1599,148 @@ impl DBBalances {
 	) -> Box<dyn ReportingStep> {
 		Box::new(DBBalances { args: args.into() })
 	}
-}
 
-impl Display for DBBalances {
-	fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-		f.write_fmt(format_args!("{}", self.id()))
+	/// Execute the given [DBBalances] steps, getting the balances at all dates from the database in a single query
+	///
+	/// Returns the result of each step, in the same order as `steps`.
+	async fn execute_balances_batch(
+		steps: &[&DBBalances],
+		context: &ReportingContext,
+	) -> Vec<Result<ReportingProducts, ReportingExecutionError>> {
+		// Check once for the whole batch, rather than for each step
+		if let Err(err) = Self::check_balanced(context).await {
+			return steps.iter().map(|_| Err(err.clone())).collect();
+		}
+
+		// Balances as at a particular time are looked up individually
+		let dates = steps
+			.iter()
+			.map(|step| context.limit_to_cutoff(&step.args))
+			.filter(|args| args.time.is_none())
+			.map(|args| args.date)
+			.collect::<HashSet<_>>()
+			.into_iter()
+			.collect::<Vec<_>>();
+
+		let balances_at_dates = match context.db_connection.get_balances_at_dates(&dates).await {
+			Ok(balances_at_dates) => balances_at_dates,
+			Err(err) => return steps.iter().map(|_| Err(err.clone().into())).collect(),
+		};
+
+		let mut results = Vec::new();
+		for step in steps {
+			let args = context.limit_to_cutoff(&step.args);
+			let balances = match args.time {
+				Some(_) => None,
+				None => balances_at_dates.get(&args.date).cloned(),
+			};
+			results.push(step.execute_with_balances(context, balances).await);
+		}
+		results
 	}
-}
 
-#[async_trait]
-impl ReportingStep for DBBalances {
-	fn id(&self) -> ReportingStepId {
-		ReportingStepId {
-			name: "DBBalances".to_string(),
-			product_kinds: vec![ReportingProductKind::BalancesAt],
-			args: ReportingStepArgs::DateArgs(self.args.clone()),
+	/// Fail with [ReportingExecutionError::UnbalancedTransaction] if [ReportingContext::reject_unbalanced_transactions] is set and any transaction in the database does not balance
+	///
+	/// Transactions are checked in the database, so that they need not be loaded.
+	async fn check_balanced(context: &ReportingContext) -> Result<(), ReportingExecutionError> {
+		if !context.reject_unbalanced_transactions {
+			return Ok(());
+		}
+
+		let unbalanced = context.db_connection.get_unbalanced_transactions().await?;
+		match unbalanced.into_iter().next() {
+			Some((transaction_id, imbalance)) => {
+				Err(ReportingExecutionError::UnbalancedTransaction {
+					transaction_id: Some(transaction_id),
+					imbalance: HashMap::from([(context.reporting_commodity.clone(), imbalance)]),
+				})
+			}
+			None => Ok(()),
 		}
 	}
 
-	async fn execute(
+	/// Execute the step, using `balances` (the balances and commodity balances) if already retrieved from the database
+	async fn execute_with_balances(
 		&self,
 		context: &ReportingContext,
-		_steps: &Vec<Box<dyn ReportingStep>>,
-		_dependencies: &ReportingGraphDependencies,
-		_products: &RwLock<ReportingProducts>,
+		balances: Option<BalancesWithCommodityBalances>,
 	) -> Result<ReportingProducts, ReportingExecutionError> {
-		// Get balances from DB
-		let balances = BalancesAt {
-			balances: context.db_connection.get_balances(self.args.date).await,
+		// Get balances from DB, excluding transactions after the cutoff if any
+		let args = context.limit_to_cutoff(&self.args);
+		let (balances, commodity_balances) = match (balances, args.time) {
+			(Some(balances), _) => balances,
+			(None, Some(time)) => {
+				let dt = args.date.and_time(time);
+				(
+					context.db_connection.get_balances_at_time(dt).await?,
+					context
+						.db_connection
+						.get_commodity_balances_at_time(dt)
+						.await?,
+				)
+			}
+			(None, None) => (
+				context.db_connection.get_balances(args.date).await?,
+				context
+					.db_connection
+					.get_commodity_balances(args.date)
+					.await?,
+			),
+		};
+		let mut balances = BalancesAt {
+			balances,
+			commodity_balances,
 		};
 
-		// Store result
-		let mut result = ReportingProducts::new();
+		// Omit accounts held only in the reporting commodity
+		let reporting_commodity = &context.reporting_commodity;
+		balances.commodity_balances.retain(|_, account_balances| {
+			account_balances
+				.keys()
+				.any(|commodity| commodity != reporting_commodity)
+		});
+
+		// Reverse the effect of any excluded transactions
+		// Excluded balances are summed in the database, so that transactions need not be loaded
+		if !context.transaction_exclusions.is_empty() {
+			let (mut conditions, mut binds) = match args.time {
+				Some(time) => (
+					vec!["dt <= ?".to_string()],
+					vec![format_datetime(args.date.and_time(time))],
+				),
+				None => (
+					vec!["DATE(dt) <= DATE(?)".to_string()],
+					vec![format_date(args.date)],
+				),
+			};
+			let mut exclusion_conditions = Vec::new();
+			for exclusion in context.transaction_exclusions.iter() {
+				let (condition, exclusion_binds) = exclusion.to_sql_condition("DBTransactions");
+				exclusion_conditions.push(format!("({})", condition));
+				binds.extend(exclusion_binds);
+			}
+			conditions.push(format!("({})", exclusion_conditions.join(" OR ")));
+
+			let (excluded_balances, excluded_commodity_balances) = context
+				.db_connection
+				.get_balances_of_transactions_where(
+					&conditions.join(" AND "),
+					binds,
+					reporting_commodity,
+				)
+				.await?;
+			combine_balances(
+				&mut balances.balances,
+				&mut balances.commodity_balances,
+				&excluded_balances,
+				&excluded_commodity_balances,
+				-1,
+				reporting_commodity,
+			);
+		}
+
+		// Store result
+		let mut result = ReportingProducts::new();
 		result.insert(
 			ReportingProductId {
 				name: self.id().name,
@@ -932,6 +1753,53 @@ impl ReportingStep for DBBalances {
 	}
 }
 
+impl Display for DBBalances {
+	fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+		f.write_fmt(format_args!("{}", self.id()))
+	}
+}
+
+#[async_trait]
+impl ReportingStep for DBBalances {
+	fn id(&self) -> ReportingStepId {
+		ReportingStepId {
+			name: "DBBalances".to_string(),
+			product_kinds: vec![ReportingProductKind::BalancesAt],
+			args: ReportingStepArgs::DateArgs(self.args.clone()),
+		}
+	}
+
+	async fn execute(
+		&self,
+		context: &ReportingContext,
+		_steps: &Vec<Box<dyn ReportingStep>>,
+		_dependencies: &ReportingGraphDependencies,
+		_products: &RwLock<ReportingProducts>,
+	) -> Result<ReportingProducts, ReportingExecutionError> {
+		Self::check_balanced(context).await?;
+		self.execute_with_balances(context, None).await
+	}
+
+	fn batch_key(&self) -> Option<String> {
+		Some("DBBalances".to_string())
+	}
+
+	async fn execute_batch(
+		&self,
+		batch: &[&dyn ReportingStep],
+		context: &ReportingContext,
+		_steps: &Vec<Box<dyn ReportingStep>>,
+		_dependencies: &ReportingGraphDependencies,
+		_products: &RwLock<ReportingProducts>,
+	) -> Vec<Result<ReportingProducts, ReportingExecutionError>> {
+		let batch = batch
+			.iter()
+			.map(|step| step.downcast_ref::<DBBalances>().unwrap()) // Steps with batch key DBBalances are always DBBalances
+			.collect::<Vec<_>>();
+		Self::execute_balances_batch(&batch, context).await
+	}
+}
+
 /// Look up transactions from the database
 #[derive(Debug)]
 pub struct DBTransactions {}
@@ -984,18 +1852,479 @@ impl ReportingStep for DBTransactions {
 	) -> Result<ReportingProducts, ReportingExecutionError> {
 		// Get transactions from DB
 		let transactions = Transactions {
-			transactions: context.db_connection.get_transactions().await,
+			transactions: context.db_connection.get_transactions().await?,
+		};
+
+		if context.reject_unbalanced_transactions {
+			transactions.check_balanced(&context.reporting_commodity)?;
+		}
+
+		// Store result
+		let mut result = ReportingProducts::new();
+		result.insert(
+			ReportingProductId {
+				name: self.id().name,
+				kind: ReportingProductKind::Transactions,
+				args: ReportingStepArgs::VoidArgs,
+			},
+			Box::new(transactions),
+		);
+		Ok(result)
+	}
+}
+
+/// Generates a general ledger [DynamicReport], listing each posting in the period with a running balance per account
+#[derive(Debug)]
+pub struct GeneralLedger {
+	pub args: DateStartDateEndAccountsArgs,
+}
+
+impl GeneralLedger {
+	fn register_lookup_fn(context: &mut ReportingContext) {
+		context.register_lookup_fn(
+			"GeneralLedger".to_string(),
+			vec![ReportingProductKind::DynamicReport],
+			Self::takes_args,
+			Self::from_args,
+		);
+	}
+
+	fn takes_args(_name: &str, args: &ReportingStepArgs, _context: &ReportingContext) -> bool {
+		matches!(args, ReportingStepArgs::DateStartDateEndAccountsArgs(_))
+	}
+
+	fn from_args(
+		_name: &str,
+		args: ReportingStepArgs,
+		_context: &ReportingContext,
+	) -> Box<dyn ReportingStep> {
+		Box::new(GeneralLedger { args: args.into() })
+	}
+}
+
+impl Display for GeneralLedger {
+	fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+		f.write_fmt(format_args!("{}", self.id()))
+	}
+}
+
+#[async_trait]
+impl ReportingStep for GeneralLedger {
+	fn id(&self) -> ReportingStepId {
+		ReportingStepId {
+			name: "GeneralLedger".to_string(),
+			product_kinds: vec![ReportingProductKind::DynamicReport],
+			args: ReportingStepArgs::DateStartDateEndAccountsArgs(self.args.clone()),
+		}
+	}
+
+	fn requires(&self, _context: &ReportingContext) -> Vec<ReportingProductId> {
+		// GeneralLedger requires AllTransactionsExceptEarningsToEquity at the end of the period
+		vec![ReportingProductId {
+			name: "AllTransactionsExceptEarningsToEquity".to_string(),
+			kind: ReportingProductKind::Transactions,
+			args: ReportingStepArgs::DateArgs(DateArgs {
+				date: self.args.date_end,
+				time: None,
+			}),
+		}]
+	}
+
+	async fn execute(
+		&self,
+		context: &ReportingContext,
+		_steps: &Vec<Box<dyn ReportingStep>>,
+		_dependencies: &ReportingGraphDependencies,
+		products: &RwLock<ReportingProducts>,
+	) -> Result<ReportingProducts, ReportingExecutionError> {
+		let products = products.read().await;
+
+		// Get transactions
+		let transactions = &products
+			.get_or_err(&ReportingProductId {
+				name: "AllTransactionsExceptEarningsToEquity".to_string(),
+				kind: ReportingProductKind::Transactions,
+				args: ReportingStepArgs::DateArgs(DateArgs {
+					date: self.args.date_end,
+					time: None,
+				}),
+			})?
+			.downcast_ref::<Transactions>()
+			.unwrap()
+			.transactions;
+
+		// Order transactions as the database does (dt, then id), with generated transactions after database transactions at the same dt
+		// Postings within a transaction are already ordered by id
+		let mut transactions = transactions
+			.iter()
+			.filter(|t| {
+				t.transaction.dt.date() <= self.args.date_end
+					&& !context.excludes_transaction(
+						t.transaction.origin.as_deref().unwrap_or("DBTransactions"),
+						t,
+					)
+			})
+			.collect::<Vec<_>>();
+		transactions.sort_by_key(|t| {
+			(
+				t.transaction.dt,
+				t.transaction.id.is_none(),
+				t.transaction.id,
+			)
+		});
+
+		// Compute opening balances and group postings in the period by account
+		let mut opening_balances: HashMap<&String, QuantityInt> = HashMap::new();
+		let mut postings_by_account: BTreeMap<&String, Vec<(&Transaction, &Posting)>> =
+			BTreeMap::new();
+		for transaction in transactions {
+			for posting in transaction.postings.iter() {
+				if let Some(accounts) = &self.args.accounts {
+					if !accounts.contains(&posting.account) {
+						continue;
+					}
+				}
+
+				if transaction.transaction.dt.date() < self.args.date_start {
+					*opening_balances.entry(&posting.account).or_default() +=
+						posting.quantity_ascost.unwrap_or(posting.quantity);
+				} else {
+					postings_by_account
+						.entry(&posting.account)
+						.or_default()
+						.push((&transaction.transaction, posting));
+				}
+			}
+		}
+
+		// Include accounts with a nonzero opening balance but no postings in the period
+		for (account, balance) in opening_balances.iter() {
+			if *balance != 0 {
+				postings_by_account.entry(account).or_default();
+			}
+		}
+
+		// Init report
+		let mut report = DynamicReport {
+			title: "General ledger".to_string(),
+			columns: vec!["Dr".to_string(), "Cr".to_string(), "Balance".to_string()],
+			entries: Vec::new(),
+			notes: Vec::new(),
+			percentage_columns: Vec::new(),
+			reference_column: None,
+		};
+
+		// Add section for each account
+		for (account, postings) in postings_by_account {
+			let mut section = Section {
+				text: Some(account.clone()),
+				id: Some(account.clone()),
+				visible: true,
+				auto_hide: false,
+				entries: Vec::new(),
+				note: None,
+			};
+
+			let mut balance = opening_balances.get(account).copied().unwrap_or(0);
+			let mut total_dr = 0;
+			let mut total_cr = 0;
+
+			section.entries.push(
+				Row {
+					text: "Opening balance".to_string(),
+					quantity: vec![0, 0, balance],
+					id: None,
+					visible: true,
+					auto_hide: false,
+					link: None,
+					heading: false,
+					bordered: false,
+					contra: false,
+					unit: RowUnit::Currency,
+					native_amounts: Vec::new(),
+					reference_quantity: None,
+					mismatch: false,
+					note: None,
+				}
+				.into(),
+			);
+
+			for (transaction, posting) in postings {
+				let quantity = posting.quantity_ascost.unwrap_or(posting.quantity);
+				balance += quantity;
+				if quantity >= 0 {
+					total_dr += quantity;
+				} else {
+					total_cr -= quantity;
+				}
+
+				section.entries.push(
+					Row {
+						text: format!(
+							"{} {}",
+							transaction.dt.date(),
+							posting
+								.description
+								.as_ref()
+								.unwrap_or(&transaction.description)
+						),
+						quantity: vec![
+							// Dr cell
+							if quantity >= 0 { quantity } else { 0 },
+							// Cr cell
+							if quantity < 0 { -quantity } else { 0 },
+							// Balance cell
+							balance,
+						],
+						id: None,
+						visible: true,
+						auto_hide: false,
+						link: None,
+						heading: false,
+						bordered: false,
+						contra: false,
+						unit: RowUnit::Currency,
+						native_amounts: Vec::new(),
+						reference_quantity: None,
+						mismatch: false,
+						note: None,
+					}
+					.into(),
+				);
+			}
+
+			section.entries.push(
+				Row {
+					text: "Closing balance".to_string(),
+					quantity: vec![total_dr, total_cr, balance],
+					id: None,
+					visible: true,
+					auto_hide: false,
+					link: Some(format!("/transactions/{}", account)),
+					heading: true,
+					bordered: true,
+					contra: false,
+					unit: RowUnit::Currency,
+					native_amounts: Vec::new(),
+					reference_quantity: None,
+					mismatch: false,
+					note: None,
+				}
+				.into(),
+			);
+
+			report.entries.push(section.into());
+		}
+
+		// Store result
+		let mut result = ReportingProducts::new();
+		result.insert(
+			ReportingProductId {
+				name: "GeneralLedger".to_string(),
+				kind: ReportingProductKind::DynamicReport,
+				args: ReportingStepArgs::DateStartDateEndAccountsArgs(self.args.clone()),
+			},
+			Box::new(report),
+		);
+		Ok(result)
+	}
+}
+
+/// Lists all transactions generated by reporting steps (rather than from the database) as at the given date, grouped by originating step (returns [DynamicReport])
+///
+/// Intended to help trace which step produced an unexpected entry.
+#[derive(Debug)]
+pub struct GeneratedTransactions {
+	pub args: DateArgs,
+}
+
+impl GeneratedTransactions {
+	fn register_lookup_fn(context: &mut ReportingContext) {
+		context.register_lookup_fn(
+			"GeneratedTransactions".to_string(),
+			vec![ReportingProductKind::DynamicReport],
+			Self::takes_args,
+			Self::from_args,
+		);
+	}
+
+	fn takes_args(_name: &str, args: &ReportingStepArgs, _context: &ReportingContext) -> bool {
+		matches!(args, ReportingStepArgs::DateArgs(_))
+	}
+
+	fn from_args(
+		_name: &str,
+		args: ReportingStepArgs,
+		_context: &ReportingContext,
+	) -> Box<dyn ReportingStep> {
+		Box::new(GeneratedTransactions { args: args.into() })
+	}
+}
+
+impl Display for GeneratedTransactions {
+	fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+		f.write_fmt(format_args!("{}", self.id()))
+	}
+}
+
+#[async_trait]
+impl ReportingStep for GeneratedTransactions {
+	fn id(&self) -> ReportingStepId {
+		ReportingStepId {
+			name: "GeneratedTransactions".to_string(),
+			product_kinds: vec![ReportingProductKind::DynamicReport],
+			args: ReportingStepArgs::DateArgs(self.args.clone()),
+		}
+	}
+
+	fn requires(&self, _context: &ReportingContext) -> Vec<ReportingProductId> {
+		vec![
+			// GeneratedTransactions requires AllTransactionsExceptEarningsToEquity
+			ReportingProductId {
+				name: "AllTransactionsExceptEarningsToEquity".to_string(),
+				kind: ReportingProductKind::Transactions,
+				args: ReportingStepArgs::DateArgs(self.args.clone()),
+			},
+			// GeneratedTransactions requires CurrentYearEarningsToEquity
+			ReportingProductId {
+				name: "CurrentYearEarningsToEquity".to_string(),
+				kind: ReportingProductKind::Transactions,
+				args: ReportingStepArgs::DateArgs(self.args.clone()),
+			},
+			// GeneratedTransactions requires RetainedEarningsToEquity
+			ReportingProductId {
+				name: "RetainedEarningsToEquity".to_string(),
+				kind: ReportingProductKind::Transactions,
+				args: ReportingStepArgs::DateArgs(self.args.clone()),
+			},
+		]
+	}
+
+	async fn execute(
+		&self,
+		_context: &ReportingContext,
+		_steps: &Vec<Box<dyn ReportingStep>>,
+		_dependencies: &ReportingGraphDependencies,
+		products: &RwLock<ReportingProducts>,
+	) -> Result<ReportingProducts, ReportingExecutionError> {
+		let products = products.read().await;
+
+		// Group generated transactions by origin
+		let mut transactions_by_origin: BTreeMap<&String, Vec<&TransactionWithPostings>> =
+			BTreeMap::new();
+		for name in [
+			"AllTransactionsExceptEarningsToEquity",
+			"CurrentYearEarningsToEquity",
+			"RetainedEarningsToEquity",
+		] {
+			let transactions = &products
+				.get_or_err(&ReportingProductId {
+					name: name.to_string(),
+					kind: ReportingProductKind::Transactions,
+					args: ReportingStepArgs::DateArgs(self.args.clone()),
+				})?
+				.downcast_ref::<Transactions>()
+				.unwrap()
+				.transactions;
+
+			for transaction in transactions.iter() {
+				if transaction.transaction.dt.date() > self.args.date {
+					continue;
+				}
+				if let Some(origin) = &transaction.transaction.origin {
+					transactions_by_origin
+						.entry(origin)
+						.or_default()
+						.push(transaction);
+				}
+			}
+		}
+
+		// Init report
+		let mut report = DynamicReport {
+			title: "Generated transactions".to_string(),
+			columns: vec!["Dr".to_string(), "Cr".to_string()],
+			entries: Vec::new(),
+			notes: Vec::new(),
+			percentage_columns: Vec::new(),
+			reference_column: None,
 		};
 
+		// Add section for each origin, and subsection for each transaction
+		for (origin, transactions) in transactions_by_origin {
+			let mut section = Section {
+				text: Some(origin.clone()),
+				id: Some(origin.clone()),
+				visible: true,
+				auto_hide: false,
+				entries: Vec::new(),
+				note: None,
+			};
+
+			for transaction in transactions {
+				let mut transaction_section = Section {
+					text: Some(format!(
+						"{} {}",
+						transaction.transaction.dt.date(),
+						transaction.transaction.description
+					)),
+					id: None,
+					visible: true,
+					auto_hide: false,
+					entries: Vec::new(),
+					note: None,
+				};
+
+				for posting in transaction.postings.iter() {
+					transaction_section.entries.push(
+						Row {
+							text: posting.account.clone(),
+							quantity: vec![
+								// Dr cell
+								if posting.quantity >= 0 {
+									posting.quantity
+								} else {
+									0
+								},
+								// Cr cell
+								if posting.quantity < 0 {
+									-posting.quantity
+								} else {
+									0
+								},
+							],
+							id: None,
+							visible: true,
+							auto_hide: false,
+							link: Some(format!("/transactions/{}", posting.account)),
+							heading: false,
+							bordered: false,
+							contra: false,
+							unit: RowUnit::Currency,
+							native_amounts: Vec::new(),
+							reference_quantity: None,
+							mismatch: false,
+							note: None,
+						}
+						.into(),
+					);
+				}
+
+				section.entries.push(transaction_section.into());
+			}
+
+			report.entries.push(section.into());
+		}
+
 		// Store result
 		let mut result = ReportingProducts::new();
 		result.insert(
 			ReportingProductId {
-				name: self.id().name,
-				kind: ReportingProductKind::Transactions,
-				args: ReportingStepArgs::VoidArgs,
+				name: "GeneratedTransactions".to_string(),
+				kind: ReportingProductKind::DynamicReport,
+				args: ReportingStepArgs::DateArgs(self.args.clone()),
 			},
-			Box::new(transactions),
+			Box::new(report),
 		);
 		Ok(result)
 	}
@@ -1028,6 +2357,32 @@ impl IncomeStatement {
 	) -> Box<dyn ReportingStep> {
 		Box::new(IncomeStatement { args: args.into() })
 	}
+
+	/// Name of the step providing balances for each period, i.e. [CashBasisTransactions] if the `cash_basis` metadata flag is set, otherwise [AllTransactionsExceptEarningsToEquity]
+	fn balances_step_name(context: &ReportingContext) -> &'static str {
+		if context.db_connection.metadata().cash_basis {
+			"CashBasisTransactions"
+		} else {
+			"AllTransactionsExceptEarningsToEquity"
+		}
+	}
+
+	/// Return true if net surplus is to be checked against the transfer to current year earnings
+	///
+	/// The transfer is computed on an accruals basis, so the check is skipped for a cash basis income statement.
+	fn checks_current_year_earnings(context: &ReportingContext) -> bool {
+		let metadata = context.db_connection.metadata();
+		metadata.check_current_year_earnings && !metadata.cash_basis
+	}
+
+	/// Return true if the period runs from the start of a financial year, i.e. corresponds to the period transferred by [CurrentYearEarningsToEquity]
+	fn is_financial_year_to_date(
+		&self,
+		context: &ReportingContext,
+		date_args: &DateStartDateEndArgs,
+	) -> bool {
+		date_args.date_start == context.sofy_from_eofy(context.get_eofy(&date_args.date_end))
+	}
 }
 
 impl Display for IncomeStatement {
@@ -1046,18 +2401,34 @@ impl ReportingStep for IncomeStatement {
 		}
 	}
 
-	fn requires(&self, _context: &ReportingContext) -> Vec<ReportingProductId> {
+	fn requires(&self, context: &ReportingContext) -> Vec<ReportingProductId> {
 		let mut result = Vec::new();
 
-		// IncomeStatement depends on AllTransactionsExceptEarningsToEquity in each requested period
+		// IncomeStatement depends on AllTransactionsExceptEarningsToEquity (or CashBasisTransactions) in each requested period
 		for date_args in self.args.dates.iter() {
 			result.push(ReportingProductId {
-				name: "AllTransactionsExceptEarningsToEquity".to_string(),
+				name: Self::balances_step_name(context).to_string(),
 				kind: ReportingProductKind::BalancesBetween,
 				args: ReportingStepArgs::DateStartDateEndArgs(date_args.clone()),
 			});
 		}
 
+		// If checking against current year earnings, also depends on CurrentYearEarningsToEquity for each financial year to date period
+		if Self::checks_current_year_earnings(context) {
+			for date_args in self.args.dates.iter() {
+				if self.is_financial_year_to_date(context, date_args) {
+					result.push(ReportingProductId {
+						name: "CurrentYearEarningsToEquity".to_string(),
+						kind: ReportingProductKind::Transactions,
+						args: ReportingStepArgs::DateArgs(DateArgs {
+							date: date_args.date_end,
+							time: None,
+						}),
+					});
+				}
+			}
+		}
+
 		result
 	}
 
@@ -1074,7 +2445,7 @@ impl ReportingStep for IncomeStatement {
 		let mut balances: Vec<&HashMap<String, QuantityInt>> = Vec::new();
 		for date_args in self.args.dates.iter() {
 			let product = products.get_or_err(&ReportingProductId {
-				name: "AllTransactionsExceptEarningsToEquity".to_string(),
+				name: Self::balances_step_name(context).to_string(),
 				kind: ReportingProductKind::BalancesBetween,
 				args: ReportingStepArgs::DateStartDateEndArgs(date_args.clone()),
 			})?;
@@ -1082,27 +2453,57 @@ impl ReportingStep for IncomeStatement {
 			balances.push(&product.downcast_ref::<BalancesBetween>().unwrap().balances);
 		}
 
+		// Sum balances across all periods for the cumulative column, so that all rows are computed consistently with the period columns
+		let cumulative_balances;
+		if self.args.cumulative {
+			let mut summed = HashMap::new();
+			for period_balances in balances.iter() {
+				for (account, quantity) in period_balances.iter() {
+					*summed.entry(account.clone()).or_insert(0) += quantity;
+				}
+			}
+			cumulative_balances = summed;
+			balances.push(&cumulative_balances);
+		}
+
 		// Get names of all income statement accounts
-		let kinds_for_account =
-			kinds_for_account(context.db_connection.get_account_configurations().await);
+		let account_configurations = context.db_connection.get_account_configurations().await?;
+		let data_for_account = data_for_account(&account_configurations);
+		let kinds_for_account = kinds_for_account(account_configurations);
 
 		// Init report
-		let mut report = DynamicReport::new(
-			"Income statement".to_string(),
-			self.args
-				.dates
-				.iter()
-				.map(|d| d.date_end.to_string())
-				.collect(),
-			Vec::new(),
-		);
+		let mut columns = self
+			.args
+			.dates
+			.iter()
+			.map(|d| {
+				context
+					.db_connection
+					.metadata()
+					.format_column_date(d.date_end)
+			})
+			.collect::<Vec<_>>();
+		if self.args.cumulative {
+			columns.push("Cumulative".to_string());
+		}
+
+		let mut report = DynamicReport::new("Income statement".to_string(), columns, Vec::new());
 
 		// Add income section
 		let mut income = Section {
 			text: Some("Income".to_string()),
 			id: None,
 			visible: true,
-			entries: entries_for_kind("drcr.income", true, &balances, &kinds_for_account),
+			auto_hide: false,
+			entries: entries_for_kind(
+				&AccountKind::Income,
+				true,
+				context.db_connection.metadata().group_accounts,
+				&balances,
+				&kinds_for_account,
+				&data_for_account,
+			),
+			note: None,
 		};
 		let total_income = income.subtotal(&report);
 		income.entries.push(
@@ -1111,9 +2512,16 @@ impl ReportingStep for IncomeStatement {
 				quantity: total_income.clone(),
 				id: Some("total_income".to_string()),
 				visible: true,
+				auto_hide: false,
 				link: None,
 				heading: true,
 				bordered: true,
+				contra: false,
+				unit: RowUnit::Currency,
+				native_amounts: Vec::new(),
+				reference_quantity: None,
+				mismatch: false,
+				note: None,
 			}
 			.into(),
 		);
@@ -1125,7 +2533,16 @@ impl ReportingStep for IncomeStatement {
 			text: Some("Expenses".to_string()),
 			id: None,
 			visible: true,
-			entries: entries_for_kind("drcr.expense", false, &balances, &kinds_for_account),
+			auto_hide: false,
+			entries: entries_for_kind(
+				&AccountKind::Expense,
+				false,
+				context.db_connection.metadata().group_accounts,
+				&balances,
+				&kinds_for_account,
+				&data_for_account,
+			),
+			note: None,
 		};
 		let total_expenses = expenses.subtotal(&report);
 		expenses.entries.push(
@@ -1134,9 +2551,16 @@ impl ReportingStep for IncomeStatement {
 				quantity: total_expenses.clone(),
 				id: Some("total_expenses".to_string()),
 				visible: true,
+				auto_hide: false,
 				link: None,
 				heading: true,
 				bordered: true,
+				contra: false,
+				unit: RowUnit::Currency,
+				native_amounts: Vec::new(),
+				reference_quantity: None,
+				mismatch: false,
+				note: None,
 			}
 			.into(),
 		);
@@ -1148,20 +2572,127 @@ impl ReportingStep for IncomeStatement {
 			.into_iter()
 			.zip(total_expenses.into_iter())
 			.map(|(i, e)| i - e)
-			.collect();
+			.collect::<Vec<_>>();
+
+		// Compare with the transfer to current year earnings
+		let mut current_year_earnings_difference = None;
+		if Self::checks_current_year_earnings(context) {
+			let mut difference = vec![0; net_surplus.len()];
+			for (col_idx, date_args) in self.args.dates.iter().enumerate() {
+				if !self.is_financial_year_to_date(context, date_args) {
+					continue;
+				}
+
+				let transactions = products
+					.get_or_err(&ReportingProductId {
+						name: "CurrentYearEarningsToEquity".to_string(),
+						kind: ReportingProductKind::Transactions,
+						args: ReportingStepArgs::DateArgs(DateArgs {
+							date: date_args.date_end,
+							time: None,
+						}),
+					})?
+					.downcast_ref::<Transactions>()
+					.unwrap();
+
+				// Current year earnings is credited with a surplus
+				let transferred = -transactions
+					.transactions
+					.iter()
+					.flat_map(|t| t.postings.iter())
+					.filter(|p| p.account == crate::CURRENT_YEAR_EARNINGS)
+					.map(|p| p.quantity)
+					.sum::<QuantityInt>();
+
+				difference[col_idx] = net_surplus[col_idx] - transferred;
+			}
+
+			if difference.iter().any(|q| *q != 0) {
+				current_year_earnings_difference = Some(difference);
+			}
+		}
+
 		report.entries.push(
 			Row {
 				text: "Net surplus (deficit)".to_string(),
 				quantity: net_surplus,
 				id: Some("net_surplus".to_string()),
 				visible: true,
+				auto_hide: false,
 				link: None,
 				heading: true,
 				bordered: true,
+				contra: false,
+				unit: RowUnit::Currency,
+				native_amounts: Vec::new(),
+				reference_quantity: None,
+				mismatch: false,
+				note: None,
 			}
 			.into(),
 		);
 
+		// Show known rounding differences (see crate::ROUNDING) after net surplus, as they are posted to equity, so are not income or expenses and are not transferred to current year earnings
+		// If the user has configured their own Rounding account as income or expenses, it is already shown in its section
+		let rounding_is_equity = kinds_for_account
+			.get(crate::ROUNDING)
+			.map(|kinds| {
+				kinds.contains(&AccountKind::Equity)
+					&& !kinds.contains(&AccountKind::Income)
+					&& !kinds.contains(&AccountKind::Expense)
+			})
+			.unwrap_or(false);
+		let rounding = balances
+			.iter()
+			.map(|b| -b.get(crate::ROUNDING).unwrap_or(&0))
+			.collect::<Vec<_>>();
+
+		if rounding_is_equity && rounding.iter().any(|q| *q != 0) {
+			report.entries.push(
+				Row {
+					text: "Rounding".to_string(),
+					quantity: rounding,
+					id: Some("rounding".to_string()),
+					visible: true,
+					auto_hide: false,
+					link: None,
+					heading: false,
+					bordered: false,
+					contra: false,
+					unit: RowUnit::Currency,
+					native_amounts: Vec::new(),
+					reference_quantity: None,
+					mismatch: false,
+					note: None,
+				}
+				.into(),
+			);
+		}
+
+		if let Some(difference) = current_year_earnings_difference {
+			// Net surplus should agree with the transfer to current year earnings, unless accounts are misconfigured
+			report.entries.push(DynamicReportEntry::Spacer);
+			report.entries.push(
+				Row {
+					text: "Warning: Difference from current year earnings".to_string(),
+					quantity: difference,
+					id: Some("current_year_earnings_difference".to_string()),
+					visible: true,
+					auto_hide: false,
+					link: None,
+					heading: false,
+					bordered: false,
+					contra: false,
+					unit: RowUnit::Currency,
+					native_amounts: Vec::new(),
+					reference_quantity: None,
+					mismatch: false,
+					note: None,
+				}
+				.into(),
+			);
+		}
+
 		// Store the result
 		let mut result = ReportingProducts::new();
 		result.insert(
@@ -1176,15 +2707,252 @@ impl ReportingStep for IncomeStatement {
 	}
 }
 
-/// Generate transactions for unreconciled statement lines
+/// Generate transactions for opening balances, posted against the [OPENING_BALANCES] equity account
+#[derive(Debug)]
+pub struct OpeningBalances {}
+
+impl OpeningBalances {
+	fn register_lookup_fn(context: &mut ReportingContext) {
+		context.register_lookup_fn(
+			"OpeningBalances".to_string(),
+			vec![ReportingProductKind::Transactions],
+			Self::takes_args,
+			Self::from_args,
+		);
+	}
+
+	fn takes_args(_name: &str, args: &ReportingStepArgs, _context: &ReportingContext) -> bool {
+		*args == ReportingStepArgs::VoidArgs
+	}
+
+	fn from_args(
+		_name: &str,
+		_args: ReportingStepArgs,
+		_context: &ReportingContext,
+	) -> Box<dyn ReportingStep> {
+		Box::new(OpeningBalances {})
+	}
+}
+
+impl Display for OpeningBalances {
+	fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+		f.write_fmt(format_args!("{}", self.id()))
+	}
+}
+
+#[async_trait]
+impl ReportingStep for OpeningBalances {
+	fn id(&self) -> ReportingStepId {
+		ReportingStepId {
+			name: "OpeningBalances".to_string(),
+			product_kinds: vec![ReportingProductKind::Transactions],
+			args: ReportingStepArgs::VoidArgs,
+		}
+	}
+
+	async fn execute(
+		&self,
+		context: &ReportingContext,
+		_steps: &Vec<Box<dyn ReportingStep>>,
+		_dependencies: &ReportingGraphDependencies,
+		_products: &RwLock<ReportingProducts>,
+	) -> Result<ReportingProducts, ReportingExecutionError> {
+		let opening_balances = context.db_connection.get_opening_balances().await?;
+
+		// Post each opening balance against the opening balances equity account
+		let mut transactions = Transactions {
+			transactions: Vec::new(),
+		};
+
+		for opening_balance in opening_balances {
+			transactions.transactions.push(TransactionWithPostings {
+				transaction: Transaction {
+					id: None,
+					dt: opening_balance.dt,
+					description: "Opening balance".to_string(),
+					origin: Some("OpeningBalances".to_string()),
+				},
+				postings: vec![
+					Posting {
+						id: None,
+						transaction_id: None,
+						description: None,
+						account: opening_balance.account.clone(),
+						quantity: opening_balance.quantity,
+						commodity: opening_balance.commodity.clone(),
+						quantity_ascost: None,
+						running_balance: None,
+					},
+					Posting {
+						id: None,
+						transaction_id: None,
+						description: None,
+						account: OPENING_BALANCES.to_string(),
+						quantity: -opening_balance.quantity,
+						commodity: opening_balance.commodity.clone(),
+						quantity_ascost: None,
+						running_balance: None,
+					},
+				],
+			});
+		}
+
+		// Store result
+		let mut result = ReportingProducts::new();
+		result.insert(
+			ReportingProductId {
+				name: self.id().name,
+				kind: ReportingProductKind::Transactions,
+				args: ReportingStepArgs::VoidArgs,
+			},
+			Box::new(transactions),
+		);
+		Ok(result)
+	}
+}
+
+/// Generate transactions for unreconciled statement lines
+///
+/// Statement lines in a foreign commodity are converted to the reporting commodity at the price on the date of the line, and raise [ReportingExecutionError::MissingCommodityPrice] if no price is available.
+#[derive(Debug)]
+pub struct PostUnreconciledStatementLines {}
+
+impl PostUnreconciledStatementLines {
+	fn register_lookup_fn(context: &mut ReportingContext) {
+		context.register_lookup_fn(
+			"PostUnreconciledStatementLines".to_string(),
+			vec![ReportingProductKind::Transactions],
+			Self::takes_args,
+			Self::from_args,
+		);
+	}
+
+	fn takes_args(_name: &str, args: &ReportingStepArgs, _context: &ReportingContext) -> bool {
+		*args == ReportingStepArgs::VoidArgs
+	}
+
+	fn from_args(
+		_name: &str,
+		_args: ReportingStepArgs,
+		_context: &ReportingContext,
+	) -> Box<dyn ReportingStep> {
+		Box::new(PostUnreconciledStatementLines {})
+	}
+}
+
+impl Display for PostUnreconciledStatementLines {
+	fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+		f.write_fmt(format_args!("{}", self.id()))
+	}
+}
+
+#[async_trait]
+impl ReportingStep for PostUnreconciledStatementLines {
+	fn id(&self) -> ReportingStepId {
+		ReportingStepId {
+			name: "PostUnreconciledStatementLines".to_string(),
+			product_kinds: vec![ReportingProductKind::Transactions],
+			args: ReportingStepArgs::VoidArgs,
+		}
+	}
+
+	async fn execute(
+		&self,
+		context: &ReportingContext,
+		_steps: &Vec<Box<dyn ReportingStep>>,
+		_dependencies: &ReportingGraphDependencies,
+		_products: &RwLock<ReportingProducts>,
+	) -> Result<ReportingProducts, ReportingExecutionError> {
+		let unreconciled_statement_lines = context
+			.db_connection
+			.get_unreconciled_statement_lines()
+			.await?;
+
+		// Prices are required only for statement lines in a foreign commodity
+		let prices = if unreconciled_statement_lines
+			.iter()
+			.any(|line| bare_commodity(&line.commodity) != context.reporting_commodity)
+		{
+			get_prices_by_commodity(context).await?
+		} else {
+			HashMap::new()
+		};
+
+		// Post unreconciled statement lines
+		let mut transactions = Transactions {
+			transactions: Vec::new(),
+		};
+
+		for line in unreconciled_statement_lines {
+			let unclassified_account = context
+				.db_connection
+				.metadata()
+				.unclassified_statement_line_account(&line.source_account, line.quantity >= 0);
+
+			// Convert lines in a foreign commodity at cost, as balances otherwise add the foreign quantity to amounts in the reporting commodity
+			let quantity_ascost = if bare_commodity(&line.commodity) == context.reporting_commodity
+			{
+				None
+			} else {
+				let price = price_at(&prices, bare_commodity(&line.commodity), line.dt.date())?;
+				Some(price.price.convert(line.quantity))
+			};
+
+			transactions.transactions.push(TransactionWithPostings {
+				transaction: Transaction {
+					id: None,
+					dt: line.dt,
+					description: line.description.clone(),
+					origin: Some("PostUnreconciledStatementLines".to_string()),
+				},
+				postings: vec![
+					Posting {
+						id: None,
+						transaction_id: None,
+						description: None,
+						account: line.source_account.clone(),
+						quantity: line.quantity,
+						commodity: line.commodity.clone(),
+						quantity_ascost,
+						running_balance: None,
+					},
+					Posting {
+						id: None,
+						transaction_id: None,
+						description: None,
+						account: unclassified_account.to_string(),
+						quantity: -line.quantity,
+						commodity: line.commodity.clone(),
+						quantity_ascost: quantity_ascost.map(|q| -q),
+						running_balance: None,
+					},
+				],
+			});
+		}
+
+		// Store result
+		let mut result = ReportingProducts::new();
+		result.insert(
+			ReportingProductId {
+				name: self.id().name,
+				kind: ReportingProductKind::Transactions,
+				args: ReportingStepArgs::VoidArgs,
+			},
+			Box::new(transactions),
+		);
+		Ok(result)
+	}
+}
+
+/// Shows, for each source account, the number and total of reconciled and unreconciled statement lines (returns [DynamicReport])
 #[derive(Debug)]
-pub struct PostUnreconciledStatementLines {}
+pub struct ReconciliationStatus {}
 
-impl PostUnreconciledStatementLines {
+impl ReconciliationStatus {
 	fn register_lookup_fn(context: &mut ReportingContext) {
 		context.register_lookup_fn(
-			"PostUnreconciledStatementLines".to_string(),
-			vec![ReportingProductKind::Transactions],
+			"ReconciliationStatus".to_string(),
+			vec![ReportingProductKind::DynamicReport],
 			Self::takes_args,
 			Self::from_args,
 		);
@@ -1199,22 +2967,22 @@ impl PostUnreconciledStatementLines {
 		_args: ReportingStepArgs,
 		_context: &ReportingContext,
 	) -> Box<dyn ReportingStep> {
-		Box::new(PostUnreconciledStatementLines {})
+		Box::new(ReconciliationStatus {})
 	}
 }
 
-impl Display for PostUnreconciledStatementLines {
+impl Display for ReconciliationStatus {
 	fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
 		f.write_fmt(format_args!("{}", self.id()))
 	}
 }
 
 #[async_trait]
-impl ReportingStep for PostUnreconciledStatementLines {
+impl ReportingStep for ReconciliationStatus {
 	fn id(&self) -> ReportingStepId {
 		ReportingStepId {
-			name: "PostUnreconciledStatementLines".to_string(),
-			product_kinds: vec![ReportingProductKind::Transactions],
+			name: "ReconciliationStatus".to_string(),
+			product_kinds: vec![ReportingProductKind::DynamicReport],
 			args: ReportingStepArgs::VoidArgs,
 		}
 	}
@@ -1226,60 +2994,146 @@ impl ReportingStep for PostUnreconciledStatementLines {
 		_dependencies: &ReportingGraphDependencies,
 		_products: &RwLock<ReportingProducts>,
 	) -> Result<ReportingProducts, ReportingExecutionError> {
-		let unreconciled_statement_lines = context
-			.db_connection
-			.get_unreconciled_statement_lines()
-			.await;
+		let statuses = context.db_connection.get_reconciliation_status().await?;
 
-		// Post unreconciled statement lines
-		let mut transactions = Transactions {
-			transactions: Vec::new(),
+		// Init report
+		let mut report = DynamicReport::new(
+			"Reconciliation status".to_string(),
+			vec![
+				"Reconciled".to_string(),
+				"Unreconciled".to_string(),
+				"Total".to_string(),
+			],
+			Vec::new(),
+		);
+
+		// Add row for each source account, in each section
+		let mut counts = Section {
+			text: Some("Statement lines".to_string()),
+			id: Some("statement_lines".to_string()),
+			visible: true,
+			auto_hide: false,
+			entries: Vec::new(),
+			note: None,
+		};
+		let mut amounts = Section {
+			text: Some("Amounts".to_string()),
+			id: Some("amounts".to_string()),
+			visible: true,
+			auto_hide: false,
+			entries: Vec::new(),
+			note: None,
 		};
+		let mut total_counts = vec![0; 3];
+
+		for status in statuses {
+			let account_counts = vec![
+				status.reconciled_count,
+				status.unreconciled_count,
+				status.reconciled_count + status.unreconciled_count,
+			];
+			for (col_idx, count) in account_counts.iter().enumerate() {
+				total_counts[col_idx] += count;
+			}
 
-		for line in unreconciled_statement_lines {
-			let unclassified_account = if line.quantity >= 0 {
-				UNCLASSIFIED_STATEMENT_LINE_DEBITS
-			} else {
-				UNCLASSIFIED_STATEMENT_LINE_CREDITS
-			};
-			transactions.transactions.push(TransactionWithPostings {
-				transaction: Transaction {
+			counts.entries.push(
+				Row {
+					text: status.source_account.clone(),
+					quantity: account_counts,
 					id: None,
-					dt: line.dt,
-					description: line.description.clone(),
-				},
-				postings: vec![
-					Posting {
-						id: None,
-						transaction_id: None,
-						description: None,
-						account: line.source_account.clone(),
-						quantity: line.quantity,
-						commodity: line.commodity.clone(),
-						quantity_ascost: None,
-					},
-					Posting {
-						id: None,
-						transaction_id: None,
-						description: None,
-						account: unclassified_account.to_string(),
-						quantity: -line.quantity,
-						commodity: line.commodity.clone(),
-						quantity_ascost: None,
-					},
-				],
-			});
+					visible: true,
+					auto_hide: false,
+					link: None,
+					heading: false,
+					bordered: false,
+					contra: false,
+					unit: RowUnit::Count,
+					native_amounts: Vec::new(),
+					reference_quantity: None,
+					mismatch: false,
+					note: None,
+				}
+				.into(),
+			);
+			amounts.entries.push(
+				Row {
+					text: status.source_account,
+					quantity: vec![
+						status.reconciled_quantity,
+						status.unreconciled_quantity,
+						status.reconciled_quantity + status.unreconciled_quantity,
+					],
+					id: None,
+					visible: true,
+					auto_hide: false,
+					link: None,
+					heading: false,
+					bordered: false,
+					contra: false,
+					unit: RowUnit::Currency,
+					native_amounts: Vec::new(),
+					reference_quantity: None,
+					mismatch: false,
+					note: None,
+				}
+				.into(),
+			);
 		}
 
+		// Counts are excluded from Section::subtotal, so are totalled above
+		counts.entries.push(
+			Row {
+				text: "Total statement lines".to_string(),
+				quantity: total_counts,
+				id: Some("total_statement_lines".to_string()),
+				visible: true,
+				auto_hide: false,
+				link: None,
+				heading: true,
+				bordered: true,
+				contra: false,
+				unit: RowUnit::Count,
+				native_amounts: Vec::new(),
+				reference_quantity: None,
+				mismatch: false,
+				note: None,
+			}
+			.into(),
+		);
+		report.entries.push(counts.into());
+		report.entries.push(DynamicReportEntry::Spacer);
+
+		let total_amounts = amounts.subtotal(&report);
+		amounts.entries.push(
+			Row {
+				text: "Total amounts".to_string(),
+				quantity: total_amounts,
+				id: Some("total_amounts".to_string()),
+				visible: true,
+				auto_hide: false,
+				link: None,
+				heading: true,
+				bordered: true,
+				contra: false,
+				unit: RowUnit::Currency,
+				native_amounts: Vec::new(),
+				reference_quantity: None,
+				mismatch: false,
+				note: None,
+			}
+			.into(),
+		);
+		report.entries.push(amounts.into());
+
 		// Store result
 		let mut result = ReportingProducts::new();
 		result.insert(
 			ReportingProductId {
 				name: self.id().name,
-				kind: ReportingProductKind::Transactions,
+				kind: ReportingProductKind::DynamicReport,
 				args: ReportingStepArgs::VoidArgs,
 			},
-			Box::new(transactions),
+			Box::new(report),
 		);
 		Ok(result)
 	}
@@ -1331,8 +3185,8 @@ impl ReportingStep for RetainedEarningsToEquity {
 	}
 
 	fn requires(&self, context: &ReportingContext) -> Vec<ReportingProductId> {
-		let eofy_date = get_eofy(&self.args.date, &context.eofy_date);
-		let last_eofy_date = eofy_date.with_year(eofy_date.year() - 1).unwrap();
+		let eofy_date = context.get_eofy(&self.args.date);
+		let last_eofy_date = context.prior_eofy(eofy_date);
 
 		// RetainedEarningsToEquity depends on AllTransactionsExceptEarningsToEquity for last financial year
 		vec![ReportingProductId {
@@ -1340,6 +3194,7 @@ impl ReportingStep for RetainedEarningsToEquity {
 			kind: ReportingProductKind::BalancesAt,
 			args: ReportingStepArgs::DateArgs(DateArgs {
 				date: last_eofy_date,
+				time: None,
 			}),
 		}]
 	}
@@ -1352,8 +3207,8 @@ impl ReportingStep for RetainedEarningsToEquity {
 		products: &RwLock<ReportingProducts>,
 	) -> Result<ReportingProducts, ReportingExecutionError> {
 		let products = products.read().await;
-		let eofy_date = get_eofy(&self.args.date, &context.eofy_date);
-		let last_eofy_date = eofy_date.with_year(eofy_date.year() - 1).unwrap();
+		let eofy_date = context.get_eofy(&self.args.date);
+		let last_eofy_date = context.prior_eofy(eofy_date);
 
 		// Get balances at end of last financial year
 		let balances_last_eofy = products
@@ -1362,6 +3217,7 @@ impl ReportingStep for RetainedEarningsToEquity {
 				kind: ReportingProductKind::BalancesAt,
 				args: ReportingStepArgs::DateArgs(DateArgs {
 					date: last_eofy_date.clone(),
+					time: None,
 				}),
 			})?
 			.downcast_ref::<BalancesAt>()
@@ -1369,7 +3225,7 @@ impl ReportingStep for RetainedEarningsToEquity {
 
 		// Get income and expense accounts
 		let kinds_for_account =
-			kinds_for_account(context.db_connection.get_account_configurations().await);
+			kinds_for_account(context.db_connection.get_account_configurations().await?);
 
 		// Transfer income and expense balances to retained earnings
 		let mut transactions = Transactions {
@@ -1380,13 +3236,14 @@ impl ReportingStep for RetainedEarningsToEquity {
 			if let Some(kinds) = kinds_for_account.get(account) {
 				if kinds
 					.iter()
-					.any(|k| k == "drcr.income" || k == "drcr.expense")
+					.any(|k| matches!(k, AccountKind::Income | AccountKind::Expense))
 				{
 					transactions.transactions.push(TransactionWithPostings {
 						transaction: Transaction {
 							id: None,
 							dt: last_eofy_date.and_hms_opt(0, 0, 0).unwrap(),
 							description: "Retained earnings".to_string(),
+							origin: Some("RetainedEarningsToEquity".to_string()),
 						},
 						postings: vec![
 							Posting {
@@ -1397,6 +3254,7 @@ impl ReportingStep for RetainedEarningsToEquity {
 								quantity: -balance,
 								commodity: context.reporting_commodity.clone(),
 								quantity_ascost: Some(-balance),
+								running_balance: None,
 							},
 							Posting {
 								id: None,
@@ -1406,6 +3264,7 @@ impl ReportingStep for RetainedEarningsToEquity {
 								quantity: *balance,
 								commodity: context.reporting_commodity.clone(),
 								quantity_ascost: Some(*balance),
+								running_balance: None,
 							},
 						],
 					})
@@ -1427,10 +3286,202 @@ impl ReportingStep for RetainedEarningsToEquity {
 	}
 }
 
+/// Revalues balances of asset and liability accounts held in commodities other than the reporting commodity, as at the given date (returns transaction list)
+///
+/// Each balance is valued using the most recent price in the `commodity_prices` table on or before the date, and the difference from its cost (see [ConvertToCost]) is charged to the [FX_GAIN_LOSS](crate::FX_GAIN_LOSS) account. The adjustment is posted to the revalued account as a zero quantity of the commodity, so that only its value in the reporting commodity changes.
+///
+/// Only period-end revaluation is performed, and realised and unrealised gains and losses are not distinguished.
+#[derive(Debug)]
+pub struct RevalueForeignCurrency {
+	pub args: DateArgs,
+}
+
+impl RevalueForeignCurrency {
+	fn register_lookup_fn(context: &mut ReportingContext) {
+		context.register_lookup_fn(
+			"RevalueForeignCurrency".to_string(),
+			vec![ReportingProductKind::Transactions],
+			Self::takes_args,
+			Self::from_args,
+		);
+	}
+
+	fn takes_args(_name: &str, args: &ReportingStepArgs, _context: &ReportingContext) -> bool {
+		matches!(args, ReportingStepArgs::DateArgs(_))
+	}
+
+	fn from_args(
+		_name: &str,
+		args: ReportingStepArgs,
+		_context: &ReportingContext,
+	) -> Box<dyn ReportingStep> {
+		Box::new(RevalueForeignCurrency { args: args.into() })
+	}
+}
+
+impl Display for RevalueForeignCurrency {
+	fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+		f.write_fmt(format_args!("{}", self.id()))
+	}
+}
+
+#[async_trait]
+impl ReportingStep for RevalueForeignCurrency {
+	fn id(&self) -> ReportingStepId {
+		ReportingStepId {
+			name: "RevalueForeignCurrency".to_string(),
+			product_kinds: vec![ReportingProductKind::Transactions],
+			args: ReportingStepArgs::DateArgs(self.args.clone()),
+		}
+	}
+
+	fn requires(&self, _context: &ReportingContext) -> Vec<ReportingProductId> {
+		// RevalueForeignCurrency depends on ConvertToCost
+		vec![ReportingProductId {
+			name: "ConvertToCost".to_string(),
+			kind: ReportingProductKind::Transactions,
+			args: ReportingStepArgs::DateArgs(self.args.clone()),
+		}]
+	}
+
+	async fn execute(
+		&self,
+		context: &ReportingContext,
+		_steps: &Vec<Box<dyn ReportingStep>>,
+		_dependencies: &ReportingGraphDependencies,
+		products: &RwLock<ReportingProducts>,
+	) -> Result<ReportingProducts, ReportingExecutionError> {
+		let products = products.read().await;
+
+		// Get transactions
+		let transactions = &products
+			.get_or_err(&ReportingProductId {
+				name: "ConvertToCost".to_string(),
+				kind: ReportingProductKind::Transactions,
+				args: ReportingStepArgs::DateArgs(self.args.clone()),
+			})?
+			.downcast_ref::<Transactions>()
+			.unwrap()
+			.transactions;
+
+		// Compute balances at cost as at the date
+		let mut balances = HashMap::new();
+		let mut commodity_balances = HashMap::new();
+		update_balances_from_transactions(
+			&mut balances,
+			&mut commodity_balances,
+			transactions.iter().filter(|t| {
+				self.args.includes_dt(t.transaction.dt)
+					&& !context.excludes_transaction(
+						t.transaction.origin.as_deref().unwrap_or("DBTransactions"),
+						t,
+					)
+			}),
+			&context.reporting_commodity,
+		);
+
+		// Get asset and liability accounts
+		let kinds_for_account =
+			kinds_for_account(context.db_connection.get_account_configurations().await?);
+
+		let prices = get_prices_by_commodity(context).await?;
+
+		// Revalue accounts in a consistent order
+		let mut accounts = commodity_balances.keys().collect::<Vec<_>>();
+		accounts.sort_by(|a, b| natural_cmp(a, b));
+
+		let mut transactions = Transactions {
+			transactions: Vec::new(),
+		};
+
+		for account in accounts {
+			if !kinds_for_account.get(account).is_some_and(|kinds| {
+				kinds
+					.iter()
+					.any(|k| matches!(k, AccountKind::Asset | AccountKind::Liability))
+			}) {
+				continue;
+			}
+
+			let mut account_commodities = commodity_balances[account].iter().collect::<Vec<_>>();
+			account_commodities.sort();
+
+			// Value each commodity at the most recent price
+			let mut value = 0;
+			for (commodity, quantity) in account_commodities.iter() {
+				if **commodity == context.reporting_commodity {
+					value += **quantity;
+				} else if **quantity != 0 {
+					let price = price_at(&prices, commodity, self.args.date)?;
+					value += price.price.convert(**quantity);
+				}
+			}
+
+			let difference = value - balances[account];
+			if difference == 0 {
+				continue;
+			}
+
+			// Post the adjustment in the first commodity other than the reporting commodity
+			let commodity = account_commodities
+				.iter()
+				.map(|(commodity, _)| *commodity)
+				.find(|commodity| **commodity != context.reporting_commodity)
+				.unwrap(); // Accounts in commodity_balances hold another commodity
+
+			transactions.transactions.push(TransactionWithPostings {
+				transaction: Transaction {
+					id: None,
+					dt: self.args.date.and_hms_opt(0, 0, 0).unwrap(),
+					description: "Foreign currency revaluation".to_string(),
+					origin: Some("RevalueForeignCurrency".to_string()),
+				},
+				postings: vec![
+					Posting {
+						id: None,
+						transaction_id: None,
+						description: None,
+						account: account.clone(),
+						quantity: 0,
+						commodity: commodity.clone(),
+						quantity_ascost: Some(difference),
+						running_balance: None,
+					},
+					Posting {
+						id: None,
+						transaction_id: None,
+						description: None,
+						account: crate::FX_GAIN_LOSS.to_string(),
+						quantity: -difference,
+						commodity: context.reporting_commodity.clone(),
+						quantity_ascost: Some(-difference),
+						running_balance: None,
+					},
+				],
+			});
+		}
+
+		// Store product
+		let mut result = ReportingProducts::new();
+		result.insert(
+			ReportingProductId {
+				name: self.id().name,
+				kind: ReportingProductKind::Transactions,
+				args: ReportingStepArgs::DateArgs(self.args.clone()),
+			},
+			Box::new(transactions),
+		);
+		Ok(result)
+	}
+}
+
 /// Generates a trial balance [DynamicReport]
+///
+/// If requested with [ReportingStepArgs::DateByCommodityArgs], a pair of Dr and Cr columns is added for each commodity, giving the balance of each account in that commodity (not converted to the reporting commodity).
 #[derive(Debug)]
 pub struct TrialBalance {
 	pub args: DateArgs,
+	pub by_commodity: bool,
 }
 
 impl TrialBalance {
@@ -1444,7 +3495,10 @@ impl TrialBalance {
 	}
 
 	fn takes_args(_name: &str, args: &ReportingStepArgs, _context: &ReportingContext) -> bool {
-		matches!(args, ReportingStepArgs::DateArgs(_))
+		matches!(
+			args,
+			ReportingStepArgs::DateArgs(_) | ReportingStepArgs::DateByCommodityArgs(_)
+		)
 	}
 
 	fn from_args(
@@ -1452,7 +3506,25 @@ impl TrialBalance {
 		args: ReportingStepArgs,
 		_context: &ReportingContext,
 	) -> Box<dyn ReportingStep> {
-		Box::new(TrialBalance { args: args.into() })
+		match args {
+			ReportingStepArgs::DateByCommodityArgs(args) => Box::new(TrialBalance {
+				args,
+				by_commodity: true,
+			}),
+			args => Box::new(TrialBalance {
+				args: args.into(),
+				by_commodity: false,
+			}),
+		}
+	}
+
+	/// Return the [ReportingStepArgs] with which the report was requested
+	fn report_args(&self) -> ReportingStepArgs {
+		if self.by_commodity {
+			ReportingStepArgs::DateByCommodityArgs(self.args.clone())
+		} else {
+			ReportingStepArgs::DateArgs(self.args.clone())
+		}
 	}
 }
 
@@ -1468,7 +3540,7 @@ impl ReportingStep for TrialBalance {
 		ReportingStepId {
 			name: "TrialBalance".to_string(),
 			product_kinds: vec![ReportingProductKind::DynamicReport],
-			args: ReportingStepArgs::DateArgs(self.args.clone()),
+			args: self.report_args(),
 		}
 	}
 
@@ -1487,7 +3559,7 @@ impl ReportingStep for TrialBalance {
 
 	async fn execute(
 		&self,
-		_context: &ReportingContext,
+		context: &ReportingContext,
 		_steps: &Vec<Box<dyn ReportingStep>>,
 		_dependencies: &ReportingGraphDependencies,
 		products: &RwLock<ReportingProducts>,
@@ -1495,57 +3567,89 @@ impl ReportingStep for TrialBalance {
 		let products = products.read().await;
 
 		// Get balances for each period
-		let balances = &products
+		let balances_at = products
 			.get_or_err(&ReportingProductId {
 				name: "AllTransactionsExceptEarningsToEquity".to_string(),
 				kind: ReportingProductKind::BalancesAt,
 				args: ReportingStepArgs::DateArgs(self.args.clone()),
 			})?
 			.downcast_ref::<BalancesAt>()
-			.unwrap()
-			.balances;
+			.unwrap();
+		let balances = &balances_at.balances;
+		let commodity_balances = &balances_at.commodity_balances;
 
 		// Get sorted list of accounts
 		let mut accounts = balances.keys().collect::<Vec<_>>();
-		accounts.sort();
+		accounts.sort_by(|a, b| natural_cmp(a, b));
+
+		// Get commodities to break down by, with the reporting commodity first
+		let mut commodities = Vec::new();
+		if self.by_commodity {
+			commodities = commodity_balances
+				.values()
+				.flat_map(|account_balances| account_balances.keys())
+				.filter(|c| **c != context.reporting_commodity)
+				.collect::<HashSet<_>>()
+				.into_iter()
+				.collect::<Vec<_>>();
+			commodities.sort();
+			commodities.insert(0, &context.reporting_commodity);
+		}
 
 		// Init report
 		let mut report = DynamicReport {
 			title: "Trial balance".to_string(),
 			columns: vec!["Dr".to_string(), "Cr".to_string()],
 			entries: Vec::new(),
+			notes: Vec::new(),
+			percentage_columns: Vec::new(),
+			reference_column: None,
 		};
+		for commodity in commodities.iter() {
+			report.columns.push(format!("Dr {}", commodity));
+			report.columns.push(format!("Cr {}", commodity));
+		}
+
+		// Split the balance into Dr and Cr cells
+		let dr_cr = |balance: QuantityInt| [balance.max(0), (-balance).max(0)];
 
 		// Add entry for each account
 		let mut section = Section {
 			text: None,
 			id: Some("accounts".to_string()),
 			visible: true,
+			auto_hide: false,
 			entries: Vec::new(),
+			note: None,
 		};
 		for account in accounts {
+			let mut quantity = dr_cr(balances[account]).to_vec();
+			for commodity in commodities.iter() {
+				let balance = match commodity_balances.get(account) {
+					Some(account_balances) => *account_balances.get(*commodity).unwrap_or(&0),
+					// Account is held only in the reporting commodity
+					None if **commodity == context.reporting_commodity => balances[account],
+					None => 0,
+				};
+				quantity.extend(dr_cr(balance));
+			}
+
 			section.entries.push(
 				Row {
 					text: account.clone(),
-					quantity: vec![
-						// Dr cell
-						if balances[account] >= 0 {
-							balances[account]
-						} else {
-							0
-						},
-						// Cr cell
-						if balances[account] < 0 {
-							-balances[account]
-						} else {
-							0
-						},
-					],
+					quantity,
 					id: None,
 					visible: true,
+					auto_hide: false,
 					link: Some(format!("/transactions/{}", account)),
 					heading: false,
 					bordered: false,
+					contra: false,
+					unit: RowUnit::Currency,
+					native_amounts: Vec::new(),
+					reference_quantity: None,
+					mismatch: false,
+					note: None,
 				}
 				.into(),
 			);
@@ -1553,6 +3657,9 @@ impl ReportingStep for TrialBalance {
 		let totals_row = section.subtotal(&report);
 		report.entries.push(section.into());
 
+		// Debits and credits should agree, unless the data is corrupt
+		let out_of_balance = totals_row[0] - totals_row[1];
+
 		// Add total row
 		report.entries.push(
 			Row {
@@ -1560,20 +3667,55 @@ impl ReportingStep for TrialBalance {
 				quantity: totals_row,
 				id: Some("totals".to_string()),
 				visible: true,
+				auto_hide: false,
 				link: None,
 				heading: true,
 				bordered: true,
+				contra: false,
+				unit: RowUnit::Currency,
+				native_amounts: Vec::new(),
+				reference_quantity: None,
+				mismatch: false,
+				note: None,
 			}
 			.into(),
 		);
 
+		if out_of_balance != 0 {
+			// Show the excess of debits in the Dr column, or of credits in the Cr column
+			report.entries.push(DynamicReportEntry::Spacer);
+			report.entries.push(
+				Row {
+					text: "Warning: Out of balance by".to_string(),
+					quantity: [
+						dr_cr(out_of_balance).to_vec(),
+						vec![0; commodities.len() * 2],
+					]
+					.concat(),
+					id: Some("out_of_balance".to_string()),
+					visible: true,
+					auto_hide: false,
+					link: None,
+					heading: true,
+					bordered: false,
+					contra: false,
+					unit: RowUnit::Currency,
+					native_amounts: Vec::new(),
+					reference_quantity: None,
+					mismatch: false,
+					note: None,
+				}
+				.into(),
+			);
+		}
+
 		// Store result
 		let mut result = ReportingProducts::new();
 		result.insert(
 			ReportingProductId {
 				name: "TrialBalance".to_string(),
 				kind: ReportingProductKind::DynamicReport,
-				args: ReportingStepArgs::DateArgs(self.args.clone()),
+				args: self.report_args(),
 			},
 			Box::new(report),
 		);
@@ -1622,3 +3764,34 @@ async fn combine_transactions_of_all_dependencies(
 
 	Ok(result)
 }
+
+/// Get the prices in the `commodity_prices` table for each commodity, sorted by date
+///
+/// Used to implement [ConvertToCost] and [RevalueForeignCurrency].
+async fn get_prices_by_commodity(
+	context: &ReportingContext,
+) -> Result<HashMap<String, Vec<CommodityPrice>>, ReportingExecutionError> {
+	let mut prices: HashMap<String, Vec<CommodityPrice>> = HashMap::new();
+	for price in context.db_connection.get_commodity_prices().await? {
+		prices
+			.entry(price.commodity.clone())
+			.or_default()
+			.push(price);
+	}
+	Ok(prices)
+}
+
+/// Find the most recent price of the commodity on or before the date, from prices returned by [get_prices_by_commodity]
+fn price_at<'a>(
+	prices: &'a HashMap<String, Vec<CommodityPrice>>,
+	commodity: &str,
+	date: NaiveDate,
+) -> Result<&'a CommodityPrice, ReportingExecutionError> {
+	prices
+		.get(commodity)
+		.and_then(|prices| prices[..prices.partition_point(|p| p.dt.date() <= date)].last())
+		.ok_or_else(|| ReportingExecutionError::MissingCommodityPrice {
+			commodity: commodity.to_string(),
+			date,
+		})
+}
@@ -19,9 +19,10 @@
 use std::collections::HashMap;
 use std::fmt::{Debug, Display};
 use std::hash::Hash;
+use std::sync::{Arc, Mutex};
 
 use async_trait::async_trait;
-use chrono::NaiveDate;
+use chrono::{NaiveDate, NaiveDateTime, NaiveTime};
 use downcast_rs::Downcast;
 use dyn_clone::DynClone;
 use indexmap::IndexMap;
@@ -31,6 +32,9 @@ use tokio::sync::RwLock;
 use crate::db::DbConnection;
 use crate::model::transaction::TransactionWithPostings;
 use crate::plugin::PluginSpec;
+use crate::util::{
+	format_date, get_eofy_with_history, prior_eofy_with_history, sofy_from_eofy_with_history,
+};
 use crate::QuantityInt;
 
 use super::calculator::ReportingGraphDependencies;
@@ -46,7 +50,25 @@ pub struct ReportingContext {
 	pub plugin_dir: String,
 	pub plugin_names: Vec<String>,
 	pub eofy_date: NaiveDate,
+	/// End dates of past financial years where the end of the financial year has changed, in ascending order (see [get_eofy_with_history])
+	///
+	/// Initialised from the database.
+	pub financial_year_ends: Vec<NaiveDate>,
 	pub reporting_commodity: String,
+	/// Transactions matching any of these are excluded when computing balances, e.g. for scenario analysis
+	pub transaction_exclusions: Vec<TransactionExclusion>,
+	/// If set, transactions dated after this date (e.g. today) are excluded, even where the reporting date is later, so reports show actual rather than projected balances
+	///
+	/// If `None` (the default), all transactions up to the reporting date are included.
+	pub transactions_cutoff: Option<NaiveDate>,
+	/// If true, [DBTransactions][super::steps::DBTransactions], [DBBalances][super::steps::DBBalances] and plugin steps fail with [ReportingExecutionError::UnbalancedTransaction] when a transaction in the database or generated by a plugin does not balance
+	///
+	/// If false (the default), unbalanced transactions are loaded as is.
+	pub reject_unbalanced_transactions: bool,
+	/// If set, [ReportingProduct]s are memoised in this cache across calls to [execute_steps](super::executor::execute_steps), until [ReportingCache::invalidate] is called
+	///
+	/// The same cache may be attached to successive [ReportingContext]s (e.g. one per request). Cached products are only reused where the configuration of the context is unchanged. The caller is responsible for invalidating the cache whenever the database is written to.
+	pub cache: Option<Arc<Mutex<ReportingCache>>>,
 
 	// State
 	pub(crate) step_lookup_fn: HashMap<
@@ -66,18 +88,56 @@ impl ReportingContext {
 		eofy_date: NaiveDate,
 		reporting_commodity: String,
 	) -> Self {
+		let financial_year_ends = db_connection.metadata().financial_year_ends.clone();
+
 		Self {
 			db_connection,
 			plugin_dir,
 			plugin_names,
 			eofy_date,
+			financial_year_ends,
 			reporting_commodity,
+			transaction_exclusions: Vec::new(),
+			transactions_cutoff: None,
+			reject_unbalanced_transactions: false,
+			cache: None,
 			step_lookup_fn: HashMap::new(),
 			step_dynamic_builders: Vec::new(),
 			plugin_specs: HashMap::new(),
 		}
 	}
 
+	/// Return the end date of the current financial year for the given date
+	pub fn get_eofy(&self, date: &NaiveDate) -> NaiveDate {
+		get_eofy_with_history(date, &self.eofy_date, &self.financial_year_ends)
+	}
+
+	/// Return the start date of the financial year, given the end date of the financial year
+	pub fn sofy_from_eofy(&self, eofy_date: NaiveDate) -> NaiveDate {
+		sofy_from_eofy_with_history(eofy_date, &self.financial_year_ends)
+	}
+
+	/// Return the end date of the preceding financial year, given the end date of the financial year
+	pub fn prior_eofy(&self, eofy_date: NaiveDate) -> NaiveDate {
+		prior_eofy_with_history(eofy_date, &self.financial_year_ends)
+	}
+
+	/// Get the key identifying the configuration of this context, for use with [ReportingCache]
+	pub(crate) fn cache_key(&self) -> String {
+		format!(
+			"{} {} {:?} {} {:?} {} {:?} {:?} {}",
+			self.db_connection.url(),
+			self.plugin_dir,
+			self.plugin_names,
+			self.eofy_date,
+			self.financial_year_ends,
+			self.reporting_commodity,
+			self.transaction_exclusions,
+			self.transactions_cutoff,
+			self.reject_unbalanced_transactions
+		)
+	}
+
 	/// Register a lookup function
 	///
 	/// A lookup function generates concrete [ReportingStep]s from a [ReportingStepId].
@@ -104,6 +164,80 @@ impl ReportingContext {
 			self.step_dynamic_builders.push(builder);
 		}
 	}
+
+	/// Return true if the transaction, generated by the step with the given name, should be excluded when computing balances
+	///
+	/// See [ReportingContext::transaction_exclusions] and [ReportingContext::transactions_cutoff].
+	pub fn excludes_transaction(
+		&self,
+		step_name: &str,
+		transaction: &TransactionWithPostings,
+	) -> bool {
+		self.transactions_cutoff
+			.is_some_and(|cutoff| transaction.transaction.dt.date() > cutoff)
+			|| self
+				.transaction_exclusions
+				.iter()
+				.any(|e| e.matches(step_name, transaction))
+	}
+
+	/// Return the [DateArgs] limited to [ReportingContext::transactions_cutoff], if it is earlier
+	pub fn limit_to_cutoff(&self, args: &DateArgs) -> DateArgs {
+		match self.transactions_cutoff {
+			Some(cutoff) if cutoff < args.date => DateArgs {
+				date: cutoff,
+				time: None,
+			},
+			_ => args.clone(),
+		}
+	}
+}
+
+/// Criterion for excluding transactions when computing balances
+///
+/// See [ReportingContext::transaction_exclusions].
+#[derive(Clone, Debug)]
+pub enum TransactionExclusion {
+	/// Exclude all transactions generated by the named step (e.g. "CalculateIncomeTax", or "DBTransactions" for transactions in the database)
+	SourceStep(String),
+	/// Exclude transactions whose description contains the given text (e.g. a tag identifying an import batch)
+	DescriptionContains(String),
+	/// Exclude transactions dated between the given dates (inclusive)
+	DateRange(NaiveDate, NaiveDate),
+}
+
+impl TransactionExclusion {
+	/// Return true if the transaction, generated by the step with the given name, matches this criterion
+	pub fn matches(&self, step_name: &str, transaction: &TransactionWithPostings) -> bool {
+		match self {
+			TransactionExclusion::SourceStep(name) => name == step_name,
+			TransactionExclusion::DescriptionContains(text) => {
+				transaction.transaction.description.contains(text)
+			}
+			TransactionExclusion::DateRange(date_start, date_end) => {
+				let date = transaction.transaction.dt.date();
+				date >= *date_start && date <= *date_end
+			}
+		}
+	}
+
+	/// Return an SQL condition on the `transactions_with_quantity_ascost` view equivalent to [TransactionExclusion::matches], with `?` placeholders, and the values to bind to them
+	pub(crate) fn to_sql_condition(&self, step_name: &str) -> (String, Vec<String>) {
+		match self {
+			TransactionExclusion::SourceStep(name) => {
+				let condition = if name == step_name { "TRUE" } else { "FALSE" };
+				(condition.to_string(), vec![])
+			}
+			TransactionExclusion::DescriptionContains(text) => (
+				"INSTR(transaction_description, ?) > 0".to_string(),
+				vec![text.clone()],
+			),
+			TransactionExclusion::DateRange(date_start, date_end) => (
+				"DATE(dt) >= DATE(?) AND DATE(dt) <= DATE(?)".to_string(),
+				vec![format_date(*date_start), format_date(*date_end)],
+			),
+		}
+	}
 }
 
 /// Function which determines whether the [ReportingStepArgs] are valid arguments for a given [ReportingStep]
@@ -174,6 +308,8 @@ pub enum ReportingProductKind {
 	BalancesBetween,
 	/// The [Box]ed [ReportingProduct] is a [DynamicReport]
 	DynamicReport,
+	/// The [Box]ed [ReportingProduct] is a [DrilldownReport]
+	DrilldownReport,
 	/// Unused in libdrcr - available for plugin use
 	Generic,
 }
@@ -190,26 +326,72 @@ pub struct Transactions {
 	pub transactions: Vec<TransactionWithPostings>,
 }
 
+impl Transactions {
+	/// Fail with [ReportingExecutionError::UnbalancedTransaction] if any transaction does not balance (see [TransactionWithPostings::validate])
+	pub fn check_balanced(&self, reporting_commodity: &str) -> Result<(), ReportingExecutionError> {
+		for transaction in self.transactions.iter() {
+			let imbalance = transaction.validate(reporting_commodity);
+			if !imbalance.is_empty() {
+				return Err(ReportingExecutionError::UnbalancedTransaction {
+					transaction_id: transaction.transaction.id,
+					imbalance,
+				});
+			}
+		}
+		Ok(())
+	}
+}
+
 impl ReportingProduct for Transactions {}
 
 /// Records cumulative account balances at a particular point in time
+///
+/// `balances` is in terms of the reporting commodity, with other commodities converted at cost. For accounts with postings in any other commodity, `commodity_balances` additionally records the balance in each commodity (including the reporting commodity), combining lots with different cost bases. Accounts held only in the reporting commodity are omitted from `commodity_balances`, so it is empty if all postings are in the reporting commodity.
 #[derive(Clone, Debug, Deserialize, Serialize)]
 pub struct BalancesAt {
 	pub balances: HashMap<String, QuantityInt>,
+	#[serde(default, skip_serializing_if = "HashMap::is_empty")]
+	pub commodity_balances: HashMap<String, HashMap<String, QuantityInt>>,
+}
+
+impl BalancesAt {
+	/// Get the balance of the account in the given commodity (not converted to the reporting commodity)
+	pub fn commodity_balance(
+		&self,
+		account: &str,
+		commodity: &str,
+		reporting_commodity: &str,
+	) -> QuantityInt {
+		match self.commodity_balances.get(account) {
+			Some(account_balances) => *account_balances.get(commodity).unwrap_or(&0),
+			None => {
+				// Account is held entirely in the reporting commodity
+				if commodity == reporting_commodity {
+					*self.balances.get(account).unwrap_or(&0)
+				} else {
+					0
+				}
+			}
+		}
+	}
 }
 
 impl ReportingProduct for BalancesAt {}
 
 /// Records the total value of transactions in each account between two points in time
+///
+/// See [BalancesAt] for the meaning of `balances` and `commodity_balances`.
 #[derive(Clone, Debug, Deserialize, Serialize)]
 pub struct BalancesBetween {
 	pub balances: HashMap<String, QuantityInt>,
+	#[serde(default, skip_serializing_if = "HashMap::is_empty")]
+	pub commodity_balances: HashMap<String, HashMap<String, QuantityInt>>,
 }
 
 impl ReportingProduct for BalancesBetween {}
 
 /// Map from [ReportingProductId] to [ReportingProduct]
-#[derive(Clone, Debug)]
+#[derive(Clone, Debug, Default)]
 pub struct ReportingProducts {
 	// This needs to be an IndexMap not HashMap, because sometimes we query which product is more up to date
 	map: IndexMap<ReportingProductId, Box<dyn ReportingProduct>>,
@@ -266,6 +448,59 @@ impl ReportingProducts {
 	}
 }
 
+/// Cache of [ReportingProduct]s which persists across reporting jobs, see [ReportingContext::cache]
+#[derive(Debug, Default)]
+pub struct ReportingCache {
+	/// [ReportingContext::cache_key] of the context in which the cached products were generated
+	key: Option<String>,
+	/// Incremented on each call to [ReportingCache::invalidate], so that products generated from a since-modified database are not stored
+	generation: u64,
+	products: ReportingProducts,
+}
+
+impl ReportingCache {
+	pub fn new() -> Self {
+		Self::default()
+	}
+
+	/// Discard all cached products, e.g. because the database has been written to
+	///
+	/// Products of any reporting job already in progress are also not stored.
+	pub fn invalidate(&mut self) {
+		self.key = None;
+		self.generation += 1;
+		self.products = ReportingProducts::new();
+	}
+
+	/// Get the current generation of the cache, to pass to [ReportingCache::store]
+	pub(crate) fn generation(&self) -> u64 {
+		self.generation
+	}
+
+	/// Get the cached products which are valid for the given [ReportingContext::cache_key], discarding any which are not
+	pub(crate) fn products_for_key(&mut self, key: &str) -> &ReportingProducts {
+		if self.key.as_deref() != Some(key) {
+			self.key = Some(key.to_string());
+			self.products = ReportingProducts::new();
+		}
+		&self.products
+	}
+
+	/// Add the products to the cache, valid for the given [ReportingContext::cache_key]
+	///
+	/// The products are not stored if the cache has been invalidated since `generation` was obtained.
+	pub(crate) fn store(&mut self, key: &str, generation: u64, products: &ReportingProducts) {
+		if generation != self.generation {
+			return;
+		}
+
+		self.products_for_key(key);
+		for (product_id, product) in products.map().iter() {
+			self.products.insert(product_id.clone(), product.clone());
+		}
+	}
+}
+
 impl Display for ReportingProducts {
 	fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
 		f.write_fmt(format_args!(
@@ -346,6 +581,31 @@ pub trait ReportingStep: Debug + Display + Downcast + Send + Sync {
 	) -> Result<ReportingProducts, ReportingExecutionError> {
 		todo!("{}", self);
 	}
+
+	/// Get the key identifying the batch in which this [ReportingStep] is executed, if any
+	///
+	/// [ReportingStep]s with the same batch key which are ready to execute at the same time are executed together by [ReportingStep::execute_batch], e.g. so that they can share a database query.
+	fn batch_key(&self) -> Option<String> {
+		None
+	}
+
+	/// Called to generate the [ReportingProduct]s for a batch of [ReportingStep]s with the same [ReportingStep::batch_key] as this one
+	///
+	/// `batch` includes this [ReportingStep]. Returns the result of each step, in the same order as `batch`. The default implementation executes each step individually.
+	async fn execute_batch(
+		&self,
+		batch: &[&dyn ReportingStep],
+		context: &ReportingContext,
+		steps: &Vec<Box<dyn ReportingStep>>,
+		dependencies: &ReportingGraphDependencies,
+		products: &RwLock<ReportingProducts>,
+	) -> Vec<Result<ReportingProducts, ReportingExecutionError>> {
+		let mut results = Vec::new();
+		for step in batch {
+			results.push(step.execute(context, steps, dependencies, products).await);
+		}
+		results
+	}
 }
 
 downcast_rs::impl_downcast!(ReportingStep);
@@ -363,9 +623,15 @@ pub enum ReportingStepArgs {
 	/// [ReportingStepArgs] implementation which takes a single date
 	DateArgs(DateArgs),
 
+	/// [ReportingStepArgs] implementation which takes a single date, for a report broken down by commodity (e.g. [TrialBalance](crate::reporting::steps::TrialBalance))
+	DateByCommodityArgs(DateArgs),
+
 	/// [ReportingStepArgs] implementation which takes a date range
 	DateStartDateEndArgs(DateStartDateEndArgs),
 
+	/// [ReportingStepArgs] implementation which takes a date range and an optional set of accounts
+	DateStartDateEndAccountsArgs(DateStartDateEndAccountsArgs),
+
 	/// [ReportingStepArgs] implementation which takes multiple [DateArgs]
 	MultipleDateArgs(MultipleDateArgs),
 
@@ -373,12 +639,39 @@ pub enum ReportingStepArgs {
 	MultipleDateStartDateEndArgs(MultipleDateStartDateEndArgs),
 }
 
+impl ReportingStepArgs {
+	/// Get the [ReportingStepArgsKind] of these args
+	pub fn kind(&self) -> ReportingStepArgsKind {
+		match self {
+			ReportingStepArgs::VoidArgs => ReportingStepArgsKind::VoidArgs,
+			ReportingStepArgs::DateArgs(_) => ReportingStepArgsKind::DateArgs,
+			ReportingStepArgs::DateByCommodityArgs(_) => ReportingStepArgsKind::DateByCommodityArgs,
+			ReportingStepArgs::DateStartDateEndArgs(_) => {
+				ReportingStepArgsKind::DateStartDateEndArgs
+			}
+			ReportingStepArgs::DateStartDateEndAccountsArgs(_) => {
+				ReportingStepArgsKind::DateStartDateEndAccountsArgs
+			}
+			ReportingStepArgs::MultipleDateArgs(_) => ReportingStepArgsKind::MultipleDateArgs,
+			ReportingStepArgs::MultipleDateStartDateEndArgs(_) => {
+				ReportingStepArgsKind::MultipleDateStartDateEndArgs
+			}
+		}
+	}
+}
+
 impl Display for ReportingStepArgs {
 	fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
 		match self {
 			ReportingStepArgs::VoidArgs => f.write_str("void"),
 			ReportingStepArgs::DateArgs(args) => f.write_fmt(format_args!("{}", args)),
+			ReportingStepArgs::DateByCommodityArgs(args) => {
+				f.write_fmt(format_args!("{}, by commodity", args))
+			}
 			ReportingStepArgs::DateStartDateEndArgs(args) => f.write_fmt(format_args!("{}", args)),
+			ReportingStepArgs::DateStartDateEndAccountsArgs(args) => {
+				f.write_fmt(format_args!("{}", args))
+			}
 			ReportingStepArgs::MultipleDateArgs(args) => f.write_fmt(format_args!("{}", args)),
 			ReportingStepArgs::MultipleDateStartDateEndArgs(args) => {
 				f.write_fmt(format_args!("{}", args))
@@ -387,15 +680,49 @@ impl Display for ReportingStepArgs {
 	}
 }
 
+/// Identifies a variant of [ReportingStepArgs], without the arguments themselves
+#[derive(Clone, Copy, Debug, Deserialize, Eq, Hash, PartialEq, Serialize)]
+pub enum ReportingStepArgsKind {
+	VoidArgs,
+	DateArgs,
+	DateByCommodityArgs,
+	DateStartDateEndArgs,
+	DateStartDateEndAccountsArgs,
+	MultipleDateArgs,
+	MultipleDateStartDateEndArgs,
+}
+
 #[derive(Clone, Debug, Deserialize, Eq, Hash, PartialEq, Serialize)]
 pub struct DateArgs {
 	#[serde(with = "crate::serde::naivedate_to_js")]
 	pub date: NaiveDate,
+	/// If specified, only transactions up to and including this time on `date` are included, rather than the whole day
+	///
+	/// This applies to [BalancesAt] products. Period-based products (e.g. [BalancesBetween]) remain date-granular.
+	#[serde(
+		default,
+		skip_serializing_if = "Option::is_none",
+		with = "crate::serde::option_naivetime_to_js"
+	)]
+	pub time: Option<NaiveTime>,
+}
+
+impl DateArgs {
+	/// Return true if a transaction at the given [NaiveDateTime] falls on or before the point in time represented by these [DateArgs]
+	pub fn includes_dt(&self, dt: NaiveDateTime) -> bool {
+		match self.time {
+			Some(time) => dt <= self.date.and_time(time),
+			None => dt.date() <= self.date,
+		}
+	}
 }
 
 impl Display for DateArgs {
 	fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-		f.write_fmt(format_args!("{}", self.date))
+		match self.time {
+			Some(time) => f.write_fmt(format_args!("{} {}", self.date, time)),
+			None => f.write_fmt(format_args!("{}", self.date)),
+		}
 	}
 }
 
@@ -433,9 +760,45 @@ impl Into<DateStartDateEndArgs> for ReportingStepArgs {
 	}
 }
 
+#[derive(Clone, Debug, Deserialize, Eq, Hash, PartialEq, Serialize)]
+pub struct DateStartDateEndAccountsArgs {
+	#[serde(with = "crate::serde::naivedate_to_js")]
+	pub date_start: NaiveDate,
+	#[serde(with = "crate::serde::naivedate_to_js")]
+	pub date_end: NaiveDate,
+	/// If specified, only these accounts are included, rather than all accounts
+	#[serde(default, skip_serializing_if = "Option::is_none")]
+	pub accounts: Option<Vec<String>>,
+}
+
+impl Display for DateStartDateEndAccountsArgs {
+	fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+		f.write_fmt(format_args!("{}, {}", self.date_start, self.date_end))?;
+		if let Some(accounts) = &self.accounts {
+			f.write_fmt(format_args!(", [{}]", accounts.join(", ")))?;
+		}
+		Ok(())
+	}
+}
+
+impl Into<DateStartDateEndAccountsArgs> for ReportingStepArgs {
+	fn into(self) -> DateStartDateEndAccountsArgs {
+		if let ReportingStepArgs::DateStartDateEndAccountsArgs(args) = self {
+			args
+		} else {
+			panic!("Expected DateStartDateEndAccountsArgs")
+		}
+	}
+}
+
 #[derive(Clone, Debug, Deserialize, Eq, Hash, PartialEq, Serialize)]
 pub struct MultipleDateArgs {
 	pub dates: Vec<DateArgs>,
+	/// If true, reports add a comparative column at the end of the preceding financial year for each date in `dates`
+	///
+	/// Currently respected only by [BalanceSheet][super::steps::BalanceSheet].
+	#[serde(default, skip_serializing_if = "std::ops::Not::not")]
+	pub with_prior_year: bool,
 }
 
 impl Display for MultipleDateArgs {
@@ -447,7 +810,11 @@ impl Display for MultipleDateArgs {
 				.map(|a| a.to_string())
 				.collect::<Vec<_>>()
 				.join(", ")
-		))
+		))?;
+		if self.with_prior_year {
+			f.write_str(", with prior year")?;
+		}
+		Ok(())
 	}
 }
 
@@ -464,6 +831,11 @@ impl Into<MultipleDateArgs> for ReportingStepArgs {
 #[derive(Clone, Debug, Deserialize, Eq, Hash, PartialEq, Serialize)]
 pub struct MultipleDateStartDateEndArgs {
 	pub dates: Vec<DateStartDateEndArgs>,
+	/// If true, reports append a column summing all periods in `dates`
+	///
+	/// Periods should be contiguous and non-overlapping, so that the cumulative column agrees with a report over the whole span.
+	#[serde(default, skip_serializing_if = "std::ops::Not::not")]
+	pub cumulative: bool,
 }
 
 impl Display for MultipleDateStartDateEndArgs {
@@ -475,7 +847,11 @@ impl Display for MultipleDateStartDateEndArgs {
 				.map(|a| format!("({})", a))
 				.collect::<Vec<_>>()
 				.join(", ")
-		))
+		))?;
+		if self.cumulative {
+			f.write_str(", cumulative")?;
+		}
+		Ok(())
 	}
 }
 
@@ -488,3 +864,48 @@ impl Into<MultipleDateStartDateEndArgs> for ReportingStepArgs {
 		}
 	}
 }
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn balances_at_serde_round_trip() {
+		let balances = BalancesAt {
+			balances: HashMap::from([("Cash".to_string(), 123456), ("Shares".to_string(), 150000)]),
+			commodity_balances: HashMap::from([(
+				"Shares".to_string(),
+				HashMap::from([("XYZ".to_string(), 1000)]),
+			)]),
+		};
+
+		let json = serde_json::to_string(&balances).unwrap();
+		let result: BalancesAt = serde_json::from_str(&json).unwrap();
+		assert_eq!(result.balances, balances.balances);
+		assert_eq!(result.commodity_balances, balances.commodity_balances);
+
+		// The same representation is used for BalancesBetween
+		let result: BalancesBetween = serde_json::from_str(&json).unwrap();
+		assert_eq!(result.balances, balances.balances);
+		assert_eq!(result.commodity_balances, balances.commodity_balances);
+	}
+
+	#[test]
+	fn balances_between_serde_round_trip() {
+		let balances = BalancesBetween {
+			balances: HashMap::from([
+				("Salary".to_string(), -1500000),
+				("Rent".to_string(), 400000),
+			]),
+			commodity_balances: HashMap::new(),
+		};
+
+		// Empty commodity_balances are omitted, and default when deserialising
+		let json = serde_json::to_string(&balances).unwrap();
+		assert!(!json.contains("commodity_balances"));
+
+		let result: BalancesBetween = serde_json::from_str(&json).unwrap();
+		assert_eq!(result.balances, balances.balances);
+		assert!(result.commodity_balances.is_empty());
+	}
+}
@@ -105,3 +105,74 @@ pub mod naivedatetime_to_js {
 		deserializer.deserialize_str(DateVisitor)
 	}
 }
+
+/// Serialises [Option]<[chrono::NaiveTime]> in database format
+///
+/// Use as `#[serde(default, skip_serializing_if = "Option::is_none", with = "crate::serde::option_naivetime_to_js")]`.
+pub mod option_naivetime_to_js {
+	use chrono::NaiveTime;
+	use serde::{
+		de::{self, Unexpected},
+		Deserialize, Deserializer, Serializer,
+	};
+
+	pub(crate) fn serialize<S: Serializer>(
+		time: &Option<NaiveTime>,
+		serializer: S,
+	) -> Result<S::Ok, S::Error> {
+		match time {
+			Some(time) => serializer.serialize_str(&time.format("%H:%M:%S%.6f").to_string()),
+			None => serializer.serialize_none(),
+		}
+	}
+
+	pub(crate) fn deserialize<'de, D: Deserializer<'de>>(
+		deserializer: D,
+	) -> Result<Option<NaiveTime>, D::Error> {
+		match Option::<String>::deserialize(deserializer)? {
+			Some(s) => match NaiveTime::parse_from_str(&s, "%H:%M:%S%.6f") {
+				Ok(time) => Ok(Some(time)),
+				Err(_) => Err(de::Error::invalid_value(
+					Unexpected::Str(&s),
+					&"a time string",
+				)),
+			},
+			None => Ok(None),
+		}
+	}
+}
+
+/// Serialises [Vec]<[chrono::NaiveDate]> in database format
+///
+/// Use as `#[serde(with = "crate::serde::vec_naivedate_to_js")]`.
+pub mod vec_naivedate_to_js {
+	use chrono::NaiveDate;
+	use serde::{
+		de::{self, Unexpected},
+		ser::SerializeSeq,
+		Deserialize, Deserializer, Serializer,
+	};
+
+	pub(crate) fn serialize<S: Serializer>(
+		dts: &[NaiveDate],
+		serializer: S,
+	) -> Result<S::Ok, S::Error> {
+		let mut seq = serializer.serialize_seq(Some(dts.len()))?;
+		for dt in dts.iter() {
+			seq.serialize_element(&dt.format("%Y-%m-%d").to_string())?;
+		}
+		seq.end()
+	}
+
+	pub(crate) fn deserialize<'de, D: Deserializer<'de>>(
+		deserializer: D,
+	) -> Result<Vec<NaiveDate>, D::Error> {
+		Vec::<String>::deserialize(deserializer)?
+			.into_iter()
+			.map(|s| {
+				NaiveDate::parse_from_str(&s, "%Y-%m-%d")
+					.map_err(|_| de::Error::invalid_value(Unexpected::Str(&s), &"a date string"))
+			})
+			.collect()
+	}
+}
@@ -16,28 +16,370 @@
 	along with this program.  If not, see <https://www.gnu.org/licenses/>.
 */
 
-use chrono::{Datelike, NaiveDate};
+use std::cmp::Ordering;
+
+use chrono::{Datelike, Months, NaiveDate, NaiveDateTime};
+
+use crate::QuantityInt;
 
 /// Return the end date of the current financial year for the given date
 pub fn get_eofy(date: &NaiveDate, eofy_date: &NaiveDate) -> NaiveDate {
-	let date_eofy = eofy_date.with_year(date.year()).unwrap();
+	let date_eofy = eofy_in_year(eofy_date, date.year());
 	if date_eofy >= *date {
 		date_eofy
 	} else {
-		date_eofy.with_year(date_eofy.year() + 1).unwrap()
+		eofy_in_year(eofy_date, date.year() + 1)
 	}
 }
 
 /// Return the start date of the financial year, given the end date of the financial year
 pub fn sofy_from_eofy(eofy_date: NaiveDate) -> NaiveDate {
-	eofy_date
-		.with_year(eofy_date.year() - 1)
-		.unwrap()
+	eofy_in_year(&eofy_date, eofy_date.year() - 1)
 		.succ_opt()
 		.unwrap()
 }
 
+/// Return the end date of the preceding financial year, given the end date of the financial year
+pub fn prior_eofy(eofy_date: NaiveDate) -> NaiveDate {
+	sofy_from_eofy(eofy_date).pred_opt().unwrap()
+}
+
+/// Return the end date of the current financial year for the given date, where the end of the financial year has changed over time
+///
+/// `financial_year_ends` lists, in ascending order, the end dates of past financial years where the end of the financial year has changed. Each earlier financial year ends on the same day of the year as the next of these, and financial years after the last of these end on the same day of the year as `eofy_date`.
+pub fn get_eofy_with_history(
+	date: &NaiveDate,
+	eofy_date: &NaiveDate,
+	financial_year_ends: &[NaiveDate],
+) -> NaiveDate {
+	let next_change = financial_year_ends.iter().find(|d| *d >= date);
+	get_eofy(date, next_change.unwrap_or(eofy_date))
+}
+
+/// Return the start date of the financial year, given the end date of the financial year, where the end of the financial year has changed over time
+///
+/// See [get_eofy_with_history] for the meaning of `financial_year_ends`. A financial year starts no earlier than the day after the previous change.
+pub fn sofy_from_eofy_with_history(
+	eofy_date: NaiveDate,
+	financial_year_ends: &[NaiveDate],
+) -> NaiveDate {
+	let sofy_date = sofy_from_eofy(eofy_date);
+	match financial_year_ends.iter().rev().find(|d| **d < eofy_date) {
+		Some(prev_change) => sofy_date.max(prev_change.succ_opt().unwrap()),
+		None => sofy_date,
+	}
+}
+
+/// Return the end date of the preceding financial year, given the end date of the financial year, where the end of the financial year has changed over time
+///
+/// See [get_eofy_with_history] for the meaning of `financial_year_ends`.
+pub fn prior_eofy_with_history(
+	eofy_date: NaiveDate,
+	financial_year_ends: &[NaiveDate],
+) -> NaiveDate {
+	sofy_from_eofy_with_history(eofy_date, financial_year_ends)
+		.pred_opt()
+		.unwrap()
+}
+
+/// Return the end date of the financial year ending in the given year, given the end date of any financial year
+///
+/// If `eofy_date` is the last day of a month, the financial year ends on the last day of that month in every year, so that a financial year ending in February is handled in leap years.
+fn eofy_in_year(eofy_date: &NaiveDate, year: i32) -> NaiveDate {
+	let is_end_of_month = eofy_date.succ_opt().unwrap().month() != eofy_date.month();
+	if is_end_of_month {
+		// Last day of the month is the day before the first day of the next month
+		let start_of_month = NaiveDate::from_ymd_opt(year, eofy_date.month(), 1).unwrap();
+		start_of_month
+			.checked_add_months(Months::new(1))
+			.unwrap()
+			.pred_opt()
+			.unwrap()
+	} else {
+		// Cannot fail, as 29 February is the last day of its month
+		eofy_date.with_year(year).unwrap()
+	}
+}
+
+/// Return the commodity without any cost basis, e.g. `"USD"` for `"USD {1.50}"`
+pub fn bare_commodity(commodity: &str) -> &str {
+	commodity.split(" {").next().unwrap()
+}
+
+/// Compare the strings in natural order, where runs of digits are compared by their numeric value, e.g. so that `"Account 2"` sorts before `"Account 10"`
+///
+/// Used to sort accounts in reports. Accounts which share a `:`-separated prefix remain adjacent, as required by [entries_for_kind](crate::reporting::dynamic_report::entries_for_kind) when grouping accounts.
+pub fn natural_cmp(a: &str, b: &str) -> Ordering {
+	let a_chunks = natural_chunks(a);
+	let b_chunks = natural_chunks(b);
+
+	for (a_chunk, b_chunk) in a_chunks.iter().zip(b_chunks.iter()) {
+		let ordering = natural_cmp_chunk(a_chunk, b_chunk);
+		if ordering != Ordering::Equal {
+			return ordering;
+		}
+	}
+
+	a_chunks.len().cmp(&b_chunks.len())
+}
+
+/// Split the string into alternating runs of ASCII digits and other characters
+fn natural_chunks(s: &str) -> Vec<&str> {
+	let mut chunks = Vec::new();
+	let mut start = 0;
+	let mut prev_is_digit = None;
+
+	for (i, c) in s.char_indices() {
+		let is_digit = c.is_ascii_digit();
+		if prev_is_digit.is_some_and(|p| p != is_digit) {
+			chunks.push(&s[start..i]);
+			start = i;
+		}
+		prev_is_digit = Some(is_digit);
+	}
+	if start < s.len() {
+		chunks.push(&s[start..]);
+	}
+
+	chunks
+}
+
+/// Compare chunks returned by [natural_chunks]
+fn natural_cmp_chunk(a: &str, b: &str) -> Ordering {
+	let is_number = |s: &str| s.starts_with(|c: char| c.is_ascii_digit());
+
+	if is_number(a) && is_number(b) {
+		// Compare numerically without parsing, so that long runs of digits cannot overflow
+		// Where the numeric values are equal, fewer leading zeroes sort first
+		let a_trimmed = a.trim_start_matches('0');
+		let b_trimmed = b.trim_start_matches('0');
+		a_trimmed
+			.len()
+			.cmp(&b_trimmed.len())
+			.then_with(|| a_trimmed.cmp(b_trimmed))
+			.then_with(|| a.len().cmp(&b.len()))
+	} else {
+		a.cmp(b)
+	}
+}
+
 /// Format the [NaiveDate] as a string
 pub fn format_date(date: NaiveDate) -> String {
 	date.format("%Y-%m-%d 00:00:00.000000").to_string()
 }
+
+/// Format the [NaiveDateTime] as a string
+pub fn format_datetime(dt: NaiveDateTime) -> String {
+	dt.format("%Y-%m-%d %H:%M:%S%.6f").to_string()
+}
+
+/// Return `numerator / denominator` as a percentage in basis points, rounding half away from zero
+///
+/// Returns `None` if `denominator` is zero.
+pub fn basis_points(numerator: QuantityInt, denominator: QuantityInt) -> Option<QuantityInt> {
+	if denominator == 0 {
+		return None;
+	}
+
+	let scaled = numerator as i128 * 10000;
+	let denominator = denominator as i128;
+	let quotient = scaled / denominator;
+	let remainder = scaled % denominator;
+
+	// Round half away from zero
+	let rounded = if remainder.abs() * 2 >= denominator.abs() {
+		quotient + (scaled.signum() * denominator.signum())
+	} else {
+		quotient
+	};
+
+	Some(rounded as QuantityInt)
+}
+
+/// Format the quantity, a multiple of 10^-dps, with thousands separators and the given number of decimal places, e.g. "-1,234.50"
+///
+/// If `commodity` is not empty, it is prefixed if it is a single character symbol (e.g. "$1,234.50"), and otherwise suffixed (e.g. "1,234.50 USD"). Equivalent to `ppWithCommodity` in the frontend.
+pub fn format_quantity(quantity: QuantityInt, dps: u32, commodity: &str) -> String {
+	let sign = if quantity < 0 { "-" } else { "" };
+	let quantity = quantity.unsigned_abs();
+	let factor = 10_u64.pow(dps);
+
+	// Insert thousands separators
+	let whole_part = (quantity / factor).to_string();
+	let mut formatted = String::new();
+	for (i, digit) in whole_part.chars().enumerate() {
+		if i > 0 && (whole_part.len() - i).is_multiple_of(3) {
+			formatted.push(',');
+		}
+		formatted.push(digit);
+	}
+
+	if dps > 0 {
+		formatted.push_str(&format!(
+			".{:0width$}",
+			quantity % factor,
+			width = dps as usize
+		));
+	}
+
+	if commodity.is_empty() {
+		format!("{}{}", sign, formatted)
+	} else {
+		// Handle commodities with a cost basis, e.g. "$ {1.50}"
+		match commodity.split_once(' ') {
+			Some((symbol, rest)) if symbol.chars().count() == 1 => {
+				format!("{}{}{} {}", sign, symbol, formatted, rest)
+			}
+			None if commodity.chars().count() == 1 => format!("{}{}{}", sign, commodity, formatted),
+			_ => format!("{}{} {}", sign, formatted, commodity),
+		}
+	}
+}
+
+/// Format the quantity as a plain decimal number with the given number of decimal places, e.g. "-1234.50"
+pub fn serialise_quantity(quantity: QuantityInt, dps: u32) -> String {
+	let sign = if quantity < 0 { "-" } else { "" };
+	let quantity = quantity.unsigned_abs();
+	let factor = 10_u64.pow(dps);
+
+	if dps == 0 {
+		format!("{}{}", sign, quantity)
+	} else {
+		format!(
+			"{}{}.{:0width$}",
+			sign,
+			quantity / factor,
+			quantity % factor,
+			width = dps as usize
+		)
+	}
+}
+
+/// Format the quantity in basis points as a percentage, e.g. "12.34%"
+pub fn format_basis_points(quantity: QuantityInt) -> String {
+	let sign = if quantity < 0 { "-" } else { "" };
+	let quantity = quantity.unsigned_abs();
+	format!("{}{}.{:02}%", sign, quantity / 100, quantity % 100)
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	fn date(year: i32, month: u32, day: u32) -> NaiveDate {
+		NaiveDate::from_ymd_opt(year, month, day).unwrap()
+	}
+
+	#[test]
+	fn eofy_30_june() {
+		let eofy_date = date(2025, 6, 30);
+
+		assert_eq!(get_eofy(&date(2024, 7, 1), &eofy_date), date(2025, 6, 30));
+		assert_eq!(get_eofy(&date(2025, 6, 30), &eofy_date), date(2025, 6, 30));
+		assert_eq!(get_eofy(&date(2025, 7, 1), &eofy_date), date(2026, 6, 30));
+		assert_eq!(get_eofy(&date(2024, 2, 29), &eofy_date), date(2024, 6, 30));
+
+		assert_eq!(sofy_from_eofy(date(2025, 6, 30)), date(2024, 7, 1));
+		assert_eq!(prior_eofy(date(2025, 6, 30)), date(2024, 6, 30));
+	}
+
+	#[test]
+	fn eofy_31_december() {
+		let eofy_date = date(2025, 12, 31);
+
+		assert_eq!(get_eofy(&date(2025, 1, 1), &eofy_date), date(2025, 12, 31));
+		assert_eq!(
+			get_eofy(&date(2025, 12, 31), &eofy_date),
+			date(2025, 12, 31)
+		);
+		assert_eq!(get_eofy(&date(2026, 1, 1), &eofy_date), date(2026, 12, 31));
+
+		// Financial year is the calendar year
+		assert_eq!(sofy_from_eofy(date(2025, 12, 31)), date(2025, 1, 1));
+		assert_eq!(prior_eofy(date(2025, 12, 31)), date(2024, 12, 31));
+	}
+
+	#[test]
+	fn eofy_end_of_february() {
+		// Year ending on the last day of February ends on 29 February in leap years, whether specified as 28 or 29 February
+		for eofy_date in [date(2023, 2, 28), date(2024, 2, 29)] {
+			assert_eq!(get_eofy(&date(2023, 3, 1), &eofy_date), date(2024, 2, 29));
+			assert_eq!(get_eofy(&date(2024, 2, 28), &eofy_date), date(2024, 2, 29));
+			assert_eq!(get_eofy(&date(2024, 2, 29), &eofy_date), date(2024, 2, 29));
+			assert_eq!(get_eofy(&date(2024, 3, 1), &eofy_date), date(2025, 2, 28));
+			assert_eq!(get_eofy(&date(2025, 2, 28), &eofy_date), date(2025, 2, 28));
+		}
+
+		assert_eq!(sofy_from_eofy(date(2024, 2, 29)), date(2023, 3, 1));
+		assert_eq!(sofy_from_eofy(date(2025, 2, 28)), date(2024, 3, 1));
+		assert_eq!(prior_eofy(date(2024, 2, 29)), date(2023, 2, 28));
+		assert_eq!(prior_eofy(date(2025, 2, 28)), date(2024, 2, 29));
+	}
+
+	#[test]
+	fn eofy_mid_month() {
+		let eofy_date = date(2025, 3, 15);
+
+		assert_eq!(get_eofy(&date(2025, 3, 15), &eofy_date), date(2025, 3, 15));
+		assert_eq!(get_eofy(&date(2025, 3, 16), &eofy_date), date(2026, 3, 15));
+		assert_eq!(sofy_from_eofy(date(2025, 3, 15)), date(2024, 3, 16));
+		assert_eq!(prior_eofy(date(2025, 3, 15)), date(2024, 3, 15));
+	}
+
+	#[test]
+	fn eofy_with_change_of_year_end() {
+		// Year ended 30 June until 30 June 2024, then a short year to 31 December 2024, then calendar years
+		let eofy_date = date(2025, 12, 31);
+		let financial_year_ends = [date(2024, 6, 30)];
+
+		assert_eq!(
+			get_eofy_with_history(&date(2024, 1, 1), &eofy_date, &financial_year_ends),
+			date(2024, 6, 30)
+		);
+		assert_eq!(
+			get_eofy_with_history(&date(2024, 7, 1), &eofy_date, &financial_year_ends),
+			date(2024, 12, 31)
+		);
+		assert_eq!(
+			get_eofy_with_history(&date(2025, 1, 1), &eofy_date, &financial_year_ends),
+			date(2025, 12, 31)
+		);
+
+		assert_eq!(
+			sofy_from_eofy_with_history(date(2024, 6, 30), &financial_year_ends),
+			date(2023, 7, 1)
+		);
+		assert_eq!(
+			sofy_from_eofy_with_history(date(2024, 12, 31), &financial_year_ends),
+			date(2024, 7, 1)
+		);
+		assert_eq!(
+			prior_eofy_with_history(date(2024, 12, 31), &financial_year_ends),
+			date(2024, 6, 30)
+		);
+		assert_eq!(
+			prior_eofy_with_history(date(2025, 12, 31), &financial_year_ends),
+			date(2024, 12, 31)
+		);
+	}
+
+	#[test]
+	fn format_quantity_dps() {
+		// No decimal places
+		assert_eq!(format_quantity(0, 0, ""), "0");
+		assert_eq!(format_quantity(1234567, 0, "$"), "$1,234,567");
+		assert_eq!(format_quantity(-1234, 0, "USD"), "-1,234 USD");
+
+		// 2 decimal places
+		assert_eq!(format_quantity(0, 2, ""), "0.00");
+		assert_eq!(format_quantity(5, 2, "$"), "$0.05");
+		assert_eq!(format_quantity(123456, 2, "$"), "$1,234.56");
+		assert_eq!(format_quantity(-100, 2, "$"), "-$1.00");
+		assert_eq!(format_quantity(150, 2, "$ {1.50}"), "$1.50 {1.50}");
+
+		// 3 decimal places
+		assert_eq!(format_quantity(5, 3, ""), "0.005");
+		assert_eq!(format_quantity(1234567, 3, "$"), "$1,234.567");
+		assert_eq!(format_quantity(-1000000, 3, "BTC"), "-1,000.000 BTC");
+	}
+}
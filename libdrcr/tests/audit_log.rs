@@ -0,0 +1,109 @@
+/*
+	DrCr: Double-entry bookkeeping framework
+	Copyright (C) 2022-2025  Lee Yingtong Li (RunasSudo)
+
+	This program is free software: you can redistribute it and/or modify
+	it under the terms of the GNU Affero General Public License as published by
+	the Free Software Foundation, either version 3 of the License, or
+	(at your option) any later version.
+
+	This program is distributed in the hope that it will be useful,
+	but WITHOUT ANY WARRANTY; without even the implied warranty of
+	MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+	GNU Affero General Public License for more details.
+
+	You should have received a copy of the GNU Affero General Public License
+	along with this program.  If not, see <https://www.gnu.org/licenses/>.
+*/
+
+//! Tests of the audit log
+
+mod common;
+
+use libdrcr::db::{DbConnection, DbError};
+
+use common::{date, empty_db, insert_transaction};
+
+/// Record an operation in the audit log, as the frontend does
+async fn record_operation(db: &DbConnection, operation: &str, table_name: &str, row_ids: &str) {
+	let mut connection = db.connect().await.unwrap();
+	sqlx::query("INSERT INTO audit_log (dt, operation, table_name, row_ids, user) VALUES (strftime('%Y-%m-%d %H:%M:%f000', 'now'), $1, $2, $3, 'alice')")
+		.bind(operation)
+		.bind(table_name)
+		.bind(row_ids)
+		.execute(&mut *connection)
+		.await
+		.unwrap();
+}
+
+#[tokio::test]
+async fn audit_log_records_operations_and_changes() {
+	let db = empty_db().await;
+	insert_transaction(
+		&db,
+		date(2025, 3, 1),
+		"Salary",
+		&[("Cash", 1000), ("Salary", -1000)],
+	)
+	.await;
+	record_operation(&db, "insert_transaction", "transactions", "1").await;
+
+	// Changes to figures from lodged returns are also recorded
+	let mut connection = db.connect().await.unwrap();
+	sqlx::query("INSERT INTO austax_reference_returns (year, item, quantity) VALUES (2025, 'income1', 1000)")
+		.execute(&mut *connection)
+		.await
+		.unwrap();
+	drop(connection);
+	record_operation(&db, "delete_statement_lines", "statement_lines", "3,4").await;
+
+	let audit_log = db.get_audit_log().await.unwrap();
+	let entries = audit_log
+		.iter()
+		.map(|e| {
+			(
+				e.operation.as_str(),
+				e.table_name.as_str(),
+				e.row_ids.clone(),
+				e.user.as_deref(),
+			)
+		})
+		.collect::<Vec<_>>();
+
+	// Most recent first
+	assert_eq!(
+		entries,
+		vec![
+			(
+				"delete_statement_lines",
+				"statement_lines",
+				vec![3, 4],
+				Some("alice")
+			),
+			("INSERT", "austax_reference_returns", vec![1], None),
+			("insert_transaction", "transactions", vec![1], Some("alice")),
+			("INSERT", "postings", vec![2], None),
+			("INSERT", "postings", vec![1], None),
+			("INSERT", "transactions", vec![1], None),
+		]
+	);
+}
+
+#[tokio::test]
+async fn audit_log_invalid_entry() {
+	let db = empty_db().await;
+	let mut connection = db.connect().await.unwrap();
+	sqlx::query("INSERT INTO audit_log (dt, operation, table_name, row_ids) VALUES ('yesterday', 'INSERT', 'transactions', '1')")
+		.execute(&mut *connection)
+		.await
+		.unwrap();
+	drop(connection);
+
+	match db.get_audit_log().await {
+		Err(DbError::InvalidMetadata { key, value }) => {
+			assert_eq!(key, "audit_log.dt");
+			assert_eq!(value, "yesterday");
+		}
+		other => panic!("Expected InvalidMetadata, got {:?}", other),
+	}
+}
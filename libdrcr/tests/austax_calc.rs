@@ -0,0 +1,155 @@
+/*
+	DrCr: Double-entry bookkeeping framework
+	Copyright (C) 2022-2025  Lee Yingtong Li (RunasSudo)
+
+	This program is free software: you can redistribute it and/or modify
+	it under the terms of the GNU Affero General Public License as published by
+	the Free Software Foundation, either version 3 of the License, or
+	(at your option) any later version.
+
+	This program is distributed in the hope that it will be useful,
+	but WITHOUT ANY WARRANTY; without even the implied warranty of
+	MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+	GNU Affero General Public License for more details.
+
+	You should have received a copy of the GNU Affero General Public License
+	along with this program.  If not, see <https://www.gnu.org/licenses/>.
+*/
+
+//! Tests of the tax calculations in the austax plugin (`calc.luau`)
+
+use mlua::{Function, Lua, Table};
+
+/// Load the `calc` module of the austax plugin
+fn load_calc(lua: &Lua) -> Table {
+	let package = lua.globals().get::<Table>("package").unwrap();
+	package
+		.set(
+			"path",
+			format!(
+				"{0}/plugins/?.luau;{0}/plugins/?/init.luau",
+				env!("CARGO_MANIFEST_DIR")
+			),
+		)
+		.unwrap();
+
+	let require = lua.load("require").eval::<Function>().unwrap();
+	require.call::<Table>("austax/calc").unwrap()
+}
+
+/// Reporting context for the financial year ending 30 June 2025, with amounts to 2 decimal places
+fn context(lua: &Lua) -> Table {
+	let context = lua.create_table().unwrap();
+	context.set("eofy_date", "2025-06-30").unwrap();
+	context.set("dps", 2).unwrap();
+	context
+}
+
+/// Convert whole dollars and cents to a quantity in cents
+fn dollars(dollars: i64, cents: i64) -> i64 {
+	dollars * 100 + cents
+}
+
+#[test]
+fn base_income_tax_brackets() {
+	let lua = Lua::new();
+	let calc = load_calc(&lua);
+	let base_income_tax = |net_taxable: i64| {
+		calc.get::<Function>("base_income_tax")
+			.unwrap()
+			.call::<i64>((net_taxable, context(&lua)))
+			.unwrap()
+	};
+
+	// Tax-free threshold
+	assert_eq!(base_income_tax(dollars(18200, 0)), 0);
+	assert_eq!(base_income_tax(dollars(18201, 0)), dollars(0, 16));
+
+	// Each bracket ends with the flat amount of the following bracket
+	assert_eq!(base_income_tax(dollars(44999, 0)), dollars(4287, 84));
+	assert_eq!(base_income_tax(dollars(45000, 0)), dollars(4288, 0));
+	assert_eq!(base_income_tax(dollars(45001, 0)), dollars(4288, 30));
+
+	assert_eq!(base_income_tax(dollars(135000, 0)), dollars(31288, 0));
+	assert_eq!(base_income_tax(dollars(135001, 0)), dollars(31288, 37));
+
+	assert_eq!(base_income_tax(dollars(190000, 0)), dollars(51638, 0));
+	assert_eq!(base_income_tax(dollars(190001, 0)), dollars(51638, 45));
+
+	// Rounded half up to the cent
+	assert_eq!(base_income_tax(dollars(18200, 3)), 0);
+	assert_eq!(base_income_tax(dollars(18200, 4)), 1);
+}
+
+#[test]
+fn lito_thresholds() {
+	let lua = Lua::new();
+	let calc = load_calc(&lua);
+	let lito = |net_taxable: i64, tax_total: i64| {
+		calc.get::<Function>("lito")
+			.unwrap()
+			.call::<i64>((net_taxable, tax_total, context(&lua)))
+			.unwrap()
+	};
+
+	// Full offset, limited to the tax payable
+	assert_eq!(lito(dollars(37500, 0), dollars(1000, 0)), dollars(700, 0));
+	assert_eq!(lito(dollars(37500, 0), dollars(500, 0)), dollars(500, 0));
+	assert_eq!(lito(dollars(37501, 0), dollars(1000, 0)), dollars(699, 95));
+
+	// Reduced by 5% to $45,000, then 1.5%
+	assert_eq!(lito(dollars(44999, 0), dollars(5000, 0)), dollars(325, 5));
+	assert_eq!(lito(dollars(45000, 0), dollars(5000, 0)), dollars(325, 0));
+	assert_eq!(lito(dollars(45001, 0), dollars(5000, 0)), dollars(324, 98));
+
+	// Nil from $66,667, not negative due to rounding
+	assert_eq!(lito(dollars(66666, 0), dollars(10000, 0)), dollars(0, 1));
+	assert_eq!(lito(dollars(66667, 0), dollars(10000, 0)), 0);
+	assert_eq!(lito(dollars(66668, 0), dollars(10000, 0)), 0);
+}
+
+#[test]
+fn medicare_levy_thresholds() {
+	let lua = Lua::new();
+	let calc = load_calc(&lua);
+	let medicare_levy = |net_taxable: i64| {
+		calc.get::<Function>("medicare_levy")
+			.unwrap()
+			.call::<i64>((net_taxable, context(&lua)))
+			.unwrap()
+	};
+
+	// Nil below the lower threshold
+	assert_eq!(medicare_levy(dollars(27221, 99)), 0);
+	assert_eq!(medicare_levy(dollars(27222, 0)), 0);
+
+	// 10% of the excess over the lower threshold, up to the upper threshold
+	assert_eq!(medicare_levy(dollars(27223, 0)), dollars(0, 10));
+	assert_eq!(medicare_levy(dollars(34026, 99)), dollars(680, 50));
+
+	// 2% of the whole amount from the upper threshold
+	assert_eq!(medicare_levy(dollars(34027, 0)), dollars(680, 54));
+	assert_eq!(medicare_levy(dollars(34028, 0)), dollars(680, 56));
+}
+
+#[test]
+fn floor_to_negative() {
+	let lua = Lua::new();
+	let calc = load_calc(&lua);
+	let floor_to = |quantity: i64, multiple: i64| {
+		calc.get::<Function>("floor_to")
+			.unwrap()
+			.call::<i64>((quantity, multiple))
+	};
+
+	assert_eq!(floor_to(dollars(1, 50), 100).unwrap(), dollars(1, 0));
+	assert_eq!(floor_to(dollars(1, 0), 100).unwrap(), dollars(1, 0));
+	assert_eq!(floor_to(0, 100).unwrap(), 0);
+
+	// Rounded toward negative infinity, not toward zero
+	assert_eq!(floor_to(-dollars(1, 0), 100).unwrap(), -dollars(1, 0));
+	assert_eq!(floor_to(-dollars(1, 50), 100).unwrap(), -dollars(2, 0));
+	assert_eq!(floor_to(-1, 100).unwrap(), -dollars(1, 0));
+
+	assert!(floor_to(100, 0).is_err());
+}
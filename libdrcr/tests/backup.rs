@@ -0,0 +1,70 @@
+/*
+	DrCr: Double-entry bookkeeping framework
+	Copyright (C) 2022-2025  Lee Yingtong Li (RunasSudo)
+
+	This program is free software: you can redistribute it and/or modify
+	it under the terms of the GNU Affero General Public License as published by
+	the Free Software Foundation, either version 3 of the License, or
+	(at your option) any later version.
+
+	This program is distributed in the hope that it will be useful,
+	but WITHOUT ANY WARRANTY; without even the implied warranty of
+	MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+	GNU Affero General Public License for more details.
+
+	You should have received a copy of the GNU Affero General Public License
+	along with this program.  If not, see <https://www.gnu.org/licenses/>.
+*/
+
+//! Tests of copying the database
+
+mod common;
+
+use libdrcr::db::DbConnection;
+
+use common::{date, fixture_db, insert_transaction};
+
+/// Return a path in the temporary directory, removing any existing file
+fn temp_path(name: &str) -> String {
+	let path = std::env::temp_dir().join(format!("drcr_{}_{}.db", name, std::process::id()));
+	let _ = std::fs::remove_file(&path);
+	path.to_str().unwrap().to_string()
+}
+
+#[tokio::test]
+async fn backup_into_file() {
+	let db = fixture_db().await;
+
+	// An in-memory database is copied into memory unless the URI specifies otherwise
+	let source_path = temp_path("backup_source");
+	db.backup_into(&format!("file:{}?mode=rwc", source_path))
+		.await
+		.unwrap();
+	let source = DbConnection::new(&format!("sqlite:{}", source_path))
+		.await
+		.unwrap();
+
+	insert_transaction(
+		&source,
+		date(2025, 3, 1),
+		"Before backup",
+		&[("Cash", 100), ("Salary", -100)],
+	)
+	.await;
+
+	let path = temp_path("backup");
+	source.backup_into(&path).await.unwrap();
+
+	// The copy is a complete database, including committed changes
+	let backup = DbConnection::new(&format!("sqlite:{}", path))
+		.await
+		.unwrap();
+	assert_eq!(backup.metadata().eofy_date, date(2025, 6, 30));
+	assert_eq!(backup.get_transactions().await.unwrap().len(), 5);
+
+	// The file must not already exist
+	assert!(source.backup_into(&path).await.is_err());
+
+	std::fs::remove_file(&path).unwrap();
+	std::fs::remove_file(&source_path).unwrap();
+}
@@ -0,0 +1,97 @@
+/*
+	DrCr: Double-entry bookkeeping framework
+	Copyright (C) 2022-2025  Lee Yingtong Li (RunasSudo)
+
+	This program is free software: you can redistribute it and/or modify
+	it under the terms of the GNU Affero General Public License as published by
+	the Free Software Foundation, either version 3 of the License, or
+	(at your option) any later version.
+
+	This program is distributed in the hope that it will be useful,
+	but WITHOUT ANY WARRANTY; without even the implied warranty of
+	MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+	GNU Affero General Public License for more details.
+
+	You should have received a copy of the GNU Affero General Public License
+	along with this program.  If not, see <https://www.gnu.org/licenses/>.
+*/
+
+//! Tests of caching reporting products across reports
+
+mod common;
+
+use std::sync::{Arc, Mutex};
+
+use libdrcr::db::DbConnection;
+use libdrcr::reporting::api;
+use libdrcr::reporting::types::ReportingCache;
+
+use common::{date, fixture_db, insert_transaction, reporting_context};
+
+/// Get the total assets at the end of FY2025, using the given cache
+async fn total_assets(db: &DbConnection, cache: &Arc<Mutex<ReportingCache>>) -> i64 {
+	let mut context = reporting_context(db.clone());
+	Arc::get_mut(&mut context).unwrap().cache = Some(cache.clone());
+
+	let report = api::balance_sheet(context, &[date(2025, 6, 30)])
+		.await
+		.unwrap();
+	report.quantity_for_id("total_assets").unwrap()[0]
+}
+
+#[tokio::test]
+async fn cache_hit() {
+	let db = fixture_db().await;
+	let cache = Arc::new(Mutex::new(ReportingCache::new()));
+	assert_eq!(total_assets(&db, &cache).await, 18000);
+
+	// Without invalidation, the cached products are reused and the new transaction is not reflected
+	insert_transaction(
+		&db,
+		date(2025, 3, 1),
+		"Salary",
+		&[("Cash", 1000), ("Salary", -1000)],
+	)
+	.await;
+	assert_eq!(total_assets(&db, &cache).await, 18000);
+}
+
+#[tokio::test]
+async fn cache_invalidate() {
+	let db = fixture_db().await;
+	let cache = Arc::new(Mutex::new(ReportingCache::new()));
+	assert_eq!(total_assets(&db, &cache).await, 18000);
+
+	insert_transaction(
+		&db,
+		date(2025, 3, 1),
+		"Salary",
+		&[("Cash", 1000), ("Salary", -1000)],
+	)
+	.await;
+	cache.lock().unwrap().invalidate();
+	assert_eq!(total_assets(&db, &cache).await, 19000);
+}
+
+#[tokio::test]
+async fn cache_key_includes_configuration() {
+	let db = fixture_db().await;
+	let cache = Arc::new(Mutex::new(ReportingCache::new()));
+	assert_eq!(total_assets(&db, &cache).await, 18000);
+
+	// Products cached for one configuration are not reused for another
+	insert_transaction(
+		&db,
+		date(2025, 3, 1),
+		"Salary",
+		&[("Cash", 1000), ("Salary", -1000)],
+	)
+	.await;
+	let mut context = reporting_context(db.clone());
+	Arc::get_mut(&mut context).unwrap().cache = Some(cache.clone());
+	Arc::get_mut(&mut context).unwrap().transactions_cutoff = Some(date(2025, 6, 30));
+	let report = api::balance_sheet(context, &[date(2025, 6, 30)])
+		.await
+		.unwrap();
+	assert_eq!(report.quantity_for_id("total_assets"), Some(&vec![19000]));
+}
@@ -0,0 +1,207 @@
+/*
+	DrCr: Double-entry bookkeeping framework
+	Copyright (C) 2022-2025  Lee Yingtong Li (RunasSudo)
+
+	This program is free software: you can redistribute it and/or modify
+	it under the terms of the GNU Affero General Public License as published by
+	the Free Software Foundation, either version 3 of the License, or
+	(at your option) any later version.
+
+	This program is distributed in the hope that it will be useful,
+	but WITHOUT ANY WARRANTY; without even the implied warranty of
+	MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+	GNU Affero General Public License for more details.
+
+	You should have received a copy of the GNU Affero General Public License
+	along with this program.  If not, see <https://www.gnu.org/licenses/>.
+*/
+
+//! Fixtures shared by the integration tests
+
+#![allow(dead_code)]
+
+use std::sync::Arc;
+
+use chrono::NaiveDate;
+use libdrcr::db::DbConnection;
+use libdrcr::model::transaction::{Posting, Transaction, TransactionWithPostings};
+use libdrcr::reporting::dynamic_report::DynamicReport;
+use libdrcr::reporting::types::ReportingContext;
+use libdrcr::QuantityInt;
+
+/// Return the date with the given year, month and day
+pub fn date(year: i32, month: u32, day: u32) -> NaiveDate {
+	NaiveDate::from_ymd_opt(year, month, day).unwrap()
+}
+
+/// Create an empty in-memory database with the financial year ending 30 June and amounts to 2 decimal places
+pub async fn empty_db() -> DbConnection {
+	DbConnection::new_in_memory(date(2025, 6, 30), "$", 2)
+		.await
+		.unwrap()
+}
+
+/// Configure the account with the given kind (e.g. `"drcr.asset"`)
+pub async fn set_account_kind(db: &DbConnection, account: &str, kind: &str) {
+	let mut connection = db.connect().await.unwrap();
+	sqlx::query("INSERT INTO account_configurations (account, kind) VALUES ($1, $2)")
+		.bind(account)
+		.bind(kind)
+		.execute(&mut *connection)
+		.await
+		.unwrap();
+}
+
+/// Configure the account with the given kind and data, given as JSON (e.g. `r#"{"contra": true}"#`)
+pub async fn set_account_kind_with_data(db: &DbConnection, account: &str, kind: &str, data: &str) {
+	let mut connection = db.connect().await.unwrap();
+	sqlx::query("INSERT INTO account_configurations (account, kind, data) VALUES ($1, $2, $3)")
+		.bind(account)
+		.bind(kind)
+		.bind(data)
+		.execute(&mut *connection)
+		.await
+		.unwrap();
+}
+
+/// Set the metadata key to the given value
+///
+/// [DbConnection::reload_metadata] must be called for the change to take effect.
+pub async fn insert_metadata(db: &DbConnection, key: &str, value: &str) {
+	let mut connection = db.connect().await.unwrap();
+	sqlx::query("INSERT INTO metadata (key, value) VALUES ($1, $2)")
+		.bind(key)
+		.bind(value)
+		.execute(&mut *connection)
+		.await
+		.unwrap();
+}
+
+/// Insert a transaction in the reporting commodity on the given date, with one posting for each `(account, quantity)`
+pub async fn insert_transaction(
+	db: &DbConnection,
+	dt: NaiveDate,
+	description: &str,
+	postings: &[(&str, QuantityInt)],
+) {
+	let postings = postings
+		.iter()
+		.map(|(account, quantity)| (*account, *quantity, "$"))
+		.collect::<Vec<_>>();
+	insert_commodity_transaction(db, dt, description, &postings).await;
+}
+
+/// Insert a transaction on the given date, with one posting for each `(account, quantity, commodity)`
+pub async fn insert_commodity_transaction(
+	db: &DbConnection,
+	dt: NaiveDate,
+	description: &str,
+	postings: &[(&str, QuantityInt, &str)],
+) {
+	db.insert_transaction(&TransactionWithPostings {
+		transaction: Transaction {
+			id: None,
+			dt: dt.and_hms_opt(0, 0, 0).unwrap(),
+			description: description.to_string(),
+			origin: None,
+		},
+		postings: postings
+			.iter()
+			.map(|(account, quantity, commodity)| Posting {
+				id: None,
+				transaction_id: None,
+				description: None,
+				account: account.to_string(),
+				quantity: *quantity,
+				commodity: commodity.to_string(),
+				quantity_ascost: None,
+				running_balance: None,
+			})
+			.collect(),
+	})
+	.await
+	.unwrap();
+}
+
+/// Insert a price of `numerator / denominator` for one unit of the commodity, as at the given date
+pub async fn insert_commodity_price(
+	db: &DbConnection,
+	dt: NaiveDate,
+	commodity: &str,
+	numerator: i64,
+	denominator: i64,
+) {
+	let mut connection = db.connect().await.unwrap();
+	sqlx::query(
+		"INSERT INTO commodity_prices (dt, commodity, price_numerator, price_denominator) VALUES ($1, $2, $3, $4)",
+	)
+	.bind(format!("{} 00:00:00.000000", dt))
+	.bind(commodity)
+	.bind(numerator)
+	.bind(denominator)
+	.execute(&mut *connection)
+	.await
+	.unwrap();
+}
+
+/// Create a database with a cash account, salary and rent over the financial years ending 30 June 2024 and 2025
+///
+/// | FY   | Salary  | Rent   |
+/// |------|---------|--------|
+/// | 2024 | $100.00 | $30.00 |
+/// | 2025 | $150.00 | $40.00 |
+pub async fn fixture_db() -> DbConnection {
+	let db = empty_db().await;
+
+	set_account_kind(&db, "Cash", "drcr.asset").await;
+	set_account_kind(&db, "Salary", "drcr.income").await;
+	set_account_kind(&db, "Rent", "drcr.expense").await;
+
+	for (year, salary, rent) in [(2024, 10000, 3000), (2025, 15000, 4000)] {
+		insert_transaction(
+			&db,
+			date(year, 1, 1),
+			"Salary",
+			&[("Cash", salary), ("Salary", -salary)],
+		)
+		.await;
+		insert_transaction(
+			&db,
+			date(year, 2, 1),
+			"Rent",
+			&[("Rent", rent), ("Cash", -rent)],
+		)
+		.await;
+	}
+
+	db
+}
+
+/// Initialise a [ReportingContext] for the database, with the built-in reporting steps and no plugins
+pub fn reporting_context(db: DbConnection) -> Arc<ReportingContext> {
+	reporting_context_with_plugins(db, &[])
+}
+
+/// Initialise a [ReportingContext] for the database, with the built-in reporting steps and the named plugins
+pub fn reporting_context_with_plugins(db: DbConnection, plugins: &[&str]) -> Arc<ReportingContext> {
+	let eofy_date = db.metadata().eofy_date;
+	let mut context = ReportingContext::new(
+		db,
+		format!("{}/plugins", env!("CARGO_MANIFEST_DIR")),
+		plugins.iter().map(|p| p.to_string()).collect(),
+		eofy_date,
+		"$".to_string(),
+	);
+	libdrcr::plugin::register_lookup_fns(&mut context).unwrap();
+	libdrcr::reporting::steps::register_lookup_fns(&mut context);
+	libdrcr::reporting::builders::register_dynamic_builders(&mut context);
+	Arc::new(context)
+}
+
+/// Return the quantities of the row with the given text, as displayed in the report
+pub fn quantity_for_text(report: &DynamicReport, text: &str) -> Option<Vec<QuantityInt>> {
+	report
+		.iter_rows()
+		.find(|(_, row)| row.text == text)
+		.map(|(_, row)| row.quantity.clone())
+}
@@ -0,0 +1,127 @@
+/*
+	DrCr: Double-entry bookkeeping framework
+	Copyright (C) 2022-2025  Lee Yingtong Li (RunasSudo)
+
+	This program is free software: you can redistribute it and/or modify
+	it under the terms of the GNU Affero General Public License as published by
+	the Free Software Foundation, either version 3 of the License, or
+	(at your option) any later version.
+
+	This program is distributed in the hope that it will be useful,
+	but WITHOUT ANY WARRANTY; without even the implied warranty of
+	MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+	GNU Affero General Public License for more details.
+
+	You should have received a copy of the GNU Affero General Public License
+	along with this program.  If not, see <https://www.gnu.org/licenses/>.
+*/
+
+//! Tests of the reporting step graph
+
+mod common;
+
+use libdrcr::reporting::calculator::{steps_as_graphviz, steps_for_targets, ReportingPlan};
+use libdrcr::reporting::dynamic_report::DynamicReport;
+use libdrcr::reporting::generate_report_with_plan;
+use libdrcr::reporting::types::{
+	DateArgs, DateStartDateEndArgs, MultipleDateStartDateEndArgs, ReportingProductId,
+	ReportingProductKind, ReportingStepArgs,
+};
+
+use common::{date, empty_db, fixture_db, insert_metadata, reporting_context, set_account_kind};
+
+#[tokio::test]
+async fn graphviz_trial_balance() {
+	let context = reporting_context(empty_db().await);
+	let targets = vec![ReportingProductId {
+		name: "TrialBalance".to_string(),
+		kind: ReportingProductKind::DynamicReport,
+		args: ReportingStepArgs::DateArgs(DateArgs {
+			date: date(2025, 6, 30),
+			time: None,
+		}),
+	}];
+	let (steps, dependencies) = steps_for_targets(targets, &context).unwrap();
+	let graphviz = steps_as_graphviz(&steps, &dependencies);
+
+	assert!(graphviz.starts_with("strict digraph drcr {\n"));
+	assert!(graphviz.ends_with("\n}"));
+
+	let lines = graphviz.lines().collect::<Vec<_>>();
+	let nodes = lines
+		.iter()
+		.filter(|l| !l.contains(" -> ") && l.ends_with("];"))
+		.collect::<Vec<_>>();
+	let product_edges = lines
+		.iter()
+		.filter(|l| l.contains(" -> ") && !l.contains("[label="))
+		.count();
+	let dependency_edges = lines
+		.iter()
+		.filter(|l| l.contains(" -> ") && l.contains("[label="))
+		.count();
+
+	// TrialBalance <- AllTransactionsExceptEarningsToEquity <- CombineOrdinaryTransactions <- DBBalances, OpeningBalances and PostUnreconciledStatementLines, the latter two generating balances from transactions
+	assert_eq!(nodes.len(), 8);
+	assert_eq!(product_edges, 8);
+	assert_eq!(dependency_edges, 7);
+
+	// Source steps, dynamic builders and reports are distinguished
+	let count_nodes = |attr: &str| nodes.iter().filter(|l| l.contains(attr)).count();
+	assert_eq!(count_nodes("fillcolor=palegreen"), 3);
+	assert_eq!(count_nodes("fillcolor=lightblue"), 2);
+	assert_eq!(count_nodes("fillcolor=orange"), 1);
+}
+
+#[tokio::test]
+async fn plan_invalidated_by_metadata() {
+	let mut db = fixture_db().await;
+	set_account_kind(&db, "Cash", "drcr.cash").await;
+
+	let targets = vec![ReportingProductId {
+		name: "IncomeStatement".to_string(),
+		kind: ReportingProductKind::DynamicReport,
+		args: ReportingStepArgs::MultipleDateStartDateEndArgs(MultipleDateStartDateEndArgs {
+			dates: vec![DateStartDateEndArgs {
+				date_start: date(2024, 7, 1),
+				date_end: date(2025, 6, 30),
+			}],
+			cumulative: false,
+		}),
+	}];
+	let uses_cash_basis = |plan: &ReportingPlan| {
+		plan.steps
+			.iter()
+			.any(|s| s.id.name == "CashBasisTransactions")
+	};
+
+	// Plan is resolved on first use and remains valid for the same configuration
+	let mut plan = None;
+	let context = reporting_context(db.clone());
+	generate_report_with_plan(targets.clone(), context.clone(), &mut plan)
+		.await
+		.unwrap();
+	let accrual_plan = plan.clone().unwrap();
+	assert!(!uses_cash_basis(&accrual_plan));
+	assert!(accrual_plan.is_valid_for(&targets, &context));
+
+	// Changing cash_basis changes the steps required, so the plan must be resolved again
+	insert_metadata(&db, "cash_basis", "true").await;
+	db.reload_metadata().await.unwrap();
+
+	let context = reporting_context(db);
+	assert!(!accrual_plan.is_valid_for(&targets, &context));
+
+	let products = generate_report_with_plan(targets.clone(), context.clone(), &mut plan)
+		.await
+		.unwrap();
+	assert!(uses_cash_basis(plan.as_ref().unwrap()));
+	assert!(plan.as_ref().unwrap().is_valid_for(&targets, &context));
+
+	let report = products
+		.get_owned_or_err(&targets[0])
+		.unwrap()
+		.downcast::<DynamicReport>()
+		.unwrap();
+	assert_eq!(report.quantity_for_id("net_surplus"), Some(&vec![11000]));
+}
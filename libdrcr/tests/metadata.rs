@@ -0,0 +1,93 @@
+/*
+	DrCr: Double-entry bookkeeping framework
+	Copyright (C) 2022-2025  Lee Yingtong Li (RunasSudo)
+
+	This program is free software: you can redistribute it and/or modify
+	it under the terms of the GNU Affero General Public License as published by
+	the Free Software Foundation, either version 3 of the License, or
+	(at your option) any later version.
+
+	This program is distributed in the hope that it will be useful,
+	but WITHOUT ANY WARRANTY; without even the implied warranty of
+	MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+	GNU Affero General Public License for more details.
+
+	You should have received a copy of the GNU Affero General Public License
+	along with this program.  If not, see <https://www.gnu.org/licenses/>.
+*/
+
+//! Tests of parsing the metadata table
+
+mod common;
+
+use libdrcr::db::DbError;
+
+use common::{empty_db, insert_metadata};
+
+/// Assert that reloading metadata, with the given key set to the given value, fails with [DbError::InvalidMetadata]
+async fn assert_invalid_metadata(key: &str, value: &str) {
+	let mut db = empty_db().await;
+	insert_metadata(&db, key, value).await;
+
+	match db.reload_metadata().await {
+		Err(DbError::InvalidMetadata {
+			key: error_key,
+			value: error_value,
+		}) => {
+			assert_eq!(error_key, key);
+			assert_eq!(error_value, value);
+		}
+		Err(e) => panic!("Unexpected error: {}", e),
+		Ok(_) => panic!("Invalid {} was accepted", key),
+	}
+}
+
+#[tokio::test]
+async fn invalid_show_native_amounts() {
+	assert_invalid_metadata("show_native_amounts", "yes").await;
+}
+
+#[tokio::test]
+async fn invalid_rounding_reconciliation() {
+	assert_invalid_metadata("rounding_reconciliation", "1").await;
+}
+
+#[tokio::test]
+async fn invalid_cash_basis() {
+	assert_invalid_metadata("cash_basis", "TRUE").await;
+}
+
+#[tokio::test]
+async fn invalid_column_date_format() {
+	assert_invalid_metadata("column_date_format", "FY%Q").await;
+}
+
+#[tokio::test]
+async fn invalid_group_accounts() {
+	assert_invalid_metadata("group_accounts", "on").await;
+}
+
+#[tokio::test]
+async fn invalid_financial_year_end() {
+	let mut db = empty_db().await;
+
+	let mut connection = db.connect().await.unwrap();
+	sqlx::query("INSERT INTO financial_year_ends (dt) VALUES ('30/06/2024')")
+		.execute(&mut *connection)
+		.await
+		.unwrap();
+	drop(connection);
+
+	assert!(matches!(
+		db.reload_metadata().await,
+		Err(DbError::InvalidMetadata {
+			key: "financial_year_ends.dt",
+			..
+		})
+	));
+}
+
+#[tokio::test]
+async fn invalid_check_current_year_earnings() {
+	assert_invalid_metadata("check_current_year_earnings", "TRUE").await;
+}
@@ -0,0 +1,967 @@
+/*
+	DrCr: Double-entry bookkeeping framework
+	Copyright (C) 2022-2025  Lee Yingtong Li (RunasSudo)
+
+	This program is free software: you can redistribute it and/or modify
+	it under the terms of the GNU Affero General Public License as published by
+	the Free Software Foundation, either version 3 of the License, or
+	(at your option) any later version.
+
+	This program is distributed in the hope that it will be useful,
+	but WITHOUT ANY WARRANTY; without even the implied warranty of
+	MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+	GNU Affero General Public License for more details.
+
+	You should have received a copy of the GNU Affero General Public License
+	along with this program.  If not, see <https://www.gnu.org/licenses/>.
+*/
+
+//! Tests of the built-in reports over a small fixture database
+
+mod common;
+
+use std::collections::HashMap;
+use std::sync::Arc;
+
+use libdrcr::db::DbConnection;
+use libdrcr::reporting::api;
+use libdrcr::reporting::dynamic_report::{DynamicReport, DynamicReportEntry};
+use libdrcr::reporting::executor::ReportingExecutionError;
+use libdrcr::reporting::types::{
+	DateArgs, DateStartDateEndArgs, MultipleDateStartDateEndArgs, ReportingContext,
+	ReportingProductId, ReportingProductKind, ReportingStepArgs, TransactionExclusion,
+};
+use libdrcr::reporting::{generate_report_with_cancellation, ReportingError};
+use libdrcr::QuantityInt;
+
+use tokio_util::sync::CancellationToken;
+
+use common::{
+	date, fixture_db, insert_commodity_price, insert_commodity_transaction, insert_metadata,
+	insert_transaction, quantity_for_text, reporting_context, reporting_context_with_plugins,
+	set_account_kind, set_account_kind_with_data,
+};
+
+#[tokio::test]
+async fn trial_balance() {
+	let context = reporting_context(fixture_db().await);
+	let report = api::trial_balance(context, date(2025, 6, 30), false)
+		.await
+		.unwrap();
+
+	assert_eq!(report.columns, vec!["Dr", "Cr"]);
+
+	// All accounts are listed in the accounts section, in order
+	let accounts = match report.by_id("accounts") {
+		Some(DynamicReportEntry::Section(section)) => section,
+		_ => panic!("Expected accounts section"),
+	};
+	let account_names = accounts
+		.entries
+		.iter()
+		.map(|e| match e {
+			DynamicReportEntry::Row(row) => row.text.as_str(),
+			_ => panic!("Expected only rows in accounts section"),
+		})
+		.collect::<Vec<_>>();
+	assert_eq!(account_names, vec!["Cash", "Rent", "Salary"]);
+
+	assert_eq!(quantity_for_text(&report, "Cash"), Some(vec![18000, 0]));
+	assert_eq!(quantity_for_text(&report, "Rent"), Some(vec![7000, 0]));
+	assert_eq!(quantity_for_text(&report, "Salary"), Some(vec![0, 25000]));
+
+	// Debits equal credits
+	assert_eq!(report.quantity_for_id("totals"), Some(&vec![25000, 25000]));
+	assert!(report.by_id("out_of_balance").is_none());
+}
+
+#[tokio::test]
+async fn balance_sheet_comparative() {
+	let context = reporting_context(fixture_db().await);
+	let report = api::balance_sheet(context, &[date(2025, 6, 30), date(2024, 6, 30)])
+		.await
+		.unwrap();
+
+	assert_eq!(report.columns, vec!["2025-06-30", "2024-06-30"]);
+
+	// Assets, liabilities and equity sections, separated by spacers
+	let section_titles = report
+		.entries
+		.iter()
+		.map(|e| match e {
+			DynamicReportEntry::Section(section) => section.text.as_deref(),
+			_ => None,
+		})
+		.collect::<Vec<_>>();
+	assert_eq!(
+		section_titles,
+		vec![
+			Some("Assets"),
+			None,
+			Some("Liabilities"),
+			None,
+			Some("Equity")
+		]
+	);
+
+	assert_eq!(quantity_for_text(&report, "Cash"), Some(vec![18000, 7000]));
+	assert_eq!(
+		report.quantity_for_id("total_assets"),
+		Some(&vec![18000, 7000])
+	);
+	assert_eq!(
+		report.quantity_for_id("total_liabilities"),
+		Some(&vec![0, 0])
+	);
+
+	// Current year earnings are for each financial year, with earlier years transferred to retained earnings
+	assert_eq!(
+		quantity_for_text(&report, "Current Year Earnings"),
+		Some(vec![11000, 7000])
+	);
+	assert_eq!(
+		quantity_for_text(&report, "Retained Earnings"),
+		Some(vec![7000, 0])
+	);
+	assert_eq!(
+		report.quantity_for_id("total_equity"),
+		Some(&vec![18000, 7000])
+	);
+}
+
+#[tokio::test]
+async fn income_statement_comparative() {
+	let context = reporting_context(fixture_db().await);
+	let report = api::income_statement(
+		context,
+		&[
+			(date(2024, 7, 1), date(2025, 6, 30)),
+			(date(2023, 7, 1), date(2024, 6, 30)),
+		],
+		false,
+	)
+	.await
+	.unwrap();
+
+	assert_eq!(report.columns.len(), 2);
+
+	assert_eq!(
+		quantity_for_text(&report, "Salary"),
+		Some(vec![15000, 10000])
+	);
+	assert_eq!(
+		report.quantity_for_id("total_income"),
+		Some(&vec![15000, 10000])
+	);
+	assert_eq!(quantity_for_text(&report, "Rent"), Some(vec![4000, 3000]));
+	assert_eq!(
+		report.quantity_for_id("total_expenses"),
+		Some(&vec![4000, 3000])
+	);
+	assert_eq!(
+		report.quantity_for_id("net_surplus"),
+		Some(&vec![11000, 7000])
+	);
+}
+
+#[tokio::test]
+async fn income_statement_cash_basis() {
+	let mut db = fixture_db().await;
+	set_account_kind(&db, "Cash", "drcr.cash").await;
+	set_account_kind(&db, "Receivables", "drcr.asset").await;
+
+	// Salary accrued but not yet received is not recognised on a cash basis
+	insert_transaction(
+		&db,
+		date(2025, 6, 30),
+		"Salary accrued",
+		&[("Receivables", 5000), ("Salary", -5000)],
+	)
+	.await;
+
+	let period = [(date(2024, 7, 1), date(2025, 6, 30))];
+	let context = reporting_context(db.clone());
+	let report = api::income_statement(context, &period, false)
+		.await
+		.unwrap();
+	assert_eq!(report.quantity_for_id("net_surplus"), Some(&vec![16000]));
+
+	insert_metadata(&db, "cash_basis", "true").await;
+	db.reload_metadata().await.unwrap();
+
+	let context = reporting_context(db);
+	let report = api::income_statement(context, &period, false)
+		.await
+		.unwrap();
+	assert_eq!(quantity_for_text(&report, "Salary"), Some(vec![15000]));
+	assert_eq!(report.quantity_for_id("net_surplus"), Some(&vec![11000]));
+}
+
+#[tokio::test]
+async fn income_statement_cash_basis_foreign_commodity_without_cost() {
+	let mut db = fixture_db().await;
+	set_account_kind(&db, "Cash", "drcr.cash").await;
+	insert_commodity_transaction(
+		&db,
+		date(2025, 3, 1),
+		"Gift in USD",
+		&[("Cash", 1000, "USD"), ("Salary", -1000, "USD")],
+	)
+	.await;
+
+	// With no cost basis, the USD postings do not count towards balances in $, whether balances come from the database or are computed from transactions
+	let period = [(date(2024, 7, 1), date(2025, 6, 30))];
+	let context = reporting_context(db.clone());
+	let accrual = api::income_statement(context, &period, false)
+		.await
+		.unwrap();
+	assert_eq!(accrual.quantity_for_id("net_surplus"), Some(&vec![11000]));
+
+	insert_metadata(&db, "cash_basis", "true").await;
+	db.reload_metadata().await.unwrap();
+
+	let context = reporting_context(db);
+	let cash_basis = api::income_statement(context, &period, false)
+		.await
+		.unwrap();
+	assert_eq!(quantity_for_text(&cash_basis, "Salary"), Some(vec![15000]));
+	assert_eq!(
+		cash_basis.quantity_for_id("net_surplus"),
+		Some(&vec![11000])
+	);
+}
+
+/// Return the [ReportingProductId] of the income statement for the financial year ending 30 June 2025
+fn income_statement_fy2025() -> ReportingProductId {
+	ReportingProductId {
+		name: "IncomeStatement".to_string(),
+		kind: ReportingProductKind::DynamicReport,
+		args: ReportingStepArgs::MultipleDateStartDateEndArgs(MultipleDateStartDateEndArgs {
+			dates: vec![DateStartDateEndArgs {
+				date_start: date(2024, 7, 1),
+				date_end: date(2025, 6, 30),
+			}],
+			cumulative: false,
+		}),
+	}
+}
+
+/// Return the total of postings to `account` in the transactions underlying the given row of the report
+async fn drilldown_total(
+	context: Arc<ReportingContext>,
+	report: &ReportingProductId,
+	row: &str,
+	account: &str,
+) -> QuantityInt {
+	api::transactions_for_row(context, report, row)
+		.await
+		.unwrap()
+		.iter()
+		.flat_map(|t| t.postings.iter())
+		.filter(|p| p.account == account)
+		.map(|p| p.quantity)
+		.sum()
+}
+
+#[tokio::test]
+async fn drilldown_income_statement_cash_basis() {
+	let mut db = fixture_db().await;
+	set_account_kind(&db, "Cash", "drcr.cash").await;
+	set_account_kind(&db, "Receivables", "drcr.asset").await;
+	insert_transaction(
+		&db,
+		date(2025, 6, 30),
+		"Salary accrued",
+		&[("Receivables", 5000), ("Salary", -5000)],
+	)
+	.await;
+
+	// The transactions drilled down into agree with the income statement, on an accruals basis
+	let context = reporting_context(db.clone());
+	assert_eq!(
+		drilldown_total(context, &income_statement_fy2025(), "Salary", "Salary").await,
+		-20000
+	);
+
+	// And on a cash basis, where the accrued salary is not recognised
+	insert_metadata(&db, "cash_basis", "true").await;
+	db.reload_metadata().await.unwrap();
+
+	let context = reporting_context(db);
+	assert_eq!(
+		drilldown_total(context, &income_statement_fy2025(), "Salary", "Salary").await,
+		-15000
+	);
+}
+
+#[tokio::test]
+async fn drilldown_income_statement_plugin_transactions() {
+	let db = fixture_db().await;
+	set_account_kind(&db, "Salary", "austax.income1").await;
+	set_account_kind(&db, "Income Tax", "drcr.expense").await;
+	set_account_kind(&db, "Income Tax Control", "drcr.liability").await;
+	insert_transaction(
+		&db,
+		date(2025, 3, 1),
+		"Salary",
+		&[("Cash", 6000000), ("Salary", -6000000)],
+	)
+	.await;
+
+	// Transactions generated by plugin steps are drilled down into, as they are reflected in the income statement
+	let context = reporting_context_with_plugins(db, &["austax"]);
+	let report = api::income_statement(
+		Arc::clone(&context),
+		&[(date(2024, 7, 1), date(2025, 6, 30))],
+		false,
+	)
+	.await
+	.unwrap();
+	let income_tax = quantity_for_text(&report, "Income Tax").unwrap()[0];
+	assert!(income_tax > 0);
+
+	assert_eq!(
+		drilldown_total(
+			context,
+			&income_statement_fy2025(),
+			"Income Tax",
+			"Income Tax"
+		)
+		.await,
+		income_tax
+	);
+}
+
+#[tokio::test]
+async fn balance_sheet_imbalance_not_reconciled_as_rounding() {
+	let mut db = fixture_db().await;
+	insert_metadata(&db, "rounding_reconciliation", "true").await;
+	db.reload_metadata().await.unwrap();
+
+	// Postings to an account with no configuration are omitted from the balance sheet
+	insert_transaction(
+		&db,
+		date(2025, 3, 1),
+		"Unknown",
+		&[("Cash", 1234), ("Mystery Account", -1234)],
+	)
+	.await;
+
+	let context = reporting_context(db);
+	let report = api::balance_sheet(context, &[date(2025, 6, 30)])
+		.await
+		.unwrap();
+
+	// The difference is not disguised as rounding
+	assert!(report.by_id("rounding").is_none());
+	assert_eq!(report.quantity_for_id("total_assets"), Some(&vec![19234]));
+	assert_eq!(report.quantity_for_id("total_equity"), Some(&vec![18000]));
+}
+
+#[tokio::test]
+async fn rounding_excluded_from_net_surplus() {
+	let mut db = fixture_db().await;
+	insert_metadata(&db, "rounding_reconciliation", "true").await;
+	insert_metadata(&db, "check_current_year_earnings", "true").await;
+	db.reload_metadata().await.unwrap();
+
+	insert_transaction(
+		&db,
+		date(2025, 6, 30),
+		"Rounding",
+		&[("Rounding", 37), ("Cash", -37)],
+	)
+	.await;
+
+	let period = [(date(2024, 7, 1), date(2025, 6, 30))];
+	let context = reporting_context(db.clone());
+	let report = api::income_statement(context, &period, false)
+		.await
+		.unwrap();
+
+	// Rounding is posted to equity, so is shown but does not affect net surplus or current year earnings
+	assert_eq!(report.quantity_for_id("rounding"), Some(&vec![-37]));
+	assert_eq!(report.quantity_for_id("net_surplus"), Some(&vec![11000]));
+	assert!(report.by_id("current_year_earnings_difference").is_none());
+
+	let context = reporting_context(db);
+	let report = api::balance_sheet(context, &[date(2025, 6, 30)])
+		.await
+		.unwrap();
+	assert_eq!(report.quantity_for_id("total_assets"), Some(&vec![17963]));
+	assert_eq!(report.quantity_for_id("total_equity"), Some(&vec![17963]));
+}
+
+#[tokio::test]
+async fn rounding_user_expense_account() {
+	let mut db = fixture_db().await;
+	set_account_kind(&db, "Rounding", "drcr.expense").await;
+	insert_metadata(&db, "check_current_year_earnings", "true").await;
+	db.reload_metadata().await.unwrap();
+
+	insert_transaction(
+		&db,
+		date(2025, 6, 30),
+		"Bank rounding",
+		&[("Rounding", 37), ("Cash", -37)],
+	)
+	.await;
+
+	for rounding_reconciliation in [false, true] {
+		if rounding_reconciliation {
+			insert_metadata(&db, "rounding_reconciliation", "true").await;
+			db.reload_metadata().await.unwrap();
+		}
+
+		// The user's account is not also configured as the equity system account
+		let account_configurations = db.get_account_configurations().await.unwrap();
+		assert_eq!(
+			account_configurations
+				.iter()
+				.filter(|c| c.account == "Rounding")
+				.count(),
+			1
+		);
+
+		let period = [(date(2024, 7, 1), date(2025, 6, 30))];
+		let context = reporting_context(db.clone());
+		let report = api::income_statement(context, &period, false)
+			.await
+			.unwrap();
+
+		// Shown once, as an expense
+		assert_eq!(
+			report
+				.iter_rows()
+				.filter(|(_, row)| row.text == "Rounding")
+				.count(),
+			1
+		);
+		assert!(report.by_id("rounding").is_none());
+		assert_eq!(report.quantity_for_id("total_expenses"), Some(&vec![4037]));
+		assert_eq!(report.quantity_for_id("net_surplus"), Some(&vec![10963]));
+		assert!(report.by_id("current_year_earnings_difference").is_none());
+
+		let context = reporting_context(db.clone());
+		let report = api::balance_sheet(context, &[date(2025, 6, 30)])
+			.await
+			.unwrap();
+		assert_eq!(report.quantity_for_id("total_assets"), Some(&vec![17963]));
+		assert_eq!(report.quantity_for_id("total_equity"), Some(&vec![17963]));
+	}
+}
+
+#[tokio::test]
+async fn balance_sheet_foreign_commodity_without_cost() {
+	let db = fixture_db().await;
+	insert_commodity_transaction(
+		&db,
+		date(2025, 3, 1),
+		"Gift in USD",
+		&[("Cash", 1000, "USD"), ("Salary", -1000, "USD")],
+	)
+	.await;
+
+	// With no cost basis, the USD postings must be converted at the recorded price
+	let context = reporting_context(db.clone());
+	let result = api::balance_sheet(context, &[date(2025, 6, 30)]).await;
+	match result {
+		Err(ReportingError::ReportingExecutionError(
+			ReportingExecutionError::MissingCommodityPrice {
+				commodity,
+				date: price_date,
+			},
+		)) => {
+			assert_eq!(commodity, "USD");
+			assert_eq!(price_date, date(2025, 3, 1));
+		}
+		_ => panic!("Expected MissingCommodityPrice"),
+	}
+
+	// Excluded transactions do not need to be converted, and leave balances in $ unchanged
+	let mut context = reporting_context(db.clone());
+	Arc::get_mut(&mut context).unwrap().transaction_exclusions =
+		vec![TransactionExclusion::DescriptionContains("USD".to_string())];
+	let report = api::balance_sheet(context, &[date(2025, 6, 30)])
+		.await
+		.unwrap();
+	assert_eq!(report.quantity_for_id("total_assets"), Some(&vec![18000]));
+	assert_eq!(report.quantity_for_id("total_equity"), Some(&vec![18000]));
+
+	// Converted at $1.50, the income is charged to current year earnings and the balance sheet balances
+	insert_commodity_price(&db, date(2025, 1, 1), "USD", 3, 2).await;
+	let context = reporting_context(db);
+	let report = api::balance_sheet(context, &[date(2025, 6, 30)])
+		.await
+		.unwrap();
+	assert_eq!(report.quantity_for_id("total_assets"), Some(&vec![19500]));
+	assert_eq!(report.quantity_for_id("total_equity"), Some(&vec![19500]));
+	assert_eq!(
+		quantity_for_text(&report, "Current Year Earnings"),
+		Some(vec![12500])
+	);
+}
+
+#[tokio::test]
+async fn balance_sheet_transaction_exclusions() {
+	let db = fixture_db().await;
+
+	// Excluding the rent transactions adds back the rent paid from cash
+	let mut context = reporting_context(db.clone());
+	Arc::get_mut(&mut context).unwrap().transaction_exclusions =
+		vec![TransactionExclusion::DescriptionContains(
+			"Rent".to_string(),
+		)];
+	let report = api::balance_sheet(context, &[date(2025, 6, 30)])
+		.await
+		.unwrap();
+	assert_eq!(report.quantity_for_id("total_assets"), Some(&vec![25000]));
+	assert_eq!(report.quantity_for_id("total_equity"), Some(&vec![25000]));
+	assert_eq!(
+		quantity_for_text(&report, "Current Year Earnings"),
+		Some(vec![15000])
+	);
+
+	// Excluding the FY2025 salary leaves only the rent in current year earnings
+	let mut context = reporting_context(db);
+	Arc::get_mut(&mut context).unwrap().transaction_exclusions =
+		vec![TransactionExclusion::DateRange(
+			date(2025, 1, 1),
+			date(2025, 1, 31),
+		)];
+	let report = api::balance_sheet(context, &[date(2025, 6, 30)])
+		.await
+		.unwrap();
+	assert_eq!(report.quantity_for_id("total_assets"), Some(&vec![3000]));
+	assert_eq!(report.quantity_for_id("total_equity"), Some(&vec![3000]));
+	assert_eq!(
+		quantity_for_text(&report, "Current Year Earnings"),
+		Some(vec![-4000])
+	);
+}
+
+#[tokio::test]
+async fn balance_sheet_group_with_contra_account() {
+	let mut db = fixture_db().await;
+	set_account_kind(&db, "Fixed Assets:Computer", "drcr.asset").await;
+	set_account_kind_with_data(
+		&db,
+		"Fixed Assets:Accumulated Depreciation",
+		"drcr.asset",
+		r#"{"contra": true}"#,
+	)
+	.await;
+	set_account_kind(&db, "Depreciation", "drcr.expense").await;
+	insert_metadata(&db, "group_accounts", "true").await;
+	db.reload_metadata().await.unwrap();
+
+	insert_transaction(
+		&db,
+		date(2025, 1, 1),
+		"Computer",
+		&[("Fixed Assets:Computer", 2000), ("Cash", -2000)],
+	)
+	.await;
+	insert_transaction(
+		&db,
+		date(2025, 6, 30),
+		"Depreciation",
+		&[
+			("Depreciation", 500),
+			("Fixed Assets:Accumulated Depreciation", -500),
+		],
+	)
+	.await;
+
+	let context = reporting_context(db);
+	let report = api::balance_sheet(context, &[date(2025, 6, 30)])
+		.await
+		.unwrap();
+
+	assert_eq!(
+		quantity_for_text(&report, "Fixed Assets:Accumulated Depreciation"),
+		Some(vec![500])
+	);
+	assert_eq!(
+		quantity_for_text(&report, "Total Fixed Assets"),
+		Some(vec![1500])
+	);
+	assert_eq!(report.quantity_for_id("total_assets"), Some(&vec![17500]));
+}
+
+#[tokio::test]
+async fn balance_sheet_select_columns() {
+	let context = reporting_context(fixture_db().await);
+	let report = api::balance_sheet(context, &[date(2025, 6, 30), date(2024, 6, 30)])
+		.await
+		.unwrap();
+
+	let prior_year = report.select_columns(&[1]).unwrap();
+	assert_eq!(prior_year.columns, vec!["2024-06-30"]);
+	assert_eq!(
+		prior_year.quantity_for_id("total_assets"),
+		Some(&vec![7000])
+	);
+
+	assert!(report.select_columns(&[0, 2]).is_none());
+}
+
+#[tokio::test]
+async fn reports_with_income_tax_deterministic() {
+	let db = fixture_db().await;
+	set_account_kind(&db, "Salary", "austax.income1").await;
+	set_account_kind(&db, "Income Tax", "drcr.expense").await;
+	set_account_kind(&db, "Income Tax Control", "drcr.liability").await;
+	insert_transaction(
+		&db,
+		date(2025, 3, 1),
+		"Salary",
+		&[("Cash", 6000000), ("Salary", -6000000)],
+	)
+	.await;
+
+	// Steps run concurrently, so each run may execute them in a different order
+	let context = reporting_context_with_plugins(db, &["austax"]);
+	let mut outputs = Vec::new();
+	for _ in 0..20 {
+		let balance_sheet = api::balance_sheet(Arc::clone(&context), &[date(2025, 6, 30)])
+			.await
+			.unwrap();
+		let income_statement = api::income_statement(
+			Arc::clone(&context),
+			&[(date(2024, 7, 1), date(2025, 6, 30))],
+			false,
+		)
+		.await
+		.unwrap();
+
+		outputs.push((
+			serde_json::to_string(&balance_sheet).unwrap(),
+			serde_json::to_string(&income_statement).unwrap(),
+		));
+	}
+
+	// Income tax is reflected in the reports
+	let income_statement: DynamicReport = serde_json::from_str(&outputs[0].1).unwrap();
+	assert!(quantity_for_text(&income_statement, "Income Tax").is_some_and(|q| q[0] > 0));
+
+	assert!(outputs.iter().all(|o| *o == outputs[0]));
+}
+
+/// Add transactions to [fixture_db] in each quarter of the financial year ending 30 June 2025
+async fn add_quarterly_transactions(db: &DbConnection) {
+	insert_transaction(
+		db,
+		date(2024, 8, 15),
+		"Salary",
+		&[("Cash", 2000), ("Salary", -2000)],
+	)
+	.await;
+	insert_transaction(
+		db,
+		date(2024, 11, 30),
+		"Rent",
+		&[("Rent", 500), ("Cash", -500)],
+	)
+	.await;
+	insert_transaction(
+		db,
+		date(2025, 5, 10),
+		"Salary",
+		&[("Cash", 1000), ("Salary", -1000)],
+	)
+	.await;
+}
+
+#[tokio::test]
+async fn income_statement_quarters_sum_to_year() {
+	let db = fixture_db().await;
+	add_quarterly_transactions(&db).await;
+	let context = reporting_context(db);
+
+	let quarters = [
+		(date(2024, 7, 1), date(2024, 9, 30)),
+		(date(2024, 10, 1), date(2024, 12, 31)),
+		(date(2025, 1, 1), date(2025, 3, 31)),
+		(date(2025, 4, 1), date(2025, 6, 30)),
+	];
+	let report = api::income_statement(Arc::clone(&context), &quarters, false)
+		.await
+		.unwrap();
+	let year = api::income_statement(context, &[(date(2024, 7, 1), date(2025, 6, 30))], false)
+		.await
+		.unwrap();
+
+	assert_eq!(
+		report.quantity_for_id("net_surplus"),
+		Some(&vec![2000, -500, 11000, 1000])
+	);
+
+	for id in ["total_income", "total_expenses", "net_surplus"] {
+		let quarters_total = report
+			.quantity_for_id(id)
+			.unwrap()
+			.iter()
+			.sum::<QuantityInt>();
+		assert_eq!(Some(&vec![quarters_total]), year.quantity_for_id(id));
+	}
+}
+
+#[tokio::test]
+async fn balance_sheet_mid_year_current_year_earnings() {
+	let db = fixture_db().await;
+	add_quarterly_transactions(&db).await;
+	let context = reporting_context(db);
+
+	let balance_sheet = api::balance_sheet(Arc::clone(&context), &[date(2024, 12, 31)])
+		.await
+		.unwrap();
+	let year_to_date =
+		api::income_statement(context, &[(date(2024, 7, 1), date(2024, 12, 31))], false)
+			.await
+			.unwrap();
+
+	// Current year earnings are accumulated from the start of the enclosing financial year
+	assert_eq!(
+		year_to_date.quantity_for_id("net_surplus"),
+		Some(&vec![1500])
+	);
+	assert_eq!(
+		quantity_for_text(&balance_sheet, "Current Year Earnings"),
+		Some(vec![1500])
+	);
+	assert_eq!(
+		quantity_for_text(&balance_sheet, "Retained Earnings"),
+		Some(vec![7000])
+	);
+	assert_eq!(
+		balance_sheet.quantity_for_id("total_assets"),
+		balance_sheet.quantity_for_id("total_equity")
+	);
+}
+
+#[tokio::test]
+async fn cancelled_report() {
+	let context = reporting_context(fixture_db().await);
+	let targets = vec![ReportingProductId {
+		name: "TrialBalance".to_string(),
+		kind: ReportingProductKind::DynamicReport,
+		args: ReportingStepArgs::DateArgs(DateArgs {
+			date: date(2025, 6, 30),
+			time: None,
+		}),
+	}];
+
+	// Cancelling the report's token aborts generation with a child token, as in the Tauri bridge
+	let report_token = CancellationToken::new();
+	let generation_token = report_token.child_token();
+	report_token.cancel();
+
+	let result =
+		generate_report_with_cancellation(targets.clone(), Arc::clone(&context), &generation_token)
+			.await;
+	assert!(matches!(
+		result,
+		Err(ReportingError::ReportingExecutionError(
+			ReportingExecutionError::Cancelled
+		))
+	));
+
+	// A fresh token is unaffected
+	let products =
+		generate_report_with_cancellation(targets.clone(), context, &CancellationToken::new())
+			.await
+			.unwrap();
+	assert!(products.get_or_err(&targets[0]).is_ok());
+}
+
+#[tokio::test]
+async fn unbalanced_transaction_rejected() {
+	let db = fixture_db().await;
+	insert_transaction(
+		&db,
+		date(2025, 3, 1),
+		"Unbalanced",
+		&[("Cash", 500), ("Salary", -400)],
+	)
+	.await;
+
+	// Unbalanced transactions are loaded as is by default
+	let context = reporting_context(db.clone());
+	assert!(api::balance_sheet(context, &[date(2025, 6, 30)])
+		.await
+		.is_ok());
+
+	let mut context = reporting_context(db);
+	Arc::get_mut(&mut context)
+		.unwrap()
+		.reject_unbalanced_transactions = true;
+
+	// Balances are checked in the database
+	let result = api::balance_sheet(Arc::clone(&context), &[date(2025, 6, 30)]).await;
+	match result {
+		Err(ReportingError::ReportingExecutionError(
+			ReportingExecutionError::UnbalancedTransaction {
+				transaction_id,
+				imbalance,
+			},
+		)) => {
+			assert_eq!(transaction_id, Some(5));
+			assert_eq!(imbalance, HashMap::from([("$".to_string(), 100)]));
+		}
+		_ => panic!("Expected UnbalancedTransaction"),
+	}
+
+	// Transactions are checked as loaded
+	let result =
+		api::income_statement(context, &[(date(2024, 7, 1), date(2025, 6, 30))], false).await;
+	assert!(matches!(
+		result,
+		Err(ReportingError::ReportingExecutionError(
+			ReportingExecutionError::UnbalancedTransaction { .. }
+		))
+	));
+}
+
+#[tokio::test]
+async fn income_tax_transactions_balanced() {
+	let db = fixture_db().await;
+	set_account_kind(&db, "Salary", "austax.income1").await;
+	set_account_kind(&db, "Income Tax", "drcr.expense").await;
+	set_account_kind(&db, "Income Tax Control", "drcr.liability").await;
+	insert_transaction(
+		&db,
+		date(2025, 3, 1),
+		"Salary",
+		&[("Cash", 6000000), ("Salary", -6000000)],
+	)
+	.await;
+
+	// Transactions generated by the plugin are checked too
+	let mut context = reporting_context_with_plugins(db, &["austax"]);
+	Arc::get_mut(&mut context)
+		.unwrap()
+		.reject_unbalanced_transactions = true;
+	let income_statement =
+		api::income_statement(context, &[(date(2024, 7, 1), date(2025, 6, 30))], false)
+			.await
+			.unwrap();
+	assert!(quantity_for_text(&income_statement, "Income Tax").is_some_and(|q| q[0] > 0));
+}
+
+#[tokio::test]
+async fn income_tax_per_owner() {
+	let db = fixture_db().await;
+	set_account_kind(&db, "Salary", "austax.income1").await;
+	set_account_kind(&db, "Salary", "austax.owner.Alice").await;
+	for (account, kind) in [
+		("Bob Salary", "drcr.income"),
+		("Bob Salary", "austax.income1"),
+		("Bob Salary", "austax.owner.Bob"),
+		("Interest", "drcr.income"),
+		("Interest", "austax.income10"),
+	] {
+		set_account_kind(&db, account, kind).await;
+	}
+	insert_transaction(
+		&db,
+		date(2025, 3, 1),
+		"Bob's salary",
+		&[("Cash", 6000000), ("Bob Salary", -6000000)],
+	)
+	.await;
+	insert_transaction(
+		&db,
+		date(2025, 3, 1),
+		"Interest",
+		&[("Cash", 5000), ("Interest", -5000)],
+	)
+	.await;
+
+	// Each owner's income is assessed separately
+	let context = reporting_context_with_plugins(db, &["austax"]);
+	let report = api::dynamic_report(context, "CalculateIncomeTax", ReportingStepArgs::VoidArgs)
+		.await
+		.unwrap();
+	assert_eq!(
+		report.quantity_for_id("Alice.total_income"),
+		Some(&vec![15000])
+	);
+	assert_eq!(
+		report.quantity_for_id("Bob.total_income"),
+		Some(&vec![6000000])
+	);
+
+	// Accounts without an owner are listed rather than rejected
+	match report.by_id("accounts_without_owner") {
+		Some(DynamicReportEntry::Section(section)) => {
+			assert_eq!(section.entries.len(), 1);
+			assert!(
+				matches!(&section.entries[0], DynamicReportEntry::Row(row) if row.text == "Interest")
+			);
+		}
+		_ => panic!("Expected accounts_without_owner section"),
+	}
+}
+
+#[tokio::test]
+async fn medicare_levy_surcharge_off_by_default() {
+	let mut db = fixture_db().await;
+	set_account_kind(&db, "Salary", "austax.income1").await;
+	insert_transaction(
+		&db,
+		date(2025, 3, 1),
+		"Salary",
+		&[("Cash", 15000000), ("Salary", -15000000)],
+	)
+	.await;
+
+	// Surcharge is not charged unless private health cover is explicitly disclaimed
+	let context = reporting_context_with_plugins(db.clone(), &["austax"]);
+	let report = api::dynamic_report(context, "CalculateIncomeTax", ReportingStepArgs::VoidArgs)
+		.await
+		.unwrap();
+	assert!(report.by_id("tax_mls").is_none());
+
+	insert_metadata(&db, "austax_private_health_cover", "false").await;
+	db.reload_metadata().await.unwrap();
+	let context = reporting_context_with_plugins(db, &["austax"]);
+	let report = api::dynamic_report(context, "CalculateIncomeTax", ReportingStepArgs::VoidArgs)
+		.await
+		.unwrap();
+	assert!(report.by_id("tax_mls").is_some());
+}
+
+#[tokio::test]
+async fn balances_at_dates_match_per_date() {
+	let db = fixture_db().await;
+	insert_commodity_transaction(
+		&db,
+		date(2024, 3, 1),
+		"Buy USD",
+		&[("Cash", 1000, "USD {1.50}"), ("Cash", -1500, "$")],
+	)
+	.await;
+	insert_commodity_transaction(
+		&db,
+		date(2025, 3, 1),
+		"Gift in USD",
+		&[("Cash", 1000, "USD"), ("Salary", -1000, "USD")],
+	)
+	.await;
+
+	// Balances and commodity balances retrieved in one query for all dates are the same as those retrieved for each date
+	let dates = [date(2023, 6, 30), date(2024, 6, 30), date(2025, 6, 30)];
+	let balances_at_dates = db.get_balances_at_dates(&dates).await.unwrap();
+	assert_eq!(balances_at_dates.len(), dates.len());
+
+	for date in dates {
+		let (balances, commodity_balances) = &balances_at_dates[&date];
+		assert_eq!(balances, &db.get_balances(date).await.unwrap());
+		assert_eq!(
+			commodity_balances,
+			&db.get_commodity_balances(date).await.unwrap()
+		);
+	}
+	assert_eq!(balances_at_dates[&date(2025, 6, 30)].1["Cash"]["USD"], 2000);
+}
@@ -14,7 +14,7 @@
 --  You should have received a copy of the GNU Affero General Public License
 --  along with this program.  If not, see <https://www.gnu.org/licenses/>.
 
--- Current version: 6 (see db.ts)
+-- Current version: 13 (see db.ts)
 
 ---------
 -- Tables
@@ -37,6 +37,24 @@ CREATE TABLE balance_assertions (
 	PRIMARY KEY(id)
 );
 
+-- Price of one unit of the commodity in terms of the reporting commodity, as at dt
+-- The price is stored exactly as the ratio price_numerator / price_denominator, where price_denominator is positive
+CREATE TABLE commodity_prices (
+	id INTEGER NOT NULL,
+	dt DATETIME,
+	commodity VARCHAR,
+	price_numerator INTEGER,
+	price_denominator INTEGER,
+	PRIMARY KEY(id)
+);
+
+-- End dates of past financial years, where the end of the financial year has changed
+CREATE TABLE financial_year_ends (
+	id INTEGER NOT NULL,
+	dt DATETIME,
+	PRIMARY KEY(id)
+);
+
 CREATE TABLE metadata (
 	id INTEGER NOT NULL,
 	key VARCHAR,
@@ -44,6 +62,16 @@ CREATE TABLE metadata (
 	PRIMARY KEY(id)
 );
 
+-- Balance of each account when the file was started, posted against the Opening Balances equity account
+CREATE TABLE opening_balances (
+	id INTEGER NOT NULL,
+	account VARCHAR,
+	dt DATETIME,
+	quantity INTEGER,
+	commodity VARCHAR,
+	PRIMARY KEY(id)
+);
+
 CREATE TABLE postings (
 	id INTEGER NOT NULL,
 	transaction_id INTEGER,
@@ -101,6 +129,165 @@ CREATE TABLE austax_cgt_cost_adjustments (
 	PRIMARY KEY (id)
 );
 
+-- Figures from lodged returns, for comparison with the computed tax summary
+-- owner is NULL, unless accounts are tagged with owners (austax.owner.*)
+CREATE TABLE austax_reference_returns (
+	id INTEGER NOT NULL,
+	year INTEGER,
+	owner VARCHAR,
+	item VARCHAR,
+	quantity INTEGER,
+	PRIMARY KEY (id)
+);
+
+---------
+-- Audit log
+
+-- Records each operation performed through DrCr, together with each change to a row of the tables below (populated by the triggers below within the same database transaction as the change)
+-- Operations (e.g. insert_transaction) record the ids of the affected rows in table_name, as a comma-separated list, and the user who performed the operation
+-- Changes to rows (INSERT, UPDATE or DELETE) record the id of the row only, as the user is not known to the database
+-- dt is in UTC
+CREATE TABLE audit_log (
+	id INTEGER NOT NULL,
+	dt DATETIME,
+	operation VARCHAR,
+	table_name VARCHAR,
+	row_ids VARCHAR,
+	user VARCHAR,
+	PRIMARY KEY(id)
+);
+
+CREATE TRIGGER audit_account_configurations_insert AFTER INSERT ON account_configurations BEGIN
+	INSERT INTO audit_log (dt, operation, table_name, row_ids) VALUES (strftime('%Y-%m-%d %H:%M:%f000', 'now'), 'INSERT', 'account_configurations', NEW.id);
+END;
+
+CREATE TRIGGER audit_account_configurations_update AFTER UPDATE ON account_configurations BEGIN
+	INSERT INTO audit_log (dt, operation, table_name, row_ids) VALUES (strftime('%Y-%m-%d %H:%M:%f000', 'now'), 'UPDATE', 'account_configurations', NEW.id);
+END;
+
+CREATE TRIGGER audit_account_configurations_delete AFTER DELETE ON account_configurations BEGIN
+	INSERT INTO audit_log (dt, operation, table_name, row_ids) VALUES (strftime('%Y-%m-%d %H:%M:%f000', 'now'), 'DELETE', 'account_configurations', OLD.id);
+END;
+
+CREATE TRIGGER audit_austax_cgt_cost_adjustments_insert AFTER INSERT ON austax_cgt_cost_adjustments BEGIN
+	INSERT INTO audit_log (dt, operation, table_name, row_ids) VALUES (strftime('%Y-%m-%d %H:%M:%f000', 'now'), 'INSERT', 'austax_cgt_cost_adjustments', NEW.id);
+END;
+
+CREATE TRIGGER audit_austax_cgt_cost_adjustments_update AFTER UPDATE ON austax_cgt_cost_adjustments BEGIN
+	INSERT INTO audit_log (dt, operation, table_name, row_ids) VALUES (strftime('%Y-%m-%d %H:%M:%f000', 'now'), 'UPDATE', 'austax_cgt_cost_adjustments', NEW.id);
+END;
+
+CREATE TRIGGER audit_austax_cgt_cost_adjustments_delete AFTER DELETE ON austax_cgt_cost_adjustments BEGIN
+	INSERT INTO audit_log (dt, operation, table_name, row_ids) VALUES (strftime('%Y-%m-%d %H:%M:%f000', 'now'), 'DELETE', 'austax_cgt_cost_adjustments', OLD.id);
+END;
+
+CREATE TRIGGER audit_austax_reference_returns_insert AFTER INSERT ON austax_reference_returns BEGIN
+	INSERT INTO audit_log (dt, operation, table_name, row_ids) VALUES (strftime('%Y-%m-%d %H:%M:%f000', 'now'), 'INSERT', 'austax_reference_returns', NEW.id);
+END;
+
+CREATE TRIGGER audit_austax_reference_returns_update AFTER UPDATE ON austax_reference_returns BEGIN
+	INSERT INTO audit_log (dt, operation, table_name, row_ids) VALUES (strftime('%Y-%m-%d %H:%M:%f000', 'now'), 'UPDATE', 'austax_reference_returns', NEW.id);
+END;
+
+CREATE TRIGGER audit_austax_reference_returns_delete AFTER DELETE ON austax_reference_returns BEGIN
+	INSERT INTO audit_log (dt, operation, table_name, row_ids) VALUES (strftime('%Y-%m-%d %H:%M:%f000', 'now'), 'DELETE', 'austax_reference_returns', OLD.id);
+END;
+
+CREATE TRIGGER audit_balance_assertions_insert AFTER INSERT ON balance_assertions BEGIN
+	INSERT INTO audit_log (dt, operation, table_name, row_ids) VALUES (strftime('%Y-%m-%d %H:%M:%f000', 'now'), 'INSERT', 'balance_assertions', NEW.id);
+END;
+
+CREATE TRIGGER audit_balance_assertions_update AFTER UPDATE ON balance_assertions BEGIN
+	INSERT INTO audit_log (dt, operation, table_name, row_ids) VALUES (strftime('%Y-%m-%d %H:%M:%f000', 'now'), 'UPDATE', 'balance_assertions', NEW.id);
+END;
+
+CREATE TRIGGER audit_balance_assertions_delete AFTER DELETE ON balance_assertions BEGIN
+	INSERT INTO audit_log (dt, operation, table_name, row_ids) VALUES (strftime('%Y-%m-%d %H:%M:%f000', 'now'), 'DELETE', 'balance_assertions', OLD.id);
+END;
+
+CREATE TRIGGER audit_commodity_prices_insert AFTER INSERT ON commodity_prices BEGIN
+	INSERT INTO audit_log (dt, operation, table_name, row_ids) VALUES (strftime('%Y-%m-%d %H:%M:%f000', 'now'), 'INSERT', 'commodity_prices', NEW.id);
+END;
+
+CREATE TRIGGER audit_commodity_prices_update AFTER UPDATE ON commodity_prices BEGIN
+	INSERT INTO audit_log (dt, operation, table_name, row_ids) VALUES (strftime('%Y-%m-%d %H:%M:%f000', 'now'), 'UPDATE', 'commodity_prices', NEW.id);
+END;
+
+CREATE TRIGGER audit_commodity_prices_delete AFTER DELETE ON commodity_prices BEGIN
+	INSERT INTO audit_log (dt, operation, table_name, row_ids) VALUES (strftime('%Y-%m-%d %H:%M:%f000', 'now'), 'DELETE', 'commodity_prices', OLD.id);
+END;
+
+CREATE TRIGGER audit_financial_year_ends_insert AFTER INSERT ON financial_year_ends BEGIN
+	INSERT INTO audit_log (dt, operation, table_name, row_ids) VALUES (strftime('%Y-%m-%d %H:%M:%f000', 'now'), 'INSERT', 'financial_year_ends', NEW.id);
+END;
+
+CREATE TRIGGER audit_financial_year_ends_update AFTER UPDATE ON financial_year_ends BEGIN
+	INSERT INTO audit_log (dt, operation, table_name, row_ids) VALUES (strftime('%Y-%m-%d %H:%M:%f000', 'now'), 'UPDATE', 'financial_year_ends', NEW.id);
+END;
+
+CREATE TRIGGER audit_financial_year_ends_delete AFTER DELETE ON financial_year_ends BEGIN
+	INSERT INTO audit_log (dt, operation, table_name, row_ids) VALUES (strftime('%Y-%m-%d %H:%M:%f000', 'now'), 'DELETE', 'financial_year_ends', OLD.id);
+END;
+
+CREATE TRIGGER audit_opening_balances_insert AFTER INSERT ON opening_balances BEGIN
+	INSERT INTO audit_log (dt, operation, table_name, row_ids) VALUES (strftime('%Y-%m-%d %H:%M:%f000', 'now'), 'INSERT', 'opening_balances', NEW.id);
+END;
+
+CREATE TRIGGER audit_opening_balances_update AFTER UPDATE ON opening_balances BEGIN
+	INSERT INTO audit_log (dt, operation, table_name, row_ids) VALUES (strftime('%Y-%m-%d %H:%M:%f000', 'now'), 'UPDATE', 'opening_balances', NEW.id);
+END;
+
+CREATE TRIGGER audit_opening_balances_delete AFTER DELETE ON opening_balances BEGIN
+	INSERT INTO audit_log (dt, operation, table_name, row_ids) VALUES (strftime('%Y-%m-%d %H:%M:%f000', 'now'), 'DELETE', 'opening_balances', OLD.id);
+END;
+
+CREATE TRIGGER audit_postings_insert AFTER INSERT ON postings BEGIN
+	INSERT INTO audit_log (dt, operation, table_name, row_ids) VALUES (strftime('%Y-%m-%d %H:%M:%f000', 'now'), 'INSERT', 'postings', NEW.id);
+END;
+
+CREATE TRIGGER audit_postings_update AFTER UPDATE ON postings BEGIN
+	INSERT INTO audit_log (dt, operation, table_name, row_ids) VALUES (strftime('%Y-%m-%d %H:%M:%f000', 'now'), 'UPDATE', 'postings', NEW.id);
+END;
+
+CREATE TRIGGER audit_postings_delete AFTER DELETE ON postings BEGIN
+	INSERT INTO audit_log (dt, operation, table_name, row_ids) VALUES (strftime('%Y-%m-%d %H:%M:%f000', 'now'), 'DELETE', 'postings', OLD.id);
+END;
+
+CREATE TRIGGER audit_statement_line_reconciliations_insert AFTER INSERT ON statement_line_reconciliations BEGIN
+	INSERT INTO audit_log (dt, operation, table_name, row_ids) VALUES (strftime('%Y-%m-%d %H:%M:%f000', 'now'), 'INSERT', 'statement_line_reconciliations', NEW.id);
+END;
+
+CREATE TRIGGER audit_statement_line_reconciliations_update AFTER UPDATE ON statement_line_reconciliations BEGIN
+	INSERT INTO audit_log (dt, operation, table_name, row_ids) VALUES (strftime('%Y-%m-%d %H:%M:%f000', 'now'), 'UPDATE', 'statement_line_reconciliations', NEW.id);
+END;
+
+CREATE TRIGGER audit_statement_line_reconciliations_delete AFTER DELETE ON statement_line_reconciliations BEGIN
+	INSERT INTO audit_log (dt, operation, table_name, row_ids) VALUES (strftime('%Y-%m-%d %H:%M:%f000', 'now'), 'DELETE', 'statement_line_reconciliations', OLD.id);
+END;
+
+CREATE TRIGGER audit_statement_lines_insert AFTER INSERT ON statement_lines BEGIN
+	INSERT INTO audit_log (dt, operation, table_name, row_ids) VALUES (strftime('%Y-%m-%d %H:%M:%f000', 'now'), 'INSERT', 'statement_lines', NEW.id);
+END;
+
+CREATE TRIGGER audit_statement_lines_update AFTER UPDATE ON statement_lines BEGIN
+	INSERT INTO audit_log (dt, operation, table_name, row_ids) VALUES (strftime('%Y-%m-%d %H:%M:%f000', 'now'), 'UPDATE', 'statement_lines', NEW.id);
+END;
+
+CREATE TRIGGER audit_statement_lines_delete AFTER DELETE ON statement_lines BEGIN
+	INSERT INTO audit_log (dt, operation, table_name, row_ids) VALUES (strftime('%Y-%m-%d %H:%M:%f000', 'now'), 'DELETE', 'statement_lines', OLD.id);
+END;
+
+CREATE TRIGGER audit_transactions_insert AFTER INSERT ON transactions BEGIN
+	INSERT INTO audit_log (dt, operation, table_name, row_ids) VALUES (strftime('%Y-%m-%d %H:%M:%f000', 'now'), 'INSERT', 'transactions', NEW.id);
+END;
+
+CREATE TRIGGER audit_transactions_update AFTER UPDATE ON transactions BEGIN
+	INSERT INTO audit_log (dt, operation, table_name, row_ids) VALUES (strftime('%Y-%m-%d %H:%M:%f000', 'now'), 'UPDATE', 'transactions', NEW.id);
+END;
+
+CREATE TRIGGER audit_transactions_delete AFTER DELETE ON transactions BEGIN
+	INSERT INTO audit_log (dt, operation, table_name, row_ids) VALUES (strftime('%Y-%m-%d %H:%M:%f000', 'now'), 'DELETE', 'transactions', OLD.id);
+END;
 --------
 -- Views
 
@@ -22,15 +22,27 @@ mod sql;
 
 use gtk::prelude::{BinExt, Cast, GtkWindowExt, HeaderBarExt};
 use gtk::{EventBox, HeaderBar};
+use libdrcr::reporting::types::ReportingCache;
 use tauri::{AppHandle, Builder, Manager, State};
 use tauri_plugin_store::StoreExt;
 use tokio::sync::Mutex;
+use tokio_util::sync::CancellationToken;
 
+use std::collections::HashMap;
 use std::fs;
+use std::sync::Arc;
 
 struct AppState {
 	db_filename: Option<String>,
-	sql_transactions: Vec<Option<crate::sql::SqliteTransaction>>,
+	/// Connection to the open database, reused across commands (see [libdrcr_bridge::connect_db])
+	db_connection: Option<libdrcr_bridge::CachedDbConnection>,
+	sql_transactions: Vec<crate::sql::SqlTransactionSlot>,
+	/// Name of the report (its first target) and cancellation token of each report request in progress, by request ID (see [libdrcr_bridge::cancel_report])
+	report_requests: HashMap<u64, (String, CancellationToken)>,
+	/// ID to assign to the next report request
+	next_report_request_id: u64,
+	/// Reporting products cached across reports, invalidated whenever the database is written to (see [libdrcr_bridge::invalidate_reporting_cache])
+	reporting_cache: Arc<std::sync::Mutex<ReportingCache>>,
 }
 
 // Filename state
@@ -49,6 +61,8 @@ async fn set_open_filename(
 ) -> Result<(), ()> {
 	let mut state = state.lock().await;
 	state.db_filename = filename.clone();
+	state.db_connection = None;
+	state.reporting_cache.lock().unwrap().invalidate();
 
 	// Persist in store
 	let store = app.store("store.json").expect("Error opening store");
@@ -85,6 +99,16 @@ async fn set_window_title(app: AppHandle, label: &str, title: &str) -> Result<()
 	Ok(())
 }
 
+// Audit log
+
+/// Get the name of the user running DrCr, to be recorded against operations in the audit log
+#[tauri::command]
+async fn get_audit_user() -> Result<Option<String>, ()> {
+	Ok(std::env::var("USER")
+		.or_else(|_| std::env::var("USERNAME"))
+		.ok())
+}
+
 // Main method
 
 #[cfg_attr(mobile, tauri::mobile_entry_point)]
@@ -111,9 +135,16 @@ pub fn run() {
 
 			app.manage(Mutex::new(AppState {
 				db_filename: db_filename,
+				db_connection: None,
 				sql_transactions: Vec::new(),
+				report_requests: HashMap::new(),
+				next_report_request_id: 0,
+				reporting_cache: Arc::new(std::sync::Mutex::new(ReportingCache::new())),
 			}));
 
+			// Roll back transactions leaked by the frontend, even if no further transactions are used
+			sql::spawn_expire_idle_transactions(app.handle().clone());
+
 			Ok(())
 		})
 		.plugin(tauri_plugin_dialog::init())
@@ -123,21 +154,33 @@ pub fn run() {
 		.plugin(tauri_plugin_store::Builder::new().build())
 		.plugin(tauri_plugin_window_state::Builder::default().build())
 		.invoke_handler(tauri::generate_handler![
+			get_audit_user,
 			get_open_filename,
 			set_open_filename,
 			set_window_title,
 			libdrcr_austax::get_tax_summary,
+			libdrcr_bridge::cancel_report,
+			libdrcr_bridge::export_backup,
+			libdrcr_bridge::get_account_config_warnings,
+			libdrcr_bridge::get_accounts_without_configuration,
 			libdrcr_bridge::get_all_transactions_except_earnings_to_equity,
 			libdrcr_bridge::get_all_transactions_except_earnings_to_equity_for_account,
+			libdrcr_bridge::get_audit_log,
 			libdrcr_bridge::get_balance_sheet,
+			libdrcr_bridge::get_balances,
 			libdrcr_bridge::get_income_statement,
+			libdrcr_bridge::get_plugins,
+			libdrcr_bridge::get_reports_missing_account_kinds,
+			libdrcr_bridge::get_transactions_for_row,
 			libdrcr_bridge::get_trial_balance,
 			libdrcr_bridge::get_validated_balance_assertions,
+			libdrcr_bridge::invalidate_reporting_cache,
 			sql::sql_transaction_begin,
 			sql::sql_transaction_execute,
 			sql::sql_transaction_select,
 			sql::sql_transaction_rollback,
-			sql::sql_transaction_commit
+			sql::sql_transaction_commit,
+			sql::sql_transaction_list_open
 		])
 		.run(tauri::generate_context!())
 		.expect("Error while running tauri application");
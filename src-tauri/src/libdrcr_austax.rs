@@ -16,20 +16,36 @@
 	along with this program.  If not, see <https://www.gnu.org/licenses/>.
 */
 
-use libdrcr::reporting::dynamic_report::DynamicReport;
+use std::collections::HashMap;
+use std::sync::Arc;
+
+use chrono::Datelike;
+use libdrcr::db::{DbConnection, DbError};
+use libdrcr::reporting::dynamic_report::{DynamicReport, DynamicReportEntry};
 use libdrcr::reporting::types::{ReportingProductId, ReportingProductKind, ReportingStepArgs};
+use libdrcr::QuantityInt;
+use sqlx::Row;
 use tauri::{AppHandle, State};
 use tokio::sync::Mutex;
 
-use crate::libdrcr_bridge::get_report;
+use crate::libdrcr_bridge::{connect_db, get_report, BridgeError};
 use crate::AppState;
 
+/// Percentage change from the prior year above which a tax summary row is flagged
+const DEVIATION_THRESHOLD_PERCENT: QuantityInt = 20;
+
+/// Prefix of account kinds which tag an account with the taxpayer (owner) it belongs to
+const OWNER_KIND_PREFIX: &str = "austax.owner.";
+
 #[tauri::command]
 pub(crate) async fn get_tax_summary(
 	app: AppHandle,
 	state: State<'_, Mutex<AppState>>,
-) -> Result<String, ()> {
-	Ok(get_report(
+) -> Result<String, BridgeError> {
+	// Connect to database before state is moved into get_report
+	let db_connection = connect_db(&mut *state.lock().await).await?;
+
+	let mut report = get_report(
 		app,
 		state,
 		&ReportingProductId {
@@ -37,9 +53,169 @@ pub(crate) async fn get_tax_summary(
 			kind: ReportingProductKind::DynamicReport,
 			args: ReportingStepArgs::VoidArgs,
 		},
+		None,
 	)
-	.await
+	.await?
 	.downcast_ref::<DynamicReport>()
 	.unwrap()
-	.to_json())
+	.clone();
+
+	// Add comparison with prior year lodged return, if available
+	let prior_year = db_connection.metadata().eofy_date.year() - 1;
+	let reference_return = get_reference_return(&db_connection, prior_year).await?;
+
+	if !reference_return.is_empty() {
+		report.reference_column = Some(format!("{} (lodged)", prior_year));
+		add_reference_column(&mut report.entries, &reference_return);
+	}
+
+	// Where accounts are tagged with owners, return a separate tax summary for each owner
+	let owners = get_owners(&db_connection).await?;
+	let reports = if owners.is_empty() {
+		vec![report]
+	} else {
+		split_report_by_owner(&report, &owners)
+	};
+
+	Ok(serde_json::to_string(&reports).unwrap())
+}
+
+/// Get the sorted list of owners which accounts are tagged with (by account kind `austax.owner.<owner>`)
+async fn get_owners(db_connection: &DbConnection) -> Result<Vec<String>, DbError> {
+	let mut owners = db_connection
+		.get_account_configurations()
+		.await?
+		.into_iter()
+		.filter_map(|c| c.kind.strip_prefix(OWNER_KIND_PREFIX).map(String::from))
+		.collect::<Vec<_>>();
+	owners.sort();
+	owners.dedup();
+	Ok(owners)
+}
+
+/// Split the combined tax summary, which has a section for each owner, into one report per owner
+///
+/// Accounts which are not tagged with an owner, if any, are listed in a further report.
+fn split_report_by_owner(report: &DynamicReport, owners: &[String]) -> Vec<DynamicReport> {
+	let mut reports = Vec::new();
+	let sections = owners
+		.iter()
+		.map(|owner| (format!("{}: {}", report.title, owner), owner.as_str()))
+		.chain(std::iter::once((
+			"Accounts without an owner".to_string(),
+			"accounts_without_owner",
+		)));
+
+	for (title, id) in sections {
+		if let Some(DynamicReportEntry::Section(section)) = report.by_id(id) {
+			let mut owner_report =
+				DynamicReport::new(title, report.columns.clone(), section.entries.clone());
+			owner_report.reference_column = report.reference_column.clone();
+			reports.push(owner_report);
+		}
+	}
+
+	reports
+}
+
+/// Figures of a lodged return, keyed by owner (`None` unless accounts are tagged with owners) and item
+type ReferenceReturn = HashMap<(Option<String>, String), QuantityInt>;
+
+/// Get the figures of the lodged return for the financial year ending in the given year
+///
+/// Returns no figures if the database predates the `austax_reference_returns` table.
+async fn get_reference_return(
+	db_connection: &DbConnection,
+	year: i32,
+) -> Result<ReferenceReturn, DbError> {
+	let mut connection = db_connection.connect().await?;
+
+	let table_exists = sqlx::query(
+		"SELECT 1 FROM sqlite_master WHERE type = 'table' AND name = 'austax_reference_returns'",
+	)
+	.fetch_optional(&mut *connection)
+	.await
+	.map_err(|source| DbError::Sql {
+		context: "getting reference return",
+		source: Arc::new(source),
+	})?
+	.is_some();
+
+	if !table_exists {
+		return Ok(HashMap::new());
+	}
+
+	Ok(
+		sqlx::query("SELECT owner, item, quantity FROM austax_reference_returns WHERE year = $1")
+			.bind(year)
+			.fetch_all(&mut *connection)
+			.await
+			.map_err(|source| DbError::Sql {
+				context: "getting reference return",
+				source: Arc::new(source),
+			})?
+			.into_iter()
+			.map(|r| {
+				(
+					(
+						r.get::<Option<String>, _>("owner"),
+						r.get::<String, _>("item"),
+					),
+					r.get::<QuantityInt, _>("quantity"),
+				)
+			})
+			.collect(),
+	)
+}
+
+/// Look up the reference figure for the tax summary row with the given id
+///
+/// A row matches a reference figure if the row id equals the item (e.g. `net_taxable`), or is `total_` followed by the item (e.g. `total_income1`). Where accounts are tagged with owners, row ids are prefixed with the owner and `.` (e.g. `Alice.net_taxable`), and match figures for that owner.
+fn reference_quantity<'a>(
+	reference_return: &'a ReferenceReturn,
+	id: &str,
+) -> Option<&'a QuantityInt> {
+	let get = |owner: Option<&str>, item: &str| {
+		let key = (owner.map(String::from), item.to_string());
+		reference_return.get(&key).or_else(|| {
+			item.strip_prefix("total_")
+				.and_then(|item| reference_return.get(&(owner.map(String::from), item.to_string())))
+		})
+	};
+
+	get(None, id).or_else(|| {
+		reference_return
+			.keys()
+			.filter_map(|(owner, _)| owner.as_deref())
+			.find_map(|owner| {
+				id.strip_prefix(owner)
+					.and_then(|rest| rest.strip_prefix('.'))
+					.and_then(|item| get(Some(owner), item))
+			})
+	})
+}
+
+/// Set the reference quantity of each row to the corresponding figure from the reference return (see [reference_quantity]), flagging large deviations
+fn add_reference_column(entries: &mut [DynamicReportEntry], reference_return: &ReferenceReturn) {
+	for entry in entries.iter_mut() {
+		match entry {
+			DynamicReportEntry::Section(section) => {
+				add_reference_column(&mut section.entries, reference_return);
+			}
+			DynamicReportEntry::Row(row) => {
+				row.reference_quantity = row
+					.id
+					.as_ref()
+					.and_then(|id| reference_quantity(reference_return, id))
+					.copied();
+
+				if let Some(reference_quantity) = row.reference_quantity {
+					let deviation = (row.quantity[0] - reference_quantity).abs();
+					row.mismatch =
+						deviation * 100 > reference_quantity.abs() * DEVIATION_THRESHOLD_PERCENT;
+				}
+			}
+			DynamicReportEntry::CalculatedRow(_) | DynamicReportEntry::Spacer => (),
+		}
+	}
 }
@@ -17,84 +17,495 @@
 */
 
 use std::collections::HashSet;
+use std::fmt::Display;
+use std::fs;
+use std::io::Write;
+use std::path::Path;
 use std::sync::Arc;
+use std::time::SystemTime;
 
-use chrono::NaiveDate;
-use libdrcr::db::DbConnection;
+use chrono::{Local, NaiveDate};
+use libdrcr::account_config::{
+	account_config_warnings, kinds_for_account, reports_missing_account_kinds,
+};
+use libdrcr::db::{DbConnection, DbError};
 use libdrcr::model::assertions::BalanceAssertion;
-use libdrcr::reporting::dynamic_report::DynamicReport;
-use libdrcr::reporting::generate_report;
+use libdrcr::model::transaction::transactions_for_account;
+use libdrcr::plugin::{discover_plugins, transaction_step_names};
+use libdrcr::reporting::dynamic_report::{DrilldownReport, DynamicReport};
+use libdrcr::reporting::executor::ReportingExecutionError;
 use libdrcr::reporting::types::{
 	BalancesAt, DateArgs, DateStartDateEndArgs, MultipleDateArgs, MultipleDateStartDateEndArgs,
 	ReportingContext, ReportingProduct, ReportingProductId, ReportingProductKind,
-	ReportingStepArgs, Transactions,
+	ReportingProducts, ReportingStepArgs, Transactions,
 };
-use serde::{Deserialize, Serialize};
+use libdrcr::reporting::{generate_report, generate_report_with_cancellation, ReportingError};
+use libdrcr::util::sofy_from_eofy_with_history;
+use libdrcr::QuantityInt;
+use serde::{Deserialize, Serialize, Serializer};
 use tauri::path::BaseDirectory;
 use tauri::{AppHandle, Manager, State};
 use tokio::sync::Mutex;
+use tokio_util::sync::CancellationToken;
+use zip::result::ZipError;
+use zip::write::SimpleFileOptions;
+use zip::ZipWriter;
 
 use crate::AppState;
 
-fn prepare_reporting_context(context: &mut ReportingContext) {
+/// Error returned to the frontend by a command, serialised as a message for display
+#[derive(Debug)]
+pub(crate) enum BridgeError {
+	/// No database file is open
+	NoDatabaseOpen,
+	/// The open database file does not exist
+	DatabaseNotFound(String),
+	Database(DbError),
+	/// A date argument is not in `YYYY-MM-DD` format
+	InvalidDate(String),
+	Reporting(ReportingError),
+	Io(std::io::Error),
+	Tauri(tauri::Error),
+	Zip(ZipError),
+}
+
+impl Display for BridgeError {
+	fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+		match self {
+			BridgeError::NoDatabaseOpen => f.write_str("No database is open"),
+			BridgeError::DatabaseNotFound(db_filename) => {
+				f.write_fmt(format_args!("Database not found: {}", db_filename))
+			}
+			BridgeError::Database(err) => err.fmt(f),
+			BridgeError::InvalidDate(date) => f.write_fmt(format_args!("Invalid date: {}", date)),
+			BridgeError::Reporting(err) => {
+				f.write_fmt(format_args!("Error generating report: {:?}", err))
+			}
+			BridgeError::Io(err) => err.fmt(f),
+			BridgeError::Tauri(err) => err.fmt(f),
+			BridgeError::Zip(err) => err.fmt(f),
+		}
+	}
+}
+
+impl Serialize for BridgeError {
+	fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+		serializer.serialize_str(&self.to_string())
+	}
+}
+
+impl From<DbError> for BridgeError {
+	fn from(value: DbError) -> Self {
+		BridgeError::Database(value)
+	}
+}
+
+impl From<ReportingError> for BridgeError {
+	fn from(value: ReportingError) -> Self {
+		BridgeError::Reporting(value)
+	}
+}
+
+impl From<ReportingExecutionError> for BridgeError {
+	fn from(value: ReportingExecutionError) -> Self {
+		BridgeError::Reporting(value.into())
+	}
+}
+
+impl From<std::io::Error> for BridgeError {
+	fn from(value: std::io::Error) -> Self {
+		BridgeError::Io(value)
+	}
+}
+
+impl From<tauri::Error> for BridgeError {
+	fn from(value: tauri::Error) -> Self {
+		BridgeError::Tauri(value)
+	}
+}
+
+impl From<ZipError> for BridgeError {
+	fn from(value: ZipError) -> Self {
+		BridgeError::Zip(value)
+	}
+}
+
+/// Get the filename of the open database, checking that the file exists
+pub(crate) fn get_db_filename(state: &AppState) -> Result<String, BridgeError> {
+	let db_filename = state
+		.db_filename
+		.clone()
+		.ok_or(BridgeError::NoDatabaseOpen)?;
+
+	if !Path::new(&db_filename).exists() {
+		return Err(BridgeError::DatabaseNotFound(db_filename));
+	}
+
+	Ok(db_filename)
+}
+
+/// [DbConnection] to the open database, cached in [AppState] so that it is not reopened on every command
+pub(crate) struct CachedDbConnection {
+	db_connection: DbConnection,
+	/// [DbConnection::last_write_time] when the metadata was last read
+	last_write_time: Option<SystemTime>,
+}
+
+/// Get a connection to the open database, reusing the cached connection if available
+///
+/// The metadata of the cached connection is re-read if the database has been written to since, e.g. if settings were changed by the frontend.
+pub(crate) async fn connect_db(state: &mut AppState) -> Result<DbConnection, BridgeError> {
+	let db_filename = get_db_filename(state)?;
+
+	match &mut state.db_connection {
+		Some(cached) => {
+			let last_write_time = cached.db_connection.last_write_time();
+			if last_write_time != cached.last_write_time {
+				cached.db_connection.reload_metadata().await?;
+				cached.last_write_time = last_write_time;
+			}
+			Ok(cached.db_connection.clone())
+		}
+		None => {
+			let db_connection =
+				DbConnection::new(format!("sqlite:{}", db_filename.as_str()).as_str()).await?;
+			state.db_connection = Some(CachedDbConnection {
+				last_write_time: db_connection.last_write_time(),
+				db_connection: db_connection.clone(),
+			});
+			Ok(db_connection)
+		}
+	}
+}
+
+/// Parse a date argument in `YYYY-MM-DD` format
+fn parse_date(date: &str) -> Result<NaiveDate, BridgeError> {
+	NaiveDate::parse_from_str(date, "%Y-%m-%d")
+		.map_err(|_| BridgeError::InvalidDate(date.to_string()))
+}
+
+/// Get the path of the plugins resource directory
+fn get_plugin_dir(app: &AppHandle) -> Result<String, BridgeError> {
+	Ok(app
+		.path()
+		.resolve("plugins", BaseDirectory::Resource)?
+		.to_string_lossy()
+		.into_owned())
+}
+
+fn prepare_reporting_context(context: &mut ReportingContext) -> Result<(), BridgeError> {
 	libdrcr::reporting::steps::register_lookup_fns(context);
 	libdrcr::reporting::builders::register_dynamic_builders(context);
-	libdrcr::plugin::register_lookup_fns(context);
+	libdrcr::plugin::register_lookup_fns(context)?;
+
+	// Transactions entered through the frontend always balance, so an unbalanced transaction indicates a malformed database
+	context.reject_unbalanced_transactions = true;
+
+	Ok(())
 }
 
+/// Generate the requested [ReportingProductId], as for [get_reports]
 pub(crate) async fn get_report(
 	app: AppHandle,
 	state: State<'_, Mutex<AppState>>,
 	target: &ReportingProductId,
-) -> Box<dyn ReportingProduct> {
-	let state = state.lock().await;
-	let db_filename = state.db_filename.clone().unwrap();
+	transactions_cutoff: Option<NaiveDate>,
+) -> Result<Box<dyn ReportingProduct>, BridgeError> {
+	let products = get_reports(app, state, vec![target.clone()], transactions_cutoff).await?;
+	Ok(products.get_owned_or_err(target)?)
+}
 
-	// Connect to database
-	let db_connection =
-		DbConnection::new(format!("sqlite:{}", db_filename.as_str()).as_str()).await;
+/// Generate all the requested [ReportingProductId]s in a single pass
+///
+/// If `transactions_cutoff` is specified, transactions dated after it are excluded (see [ReportingContext::transactions_cutoff]). Generation may be aborted by [cancel_report] with the name of the first target.
+pub(crate) async fn get_reports(
+	app: AppHandle,
+	state: State<'_, Mutex<AppState>>,
+	mut targets: Vec<ReportingProductId>,
+	transactions_cutoff: Option<NaiveDate>,
+) -> Result<ReportingProducts, BridgeError> {
+	let context = get_reporting_context(&app, &state, transactions_cutoff).await?;
+
+	// Register the request, so that cancel_report can abort it
+	let cancellation_token = CancellationToken::new();
+	let request_id = {
+		let mut state = state.lock().await;
+		let request_id = state.next_report_request_id;
+		state.next_report_request_id += 1;
+		if let Some(target) = targets.first() {
+			state.report_requests.insert(
+				request_id,
+				(target.name.clone(), cancellation_token.clone()),
+			);
+		}
+		request_id
+	};
+
+	// Add transaction steps of the plugins enabled for this file (e.g. CalculateIncomeTax for austax)
+	for name in transaction_step_names(&context) {
+		targets.push(ReportingProductId {
+			name,
+			kind: ReportingProductKind::Transactions,
+			args: ReportingStepArgs::VoidArgs,
+		});
+	}
+
+	let result = generate_report_with_cancellation(targets, context, &cancellation_token).await;
+
+	// Generation has finished, so the request can no longer be cancelled
+	state.lock().await.report_requests.remove(&request_id);
+
+	Ok(result?)
+}
+
+/// Generate the named [DynamicReport] with the given args, as for [get_reports]
+async fn get_dynamic_report(
+	app: AppHandle,
+	state: State<'_, Mutex<AppState>>,
+	name: &str,
+	args: ReportingStepArgs,
+	transactions_cutoff: Option<NaiveDate>,
+) -> Result<DynamicReport, BridgeError> {
+	Ok(*get_report(
+		app,
+		state,
+		&ReportingProductId {
+			name: name.to_string(),
+			kind: ReportingProductKind::DynamicReport,
+			args,
+		},
+		transactions_cutoff,
+	)
+	.await?
+	.downcast::<DynamicReport>()
+	.unwrap()) // Products of kind DynamicReport are always DynamicReport
+}
+
+/// Cancel every in-progress generation of the named report (see [get_reports])
+///
+/// Called by each report view when it is closed, so that abandoned reports do not continue to occupy the executor.
+#[tauri::command]
+pub(crate) async fn cancel_report(
+	state: State<'_, Mutex<AppState>>,
+	report: String,
+) -> Result<(), BridgeError> {
+	let mut state = state.lock().await;
+
+	state
+		.report_requests
+		.retain(|_, (name, cancellation_token)| {
+			if *name == report {
+				cancellation_token.cancel();
+				false
+			} else {
+				true
+			}
+		});
+
+	Ok(())
+}
+
+/// Initialise a [ReportingContext] for the open database, excluding transactions dated after `transactions_cutoff` if specified
+async fn get_reporting_context(
+	app: &AppHandle,
+	state: &State<'_, Mutex<AppState>>,
+	transactions_cutoff: Option<NaiveDate>,
+) -> Result<Arc<ReportingContext>, BridgeError> {
+	let mut state = state.lock().await;
+	let db_connection = connect_db(&mut state).await?;
 
 	// Initialise ReportingContext
 	let eofy_date = db_connection.metadata().eofy_date;
 	let plugin_names = db_connection.metadata().plugins.clone();
 	let mut context = ReportingContext::new(
 		db_connection,
-		app.path()
-			.resolve("plugins", BaseDirectory::Resource)
-			.unwrap()
-			.to_str()
-			.unwrap()
-			.to_string(),
+		get_plugin_dir(app)?,
 		plugin_names,
 		eofy_date,
 		"$".to_string(),
 	);
-	prepare_reporting_context(&mut context);
+	prepare_reporting_context(&mut context)?;
 
-	// Get dynamic report
-	let mut targets = vec![target.clone()];
+	context.transactions_cutoff = transactions_cutoff;
 
-	// Add plugin targets
-	// FIXME: Detect this robustly
-	if context.plugin_names.contains(&"austax".to_string()) {
-		targets.push(ReportingProductId {
-			name: "CalculateIncomeTax".to_string(),
-			kind: ReportingProductKind::Transactions,
-			args: ReportingStepArgs::VoidArgs,
-		});
+	context.cache = Some(state.reporting_cache.clone());
+
+	Ok(Arc::new(context))
+}
+
+/// Discard cached reporting products, to be called whenever the database is written to
+///
+/// Writes within a transaction (see [crate::sql::sql_transaction_commit]) invalidate the cache automatically.
+#[tauri::command]
+pub(crate) async fn invalidate_reporting_cache(
+	state: State<'_, Mutex<AppState>>,
+) -> Result<(), BridgeError> {
+	let state = state.lock().await;
+	state.reporting_cache.lock().unwrap().invalidate();
+	Ok(())
+}
+
+/// Export a zip file at `path` containing a copy of the database and CSV and HTML exports of the standard reports for the current financial year
+///
+/// HTML exports can be printed or saved as PDF from a browser, as reports are from within DrCr.
+#[tauri::command]
+pub(crate) async fn export_backup(
+	app: AppHandle,
+	state: State<'_, Mutex<AppState>>,
+	path: String,
+) -> Result<(), BridgeError> {
+	let (db_filename, db_connection) = {
+		let mut state = state.lock().await;
+		(get_db_filename(&state)?, connect_db(&mut state).await?)
+	};
+
+	// Copy the database, via a temporary file, as the database file itself may be inconsistent while in use
+	let backup_path = std::env::temp_dir().join(format!(
+		"drcr_backup_{}_{}.db",
+		std::process::id(),
+		SystemTime::now()
+			.duration_since(SystemTime::UNIX_EPOCH)
+			.unwrap()
+			.as_nanos()
+	));
+	db_connection
+		.backup_into(&backup_path.to_string_lossy())
+		.await?;
+	let db_bytes = fs::read(&backup_path);
+	fs::remove_file(&backup_path)?;
+	let db_bytes = db_bytes?;
+
+	// Get period for reports
+	let eofy_date = db_connection.metadata().eofy_date;
+	let sofy_date =
+		sofy_from_eofy_with_history(eofy_date, &db_connection.metadata().financial_year_ends);
+	let dps = db_connection.metadata().dps;
+
+	// Generate standard reports
+	let reports = [
+		(
+			"Balance sheet",
+			ReportingProductId {
+				name: "BalanceSheet".to_string(),
+				kind: ReportingProductKind::DynamicReport,
+				args: ReportingStepArgs::MultipleDateArgs(MultipleDateArgs {
+					dates: vec![DateArgs {
+						date: eofy_date,
+						time: None,
+					}],
+					with_prior_year: false,
+				}),
+			},
+		),
+		(
+			"Income statement",
+			ReportingProductId {
+				name: "IncomeStatement".to_string(),
+				kind: ReportingProductKind::DynamicReport,
+				args: ReportingStepArgs::MultipleDateStartDateEndArgs(
+					MultipleDateStartDateEndArgs {
+						dates: vec![DateStartDateEndArgs {
+							date_start: sofy_date,
+							date_end: eofy_date,
+						}],
+						cumulative: false,
+					},
+				),
+			},
+		),
+		(
+			"Trial balance",
+			ReportingProductId {
+				name: "TrialBalance".to_string(),
+				kind: ReportingProductKind::DynamicReport,
+				args: ReportingStepArgs::DateArgs(DateArgs {
+					date: eofy_date,
+					time: None,
+				}),
+			},
+		),
+	];
+
+	let products = get_reports(
+		app,
+		state,
+		reports.iter().map(|(_, target)| target.clone()).collect(),
+		None,
+	)
+	.await?;
+
+	// Write zip file
+	let mut zip = ZipWriter::new(fs::File::create(&path)?);
+	let options = SimpleFileOptions::default();
+
+	let db_basename = Path::new(&db_filename)
+		.file_name()
+		.unwrap()
+		.to_string_lossy();
+	zip.start_file(db_basename, options)?;
+	zip.write_all(&db_bytes)?;
+
+	for (name, target) in reports.iter() {
+		let report = products
+			.get_or_err(target)?
+			.downcast_ref::<DynamicReport>()
+			.unwrap();
+
+		zip.start_file(format!("{}.csv", name), options)?;
+		zip.write_all(report.to_csv(dps).as_bytes())?;
+
+		zip.start_file(format!("{}.html", name), options)?;
+		zip.write_all(report.to_html_document(dps).as_bytes())?;
 	}
 
-	let products = generate_report(targets, Arc::new(context)).await.unwrap();
-	let result = products.get_owned_or_err(&target).unwrap();
+	zip.finish()?;
+
+	Ok(())
+}
+
+#[tauri::command]
+pub(crate) async fn get_accounts_without_configuration(
+	state: State<'_, Mutex<AppState>>,
+) -> Result<String, BridgeError> {
+	let mut state = state.lock().await;
+	let db_connection = connect_db(&mut state).await?;
+
+	let accounts = db_connection.accounts_without_configuration().await?;
+
+	Ok(serde_json::to_string(&accounts).unwrap())
+}
+
+#[tauri::command]
+pub(crate) async fn get_account_config_warnings(
+	state: State<'_, Mutex<AppState>>,
+) -> Result<String, BridgeError> {
+	let mut state = state.lock().await;
+	let db_connection = connect_db(&mut state).await?;
+
+	let accounts = db_connection.get_posting_accounts().await?;
+	let kinds_for_account = kinds_for_account(db_connection.get_account_configurations().await?);
+	let warnings = account_config_warnings(&accounts, &kinds_for_account);
 
-	result
+	Ok(serde_json::to_string(&warnings).unwrap())
+}
+
+#[tauri::command]
+pub(crate) async fn get_audit_log(
+	state: State<'_, Mutex<AppState>>,
+) -> Result<String, BridgeError> {
+	let mut state = state.lock().await;
+	let db_connection = connect_db(&mut state).await?;
+
+	let audit_log = db_connection.get_audit_log().await?;
+
+	Ok(serde_json::to_string(&audit_log).unwrap())
 }
 
 #[tauri::command]
 pub(crate) async fn get_all_transactions_except_earnings_to_equity(
 	app: AppHandle,
 	state: State<'_, Mutex<AppState>>,
-) -> Result<String, ()> {
+) -> Result<String, BridgeError> {
 	let transactions = get_report(
 		app,
 		state,
@@ -103,10 +514,12 @@ pub(crate) async fn get_all_transactions_except_earnings_to_equity(
 			kind: ReportingProductKind::Transactions,
 			args: ReportingStepArgs::DateArgs(DateArgs {
 				date: NaiveDate::from_ymd_opt(9999, 12, 31).unwrap(),
+				time: None,
 			}),
 		},
+		None,
 	)
-	.await
+	.await?
 	.downcast::<Transactions>()
 	.unwrap()
 	.transactions;
@@ -119,7 +532,18 @@ pub(crate) async fn get_all_transactions_except_earnings_to_equity_for_account(
 	app: AppHandle,
 	state: State<'_, Mutex<AppState>>,
 	account: String,
-) -> Result<String, ()> {
+	date_start: Option<String>,
+	date_end: Option<String>,
+) -> Result<String, BridgeError> {
+	let date_start = match date_start {
+		Some(date) => parse_date(&date)?,
+		None => NaiveDate::MIN,
+	};
+	let date_end = match date_end {
+		Some(date) => parse_date(&date)?,
+		None => NaiveDate::from_ymd_opt(9999, 12, 31).unwrap(),
+	};
+
 	let transactions = get_report(
 		app,
 		state,
@@ -127,22 +551,22 @@ pub(crate) async fn get_all_transactions_except_earnings_to_equity_for_account(
 			name: "AllTransactionsExceptEarningsToEquity".to_string(),
 			kind: ReportingProductKind::Transactions,
 			args: ReportingStepArgs::DateArgs(DateArgs {
-				date: NaiveDate::from_ymd_opt(9999, 12, 31).unwrap(),
+				date: date_end,
+				time: None,
 			}),
 		},
+		None,
 	)
-	.await
+	.await?
 	.downcast::<Transactions>()
 	.unwrap()
 	.transactions;
 
-	// Filter only transactions affecting this account
-	let filtered_transactions = transactions
-		.into_iter()
-		.filter(|t| t.postings.iter().any(|p| p.account == account))
-		.collect::<Vec<_>>();
+	// Filter only transactions affecting this account within the date range
+	let account_transactions =
+		transactions_for_account(transactions, &account, date_start, date_end);
 
-	Ok(serde_json::to_string(&filtered_transactions).unwrap())
+	Ok(serde_json::to_string(&account_transactions).unwrap())
 }
 
 #[tauri::command]
@@ -150,29 +574,55 @@ pub(crate) async fn get_balance_sheet(
 	app: AppHandle,
 	state: State<'_, Mutex<AppState>>,
 	dates: Vec<String>,
-) -> Result<String, ()> {
-	let mut date_args = Vec::new();
-	for date in dates.iter() {
-		date_args.push(DateArgs {
-			date: NaiveDate::parse_from_str(date, "%Y-%m-%d").expect("Invalid date"),
-		})
-	}
+	exclude_future_transactions: bool,
+) -> Result<String, BridgeError> {
+	let dates = dates
+		.iter()
+		.map(|date| parse_date(date))
+		.collect::<Result<Vec<_>, _>>()?;
 
-	Ok(get_report(
+	let report = get_dynamic_report(
+		app,
+		state,
+		"BalanceSheet",
+		ReportingStepArgs::MultipleDateArgs(MultipleDateArgs {
+			dates: dates
+				.into_iter()
+				.map(|date| DateArgs { date, time: None })
+				.collect(),
+			with_prior_year: false,
+		}),
+		exclude_future_transactions.then(|| Local::now().date_naive()),
+	)
+	.await?;
+	Ok(report.to_json())
+}
+
+#[tauri::command]
+pub(crate) async fn get_balances(
+	app: AppHandle,
+	state: State<'_, Mutex<AppState>>,
+	date: String,
+) -> Result<String, BridgeError> {
+	let date = parse_date(&date)?;
+
+	// Balances of ordinary transactions, including income tax where calculated by plugins
+	let balances = get_report(
 		app,
 		state,
 		&ReportingProductId {
-			name: "BalanceSheet".to_string(),
-			kind: ReportingProductKind::DynamicReport,
-			args: ReportingStepArgs::MultipleDateArgs(MultipleDateArgs {
-				dates: date_args.clone(),
-			}),
+			name: "CombineOrdinaryTransactions".to_string(),
+			kind: ReportingProductKind::BalancesAt,
+			args: ReportingStepArgs::DateArgs(DateArgs { date, time: None }),
 		},
+		None,
 	)
-	.await
-	.downcast_ref::<DynamicReport>()
+	.await?
+	.downcast::<BalancesAt>()
 	.unwrap()
-	.to_json())
+	.balances;
+
+	Ok(serde_json::to_string(&balances).unwrap())
 }
 
 #[tauri::command]
@@ -180,30 +630,97 @@ pub(crate) async fn get_income_statement(
 	app: AppHandle,
 	state: State<'_, Mutex<AppState>>,
 	dates: Vec<(String, String)>,
-) -> Result<String, ()> {
-	let mut date_args = Vec::new();
-	for (date_start, date_end) in dates.iter() {
-		date_args.push(DateStartDateEndArgs {
-			date_start: NaiveDate::parse_from_str(date_start, "%Y-%m-%d").expect("Invalid date"),
-			date_end: NaiveDate::parse_from_str(date_end, "%Y-%m-%d").expect("Invalid date"),
+	cumulative: Option<bool>,
+) -> Result<String, BridgeError> {
+	let periods = dates
+		.iter()
+		.map(|(date_start, date_end)| -> Result<_, BridgeError> {
+			Ok((parse_date(date_start)?, parse_date(date_end)?))
 		})
-	}
+		.collect::<Result<Vec<_>, _>>()?;
+
+	let report = get_dynamic_report(
+		app,
+		state,
+		"IncomeStatement",
+		ReportingStepArgs::MultipleDateStartDateEndArgs(MultipleDateStartDateEndArgs {
+			dates: periods
+				.into_iter()
+				.map(|(date_start, date_end)| DateStartDateEndArgs {
+					date_start,
+					date_end,
+				})
+				.collect(),
+			cumulative: cumulative.unwrap_or(false),
+		}),
+		None,
+	)
+	.await?;
+	Ok(report.to_json())
+}
+
+#[derive(Serialize)]
+struct InstalledPlugin {
+	name: String,
+	/// Error loading the plugin, if it is not valid
+	error: Option<String>,
+}
+
+/// Get the plugins installed in the plugins resource directory, including any which cannot be loaded
+#[tauri::command]
+pub(crate) async fn get_plugins(app: AppHandle) -> Result<String, BridgeError> {
+	let plugins = discover_plugins(&get_plugin_dir(&app)?)?
+		.into_iter()
+		.map(|(name, result)| InstalledPlugin {
+			name,
+			error: result.err(),
+		})
+		.collect::<Vec<_>>();
+
+	Ok(serde_json::to_string(&plugins).unwrap())
+}
+
+#[tauri::command]
+pub(crate) async fn get_reports_missing_account_kinds(
+	state: State<'_, Mutex<AppState>>,
+) -> Result<String, BridgeError> {
+	let mut state = state.lock().await;
+	let db_connection = connect_db(&mut state).await?;
+
+	let account_configurations = db_connection.get_account_configurations().await?;
+	let reports = reports_missing_account_kinds(&account_configurations);
+
+	Ok(serde_json::to_string(&reports).unwrap())
+}
 
-	Ok(get_report(
+/// Get the transactions underlying a row of the given report (see [DrilldownReport::transactions_for_row])
+///
+/// `report` identifies the report as a [DynamicReport] product. Transactions are drawn from the same source as the report, including any plugin steps (see [get_reports]).
+#[tauri::command]
+pub(crate) async fn get_transactions_for_row(
+	app: AppHandle,
+	state: State<'_, Mutex<AppState>>,
+	report: ReportingProductId,
+	row: String,
+) -> Result<String, BridgeError> {
+	let drilldown_report = get_report(
 		app,
 		state,
 		&ReportingProductId {
-			name: "IncomeStatement".to_string(),
-			kind: ReportingProductKind::DynamicReport,
-			args: ReportingStepArgs::MultipleDateStartDateEndArgs(MultipleDateStartDateEndArgs {
-				dates: date_args.clone(),
-			}),
+			kind: ReportingProductKind::DrilldownReport,
+			..report
 		},
+		None,
 	)
-	.await
-	.downcast_ref::<DynamicReport>()
-	.unwrap()
-	.to_json())
+	.await?
+	.downcast::<DrilldownReport>()
+	.unwrap(); // Products of kind DrilldownReport are always DrilldownReport
+
+	let transactions = drilldown_report
+		.transactions_for_row(&row)
+		.ok_or(ReportingError::RowNotFound(row))?;
+
+	Ok(serde_json::to_string(&transactions).unwrap())
 }
 
 #[tauri::command]
@@ -211,22 +728,24 @@ pub(crate) async fn get_trial_balance(
 	app: AppHandle,
 	state: State<'_, Mutex<AppState>>,
 	date: String,
-) -> Result<String, ()> {
-	let date = NaiveDate::parse_from_str(&date, "%Y-%m-%d").expect("Invalid date");
+	by_commodity: Option<bool>,
+) -> Result<String, BridgeError> {
+	let date = parse_date(&date)?;
 
-	Ok(get_report(
+	let args = DateArgs { date, time: None };
+	let report = get_dynamic_report(
 		app,
 		state,
-		&ReportingProductId {
-			name: "TrialBalance".to_string(),
-			kind: ReportingProductKind::DynamicReport,
-			args: ReportingStepArgs::DateArgs(DateArgs { date }),
+		"TrialBalance",
+		if by_commodity.unwrap_or(false) {
+			ReportingStepArgs::DateByCommodityArgs(args)
+		} else {
+			ReportingStepArgs::DateArgs(args)
 		},
+		None,
 	)
-	.await
-	.downcast_ref::<DynamicReport>()
-	.unwrap()
-	.to_json())
+	.await?;
+	Ok(report.to_json())
 }
 
 #[derive(Deserialize, Serialize)]
@@ -234,24 +753,22 @@ struct ValidatedBalanceAssertion {
 	#[serde(flatten)]
 	assertion: BalanceAssertion,
 	is_valid: bool,
+	/// Actual balance of the account in [ValidatedBalanceAssertion::actual_commodity]
+	actual_quantity: QuantityInt,
+	/// Commodity in which the actual balance is expressed (the asserted commodity)
+	actual_commodity: String,
 }
 
 #[tauri::command]
 pub(crate) async fn get_validated_balance_assertions(
 	app: AppHandle,
 	state: State<'_, Mutex<AppState>>,
-) -> Result<String, ()> {
-	let state = state.lock().await;
-	let db_filename = state.db_filename.clone().unwrap();
-
-	// Connect to database
-	let db_connection =
-		DbConnection::new(format!("sqlite:{}", db_filename.as_str()).as_str()).await;
-
-	let reporting_commodity = db_connection.metadata().reporting_commodity.clone(); // Needed later
+) -> Result<String, BridgeError> {
+	let mut state = state.lock().await;
+	let db_connection = connect_db(&mut state).await?;
 
 	// First get balance assertions from database
-	let balance_assertions = db_connection.get_balance_assertions().await;
+	let balance_assertions = db_connection.get_balance_assertions().await?;
 
 	// Get dates of balance assertions
 	let dates = balance_assertions
@@ -262,19 +779,16 @@ pub(crate) async fn get_validated_balance_assertions(
 	// Initialise ReportingContext
 	let eofy_date = db_connection.metadata().eofy_date;
 	let plugin_names = db_connection.metadata().plugins.clone();
+	// Balances must be keyed by the same reporting commodity as the assertions
+	let reporting_commodity = db_connection.metadata().reporting_commodity.clone();
 	let mut context = ReportingContext::new(
 		db_connection,
-		app.path()
-			.resolve("plugins", BaseDirectory::Resource)
-			.unwrap()
-			.to_str()
-			.unwrap()
-			.to_string(),
+		get_plugin_dir(&app)?,
 		plugin_names,
 		eofy_date,
-		"$".to_string(),
+		reporting_commodity,
 	);
-	prepare_reporting_context(&mut context);
+	prepare_reporting_context(&mut context)?;
 
 	// Get report targets
 	let mut targets = Vec::new();
@@ -283,22 +797,25 @@ pub(crate) async fn get_validated_balance_assertions(
 		targets.push(ReportingProductId {
 			name: "CombineOrdinaryTransactions".to_string(),
 			kind: ReportingProductKind::BalancesAt,
-			args: ReportingStepArgs::DateArgs(DateArgs { date: dt.date() }),
+			args: ReportingStepArgs::DateArgs(DateArgs {
+				date: dt.date(),
+				time: None,
+			}),
 		});
 	}
-	
-	// Add plugin targets
-	// FIXME: Detect this robustly
-	if context.plugin_names.contains(&"austax".to_string()) {
+
+	// Add transaction steps of the plugins enabled for this file (e.g. CalculateIncomeTax for austax)
+	for name in transaction_step_names(&context) {
 		targets.push(ReportingProductId {
-			name: "CalculateIncomeTax".to_string(),
+			name,
 			kind: ReportingProductKind::Transactions,
 			args: ReportingStepArgs::VoidArgs,
 		});
 	}
 
 	// Run report
-	let products = generate_report(targets, Arc::new(context)).await.unwrap();
+	let context = Arc::new(context);
+	let products = generate_report(targets, Arc::clone(&context)).await?;
 
 	// Validate each balance assertion
 	let mut validated_assertions = Vec::new();
@@ -309,23 +826,27 @@ pub(crate) async fn get_validated_balance_assertions(
 				kind: ReportingProductKind::BalancesAt,
 				args: ReportingStepArgs::DateArgs(DateArgs {
 					date: balance_assertion.dt.date(),
+					time: None,
 				}),
-			})
-			.unwrap()
+			})?
 			.downcast_ref::<BalancesAt>()
 			.unwrap();
 
-		let account_balance = *balances_at_date
-			.balances
-			.get(&balance_assertion.account)
-			.unwrap_or(&0);
+		// Compare against the quantity of the asserted commodity actually held in the account
+		let account_balance = balances_at_date.commodity_balance(
+			&balance_assertion.account,
+			&balance_assertion.commodity,
+			&context.reporting_commodity,
+		);
 
-		let is_valid = balance_assertion.quantity == account_balance
-			&& balance_assertion.commodity == reporting_commodity;
+		let is_valid = balance_assertion.quantity == account_balance;
+		let actual_commodity = balance_assertion.commodity.clone();
 
 		validated_assertions.push(ValidatedBalanceAssertion {
 			assertion: balance_assertion,
 			is_valid,
+			actual_quantity: account_balance,
+			actual_commodity,
 		});
 	}
 
@@ -16,7 +16,11 @@
 	along with this program.  If not, see <https://www.gnu.org/licenses/>.
 */
 
+use std::fmt::Display;
+use std::time::{Duration, Instant};
+
 use indexmap::IndexMap;
+use serde::{Deserialize, Serialize, Serializer};
 use serde_json::Value as JsonValue;
 use sqlx::{Column, Executor, Row, Sqlite, Transaction, TypeInfo, Value, ValueRef};
 use sqlx::query::Query;
@@ -24,15 +28,124 @@ use sqlx::sqlite::{SqliteArguments, SqliteRow, SqliteValueRef};
 use sqlx::types::time::{Date, PrimitiveDateTime, Time};
 use tokio::sync::Mutex;
 
-use tauri::State;
+use tauri::{AppHandle, Manager, State};
 use tauri_plugin_sql::{DbInstances, DbPool, Error};
 
 use crate::AppState;
 
 pub type SqliteTransaction = Transaction<'static, Sqlite>;
 
+/// Time after which an open transaction which has not been used is rolled back, so that transactions leaked by the frontend do not accumulate
+const SQL_TRANSACTION_IDLE_TIMEOUT: Duration = Duration::from_secs(60);
+
+/// Interval at which idle transactions are checked for, see [spawn_expire_idle_transactions]
+const SQL_TRANSACTION_EXPIRY_INTERVAL: Duration = Duration::from_secs(15);
+
+/// Handle to an open database transaction, as passed to the frontend
+///
+/// Slots in [AppState::sql_transactions] are reused, so the generation distinguishes successive transactions using the same slot.
+#[derive(Clone, Copy, Debug, Deserialize, Serialize)]
+pub struct SqlTransactionHandle {
+	index: usize,
+	generation: u64,
+}
+
+/// Slot in [AppState::sql_transactions] which may hold an open database transaction
+pub struct SqlTransactionSlot {
+	generation: u64,
+	transaction: Option<SqliteTransaction>,
+	/// Time at which the transaction was begun or last used
+	last_used: Instant,
+}
+
+/// Open database transaction, as reported by [sql_transaction_list_open]
+#[derive(Serialize)]
+pub struct OpenSqlTransaction {
+	handle: SqlTransactionHandle,
+	/// Time since the transaction was begun or last used, in seconds
+	idle_seconds: u64,
+}
+
+#[derive(Debug)]
+pub enum SqlTransactionError {
+	Database(Error),
+	/// The handle does not refer to an open transaction, e.g. because the transaction has already been committed or rolled back, or was rolled back after being idle
+	InvalidHandle(SqlTransactionHandle),
+}
+
+impl Display for SqlTransactionError {
+	fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+		match self {
+			SqlTransactionError::Database(err) => err.fmt(f),
+			SqlTransactionError::InvalidHandle(handle) => f.write_fmt(format_args!("Database transaction handle {}/{} does not refer to an open transaction", handle.index, handle.generation)),
+		}
+	}
+}
+
+impl Serialize for SqlTransactionError {
+	fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+		serializer.serialize_str(&self.to_string())
+	}
+}
+
+impl From<Error> for SqlTransactionError {
+	fn from(value: Error) -> Self {
+		SqlTransactionError::Database(value)
+	}
+}
+
+impl From<sqlx::Error> for SqlTransactionError {
+	fn from(value: sqlx::Error) -> Self {
+		SqlTransactionError::Database(value.into())
+	}
+}
+
+/// Get a reference to the open transaction referred to by the handle
+fn get_transaction(state: &mut AppState, handle: SqlTransactionHandle) -> Result<&mut SqliteTransaction, SqlTransactionError> {
+	match state.sql_transactions.get_mut(handle.index) {
+		Some(slot) if slot.generation == handle.generation => {
+			slot.last_used = Instant::now();
+			slot.transaction.as_mut().ok_or(SqlTransactionError::InvalidHandle(handle))
+		}
+		_ => Err(SqlTransactionError::InvalidHandle(handle)),
+	}
+}
+
+/// Remove the open transaction referred to by the handle, freeing its slot
+fn take_transaction(state: &mut AppState, handle: SqlTransactionHandle) -> Result<SqliteTransaction, SqlTransactionError> {
+	match state.sql_transactions.get_mut(handle.index) {
+		Some(slot) if slot.generation == handle.generation => slot.transaction.take().ok_or(SqlTransactionError::InvalidHandle(handle)),
+		_ => Err(SqlTransactionError::InvalidHandle(handle)),
+	}
+}
+
+/// Roll back open transactions which have been idle for longer than [SQL_TRANSACTION_IDLE_TIMEOUT], freeing their slots
+async fn expire_idle_transactions(state: &mut AppState) {
+	let now = Instant::now();
+	for slot in state.sql_transactions.iter_mut() {
+		if slot.transaction.is_some() && now.duration_since(slot.last_used) > SQL_TRANSACTION_IDLE_TIMEOUT {
+			// The transaction is rolled back when dropped in any case, so errors can be ignored
+			let _ = slot.transaction.take().unwrap().rollback().await;
+		}
+	}
+}
+
+/// Periodically roll back idle transactions (see [expire_idle_transactions]), so that a leaked transaction does not hold the database lock while no other commands are run
+pub fn spawn_expire_idle_transactions(app: AppHandle) {
+	tauri::async_runtime::spawn(async move {
+		let mut interval = tokio::time::interval(SQL_TRANSACTION_EXPIRY_INTERVAL);
+		loop {
+			interval.tick().await;
+			
+			let state = app.state::<Mutex<AppState>>();
+			let mut state = state.lock().await;
+			expire_idle_transactions(&mut state).await;
+		}
+	});
+}
+
 #[tauri::command]
-pub async fn sql_transaction_begin(state: State<'_, Mutex<AppState>>, db_instances: State<'_, DbInstances>, db: String) -> Result<usize, Error> {
+pub async fn sql_transaction_begin(state: State<'_, Mutex<AppState>>, db_instances: State<'_, DbInstances>, db: String) -> Result<SqlTransactionHandle, SqlTransactionError> {
 	let instances = db_instances.0.read().await;
 	let db = instances.get(&db).ok_or(Error::DatabaseNotLoaded(db))?;
 	
@@ -46,27 +159,29 @@ pub async fn sql_transaction_begin(state: State<'_, Mutex<AppState>>, db_instanc
 	
 	// Store transaction in state
 	let mut state = state.lock().await;
-	let available_index = state.sql_transactions.iter().position(|t| t.is_none());
+	expire_idle_transactions(&mut state).await;
+	let available_index = state.sql_transactions.iter().position(|t| t.transaction.is_none());
 	match available_index {
 		Some(i) => {
-			state.sql_transactions[i] = Some(transaction);
-			Ok(i)
+			// Reuse the slot with a new generation, so stale handles to the slot are detected
+			let slot = &mut state.sql_transactions[i];
+			slot.generation += 1;
+			slot.transaction = Some(transaction);
+			slot.last_used = Instant::now();
+			Ok(SqlTransactionHandle { index: i, generation: slot.generation })
 		}
 		None => {
-			state.sql_transactions.push(Some(transaction));
-			Ok(state.sql_transactions.len() - 1)
+			state.sql_transactions.push(SqlTransactionSlot { generation: 0, transaction: Some(transaction), last_used: Instant::now() });
+			Ok(SqlTransactionHandle { index: state.sql_transactions.len() - 1, generation: 0 })
 		}
 	}
 }
 
 #[tauri::command]
-pub async fn sql_transaction_execute(state: State<'_, Mutex<AppState>>, transaction_instance_id: usize, query: String, values: Vec<JsonValue>) -> Result<(u64, i64), Error> {
+pub async fn sql_transaction_execute(state: State<'_, Mutex<AppState>>, transaction_instance_id: SqlTransactionHandle, query: String, values: Vec<JsonValue>) -> Result<(u64, i64), SqlTransactionError> {
 	let mut state = state.lock().await;
-	let transaction =
-		state.sql_transactions.get_mut(transaction_instance_id)
-		.expect("Invalid database transaction ID")
-		.as_mut()  // Take reference to transaction rather than moving out of the Vec
-		.expect("Database transaction ID used after closed");
+	expire_idle_transactions(&mut state).await;
+	let transaction = get_transaction(&mut state, transaction_instance_id)?;
 	
 	let query = prepare_query(&query, values);
 	let result = transaction.execute(query).await?;
@@ -77,45 +192,55 @@ pub async fn sql_transaction_execute(state: State<'_, Mutex<AppState>>, transact
 }
 
 #[tauri::command]
-pub async fn sql_transaction_select(state: State<'_, Mutex<AppState>>, transaction_instance_id: usize, query: String, values: Vec<JsonValue>) -> Result<Vec<IndexMap<String, JsonValue>>, Error> {
+pub async fn sql_transaction_select(state: State<'_, Mutex<AppState>>, transaction_instance_id: SqlTransactionHandle, query: String, values: Vec<JsonValue>) -> Result<Vec<IndexMap<String, JsonValue>>, SqlTransactionError> {
 	let mut state = state.lock().await;
-	let transaction =
-		state.sql_transactions.get_mut(transaction_instance_id)
-		.expect("Invalid database transaction ID")
-		.as_mut()  // Take reference to transaction rather than moving out of the Vec
-		.expect("Database transaction ID used after closed");
+	expire_idle_transactions(&mut state).await;
+	let transaction = get_transaction(&mut state, transaction_instance_id)?;
 	
 	let query = prepare_query(&query, values);
 	let rows = transaction.fetch_all(query).await?;
-	rows_to_vec(rows)
+	Ok(rows_to_vec(rows)?)
 }
 
 #[tauri::command]
-pub async fn sql_transaction_rollback(state: State<'_, Mutex<AppState>>, transaction_instance_id: usize) -> Result<(), Error> {
+pub async fn sql_transaction_rollback(state: State<'_, Mutex<AppState>>, transaction_instance_id: SqlTransactionHandle) -> Result<(), SqlTransactionError> {
 	let mut state = state.lock().await;
-	
-	let transaction = state.sql_transactions.get_mut(transaction_instance_id)
-		.expect("Invalid database transaction ID")
-		.take()  // Remove from Vec
-		.expect("Database transaction ID used after closed");
+	expire_idle_transactions(&mut state).await;
+	let transaction = take_transaction(&mut state, transaction_instance_id)?;
 	
 	transaction.rollback().await?;
 	Ok(())
 }
 
 #[tauri::command]
-pub async fn sql_transaction_commit(state: State<'_, Mutex<AppState>>, transaction_instance_id: usize) -> Result<(), Error> {
+pub async fn sql_transaction_commit(state: State<'_, Mutex<AppState>>, transaction_instance_id: SqlTransactionHandle) -> Result<(), SqlTransactionError> {
 	let mut state = state.lock().await;
-	
-	let transaction = state.sql_transactions.get_mut(transaction_instance_id)
-		.expect("Invalid database transaction ID")
-		.take()  // Remove from Vec
-		.expect("Database transaction ID used after closed");
+	expire_idle_transactions(&mut state).await;
+	let transaction = take_transaction(&mut state, transaction_instance_id)?;
 	
 	transaction.commit().await?;
+	
+	// Cached reporting products may no longer reflect the database
+	state.reporting_cache.lock().unwrap().invalidate();
 	Ok(())
 }
 
+/// List the open transactions, for diagnosing transactions leaked by the frontend
+#[tauri::command]
+pub async fn sql_transaction_list_open(state: State<'_, Mutex<AppState>>) -> Result<Vec<OpenSqlTransaction>, SqlTransactionError> {
+	let mut state = state.lock().await;
+	expire_idle_transactions(&mut state).await;
+	
+	let now = Instant::now();
+	Ok(state.sql_transactions.iter().enumerate()
+		.filter(|(_, slot)| slot.transaction.is_some())
+		.map(|(index, slot)| OpenSqlTransaction {
+			handle: SqlTransactionHandle { index, generation: slot.generation },
+			idle_seconds: now.duration_since(slot.last_used).as_secs(),
+		})
+		.collect())
+}
+
 fn prepare_query<'a, 'b: 'a>(_query: &'b str, _values: Vec<JsonValue>) -> Query<'b, Sqlite, SqliteArguments<'a>> {
 	// Copied from tauri_plugin_sql/src/commands.rs
 	// Copyright 2019-2023 Tauri Programme within The Commons Conservancy
@@ -128,7 +253,12 @@ fn prepare_query<'a, 'b: 'a>(_query: &'b str, _values: Vec<JsonValue>) -> Query<
 		} else if value.is_string() {
 			query = query.bind(value.as_str().unwrap().to_owned())
 		} else if let Some(number) = value.as_number() {
-			query = query.bind(number.as_f64().unwrap_or_default())
+			// Bind integers as i64 to avoid loss of precision in quantities
+			if let Some(int) = number.as_i64() {
+				query = query.bind(int)
+			} else {
+				query = query.bind(number.as_f64().unwrap_or_default())
+			}
 		} else {
 			query = query.bind(value);
 		}
@@ -231,3 +361,37 @@ fn decode_sqlite_to_json(v: SqliteValueRef) -> Result<JsonValue, Error> {
 	
 	Ok(res)
 }
+
+#[cfg(test)]
+mod tests {
+	use sqlx::{Connection, SqliteConnection};
+	
+	use super::*;
+	
+	#[tokio::test]
+	async fn large_integer_round_trip() {
+		let mut connection = SqliteConnection::connect("sqlite::memory:").await.unwrap();
+		connection
+			.execute("CREATE TABLE postings (id INTEGER NOT NULL, quantity INTEGER, PRIMARY KEY(id))")
+			.await
+			.unwrap();
+		
+		// 2^53 + 1 cannot be represented exactly as f64
+		let quantity: i64 = 9007199254740993;
+		assert_ne!(quantity as f64 as i64, quantity);
+		
+		prepare_query("INSERT INTO postings (quantity) VALUES ($1)", vec![JsonValue::from(quantity)])
+			.execute(&mut connection)
+			.await
+			.unwrap();
+		
+		let rows = prepare_query("SELECT quantity FROM postings WHERE quantity = $1", vec![JsonValue::from(quantity)])
+			.fetch_all(&mut connection)
+			.await
+			.unwrap();
+		let rows = rows_to_vec(rows).unwrap();
+		
+		assert_eq!(rows.len(), 1);
+		assert_eq!(rows[0]["quantity"], JsonValue::from(quantity));
+	}
+}
@@ -0,0 +1,45 @@
+/*
+	DrCr: Web-based double-entry bookkeeping framework
+*/
+
+import dayjs from 'dayjs';
+import { beforeEach, describe, expect, it, vi } from 'vitest';
+
+const metadata = vi.hoisted(() => ({ eofy_date: '' }));
+vi.mock('./db.ts', () => ({ db: { metadata } }));
+
+import { startOfFinancialYear } from './dates.ts';
+
+function sofy(date: string): string {
+    return startOfFinancialYear(dayjs(date)).format('YYYY-MM-DD');
+}
+
+// Financial year ending on the last day of February ends on 29 February in leap years, however the year end is specified
+for (const eofyDate of ['2023-02-28', '2024-02-29']) {
+    describe('financial year ending ' + eofyDate, () => {
+        beforeEach(() => {
+            metadata.eofy_date = eofyDate;
+        });
+
+        it('starts on 1 March', () => {
+            expect(sofy('2023-03-01')).toBe('2023-03-01');
+            expect(sofy('2024-02-28')).toBe('2023-03-01');
+            expect(sofy('2024-02-29')).toBe('2023-03-01');
+            expect(sofy('2024-03-01')).toBe('2024-03-01');
+            expect(sofy('2025-02-28')).toBe('2024-03-01');
+            expect(sofy('2025-03-01')).toBe('2025-03-01');
+        });
+    });
+}
+
+describe('financial year ending 30 June', () => {
+    beforeEach(() => {
+        metadata.eofy_date = '2025-06-30';
+    });
+
+    it('starts on 1 July', () => {
+        expect(sofy('2024-02-29')).toBe('2023-07-01');
+        expect(sofy('2024-06-30')).toBe('2023-07-01');
+        expect(sofy('2024-07-01')).toBe('2024-07-01');
+    });
+});
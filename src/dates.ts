@@ -48,19 +48,30 @@ function getEofy(date: Dayjs): Dayjs {
         return date.set('month', 11).set('date', 31);
     }
 
-    let candidate = template.set('year', date.year());
+    let candidate = eofyInYear(template, date.year());
     if (candidate.isBefore(date, 'day')) {
-        candidate = candidate.add(1, 'year');
+        candidate = eofyInYear(template, date.year() + 1);
     }
     return candidate;
 }
 
+// Return the end of the financial year ending in the given year
+// If the financial year ends on the last day of a month, it does so in every year, so a year ending on 29 February is handled in non-leap years
+function eofyInYear(eofy: Dayjs, year: number): Dayjs {
+    const isEndOfMonth = eofy.add(1, 'day').month() !== eofy.month();
+    if (isEndOfMonth) {
+        const startOfMonth = eofy.set('date', 1).set('year', year);
+        return startOfMonth.set('date', startOfMonth.daysInMonth());
+    }
+    return eofy.set('year', year);
+}
+
 export function startOfFinancialYear(date: Dayjs): Dayjs {
     if (!date.isValid()) {
         return date;
     }
     const eofy = getEofy(date);
-    return eofy.subtract(1, 'year').add(1, 'day');
+    return eofyInYear(eofy, eofy.year() - 1).add(1, 'day');
 }
 
 // Subtitle helpers used across reports
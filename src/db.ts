@@ -24,10 +24,10 @@ import Database from '@tauri-apps/plugin-sql';
 import { reactive } from 'vue';
 
 import { asCost } from './amounts.ts';
-import { ExtendedDatabase } from './dbutil.ts';
+import { DatabaseTransaction, ExtendedDatabase } from './dbutil.ts';
 import { CriticalError } from './error.ts';
 
-export const DB_VERSION = 6;  // Should match schema.sql
+export const DB_VERSION = 13;  // Should match schema.sql
 export const DT_FORMAT = 'YYYY-MM-DD HH:mm:ss.SSS000';
 
 export const db = reactive({
@@ -89,6 +89,11 @@ export const db = reactive({
 			// Initialise cached data
 			const metadataRaw: {key: string, value: string}[] = await session.select("SELECT * FROM metadata");
 			const metadataObject = Object.fromEntries(metadataRaw.map((x) => [x.key, x.value]));
+			for (const key of ['eofy_date', 'reporting_commodity', 'amount_dps']) {
+				if (metadataObject[key] === undefined) {
+					throw new CriticalError('Database is missing required metadata: ' + key);
+				}
+			}
 			this.metadata.version = parseInt(metadataObject.version);
 			this.metadata.eofy_date = metadataObject.eofy_date;
             this.metadata.reporting_commodity = metadataObject.reporting_commodity;
@@ -97,7 +102,7 @@ export const db = reactive({
             this.metadata.place_separator = (metadataObject.place_separator ?? '\u202F');
             this.metadata.decimal_separator = (metadataObject.decimal_separator ?? '.');
             this.metadata.date_style = (metadataObject.date_style ?? 'YYYY-MM-DD');
-            this.metadata.plugins = metadataObject.plugins.length > 0 ? metadataObject.plugins.split(';') : [];
+            this.metadata.plugins = (metadataObject.plugins ?? '').length > 0 ? metadataObject.plugins.split(';') : [];
         }
     },
 	
@@ -155,6 +160,28 @@ export async function createNewDatabase(filename: string, eofy_date: string, rep
 	await transaction.commit();
 }
 
+export async function setMetadata(tx: DatabaseTransaction, key: string, value: string) {
+	// Upsert the metadata key
+	const res = await tx.execute(`UPDATE metadata SET value = ? WHERE key = ?`, [value, key]);
+	if (res.rowsAffected === 0) {
+		await tx.execute(`INSERT INTO metadata (key, value) VALUES (?, ?)`, [key, value]);
+	}
+}
+
+let auditUser: string | null | undefined = undefined;
+
+export async function recordAuditOperation(tx: DatabaseTransaction, operation: string, tableName: string, rowIds: number[]) {
+	// Record the operation in the audit log, within the same database transaction as the change
+	// The user is the user running DrCr, rather than a setting in the database, so that each user's changes are attributed to them
+	if (auditUser === undefined) {
+		auditUser = await invoke('get_audit_user');
+	}
+	await tx.execute(
+		`INSERT INTO audit_log (dt, operation, table_name, row_ids, user) VALUES (strftime('%Y-%m-%d %H:%M:%f000', 'now'), ?, ?, ?, ?)`,
+		[operation, tableName, rowIds.join(','), auditUser]
+	);
+}
+
 export function joinedToTransactions(joinedTransactionPostings: JoinedTransactionPosting[]): Transaction[] {
 	// Group postings into transactions
 	const transactions: Transaction[] = [];
@@ -376,6 +403,18 @@ export interface StatementLine {
 	dedup_ignore?: number
 }
 
+const AUDITED_TABLES = ['account_configurations', 'austax_cgt_cost_adjustments', 'austax_reference_returns', 'balance_assertions', 'commodity_prices', 'financial_year_ends', 'opening_balances', 'postings', 'statement_line_reconciliations', 'statement_lines', 'transactions'];
+
+async function createAuditTriggers(tx: DatabaseTransaction, table: string) {
+	// Create triggers recording changes to the table in the audit log, as in schema.sql
+	for (const operation of ['INSERT', 'UPDATE', 'DELETE']) {
+		const row = operation === 'DELETE' ? 'OLD' : 'NEW';
+		await tx.execute(`CREATE TRIGGER audit_${table}_${operation.toLowerCase()} AFTER ${operation} ON ${table} BEGIN
+			INSERT INTO audit_log (dt, operation, table_name, row_ids) VALUES (strftime('%Y-%m-%d %H:%M:%f000', 'now'), '${operation}', '${table}', ${row}.id);
+		END`);
+	}
+}
+
 async function migrateDatabase(session: ExtendedDatabase, fromVersion: number, toVersion: number) {
 	// Perform simple in-place migrations
 	const tx = await session.begin();
@@ -395,6 +434,81 @@ async function migrateDatabase(session: ExtendedDatabase, fromVersion: number, t
 				// v5 -> v6: allow ignoring duplicate detection for specific lines
 				await tx.execute(`ALTER TABLE statement_lines ADD COLUMN dedup_ignore INTEGER DEFAULT 0`);
 				break;
+			case 6:
+				// v6 -> v7: add audit log table (triggers are created in the v12 -> v13 migration)
+				await tx.execute(`CREATE TABLE audit_log (
+					id INTEGER NOT NULL,
+					dt DATETIME,
+					operation VARCHAR,
+					table_name VARCHAR,
+					row_id INTEGER,
+					user VARCHAR,
+					PRIMARY KEY(id)
+				)`);
+				break;
+			case 7:
+				// v7 -> v8: add commodity prices for converting postings without a cost basis
+				await tx.execute(`CREATE TABLE commodity_prices (
+					id INTEGER NOT NULL,
+					dt DATETIME,
+					commodity VARCHAR,
+					price REAL,
+					PRIMARY KEY(id)
+				)`);
+				break;
+			case 8:
+				// v8 -> v9: add opening balances for files started part way through
+				await tx.execute(`CREATE TABLE opening_balances (
+					id INTEGER NOT NULL,
+					account VARCHAR,
+					dt DATETIME,
+					quantity INTEGER,
+					commodity VARCHAR,
+					PRIMARY KEY(id)
+				)`);
+				break;
+			case 9:
+				// v9 -> v10: add end dates of past financial years, for files where the end of the financial year has changed
+				await tx.execute(`CREATE TABLE financial_year_ends (
+					id INTEGER NOT NULL,
+					dt DATETIME,
+					PRIMARY KEY(id)
+				)`);
+				break;
+			case 10:
+				// v10 -> v11: add figures from lodged returns, for comparison with the austax tax summary
+				await tx.execute(`CREATE TABLE austax_reference_returns (
+					id INTEGER NOT NULL,
+					year INTEGER,
+					owner VARCHAR,
+					item VARCHAR,
+					quantity INTEGER,
+					PRIMARY KEY (id)
+				)`);
+				break;
+			case 11:
+				// v11 -> v12: store commodity prices exactly as a ratio of integers, rather than as floating point
+				// Existing prices are converted to 6 decimal places
+				await tx.execute(`ALTER TABLE commodity_prices ADD COLUMN price_numerator INTEGER`);
+				await tx.execute(`ALTER TABLE commodity_prices ADD COLUMN price_denominator INTEGER`);
+				await tx.execute(`UPDATE commodity_prices SET price_numerator = CAST(ROUND(price * 1000000) AS INTEGER), price_denominator = 1000000`);
+				await tx.execute(`ALTER TABLE commodity_prices DROP COLUMN price`);
+				break;
+			case 12:
+				// v12 -> v13: record the ids of all rows affected by an operation, and no longer take the user from the audit_user metadata key
+				// Triggers from earlier versions refer to row_id, so must be dropped before the column
+				for (const table of AUDITED_TABLES) {
+					for (const operation of ['insert', 'update', 'delete']) {
+						await tx.execute(`DROP TRIGGER IF EXISTS audit_${table}_${operation}`);
+					}
+				}
+				await tx.execute(`ALTER TABLE audit_log ADD COLUMN row_ids VARCHAR`);
+				await tx.execute(`UPDATE audit_log SET row_ids = CAST(row_id AS TEXT)`);
+				await tx.execute(`ALTER TABLE audit_log DROP COLUMN row_id`);
+				for (const table of AUDITED_TABLES) {
+					await createAuditTriggers(tx, table);
+				}
+				break;
 			default:
 				await tx.rollback();
 				throw new CriticalError('No migration path from version ' + v);
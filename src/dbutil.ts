@@ -27,7 +27,9 @@ export class ExtendedDatabase {
 	}
 	
 	async execute(query: string, bindValues?: unknown[]): Promise<QueryResult> {
-		return await this.db.execute(query, bindValues);
+		const result = await this.db.execute(query, bindValues);
+		await invoke('invalidate_reporting_cache');  // Cached reports may no longer reflect the database
+		return result;
 	}
 	
 	async select<T>(query: string, bindValues?: unknown[]): Promise<T> {
@@ -35,7 +37,7 @@ export class ExtendedDatabase {
 	}
 	
 	async begin(): Promise<DatabaseTransaction> {
-		const transactionInstanceId: number = await invoke('sql_transaction_begin', {
+		const transactionInstanceId: SqlTransactionHandle = await invoke('sql_transaction_begin', {
 			db: this.db.path
 		});
 		const db_transaction = new DatabaseTransaction(this, transactionInstanceId);
@@ -44,11 +46,17 @@ export class ExtendedDatabase {
 	}
 }
 
+// Handle to an open database transaction, see src-tauri/src/sql.rs
+export interface SqlTransactionHandle {
+	index: number;
+	generation: number;
+}
+
 export class DatabaseTransaction {
 	db: ExtendedDatabase;
-	transactionInstanceId: number;
+	transactionInstanceId: SqlTransactionHandle;
 	
-	constructor(db: ExtendedDatabase, transactionInstanceId: number) {
+	constructor(db: ExtendedDatabase, transactionInstanceId: SqlTransactionHandle) {
 		this.db = db;
 		this.transactionInstanceId = transactionInstanceId;
 	}
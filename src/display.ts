@@ -57,6 +57,16 @@ export function ppWithCommodity(quantity: number, commodity: string): string {
 	}
 }
 
+export function ppBasisPoints(quantity: number): string {
+	// Pretty print the quantity in basis points as a percentage
+	if (quantity < 0) {
+		return '−' + ppBasisPoints(-quantity);
+	}
+	
+	const decimalSep = db.metadata.decimal_separator ?? '.';
+	return Math.floor(quantity / 100).toString() + decimalSep + (quantity % 100).toString().padStart(2, '0') + '%';
+}
+
 export function ppBracketed(quantity: number, link?: string): string {
 	// Pretty print the quantity with brackets for negative numbers
 	let text, space;
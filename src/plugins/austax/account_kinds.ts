@@ -19,6 +19,9 @@
 import { resolveResource } from '@tauri-apps/api/path';
 import { readTextFile } from '@tauri-apps/plugin-fs';
 
+// Prefix of account kinds which tag an account with the taxpayer (owner) it belongs to (see OWNER_KIND_PREFIX in account_kinds.luau)
+export const OWNER_KIND_PREFIX = 'austax.owner.';
+
 // Dodgy implementation to parse Lua table as JSON
 function parseLua(luaArray: string): any[] {
 	luaArray = luaArray.trim();
@@ -65,11 +68,12 @@ export async function getAccountKinds(): Promise<[string, string][]> {
 		accountKinds.push(['austax.' + code, name + ' (' + number + ')']);
 	}
 	
-	// Hard-coded types
-	accountKinds.push(['austax.offset', 'Tax offset']);
-	accountKinds.push(['austax.paygw', 'PAYG withheld amounts']);
-	accountKinds.push(['austax.cgtasset', 'CGT asset']);
-	accountKinds.push(['austax.rfb', 'Reportable fringe benefit']);
+	// Parse other_types
+	const otherTypesLua = luaFileContent.match(/local other_types = ({.*?\n})\n/s)![1];
+	const otherTypes = parseLua(otherTypesLua);
+	for (const [code, name] of otherTypes) {
+		accountKinds.push(['austax.' + code, name]);
+	}
 	
 	return accountKinds;
 }
@@ -21,10 +21,12 @@ import austax from './plugins/austax/plugin.ts';
 
 export const drcrAccountKinds: [string, string][] = [
 	['drcr.asset', 'Asset'],
+	['drcr.cash', 'Cash or bank (for cash basis)'],
 	['drcr.liability', 'Liability'],
 	['drcr.income', 'Income'],
 	['drcr.expense', 'Expense'],
-	['drcr.equity', 'Equity']
+	['drcr.equity', 'Equity'],
+	['drcr.always_visible', 'Always visible (show even when zero)']
 ];
 
 export async function getAccountKinds() {
@@ -34,6 +36,9 @@ export async function getAccountKinds() {
 	if (db.metadata.plugins.indexOf('austax') >= 0) {
 		accountKinds.push(...await austax.getAccountKinds());
 	}
+	if (db.metadata.plugins.indexOf('depreciation') >= 0) {
+		accountKinds.push(['depreciation.asset', 'Depreciable asset']);
+	}
 	
 	return accountKinds;
 }
@@ -17,12 +17,16 @@
 */
 
 import { db, serialiseAmount } from '../db.ts';
+import { ppBasisPoints } from '../display.ts';
 import { CriticalError } from '../error.ts';
 
 export class DynamicReport {
 	title!: string;
 	columns!: string[];
 	entries!: DynamicReportEntry[];
+	notes!: Note[];
+	percentage_columns?: PercentageColumn[];
+	reference_column?: string;
 	
 	static fromJSON(json: string): DynamicReport {
 		return Object.assign(new DynamicReport(), JSON.parse(json));
@@ -32,6 +36,30 @@ export class DynamicReport {
 		return reportEntryById(this, id);
 	}
 	
+	// Return the quantities for the Row with the given id (equivalent to DynamicReport::quantity_for_id in libdrcr)
+	quantityForId(id: string): number[] | null {
+		const entry = this.byId(id);
+		if (entry === null) {
+			return null;
+		}
+		if ((entry as { Row: Row }).Row) {
+			return (entry as { Row: Row }).Row.quantity;
+		}
+		throw new CriticalError('Called quantityForId on non-Row');
+	}
+	
+	// Return the quantities for the Row with the given id, or the subtotals for the Section with the given id (equivalent to DynamicReport::subtotal_for_id in libdrcr)
+	subtotalForId(id: string): number[] | null {
+		const entry = this.byId(id);
+		if (entry === null) {
+			return null;
+		}
+		if ((entry as { Section: Section }).Section) {
+			return sectionSubtotal((entry as { Section: Section }).Section, this.columns.length);
+		}
+		return (entry as { Row: Row }).Row.quantity;
+	}
+	
 	// Convert to report to CSV
 	toCSV(columns?: string[], subtitle?: string): string {
 		let csv = '';
@@ -46,11 +74,14 @@ export class DynamicReport {
 		for (const column of columns || this.columns) {
 			csv += ',' + escapeCSV(column);
 		}
+		if (this.reference_column !== undefined) {
+			csv += ',' + escapeCSV(this.reference_column);
+		}
 		csv += '\n';
 		
 		// Entries
 		for (const entry of this.entries) {
-			csv += entryToCSV(entry);
+			csv += entryToCSV(entry, this.percentage_columns ?? [], this.reference_column !== undefined);
 		}
 		
 		return csv;
@@ -66,6 +97,7 @@ export interface Section {
 	visible: boolean;
 	auto_hide: boolean;
 	entries: DynamicReportEntry[];
+	note: string | null;
 }
 
 export interface Row {
@@ -77,6 +109,32 @@ export interface Row {
 	link: string | null;
 	heading: boolean;
 	bordered: boolean;
+	contra: boolean;
+	note: string | null;
+	unit: RowUnit;
+	native_amounts: (NativeAmount | null)[];
+	reference_quantity: number | null;
+	mismatch: boolean;
+}
+
+export interface NativeAmount {
+	quantity: number;
+	commodity: string;
+}
+
+// Currency amounts are multiples of 10^-dps; BasisPoints are hundredths of a percent; Count is a number of items
+export type RowUnit = 'Currency' | 'BasisPoints' | 'Count';
+
+// Column expressing another column as a percentage (in basis points) of the row with id base_id
+export interface PercentageColumn {
+	column: number;
+	base_id: string;
+	base_is_zero: boolean;
+}
+
+export interface Note {
+	reference: string;
+	text: string;
 }
 
 export interface Spacer {
@@ -101,6 +159,29 @@ export function reportEntryById(report: DynamicReport | Section, id: string): Dy
 	return null;
 }
 
+// Calculate the subtotals for the Section (equivalent to Section::subtotal in libdrcr)
+export function sectionSubtotal(section: Section, numColumns: number): number[] {
+	const subtotals: number[] = new Array(numColumns).fill(0);
+	for (const entry of section.entries) {
+		if ((entry as { Section: Section }).Section) {
+			for (const [index, subtotal] of sectionSubtotal((entry as { Section: Section }).Section, numColumns).entries()) {
+				subtotals[index] += subtotal;
+			}
+		} else if ((entry as { Row: Row }).Row) {
+			const row = (entry as { Row: Row }).Row;
+			// Percentages cannot be added to amounts, and heading rows would otherwise be counted twice
+			if (row.unit !== 'Currency' || row.heading) {
+				continue;
+			}
+			const sign = row.contra ? -1 : 1;
+			for (const [index, quantity] of row.quantity.entries()) {
+				subtotals[index] += sign * quantity;
+			}
+		}
+	}
+	return subtotals;
+}
+
 // Escape the given text as contents of a single CSV field
 function escapeCSV(cell: string): string {
 	if (cell.indexOf('"') >= 0) {
@@ -112,21 +193,35 @@ function escapeCSV(cell: string): string {
 	return cell;
 }
 
-function entryToCSV(entry: DynamicReportEntry): string {
+function entryToCSV(entry: DynamicReportEntry, percentageColumns: PercentageColumn[], referenceColumn: boolean): string {
 	if (entry === 'Spacer') {
 		return '\n';
 	} else if ((entry as { Section: Section }).Section) {
 		const section = (entry as { Section: Section }).Section;
 		let csv = '';
 		for (const sectionEntry of section.entries) {
-			csv += entryToCSV(sectionEntry);
+			csv += entryToCSV(sectionEntry, percentageColumns, referenceColumn);
 		}
 		return csv;
 	} else if ((entry as { Row: Row }).Row) {
 		const row = (entry as { Row: Row}).Row;
 		let csv = escapeCSV(row.text);
-		for (const quantity of row.quantity) {
-			csv += ',' + escapeCSV(serialiseAmount(quantity, db.metadata.reporting_commodity));
+		for (const [index, quantity] of row.quantity.entries()) {
+			const percentageColumn = percentageColumns.find((c) => c.column === index);
+			if (percentageColumn) {
+				// Percentage of a zero base is undefined
+				csv += ',' + (percentageColumn.base_is_zero ? '' : escapeCSV(ppBasisPoints(quantity)));
+			} else if (row.unit === 'BasisPoints') {
+				csv += ',' + escapeCSV(ppBasisPoints(quantity));
+			} else if (row.unit === 'Count') {
+				csv += ',' + quantity.toString();
+			} else {
+				csv += ',' + escapeCSV(serialiseAmount(quantity, db.metadata.reporting_commodity));
+			}
+		}
+		if (referenceColumn) {
+			// Blank if there is no reference figure for the row
+			csv += ',' + (row.reference_quantity === null ? '' : escapeCSV(serialiseAmount(row.reference_quantity, db.metadata.reporting_commodity)));
 		}
 		csv += '\n';
 		return csv;